@@ -0,0 +1,46 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::PrivacyPayload;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::user::privacy;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/account").service(
+        web::resource("/privacy")
+            .route(web::get().to(handle_get_privacy))
+            .route(web::put().to(handle_set_privacy)),
+    )
+}
+
+pub async fn handle_get_privacy(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let settings = privacy::get_settings(&redis, &claims.username).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Privacy settings retrieved successfully")
+            .data(settings)
+            .into(),
+    )
+}
+
+pub async fn handle_set_privacy(
+    payload: web::Json<PrivacyPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let settings =
+        privacy::set_strip_shared_metadata(&redis, &claims.username, payload.strip_shared_metadata)
+            .await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Privacy settings updated successfully")
+            .data(settings)
+            .into(),
+    )
+}
@@ -0,0 +1,44 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::ActivityQuery;
+use crate::api::utils::types::Response;
+use crate::constants::MAX_ACTIVITY_ENTRIES;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::sync::activity::{self, ActivityFilter};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/activity").service(web::resource("").route(web::get().to(handle_activity)))
+}
+
+/// Returns what happened to the user's files recently, newest first. The returned cursor is
+/// passed back as `before` to page through older activity.
+pub async fn handle_activity(
+    query: web::Query<ActivityQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_ACTIVITY_ENTRIES);
+    let filter = ActivityFilter {
+        kind: query.kind,
+        other_devices: query.other_devices,
+    };
+
+    let page = activity::feed(
+        &redis,
+        &claims.username,
+        &claims.device_id,
+        query.before.as_deref(),
+        &filter,
+        limit,
+    )
+    .await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Activity listed successfully")
+            .data(page)
+            .into(),
+    )
+}
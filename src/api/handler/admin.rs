@@ -0,0 +1,600 @@
+use crate::api::handler::audit;
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::{AuditQuery, DeadLetterQuery, GcQuery, QuotaPayload};
+use crate::api::utils::responses::ScrubResponse;
+use crate::api::utils::types::Response;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::jobs::models::Queue;
+use crate::jobs::queue;
+use crate::jwt::models::Claims;
+use crate::metrics::registry;
+use crate::redis::client::RedisClient;
+use crate::redis::client::RedisKey;
+use crate::scan::status;
+use crate::scheduler::runner::{Scheduler, Trigger};
+use crate::scheduler::tasks::MaintenanceTask;
+use crate::storage::blob::BlobStore;
+use crate::storage::content_type::TypePolicy;
+use crate::storage::gc::GarbageCollector;
+use crate::storage::scrubber::ScrubStatus;
+use crate::storage::{content, files, locks, quota};
+use crate::user::models::User;
+use crate::user::roles;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/admin")
+        .service(web::resource("/gc").route(web::post().to(handle_gc)))
+        .service(web::resource("/rebuild").route(web::post().to(handle_rebuild)))
+        .service(web::resource("/scrub").route(web::get().to(handle_scrub_status)))
+        .service(web::resource("/metrics").route(web::get().to(handle_metrics)))
+        .service(
+            web::resource("/users/{username}/type-policy")
+                .route(web::get().to(handle_get_type_policy))
+                .route(web::put().to(handle_set_type_policy)),
+        )
+        .service(
+            web::resource("/users/{username}/quota")
+                .route(web::get().to(handle_get_quota))
+                .route(web::put().to(handle_set_quota)),
+        )
+        .service(web::resource("/locks/{id}").route(web::delete().to(handle_break_lock)))
+        .service(web::resource("/jobs").route(web::get().to(handle_job_stats)))
+        .service(web::resource("/jobs/dead").route(web::get().to(handle_dead_letters)))
+        .service(web::resource("/jobs/{id}").route(web::delete().to(handle_job_discard)))
+        .service(web::resource("/jobs/{id}/retry").route(web::post().to(handle_job_retry)))
+        .service(web::resource("/schedule").route(web::get().to(handle_schedule)))
+        .service(web::resource("/schedule/{task}/run").route(web::post().to(handle_task_run)))
+        .service(web::resource("/scan/{id}").route(web::post().to(handle_rescan)))
+        .service(web::resource("/quarantine").route(web::get().to(handle_quarantine_list)))
+        .service(web::resource("/audit").route(web::get().to(handle_audit_log)))
+        .service(web::resource("/audit/export").route(web::get().to(handle_audit_export)))
+        .service(web::resource("/audit/verify").route(web::get().to(handle_audit_verify)))
+        .service(
+            web::resource("/quarantine/{hash}")
+                .route(web::post().to(handle_quarantine_release))
+                .route(web::delete().to(handle_quarantine_discard)),
+        )
+}
+
+/// Records an administrative action in the audit log.
+async fn record_action(
+    redis: &RedisClient,
+    request: &HttpRequest,
+    claims: &Claims,
+    target: String,
+    detail: &str,
+) {
+    let actor = Actor::from_claims(claims, request);
+    store::record(
+        redis,
+        &actor,
+        AuditAction::Admin,
+        Some(target),
+        Some(detail.to_string()),
+    )
+    .await;
+}
+
+pub fn require_admin(claims: &Claims) -> Result<(), ServiceError> {
+    if !roles::is_admin(&claims.username) {
+        return Err(ServiceError::Forbidden(
+            "This action requires administrator privileges".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Dry runs delete nothing and report what a collection would reclaim, so they answer with
+/// the report. Actual collections run in the background as the `gc` task, whose last run
+/// the schedule lists.
+pub async fn handle_gc(
+    request: HttpRequest,
+    query: web::Query<GcQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+    scheduler: web::Data<Arc<Scheduler>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    if query.dry_run {
+        let collector = GarbageCollector::new(redis.get_ref().clone(), blobs.get_ref().clone());
+        let report = collector.run(true).await?;
+
+        let detail = "Ran a garbage collection dry run";
+        record_action(&redis, &request, &claims, "storage".to_string(), detail).await;
+
+        return Ok(Response::new(StatusCode::OK, "Garbage collection finished")
+            .data(report)
+            .into());
+    }
+
+    scheduler
+        .start(MaintenanceTask::Gc, Trigger::Manual)
+        .await?;
+
+    let detail = "Started a garbage collection";
+    record_action(&redis, &request, &claims, "storage".to_string(), detail).await;
+
+    Ok(Response::<()>::new(StatusCode::ACCEPTED, "Garbage collection started").into())
+}
+
+/// Runs in the background as the `rebuild` task, whose last run the schedule lists.
+pub async fn handle_rebuild(
+    request: HttpRequest,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    scheduler: web::Data<Arc<Scheduler>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    scheduler
+        .start(MaintenanceTask::Rebuild, Trigger::Manual)
+        .await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        "storage".to_string(),
+        "Started a rebuild of the storage redundancy",
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::ACCEPTED, "Storage rebuild started").into())
+}
+
+pub async fn handle_scrub_status(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let status = match redis.async_exists(RedisKey::ScrubStatus).await? {
+        true => Some(
+            redis
+                .d_async_get::<ScrubStatus>(RedisKey::ScrubStatus)
+                .await?,
+        ),
+        false => None,
+    };
+    let corrupted_blobs = redis.async_smembers(RedisKey::CorruptedBlobs).await?;
+    let corrupted_files = files::all_files(&redis)
+        .await?
+        .into_iter()
+        .filter(|file| file.corrupted)
+        .collect();
+
+    let response = ScrubResponse {
+        status,
+        corrupted_blobs,
+        corrupted_files,
+    };
+
+    Ok(
+        Response::new(StatusCode::OK, "Scrub status retrieved successfully")
+            .data(response)
+            .into(),
+    )
+}
+
+pub async fn handle_metrics(claims: web::ReqData<Claims>) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(registry::render()))
+}
+
+pub async fn handle_get_type_policy(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let user = load_user(&redis, &path).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Type policy retrieved successfully")
+            .data(user.type_policy)
+            .into(),
+    )
+}
+
+pub async fn handle_set_type_policy(
+    request: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Json<TypePolicy>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let mut user = load_user(&redis, &path).await?;
+    user.type_policy = payload.into_inner();
+    redis
+        .s_async_set(RedisKey::Account(user.username.clone()), &user)
+        .await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("user:{}", user.username),
+        "Changed the type policy",
+    )
+    .await;
+
+    Ok(
+        Response::new(StatusCode::OK, "Type policy updated successfully")
+            .data(user.type_policy)
+            .into(),
+    )
+}
+
+pub async fn handle_get_quota(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let user = load_user(&redis, &path).await?;
+    let usage = quota::usage(&redis, &user.username).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Quota retrieved successfully")
+            .data(usage)
+            .into(),
+    )
+}
+
+pub async fn handle_set_quota(
+    request: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Json<QuotaPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let mut user = load_user(&redis, &path).await?;
+    user.quota = payload.quota;
+    redis
+        .s_async_set(RedisKey::Account(user.username.clone()), &user)
+        .await?;
+    let usage = quota::usage(&redis, &user.username).await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("user:{}", user.username),
+        "Changed the quota",
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::OK, "Quota updated successfully")
+        .data(usage)
+        .into())
+}
+
+async fn load_user(redis: &RedisClient, username: &str) -> Result<User, ServiceError> {
+    let exists = redis
+        .async_exists(RedisKey::Account(username.to_string()))
+        .await?;
+
+    if !exists {
+        return Err(ServiceError::NotFound(format!(
+            "User {} does not exist",
+            username
+        )));
+    }
+
+    redis
+        .d_async_get(RedisKey::Account(username.to_string()))
+        .await
+}
+
+/// Breaks the lease on a file whatever its holder, e.g. when a device holding it was lost.
+pub async fn handle_break_lock(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let lock = locks::break_lock(&redis, &path).await?;
+    log::info!(
+        "{} broke the lock of {} ({}) on {}",
+        claims.username,
+        lock.username,
+        lock.device_id,
+        lock.file
+    );
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("file:{}", lock.file),
+        "Broke the lock",
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::OK, "Lock broken successfully")
+        .data(lock)
+        .into())
+}
+
+pub async fn handle_job_stats(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let stats = queue::stats(&redis).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Queue statistics retrieved successfully")
+            .data(stats)
+            .into(),
+    )
+}
+
+/// Lists the jobs that ran out of attempts, in the default queue unless another is given.
+pub async fn handle_dead_letters(
+    query: web::Query<DeadLetterQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let jobs = queue::dead_letters(&redis, query.queue.unwrap_or(Queue::Default)).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Dead jobs listed successfully")
+            .data(jobs)
+            .into(),
+    )
+}
+
+/// Runs a dead job again with a fresh set of attempts.
+pub async fn handle_job_retry(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let job = queue::retry_dead(&redis, &path).await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("job:{}", job.id),
+        "Retried the dead job",
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::ACCEPTED, "Job queued again")
+        .data(job)
+        .into())
+}
+
+pub async fn handle_job_discard(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let job = queue::discard_dead(&redis, &path).await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("job:{}", job.id),
+        "Discarded the dead job",
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::OK, "Job discarded successfully")
+        .data(job)
+        .into())
+}
+
+/// Lists the maintenance tasks with their schedule and how their last run went.
+pub async fn handle_schedule(
+    claims: web::ReqData<Claims>,
+    scheduler: web::Data<Arc<Scheduler>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let tasks = scheduler.list().await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Scheduled tasks listed successfully")
+            .data(tasks)
+            .into(),
+    )
+}
+
+/// Starts a maintenance task now, whatever its schedule.
+pub async fn handle_task_run(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    scheduler: web::Data<Arc<Scheduler>>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let task = MaintenanceTask::from_name(&path)
+        .ok_or_else(|| ServiceError::NotFound(format!("Task {} does not exist", path)))?;
+    scheduler.start(task, Trigger::Manual).await?;
+    log::info!("{} started {}", claims.username, task.name());
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("task:{}", task.name()),
+        "Started the task",
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::ACCEPTED, "Task started").into())
+}
+
+/// Scans the current version of a file again, e.g. after a failed scan or a signature update.
+pub async fn handle_rescan(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let file = files::get_file(&redis, &path).await?;
+    let data = content::read_content(blobs.as_ref().as_ref(), &file.hash)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to read the file".to_string(), Some(error))
+        })?;
+
+    let result = status::scan(&redis, &file.hash, &data)
+        .await?
+        .ok_or_else(|| ServiceError::BadRequest("Malware scanning is disabled".to_string()))?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("file:{}", file.id),
+        "Scanned the file again",
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::OK, "File scanned successfully")
+        .data(result)
+        .into())
+}
+
+pub async fn handle_quarantine_list(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let samples = status::list_quarantine(&redis).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Quarantine listed successfully")
+            .data(samples)
+            .into(),
+    )
+}
+
+/// Releases a false positive: its content is marked clean and can be downloaded again.
+pub async fn handle_quarantine_release(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    status::remove_from_quarantine(&redis, &path, true).await?;
+    log::info!("{} released {} from the quarantine", claims.username, path);
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("content:{}", path),
+        "Released the content from the quarantine",
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::OK, "Content released successfully").into())
+}
+
+/// Discards a sample. Files still holding the content keep being refused for download.
+pub async fn handle_quarantine_discard(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    status::remove_from_quarantine(&redis, &path, false).await?;
+
+    record_action(
+        &redis,
+        &request,
+        &claims,
+        format!("content:{}", path),
+        "Discarded the quarantined content",
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::OK, "Content discarded successfully").into())
+}
+
+/// Lists the whole audit log, or the entries of the user given in the query.
+pub async fn handle_audit_log(
+    query: web::Query<AuditQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    audit::audit_page(&redis, query.user.as_deref(), &query).await
+}
+
+pub async fn handle_audit_export(
+    request: HttpRequest,
+    query: web::Query<AuditQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let target = match &query.user {
+        Some(user) => format!("user:{}", user),
+        None => "audit".to_string(),
+    };
+    record_action(&redis, &request, &claims, target, "Exported the audit log").await;
+
+    audit::audit_export(&redis, query.user.as_deref(), &query).await
+}
+
+/// Checks the hash chain of the whole audit log.
+pub async fn handle_audit_verify(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    require_admin(&claims)?;
+
+    let report = store::verify(&redis).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Audit log verified successfully")
+            .data(report)
+            .into(),
+    )
+}
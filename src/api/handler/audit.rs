@@ -0,0 +1,109 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::AuditQuery;
+use crate::api::utils::types::Response;
+use crate::audit::models::AuditFilter;
+use crate::audit::store;
+use crate::constants::MAX_AUDIT_ENTRIES;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/audit")
+        .service(web::resource("").route(web::get().to(handle_audit_log)))
+        .service(web::resource("/export").route(web::get().to(handle_audit_export)))
+}
+
+pub fn filter(query: &AuditQuery) -> AuditFilter {
+    AuditFilter {
+        action: query.action,
+        target: query.target.clone(),
+        from: query.from,
+        to: query.to,
+    }
+}
+
+/// Lists the entries of the audit log of `username`, or of the whole log, a page at a time.
+pub async fn audit_page(
+    redis: &RedisClient,
+    username: Option<&str>,
+    query: &AuditQuery,
+) -> Result<HttpResponse, ServiceError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_AUDIT_ENTRIES);
+    let page = store::query(
+        redis,
+        username,
+        query.since.as_deref(),
+        &filter(query),
+        limit,
+    )
+    .await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Audit log retrieved successfully")
+            .data(page)
+            .into(),
+    )
+}
+
+/// Exports the matching entries as JSON lines, oldest first.
+pub async fn audit_export(
+    redis: &RedisClient,
+    username: Option<&str>,
+    query: &AuditQuery,
+) -> Result<HttpResponse, ServiceError> {
+    let filter = filter(query);
+    let mut cursor = query.since.clone();
+    let mut body = Vec::new();
+
+    loop {
+        let page = store::query(
+            redis,
+            username,
+            cursor.as_deref(),
+            &filter,
+            MAX_AUDIT_ENTRIES,
+        )
+        .await?;
+        if page.entries.is_empty() {
+            break;
+        }
+
+        for entry in &page.entries {
+            serde_json::to_writer(&mut body, entry).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to serialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })?;
+            body.push(b'\n');
+        }
+        cursor = page.cursor;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"audit.jsonl\"",
+        ))
+        .body(body))
+}
+
+pub async fn handle_audit_log(
+    query: web::Query<AuditQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    audit_page(&redis, Some(&claims.username), &query).await
+}
+
+pub async fn handle_audit_export(
+    query: web::Query<AuditQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    audit_export(&redis, Some(&claims.username), &query).await
+}
@@ -0,0 +1,37 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::BatchPayload;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::storage::batch::Batch;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/batch").service(web::resource("").route(web::post().to(handle_batch)))
+}
+
+pub async fn handle_batch(
+    payload: web::Json<BatchPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let batch = Batch::new(
+        redis.get_ref().clone(),
+        claims.username.clone(),
+        claims.device_id.clone(),
+    );
+    let report = batch.run(&payload.operations, payload.atomic).await?;
+
+    let response = match report.failed {
+        0 => Response::new(StatusCode::OK, "Batch applied successfully"),
+        _ if report.atomic => Response::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The batch was not applied, an operation failed",
+        ),
+        _ => Response::new(StatusCode::OK, "Batch applied, some operations failed"),
+    };
+
+    Ok(response.data(report).into())
+}
@@ -0,0 +1,53 @@
+use crate::api::utils::errors::ServiceError;
+use crate::collab::room::{Rooms, COLLAB_DEVICE};
+use crate::collab::session;
+use crate::constants::MAX_COLLAB_DOCUMENT_SIZE;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::scan::status;
+use crate::storage::{content_type, files, locks};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/collab").service(web::resource("/{id}").route(web::get().to(handle_collaboration)))
+}
+
+/// Opens a y-websocket connection to a text document. Browsers pass the token as the
+/// `access_token` query parameter, since they cannot set headers on the handshake.
+pub async fn handle_collaboration(
+    request: HttpRequest,
+    body: web::Payload,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    rooms: web::Data<Arc<Rooms>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    if !content_type::is_text(&file.content_type) {
+        return Err(ServiceError::UnsupportedMediaType(
+            "Only text documents can be edited together".to_string(),
+        ));
+    }
+    if file.size > MAX_COLLAB_DOCUMENT_SIZE {
+        return Err(ServiceError::BadRequest(
+            "The document is too large to be edited together".to_string(),
+        ));
+    }
+    status::check_download(&redis, &file.hash).await?;
+    locks::check_writable(&redis, &file.id, &claims.username, COLLAB_DEVICE).await?;
+
+    let (response, socket, stream) = actix_ws::handle(&request, body)
+        .map_err(|error| ServiceError::BadRequest(error.to_string()))?;
+
+    let (room, peer) = rooms.join(&file).await?;
+    actix_web::rt::spawn(session::run(
+        rooms.get_ref().clone(),
+        room,
+        peer,
+        socket,
+        stream,
+    ));
+
+    Ok(response)
+}
@@ -1,9 +1,26 @@
-use crate::api::handler::{login, upload};
+use crate::api::handler::{
+    account, activity, admin, audit, batch, collab, file, folder, job, keys, login, metadata,
+    recent, search, sync, webhook,
+};
 use actix_web::Scope;
 
 pub fn register_endpoints() -> Scope {
     Scope::new("/api")
         .service(Scope::new("/v1"))
         .service(login::register_endpoints())
-        .service(upload::register_endpoints())
+        .service(file::register_endpoints())
+        .service(folder::register_endpoints())
+        .service(job::register_endpoints())
+        .service(metadata::register_endpoints())
+        .service(batch::register_endpoints())
+        .service(search::register_endpoints())
+        .service(sync::register_endpoints())
+        .service(activity::register_endpoints())
+        .service(recent::register_endpoints())
+        .service(keys::register_endpoints())
+        .service(account::register_endpoints())
+        .service(audit::register_endpoints())
+        .service(collab::register_endpoints())
+        .service(webhook::register_endpoints())
+        .service(admin::register_endpoints())
 }
@@ -0,0 +1,698 @@
+use crate::api::handler::upload;
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::headers;
+use crate::api::utils::payloads::{
+    CopyPayload, DiffQuery, LockQuery, MetadataPayload, MovePayload, PresignPayload, PreviewQuery,
+    UpdateQuery, VersionQuery,
+};
+use crate::api::utils::responses::{FileListing, PresignedUrl};
+use crate::api::utils::types::Response;
+use crate::archive::listing;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::constants::MAX_DIFF_SIZE;
+use crate::jwt::models::Claims;
+use crate::jwt::presigned::Grant;
+use crate::media::strip;
+use crate::redis::client::RedisClient;
+use crate::scan::status;
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
+use crate::storage::copy::Copier;
+use crate::storage::diff::{self, DiffFormat, Versions};
+use crate::storage::models::{File, FileMetadata};
+use crate::storage::{content, content_type, files, folders, locks, metadata, previews};
+use crate::sync::conflict;
+use crate::sync::journal::{self, Change, ChangeKind};
+use crate::sync::recent;
+use crate::user::privacy;
+use actix_multipart::Multipart;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/file")
+        .service(web::resource("/upload").route(web::post().to(upload::handle_file_upload)))
+        .service(web::resource("/list").route(web::get().to(handle_file_list)))
+        .service(
+            web::resource("/{id}")
+                .route(web::get().to(handle_file_download))
+                .route(web::put().to(handle_file_update))
+                .route(web::delete().to(handle_file_delete)),
+        )
+        .service(web::resource("/{id}/move").route(web::post().to(handle_file_move)))
+        .service(web::resource("/{id}/copy").route(web::post().to(handle_file_copy)))
+        .service(web::resource("/{id}/metadata").route(web::patch().to(handle_file_metadata)))
+        .service(web::resource("/{id}/preview").route(web::get().to(handle_file_preview)))
+        .service(web::resource("/{id}/entries").route(web::get().to(handle_file_entries)))
+        .service(web::resource("/{id}/diff").route(web::get().to(handle_file_diff)))
+        .service(web::resource("/{id}/scan").route(web::get().to(handle_scan_status)))
+        .service(web::resource("/{id}/presign").route(web::post().to(handle_presign)))
+        .service(
+            web::resource("/{id}/lock")
+                .route(web::get().to(handle_lock_status))
+                .route(web::post().to(handle_lock))
+                .route(web::put().to(handle_lock_renewal))
+                .route(web::delete().to(handle_unlock)),
+        )
+}
+
+pub async fn handle_file_list(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let files = files::list_files(&redis, &claims.username).await?;
+
+    let ids = files.iter().map(|file| file.id.clone()).collect::<Vec<_>>();
+    let mut locks = locks::get_locks(&redis, &ids).await?;
+    let listing = files
+        .into_iter()
+        .map(|file| FileListing {
+            lock: locks.remove(&file.id),
+            file,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Response::new(StatusCode::OK, "Files listed successfully")
+        .data(listing)
+        .into())
+}
+
+/// Images downloaded through a pre-signed URL lose their location and identifying metadata
+/// when the URL or the owner's privacy settings ask for it.
+pub async fn handle_file_download(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<VersionQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let version = file.version(query.version).ok_or_else(|| {
+        ServiceError::NotFound("The requested version does not exist".to_string())
+    })?;
+    status::check_download(&redis, &version.hash).await?;
+    let content_type = file.content_type_of(version);
+
+    let shared = request
+        .extensions()
+        .get::<Grant>()
+        .map(|grant| grant.strip_metadata);
+    let strip = match shared {
+        _ if !content_type::essence(content_type).starts_with("image/") => false,
+        Some(requested) => {
+            requested
+                || privacy::get_settings(&redis, &file.owner)
+                    .await?
+                    .strip_shared_metadata
+        }
+        None => false,
+    };
+
+    let (data, etag) = match strip {
+        true => (
+            strip::stripped_copy(blobs.get_ref().clone(), &version.hash, content_type).await?,
+            strip::stripped_key(&version.hash),
+        ),
+        false => (
+            content::read_content(blobs.as_ref().as_ref(), &version.hash)
+                .await
+                .map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to read the file".to_string(),
+                        Some(error),
+                    )
+                })?,
+            version.hash.clone(),
+        ),
+    };
+
+    let actor = Actor::from_claims(&claims, &request);
+    let detail = format!("Version {}", version.version);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::Download,
+        Some(format!("file:{}", file.id)),
+        Some(detail),
+    )
+    .await;
+    recent::touch(&redis, &file.owner, &claims.device_id, &file.id).await;
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("ETag", headers::etag(&etag)))
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file.name.replace('"', "")),
+        ))
+        .body(data))
+}
+
+/// Uploads a new version. Clients pass the ETag of the version their change is based on in
+/// `If-Match`; stale changes are refused with 412, or kept as a conflicted copy in sync mode.
+pub async fn handle_file_update(
+    request: HttpRequest,
+    path: web::Path<String>,
+    mut payload: Multipart,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let mut file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    locks::check_writable(&redis, &file.id, &claims.username, &claims.device_id).await?;
+
+    let grant = request.extensions().get::<Grant>().cloned();
+    if let Some(grant) = &grant {
+        grant.check_content_length(&request)?;
+    }
+    let max_size = grant.and_then(|grant| grant.max_size);
+    let uploaded = upload::extract_files(&mut payload, max_size)
+        .await?
+        .pop()
+        .ok_or_else(|| ServiceError::BadRequest("No file was uploaded".to_string()))?;
+
+    let query = web::Query::<UpdateQuery>::from_query(request.query_string())
+        .map_err(|error| ServiceError::BadRequest(error.to_string()))?;
+    let expected = headers::if_match(&request);
+    let base = match &expected {
+        Some(tags) if tags.contains(&file.hash) => Some(file.hash.clone()),
+        Some(_) if query.sync => {
+            return keep_conflicted_copy(&request, &claims, &redis, &blobs, &file, uploaded).await
+        }
+        Some(tags) => {
+            return Err(ServiceError::PreconditionFailed(format!(
+                "The file has changed since version {}",
+                tags.join(", ")
+            )))
+        }
+        None => None,
+    };
+
+    // Another device can still commit between the check above and the write, in which case
+    // the write is refused as stale and the change is kept as a conflicted copy instead.
+    let retained = match query.sync && base.is_some() {
+        true => Some(uploaded.clone()),
+        false => None,
+    };
+    let result = files::add_file_version(
+        &redis,
+        blobs.as_ref().as_ref(),
+        &mut file,
+        &claims.device_id,
+        uploaded,
+        base.as_deref(),
+    )
+    .await;
+    match (result, retained) {
+        (Err(ServiceError::PreconditionFailed(_)), Some(uploaded)) => {
+            let current = files::get_owned_file(&redis, &path, &claims.username).await?;
+            return keep_conflicted_copy(&request, &claims, &redis, &blobs, &current, uploaded)
+                .await;
+        }
+        (result, _) => result?,
+    }
+    previews::enqueue_generation(&redis, &file.owner, &file.hash, &file.content_type).await?;
+    indexer::enqueue_indexing(&redis, &file).await?;
+
+    let actor = Actor::from_claims(&claims, &request);
+    let version = file.versions.last().map_or(1, |version| version.version);
+    let detail = format!("Version {}", version);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::Upload,
+        Some(format!("file:{}", file.id)),
+        Some(detail),
+    )
+    .await;
+
+    Ok(Response::new(StatusCode::OK, "File updated successfully")
+        .data(file)
+        .into())
+}
+
+async fn keep_conflicted_copy(
+    request: &HttpRequest,
+    claims: &Claims,
+    redis: &RedisClient,
+    blobs: &Arc<dyn BlobStore>,
+    file: &FileMetadata,
+    uploaded: File,
+) -> Result<HttpResponse, ServiceError> {
+    let copy =
+        conflict::create_conflicted_copy(redis, blobs.as_ref(), file, &claims.device_id, uploaded)
+            .await?;
+    previews::enqueue_generation(redis, &copy.owner, &copy.hash, &copy.content_type).await?;
+    indexer::enqueue_indexing(redis, &copy).await?;
+    let actor = Actor::from_claims(claims, request);
+    let detail = format!("Conflicted copy of {}", file.id);
+    store::record(
+        redis,
+        &actor,
+        AuditAction::Upload,
+        Some(format!("file:{}", copy.id)),
+        Some(detail),
+    )
+    .await;
+    log::info!("Kept a conflicting change to {} as {}", file.id, copy.id);
+
+    Ok(Response::new(
+        StatusCode::CREATED,
+        "The file has changed meanwhile, a conflicted copy was created",
+    )
+    .data(copy)
+    .into())
+}
+
+pub async fn handle_file_move(
+    path: web::Path<String>,
+    payload: web::Json<MovePayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    locks::check_writable(&redis, &file.id, &claims.username, &claims.device_id).await?;
+
+    if let Some(name) = &payload.name {
+        files::validate_name(name)?;
+    }
+    let folder = payload
+        .folder
+        .as_deref()
+        .map(files::normalize_folder)
+        .transpose()?;
+    if let Some(folder) = &folder {
+        folders::ensure_folder(&redis, &file.owner, folder).await?;
+    }
+
+    let file = files::update_file(&redis, &file.id, &claims.username, |file| {
+        if let Some(name) = &payload.name {
+            file.name = name.clone();
+        }
+        if let Some(folder) = &folder {
+            file.folder = folder.clone();
+        }
+        file.updated_at = chrono::Utc::now().timestamp();
+
+        Ok(())
+    })
+    .await?;
+    indexer::enqueue_move(&redis, &file).await?;
+
+    let change = Change::new(ChangeKind::Moved, &file, &claims.device_id);
+    journal::record(&redis, &file.owner, change).await?;
+
+    Ok(Response::new(StatusCode::OK, "File moved successfully")
+        .data(file)
+        .into())
+}
+
+pub async fn handle_file_copy(
+    path: web::Path<String>,
+    payload: web::Json<CopyPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+
+    let copier = Copier::new(
+        redis.get_ref().clone(),
+        claims.username.clone(),
+        claims.device_id.clone(),
+    );
+    let folder = payload.folder.as_deref().unwrap_or(&file.folder);
+    let name = payload.name.as_deref().unwrap_or(&file.name);
+    let copy = copier.copy_file(&file, folder, name).await?;
+
+    Ok(Response::new(StatusCode::OK, "File copied successfully")
+        .data(copy)
+        .into())
+}
+
+pub async fn handle_file_metadata(
+    path: web::Path<String>,
+    payload: web::Json<MetadataPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    locks::check_writable(&redis, &file.id, &claims.username, &claims.device_id).await?;
+
+    metadata::validate(&payload)?;
+    let file = files::update_file(&redis, &file.id, &claims.username, |file| {
+        metadata::edit(&mut file.tags, &mut file.attributes, &payload);
+        Ok(())
+    })
+    .await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "File metadata updated successfully")
+            .data(file)
+            .into(),
+    )
+}
+
+pub async fn handle_file_delete(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    locks::check_writable(&redis, &file.id, &claims.username, &claims.device_id).await?;
+
+    // The content stays in the blob store until the garbage collector finds it unreferenced.
+    files::delete_file(&redis, &file, &claims.device_id).await?;
+    indexer::enqueue_removal(&redis, &file).await?;
+
+    let actor = Actor::from_claims(&claims, &request);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::Delete,
+        Some(format!("file:{}", file.id)),
+        Some(format!(
+            "{}/{}",
+            file.folder.trim_end_matches('/'),
+            file.name
+        )),
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::OK, "File deleted successfully").into())
+}
+
+pub async fn handle_file_preview(
+    path: web::Path<String>,
+    query: web::Query<PreviewQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+
+    let candidates = [
+        (
+            previews::snippet_key(&file.hash),
+            "text/plain; charset=utf-8",
+        ),
+        (query.size.key(&file.hash), "image/jpeg"),
+    ];
+
+    for (key, content_type) in candidates {
+        if let Ok(preview) = blobs.get(&key).await {
+            return Ok(HttpResponse::Ok().content_type(content_type).body(preview));
+        }
+    }
+
+    // Previews are generated after the upload returns, or may predate this file entirely.
+    previews::enqueue_generation(&redis, &file.owner, &file.hash, &file.content_type).await?;
+
+    Err(ServiceError::NotFound(
+        "The preview of this file is not available yet".to_string(),
+    ))
+}
+
+pub async fn handle_file_entries(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+
+    if content_type::essence(&file.content_type) != "application/zip" {
+        return Err(ServiceError::BadRequest(
+            "Only ZIP archives can be listed".to_string(),
+        ));
+    }
+
+    let data = content::read_content(blobs.as_ref().as_ref(), &file.hash)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to read the file".to_string(), Some(error))
+        })?;
+
+    let entries = web::block(move || listing::list_zip(&data))
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to list the archive".to_string(),
+                Some(error.into()),
+            )
+        })?
+        .map_err(|error| ServiceError::BadRequest(format!("The archive is invalid: {}", error)))?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Archive entries listed successfully")
+            .data(entries)
+            .into(),
+    )
+}
+
+/// Compares two versions of a text document, line by line or word by word.
+pub async fn handle_file_diff(
+    path: web::Path<String>,
+    query: web::Query<DiffQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let missing = || ServiceError::NotFound("The requested version does not exist".to_string());
+
+    let to = file.version(query.to).ok_or_else(missing)?;
+    let from = match query.from {
+        Some(from) => file.version(Some(from)),
+        None => file
+            .versions
+            .iter()
+            .rev()
+            .find(|version| version.version < to.version),
+    }
+    .ok_or_else(missing)?;
+
+    let text = content_type::is_text(file.content_type_of(from))
+        && content_type::is_text(file.content_type_of(to));
+    if text && (from.size > MAX_DIFF_SIZE || to.size > MAX_DIFF_SIZE) {
+        return Err(ServiceError::BadRequest(format!(
+            "Only versions up to {} bytes can be compared",
+            MAX_DIFF_SIZE
+        )));
+    }
+
+    let read = |hash: String| {
+        let blobs = blobs.get_ref().clone();
+        async move {
+            content::read_content(blobs.as_ref(), &hash)
+                .await
+                .map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to read the file".to_string(),
+                        Some(error),
+                    )
+                })
+        }
+    };
+    // Binary versions are compared by hash, their content is not needed.
+    let (old, new) = match text {
+        true => {
+            status::check_download(&redis, &from.hash).await?;
+            status::check_download(&redis, &to.hash).await?;
+            (read(from.hash.clone()).await?, read(to.hash.clone()).await?)
+        }
+        false => (from.hash.clone().into_bytes(), to.hash.clone().into_bytes()),
+    };
+
+    let versions = Versions {
+        name: file.name.clone(),
+        from: from.version,
+        to: to.version,
+        old,
+        new,
+        text,
+    };
+    let granularity = query.granularity;
+    let context = query.context.unwrap_or(diff::DEFAULT_CONTEXT);
+
+    match query.format {
+        DiffFormat::Json => {
+            let result = web::block(move || versions.diff(granularity, context))
+                .await
+                .map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to compare the versions".to_string(),
+                        Some(error.into()),
+                    )
+                })?;
+
+            Ok(
+                Response::new(StatusCode::OK, "Versions compared successfully")
+                    .data(result)
+                    .into(),
+            )
+        }
+        DiffFormat::Patch => {
+            let patch = web::block(move || versions.patch(granularity, context))
+                .await
+                .map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to compare the versions".to_string(),
+                        Some(error.into()),
+                    )
+                })?;
+
+            Ok(HttpResponse::Ok()
+                .content_type("text/x-diff; charset=utf-8")
+                .body(patch))
+        }
+    }
+}
+
+/// The malware scan result of a version. Content that was never scanned has no result.
+pub async fn handle_scan_status(
+    path: web::Path<String>,
+    query: web::Query<VersionQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let version = file.version(query.version).ok_or_else(|| {
+        ServiceError::NotFound("The requested version does not exist".to_string())
+    })?;
+
+    let result = status::get_result(&redis, &version.hash)
+        .await?
+        .ok_or_else(|| ServiceError::NotFound("The file has not been scanned".to_string()))?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Scan status retrieved successfully")
+            .data(result)
+            .into(),
+    )
+}
+
+/// Issues a URL that downloads or updates the file without a token, for embeds and scripts.
+pub async fn handle_presign(
+    request: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Json<PresignPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let method = Method::from_bytes(payload.method.to_uppercase().as_bytes())
+        .map_err(|_| ServiceError::BadRequest("Invalid method".to_string()))?;
+
+    let payload = payload.into_inner();
+    let grant = Grant::new(
+        &claims.username,
+        method,
+        signed_path(request.path())?,
+        payload.expires_in,
+        payload.ip,
+        payload.max_size,
+        payload.strip_metadata,
+    )?;
+
+    let actor = Actor::from_claims(&claims, &request);
+    let detail = format!("{} until {}", grant.method, grant.expires_at);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::Share,
+        Some(format!("file:{}", file.id)),
+        Some(detail),
+    )
+    .await;
+
+    Ok(
+        Response::new(StatusCode::CREATED, "Pre-signed URL created successfully")
+            .data(PresignedUrl {
+                url: grant.url()?,
+                grant,
+            })
+            .into(),
+    )
+}
+
+/// The path a pre-signed URL grants: the file's own, which the presign endpoint's path extends
+/// with `/presign`.
+fn signed_path(path: &str) -> Result<String, ServiceError> {
+    path.strip_suffix("/presign")
+        .map(str::to_string)
+        .ok_or_else(|| ServiceError::BadRequest(format!("{} cannot be pre-signed", path)))
+}
+
+pub async fn handle_lock_status(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let lock = locks::get_lock(&redis, &file.id).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Lock state retrieved successfully")
+            .data(lock)
+            .into(),
+    )
+}
+
+/// Checks the file out for exclusive editing. Until the lease expires or is released, only this
+/// device can change the file.
+pub async fn handle_lock(
+    path: web::Path<String>,
+    query: web::Query<LockQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let lock = locks::acquire(
+        &redis,
+        &file.id,
+        &claims.username,
+        &claims.device_id,
+        query.duration,
+    )
+    .await?;
+
+    Ok(Response::new(StatusCode::OK, "File locked successfully")
+        .data(lock)
+        .into())
+}
+
+pub async fn handle_lock_renewal(
+    path: web::Path<String>,
+    query: web::Query<LockQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    let lock = locks::renew(
+        &redis,
+        &file.id,
+        &claims.username,
+        &claims.device_id,
+        query.duration,
+    )
+    .await?;
+
+    Ok(Response::new(StatusCode::OK, "Lock renewed successfully")
+        .data(lock)
+        .into())
+}
+
+pub async fn handle_unlock(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let file = files::get_owned_file(&redis, &path, &claims.username).await?;
+    locks::release(&redis, &file.id, &claims.username, &claims.device_id).await?;
+
+    Ok(Response::<()>::new(StatusCode::OK, "File unlocked successfully").into())
+}
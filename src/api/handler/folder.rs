@@ -0,0 +1,203 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::{
+    ArchiveQuery, FolderCopyPayload, FolderPayload, MetadataPayload,
+};
+use crate::api::utils::responses::FolderCopyResponse;
+use crate::api::utils::types::Response;
+use crate::archive::stream::{self, ArchiveEntry};
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::scan::status;
+use crate::storage::blob::BlobStore;
+use crate::storage::copy::{Copier, FolderCopy};
+use crate::storage::models::FileMetadata;
+use crate::storage::{files, folders, metadata};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/folder")
+        .service(web::resource("").route(web::post().to(handle_folder_create)))
+        .service(web::resource("/list").route(web::get().to(handle_folder_list)))
+        .service(web::resource("/{id}/metadata").route(web::patch().to(handle_folder_metadata)))
+        .service(web::resource("/{id}/archive").route(web::get().to(handle_folder_archive)))
+        .service(web::resource("/{id}/copy").route(web::post().to(handle_folder_copy)))
+}
+
+pub async fn handle_folder_create(
+    payload: web::Json<FolderPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let path = files::normalize_folder(&payload.path)?;
+    let folder = folders::ensure_folder(&redis, &claims.username, &path).await?;
+
+    Ok(Response::new(StatusCode::OK, "Folder created successfully")
+        .data(folder)
+        .into())
+}
+
+pub async fn handle_folder_list(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let folders = folders::list_folders(&redis, &claims.username).await?;
+
+    Ok(Response::new(StatusCode::OK, "Folders listed successfully")
+        .data(folders)
+        .into())
+}
+
+pub async fn handle_folder_metadata(
+    path: web::Path<String>,
+    payload: web::Json<MetadataPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let mut folder = folders::get_owned_folder(&redis, &path, &claims.username).await?;
+
+    metadata::apply_changes(
+        &redis,
+        &folder.owner,
+        &metadata::folder_member(&folder.id),
+        &mut folder.tags,
+        &mut folder.attributes,
+        &payload,
+    )
+    .await?;
+    folders::save_folder(&redis, &folder).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Folder metadata updated successfully")
+            .data(folder)
+            .into(),
+    )
+}
+
+pub async fn handle_folder_copy(
+    path: web::Path<String>,
+    payload: web::Json<FolderCopyPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let folder = folders::get_owned_folder(&redis, &path, &claims.username).await?;
+
+    let copier = Copier::new(
+        redis.get_ref().clone(),
+        claims.username.clone(),
+        claims.device_id.clone(),
+    );
+
+    let response = match copier.copy_folder(folder, &payload.destination).await? {
+        FolderCopy::Done(report) => Response::new(StatusCode::OK, "Folder copied successfully")
+            .data(FolderCopyResponse {
+                report: Some(report),
+                job: None,
+            }),
+        FolderCopy::Started(job) => Response::new(StatusCode::ACCEPTED, "Folder copy started")
+            .data(FolderCopyResponse {
+                report: None,
+                job: Some(*job),
+            }),
+    };
+
+    Ok(response.into())
+}
+
+pub async fn handle_folder_archive(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ArchiveQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let folder = folders::get_owned_folder(&redis, &path, &claims.username).await?;
+    let files = files::list_files(&redis, &claims.username).await?;
+
+    let entries = archive_entries(&folder.path, files);
+    for entry in &entries {
+        status::check_download(&redis, &entry.hash).await?;
+    }
+    let name = match folder.path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.replace('"', ""),
+        _ => "files".to_string(),
+    };
+
+    let actor = Actor::from_claims(&claims, &request);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::Download,
+        Some(format!("folder:{}", folder.id)),
+        Some(format!(
+            "{} files as {}",
+            entries.len(),
+            query.format.extension()
+        )),
+    )
+    .await;
+
+    let receiver = stream::spawn_archive(blobs.get_ref().clone(), query.format, entries);
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                name,
+                query.format.extension()
+            ),
+        ))
+        .streaming(receiver))
+}
+
+/// The files under `root`, with paths relative to it. Corrupted files are left out rather than
+/// failing the archive halfway through, and clashing names get a numbered suffix.
+fn archive_entries(root: &str, mut files: Vec<FileMetadata>) -> Vec<ArchiveEntry> {
+    let prefix = match root {
+        "/" => "/".to_string(),
+        _ => format!("{}/", root),
+    };
+
+    files.sort_by(|a, b| (&a.folder, &a.name).cmp(&(&b.folder, &b.name)));
+
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for file in files {
+        if file.corrupted || (file.folder != root && !file.folder.starts_with(&prefix)) {
+            continue;
+        }
+
+        let relative = file.folder[root.len()..].trim_start_matches('/');
+        let path = match relative {
+            "" => file.name.clone(),
+            relative => format!("{}/{}", relative, file.name),
+        };
+
+        let mut unique = path.clone();
+        let mut counter = 1;
+        while !seen.insert(unique.clone()) {
+            unique = match path.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() && !stem.ends_with('/') => {
+                    format!("{} ({}).{}", stem, counter, extension)
+                }
+                _ => format!("{} ({})", path, counter),
+            };
+            counter += 1;
+        }
+
+        entries.push(ArchiveEntry {
+            path: unique,
+            hash: file.hash,
+            modified: file.updated_at,
+        });
+    }
+
+    entries
+}
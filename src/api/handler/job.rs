@@ -0,0 +1,24 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::types::Response;
+use crate::jobs::store;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/job").service(web::resource("/{id}").route(web::get().to(handle_job_status)))
+}
+
+pub async fn handle_job_status(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let job = store::get_owned_job(&redis, &path, &claims.username).await?;
+
+    Ok(Response::new(StatusCode::OK, "Job retrieved successfully")
+        .data(job)
+        .into())
+}
@@ -0,0 +1,81 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::types::Response;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::user::access_keys::{self, AccessKeyInfo};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/keys")
+        .service(
+            web::resource("")
+                .route(web::get().to(handle_key_list))
+                .route(web::post().to(handle_key_creation)),
+        )
+        .service(web::resource("/{id}").route(web::delete().to(handle_key_deletion)))
+}
+
+/// Creates an S3 access key. The secret is only ever returned here.
+pub async fn handle_key_creation(
+    request: HttpRequest,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let key = access_keys::create_key(&redis, &claims.username).await?;
+    let actor = Actor::from_claims(&claims, &request);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::AccessKeyCreated,
+        Some(format!("access_key:{}", key.id)),
+        None,
+    )
+    .await;
+
+    Ok(
+        Response::new(StatusCode::CREATED, "Access key created successfully")
+            .data(key)
+            .into(),
+    )
+}
+
+pub async fn handle_key_list(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let keys = access_keys::list_keys(&redis, &claims.username)
+        .await?
+        .iter()
+        .map(|key| key.info())
+        .collect::<Vec<AccessKeyInfo>>();
+
+    Ok(
+        Response::new(StatusCode::OK, "Access keys listed successfully")
+            .data(keys)
+            .into(),
+    )
+}
+
+pub async fn handle_key_deletion(
+    request: HttpRequest,
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    access_keys::delete_key(&redis, &claims.username, &path).await?;
+    let actor = Actor::from_claims(&claims, &request);
+    store::record(
+        &redis,
+        &actor,
+        AuditAction::AccessKeyDeleted,
+        Some(format!("access_key:{}", path)),
+        None,
+    )
+    .await;
+
+    Ok(Response::<()>::new(StatusCode::OK, "Access key deleted successfully").into())
+}
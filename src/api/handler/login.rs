@@ -2,13 +2,17 @@ use crate::api::utils::errors::ServiceError;
 use crate::api::utils::payloads::{LoginPayload, RegistrationPayload};
 use crate::api::utils::responses::{LoginResponse, RegistrationResponse};
 use crate::api::utils::types::Response;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
 use crate::conditional;
 use crate::jwt::models::Claims;
 use crate::jwt::token;
 use crate::redis::client::{RedisClient, RedisKey};
 use crate::user::models::User;
+use crate::webhooks::delivery;
+use crate::webhooks::models::Event;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, Scope};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
 use std::sync::Arc;
 
 pub fn register_endpoints() -> Scope {
@@ -16,12 +20,23 @@ pub fn register_endpoints() -> Scope {
         .service(web::resource("/register").route(web::post().to(handle_registration)))
         .service(web::resource("/login").route(web::post().to(handle_login)))
         .service(web::resource("/logout").route(web::post().to(handle_logout)))
+        .service(web::resource("/refresh").route(web::post().to(handle_refresh)))
 }
 
 pub async fn handle_registration(
     payload: web::Json<RegistrationPayload>,
     redis: web::Data<Arc<RedisClient>>,
 ) -> Result<HttpResponse, ServiceError> {
+    // Redis keys put the username before other parts separated by ':'.
+    conditional!(
+        payload.username.is_empty() || payload.username.contains(':'),
+        {
+            return Err(ServiceError::BadRequest(
+                "Usernames cannot be empty or contain ':'.".to_string(),
+            ));
+        }
+    );
+
     let exists = redis
         .async_exists(RedisKey::Account(payload.username.clone()))
         .await?;
@@ -54,6 +69,7 @@ pub async fn handle_registration(
 }
 
 pub async fn handle_login(
+    request: HttpRequest,
     payload: web::Json<LoginPayload>,
     redis: web::Data<Arc<RedisClient>>,
 ) -> Result<HttpResponse, ServiceError> {
@@ -62,6 +78,10 @@ pub async fn handle_login(
         .await?;
 
     conditional!(!exists, {
+        // Kept out of any user's log, the account does not exist.
+        let actor = Actor::new("", &payload.device_id, &request);
+        store::record_unknown_login(&redis, &actor, &payload.username).await;
+
         return Err(ServiceError::BadRequest(
             "An account with that username does not exist.".to_string(),
         ));
@@ -79,7 +99,18 @@ pub async fn handle_login(
             )
         })?;
 
+    let actor = Actor::new(&payload.username, &payload.device_id, &request);
+    let target = Some(format!("user:{}", payload.username));
     conditional!(!valid, {
+        store::record(
+            &redis,
+            &actor,
+            AuditAction::LoginFailed,
+            target,
+            Some("Invalid password".to_string()),
+        )
+        .await;
+
         return Err(ServiceError::BadRequest("Invalid password.".to_string()));
     });
 
@@ -90,6 +121,10 @@ pub async fn handle_login(
             Some(error.into()),
         )
     })?;
+    store::record(&redis, &actor, AuditAction::Login, target, None).await;
+    let login = serde_json::json!({ "device_id": payload.device_id });
+    delivery::notify(&redis, &payload.username, Event::Login, None, &login).await;
+
     let response = LoginResponse { token };
 
     Ok(
@@ -99,6 +134,37 @@ pub async fn handle_login(
     )
 }
 
-pub async fn handle_logout(_claims: web::ReqData<Claims>) -> Result<HttpResponse, ServiceError> {
+pub async fn handle_logout(
+    request: HttpRequest,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let actor = Actor::from_claims(&claims, &request);
+    let target = Some(format!("user:{}", claims.username));
+    store::record(&redis, &actor, AuditAction::Logout, target, None).await;
+
     Ok(Response::<()>::new(StatusCode::OK, "Logged out successfully").into())
 }
+
+/// Trades a valid token for a fresh one, for the same user and device.
+pub async fn handle_refresh(
+    request: HttpRequest,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let token = token::create_token(claims.username.clone(), claims.device_id.clone()).map_err(
+        |error| {
+            ServiceError::InternalServerError("Failed to generate a token".to_string(), Some(error))
+        },
+    )?;
+
+    let actor = Actor::from_claims(&claims, &request);
+    let target = Some(format!("user:{}", claims.username));
+    store::record(&redis, &actor, AuditAction::TokenRefreshed, target, None).await;
+
+    Ok(
+        Response::<LoginResponse>::new(StatusCode::OK, "Token refreshed successfully")
+            .data(LoginResponse { token })
+            .into(),
+    )
+}
@@ -0,0 +1,73 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::MetadataQuery;
+use crate::api::utils::responses::MetadataQueryResponse;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::storage::{files, folders, metadata};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/metadata").service(web::resource("/query").route(web::get().to(handle_query)))
+}
+
+pub async fn handle_query(
+    query: web::Query<MetadataQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let tags = query
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let mut attributes = Vec::new();
+    for pair in query.attributes.as_deref().unwrap_or_default().split(',') {
+        if pair.trim().is_empty() {
+            continue;
+        }
+
+        let (key, value) = pair.split_once('=').ok_or_else(|| {
+            ServiceError::BadRequest(format!("'{}' is not a key=value pair", pair))
+        })?;
+        attributes.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    let members = metadata::query(&redis, &claims.username, &tags, &attributes).await?;
+
+    let mut response = MetadataQueryResponse {
+        files: Vec::new(),
+        folders: Vec::new(),
+    };
+    // Members deleted since the index was read are skipped.
+    for member in members {
+        match member.split_once(':') {
+            Some(("file", id)) => match files::get_owned_file(&redis, id, &claims.username).await {
+                Ok(file) => response.files.push(file),
+                Err(ServiceError::NotFound(_)) => continue,
+                Err(error) => return Err(error),
+            },
+            Some(("folder", id)) => {
+                match folders::get_owned_folder(&redis, id, &claims.username).await {
+                    Ok(folder) => response.folders.push(folder),
+                    Err(ServiceError::NotFound(_)) => continue,
+                    Err(error) => return Err(error),
+                }
+            }
+            _ => log::warn!("Unknown metadata index member: {}", member),
+        }
+    }
+
+    Ok(
+        Response::new(StatusCode::OK, "Metadata query completed successfully")
+            .data(response)
+            .into(),
+    )
+}
@@ -1,3 +1,18 @@
+pub mod account;
+pub mod activity;
+pub mod admin;
+pub mod audit;
+pub mod batch;
+pub mod collab;
 pub mod endpoints;
+pub mod file;
+pub mod folder;
+pub mod job;
+pub mod keys;
 pub mod login;
+pub mod metadata;
+pub mod recent;
+pub mod search;
+pub mod sync;
 pub mod upload;
+pub mod webhook;
@@ -0,0 +1,35 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::RecentQuery;
+use crate::api::utils::types::Response;
+use crate::constants::RECENT_FILES_LENGTH;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::sync::recent;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/recent").service(web::resource("").route(web::get().to(handle_recent_files)))
+}
+
+/// Lists the files the user opened or modified most recently, on every device or on one.
+pub async fn handle_recent_files(
+    query: web::Query<RecentQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let limit = query.limit.unwrap_or(20).clamp(1, RECENT_FILES_LENGTH);
+    let device = match query.device.as_deref() {
+        Some("current") => Some(claims.device_id.as_str()),
+        device => device,
+    };
+
+    let files = recent::list(&redis, &claims.username, device, limit).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Recent files listed successfully")
+            .data(files)
+            .into(),
+    )
+}
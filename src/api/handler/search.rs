@@ -0,0 +1,62 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::SearchQuery;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::search::index::{SearchFilter, SearchIndex};
+use crate::storage::files;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/search").service(web::resource("").route(web::get().to(handle_search)))
+}
+
+pub async fn handle_search(
+    query: web::Query<SearchQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    search: web::Data<Arc<SearchIndex>>,
+) -> Result<HttpResponse, ServiceError> {
+    let query = query.into_inner();
+    let filter = SearchFilter {
+        folder: query
+            .folder
+            .as_deref()
+            .map(files::normalize_folder)
+            .transpose()?,
+        kind: query.kind.map(|kind| kind.to_lowercase()),
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+
+    let username = claims.username.clone();
+    let search = search.get_ref().clone();
+    let hits = web::block(move || search.search(&username, &query.q, &filter))
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to search the index".to_string(),
+                Some(error.into()),
+            )
+        })??;
+
+    // The index may lag behind deletions, so only report files the user can still read.
+    let mut results = Vec::with_capacity(hits.len());
+    for hit in hits {
+        match files::get_owned_file(&redis, &hit.id, &claims.username).await {
+            Ok(_) => results.push(hit),
+            Err(ServiceError::NotFound(_)) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(
+        Response::new(StatusCode::OK, "Search completed successfully")
+            .data(results)
+            .into(),
+    )
+}
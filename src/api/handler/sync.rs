@@ -0,0 +1,34 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::ChangesQuery;
+use crate::api::utils::responses::ChangesResponse;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::sync::journal;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/sync").service(web::resource("/changes").route(web::get().to(handle_changes)))
+}
+
+/// Returns the changes recorded after the `since` cursor. Clients store the returned cursor
+/// and pass it back to only receive newer changes.
+pub async fn handle_changes(
+    query: web::Query<ChangesQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let changes = journal::changes(&redis, &claims.username, query.since.as_deref(), limit).await?;
+
+    let cursor = changes
+        .last()
+        .map(|change| change.id.clone())
+        .or_else(|| query.since.clone());
+
+    Ok(Response::new(StatusCode::OK, "Changes listed successfully")
+        .data(ChangesResponse { changes, cursor })
+        .into())
+}
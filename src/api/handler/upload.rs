@@ -1,50 +1,179 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::UploadQuery;
+use crate::api::utils::responses::ExtractionResponse;
 use crate::api::utils::types::Response;
+use crate::archive::extract::{ArchiveKind, Extractor};
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::constants::BACKGROUND_EXTRACTION_SIZE;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
 use crate::storage::models::File;
+use crate::storage::{content_type, files, previews};
 use actix_multipart::{Multipart, MultipartError};
-use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, Scope};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
 use futures::stream::TryStreamExt;
+use std::sync::Arc;
 
-pub fn register_endpoints() -> Scope {
-    Scope::new("/file").service(web::resource("/upload").route(web::post().to(handle_file_upload)))
-}
+pub async fn handle_file_upload(
+    request: HttpRequest,
+    mut payload: Multipart,
+    query: web::Query<UploadQuery>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let uploaded = extract_files(&mut payload, None).await?;
+    let folder = query.folder.clone().unwrap_or_else(|| "/".to_string());
+
+    let actor = Actor::from_claims(&claims, &request);
+    if query.extract {
+        return handle_archive_extraction(uploaded, folder, actor, claims, redis, blobs).await;
+    }
+
+    let mut files = Vec::with_capacity(uploaded.len());
+    for file in uploaded {
+        let metadata = files::create_file(
+            &redis,
+            blobs.as_ref().as_ref(),
+            &claims.username,
+            &claims.device_id,
+            &folder,
+            file,
+        )
+        .await?;
 
-pub async fn handle_file_upload(mut payload: Multipart) -> HttpResponse {
-    let files = extract_files(&mut payload)
-        .await
-        .expect("Failed to extract files");
+        previews::enqueue_generation(
+            &redis,
+            &metadata.owner,
+            &metadata.hash,
+            &metadata.content_type,
+        )
+        .await?;
+        indexer::enqueue_indexing(&redis, &metadata).await?;
+        store::record(
+            &redis,
+            &actor,
+            AuditAction::Upload,
+            Some(format!("file:{}", metadata.id)),
+            Some(format!("{} bytes", metadata.size)),
+        )
+        .await;
+        files.push(metadata);
+    }
 
-    Response::new(StatusCode::OK, "Files uploaded successfully")
+    Ok(Response::new(StatusCode::OK, "Files uploaded successfully")
         .data(files)
-        .into()
+        .into())
+}
+
+async fn handle_archive_extraction(
+    uploaded: Vec<File>,
+    folder: String,
+    actor: Actor,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    if let Some(file) = uploaded
+        .iter()
+        .find(|file| ArchiveKind::detect(&file.data).is_none())
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "{} is not a ZIP or tar archive",
+            file.name
+        )));
+    }
+
+    let mut status = StatusCode::OK;
+    let mut extractions = Vec::with_capacity(uploaded.len());
+    for archive in uploaded {
+        let extractor = Extractor::new(
+            redis.get_ref().clone(),
+            blobs.get_ref().clone(),
+            claims.username.clone(),
+            claims.device_id.clone(),
+        );
+        let name = archive.name.clone();
+        store::record(
+            &redis,
+            &actor,
+            AuditAction::Upload,
+            Some(format!("folder:{}", folder)),
+            Some(format!("Archive {} to extract", name)),
+        )
+        .await;
+
+        if archive.size > *BACKGROUND_EXTRACTION_SIZE {
+            status = StatusCode::ACCEPTED;
+            extractions.push(ExtractionResponse {
+                archive: name,
+                report: None,
+                job: Some(extractor.enqueue(archive, folder.clone()).await?),
+            });
+        } else {
+            extractions.push(ExtractionResponse {
+                archive: name,
+                report: Some(extractor.extract(archive, &folder, None).await?),
+                job: None,
+            });
+        }
+    }
+
+    Ok(Response::new(status, "Archives extracted successfully")
+        .data(extractions)
+        .into())
 }
 
-pub async fn extract_files(payload: &mut Multipart) -> Result<Vec<File>, MultipartError> {
+/// Reads the files of a multipart upload, refusing it as soon as their total size goes past
+/// `max_size`, so the limit also bounds what is held in memory.
+pub async fn extract_files(
+    payload: &mut Multipart,
+    max_size: Option<u64>,
+) -> Result<Vec<File>, ServiceError> {
     let mut files = Vec::new();
+    let mut total = 0;
 
-    log::info!("Iterating files...");
-    while let Some(mut field) = payload.try_next().await? {
+    while let Some(mut field) = payload.try_next().await.map_err(unreadable)? {
         let mut data = Vec::new();
 
-        log::info!("Getting file...");
-        let file_name = field.name().to_string();
-        log::info!("File name: {}", file_name);
+        let file_name = field
+            .content_disposition()
+            .get_filename()
+            .map(str::to_string)
+            .unwrap_or_else(|| field.name().to_string());
+        // Parts without a Content-Type are reported as text/plain, which is not a declaration.
+        let declared_type = field
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(content_type::essence);
 
-        log::info!("Reading file...");
-        while let Some(chunk) = field.try_next().await? {
-            log::info!("Getting chunk: {}", chunk.len());
+        while let Some(chunk) = field.try_next().await.map_err(unreadable)? {
+            total += chunk.len() as u64;
+            if let Some(max_size) = max_size.filter(|max_size| total > *max_size) {
+                return Err(ServiceError::BadRequest(format!(
+                    "The upload exceeds the {} bytes allowed",
+                    max_size
+                )));
+            }
             data.extend_from_slice(&chunk);
         }
-        log::info!("File read: {}", data.len());
 
         files.push(File {
             name: file_name,
             size: data.len(),
             data,
+            declared_type,
         });
     }
 
-    log::info!("Files: {}", files.len());
-
     Ok(files)
 }
+
+fn unreadable(error: MultipartError) -> ServiceError {
+    ServiceError::BadRequest(format!("Failed to read the uploaded files: {}", error))
+}
@@ -0,0 +1,169 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::WebhookPayload;
+use crate::api::utils::types::Response;
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::storage::files;
+use crate::webhooks::models::{Event, Webhook, WebhookInfo};
+use crate::webhooks::{address, delivery, store};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Scope};
+use std::sync::Arc;
+
+pub fn register_endpoints() -> Scope {
+    Scope::new("/webhooks")
+        .service(
+            web::resource("")
+                .route(web::get().to(handle_webhook_list))
+                .route(web::post().to(handle_webhook_creation)),
+        )
+        .service(
+            web::resource("/{id}")
+                .route(web::put().to(handle_webhook_update))
+                .route(web::delete().to(handle_webhook_deletion)),
+        )
+        .service(web::resource("/{id}/ping").route(web::post().to(handle_webhook_ping)))
+        .service(web::resource("/{id}/deliveries").route(web::get().to(handle_delivery_list)))
+        .service(
+            web::resource("/{id}/deliveries/{delivery}/redeliver")
+                .route(web::post().to(handle_redelivery)),
+        )
+}
+
+/// Checks the settings of a webhook, returning its URL and folder as they are stored. The URL
+/// has to point to a public address.
+async fn validate(payload: &WebhookPayload) -> Result<(String, Option<String>), ServiceError> {
+    let url = reqwest::Url::parse(payload.url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .ok_or_else(|| {
+            ServiceError::BadRequest(format!("'{}' is not a valid HTTP URL", payload.url))
+        })?;
+    address::resolve(&url).await?;
+
+    if payload.events.is_empty() || payload.events.contains(&Event::Ping) {
+        return Err(ServiceError::BadRequest(
+            "A webhook must subscribe to at least one event, other than ping".to_string(),
+        ));
+    }
+
+    let folder = payload
+        .folder
+        .as_deref()
+        .map(files::normalize_folder)
+        .transpose()?;
+
+    Ok((url.to_string(), folder))
+}
+
+/// Creates a webhook. The secret signing its payloads is only ever returned here.
+pub async fn handle_webhook_creation(
+    payload: web::Json<WebhookPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let (url, folder) = validate(&payload).await?;
+
+    let mut webhook = Webhook::new(claims.username.clone(), url, payload.events.clone(), folder);
+    webhook.active = payload.active;
+    store::create_webhook(&redis, &webhook).await?;
+
+    Ok(
+        Response::new(StatusCode::CREATED, "Webhook created successfully")
+            .data(webhook)
+            .into(),
+    )
+}
+
+pub async fn handle_webhook_list(
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let webhooks = store::list_webhooks(&redis, &claims.username)
+        .await?
+        .iter()
+        .map(|webhook| webhook.info())
+        .collect::<Vec<WebhookInfo>>();
+
+    Ok(
+        Response::new(StatusCode::OK, "Webhooks listed successfully")
+            .data(webhooks)
+            .into(),
+    )
+}
+
+pub async fn handle_webhook_update(
+    path: web::Path<String>,
+    payload: web::Json<WebhookPayload>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let mut webhook = store::get_owned_webhook(&redis, &path, &claims.username).await?;
+    let (url, folder) = validate(&payload).await?;
+
+    webhook.url = url;
+    webhook.events = payload.events.clone();
+    webhook.folder = folder;
+    webhook.active = payload.active;
+    webhook.updated_at = chrono::Utc::now().timestamp();
+    store::save_webhook(&redis, &webhook).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Webhook updated successfully")
+            .data(webhook.info())
+            .into(),
+    )
+}
+
+pub async fn handle_webhook_deletion(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let webhook = store::get_owned_webhook(&redis, &path, &claims.username).await?;
+    store::delete_webhook(&redis, &webhook).await?;
+
+    Ok(Response::<()>::new(StatusCode::OK, "Webhook deleted successfully").into())
+}
+
+pub async fn handle_webhook_ping(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let webhook = store::get_owned_webhook(&redis, &path, &claims.username).await?;
+    let delivery = delivery::ping(&redis, &webhook).await?;
+
+    Ok(Response::new(StatusCode::ACCEPTED, "Ping queued")
+        .data(delivery)
+        .into())
+}
+
+pub async fn handle_delivery_list(
+    path: web::Path<String>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let webhook = store::get_owned_webhook(&redis, &path, &claims.username).await?;
+    let deliveries = store::list_deliveries(&redis, &webhook.id).await?;
+
+    Ok(
+        Response::new(StatusCode::OK, "Deliveries listed successfully")
+            .data(deliveries)
+            .into(),
+    )
+}
+
+pub async fn handle_redelivery(
+    path: web::Path<(String, String)>,
+    claims: web::ReqData<Claims>,
+    redis: web::Data<Arc<RedisClient>>,
+) -> Result<HttpResponse, ServiceError> {
+    let (id, delivery_id) = path.into_inner();
+    let webhook = store::get_owned_webhook(&redis, &id, &claims.username).await?;
+    let delivery = delivery::redeliver(&redis, &webhook, &delivery_id).await?;
+
+    Ok(Response::new(StatusCode::ACCEPTED, "Delivery queued again")
+        .data(delivery)
+        .into())
+}
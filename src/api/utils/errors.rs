@@ -1,5 +1,6 @@
 use crate::api::utils::types::Response;
 use crate::conditional;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use std::fmt::{Debug, Display, Formatter};
@@ -8,6 +9,17 @@ pub enum ServiceError {
     InternalServerError(String, Option<anyhow::Error>),
     BadRequest(String),
     NotFound(String),
+    Forbidden(String),
+    Conflict(String),
+    PreconditionFailed(String),
+    Gone(String),
+    Locked(String),
+    UnsupportedMediaType(String),
+    InsufficientStorage(String),
+    Infected(String),
+    NotScanned(String),
+    /// Temporarily unavailable, worth retrying after the given number of seconds.
+    ServiceUnavailable(String, u64),
 
     MissingToken,
     InvalidToken,
@@ -22,6 +34,24 @@ impl Display for ServiceError {
             }
             ServiceError::BadRequest(message) => write!(f, "Bad request: {}", message),
             ServiceError::NotFound(message) => write!(f, "Resource not found: {}", message),
+            ServiceError::Forbidden(message) => write!(f, "Forbidden: {}", message),
+            ServiceError::Conflict(message) => write!(f, "Conflict: {}", message),
+            ServiceError::PreconditionFailed(message) => {
+                write!(f, "Precondition failed: {}", message)
+            }
+            ServiceError::Gone(message) => write!(f, "Gone: {}", message),
+            ServiceError::Locked(message) => write!(f, "Locked: {}", message),
+            ServiceError::UnsupportedMediaType(message) => {
+                write!(f, "Unsupported media type: {}", message)
+            }
+            ServiceError::InsufficientStorage(message) => {
+                write!(f, "Insufficient storage: {}", message)
+            }
+            ServiceError::Infected(message) => write!(f, "Infected: {}", message),
+            ServiceError::NotScanned(message) => write!(f, "Not scanned: {}", message),
+            ServiceError::ServiceUnavailable(message, _) => {
+                write!(f, "Service unavailable: {}", message)
+            }
             ServiceError::MissingToken => write!(f, "Missing token header"),
             ServiceError::InvalidToken => write!(f, "Invalid token"),
             ServiceError::ExpiredToken => write!(f, "Expired token, please refresh it"),
@@ -41,6 +71,16 @@ impl ResponseError for ServiceError {
             ServiceError::InternalServerError(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
             ServiceError::BadRequest(_) => StatusCode::BAD_REQUEST,
             ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ServiceError::Conflict(_) => StatusCode::CONFLICT,
+            ServiceError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ServiceError::Gone(_) => StatusCode::GONE,
+            ServiceError::Locked(_) => StatusCode::LOCKED,
+            ServiceError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ServiceError::InsufficientStorage(_) => StatusCode::INSUFFICIENT_STORAGE,
+            ServiceError::Infected(_) => StatusCode::FORBIDDEN,
+            ServiceError::NotScanned(_) => StatusCode::CONFLICT,
+            ServiceError::ServiceUnavailable(_, _) => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::UNAUTHORIZED,
         }
     }
@@ -65,6 +105,52 @@ impl ResponseError for ServiceError {
                 let message = format!("Resource not found: {}", message);
                 Response::<()>::new(StatusCode::NOT_FOUND, &message).into()
             }
+            ServiceError::Forbidden(message) => {
+                let message = format!("Forbidden: {}", message);
+                Response::<()>::new(StatusCode::FORBIDDEN, &message).into()
+            }
+            ServiceError::Conflict(message) => {
+                let message = format!("Conflict: {}", message);
+                Response::<()>::new(StatusCode::CONFLICT, &message).into()
+            }
+            ServiceError::PreconditionFailed(message) => {
+                let message = format!("Precondition failed: {}", message);
+                Response::<()>::new(StatusCode::PRECONDITION_FAILED, &message).into()
+            }
+            ServiceError::Gone(message) => {
+                let message = format!("Gone: {}", message);
+                Response::<()>::new(StatusCode::GONE, &message).into()
+            }
+            ServiceError::Locked(message) => {
+                let message = format!("Locked: {}", message);
+                Response::<()>::new(StatusCode::LOCKED, &message).into()
+            }
+            ServiceError::UnsupportedMediaType(message) => {
+                let message = format!("Unsupported media type: {}", message);
+                Response::<()>::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, &message).into()
+            }
+            ServiceError::InsufficientStorage(message) => {
+                let message = format!("Insufficient storage: {}", message);
+                Response::<()>::new(StatusCode::INSUFFICIENT_STORAGE, &message).into()
+            }
+            ServiceError::Infected(message) => {
+                let message = format!("Infected: {}", message);
+                Response::<()>::new(StatusCode::FORBIDDEN, &message).into()
+            }
+            ServiceError::NotScanned(message) => {
+                let message = format!("Not scanned: {}", message);
+                Response::<()>::new(StatusCode::CONFLICT, &message).into()
+            }
+            ServiceError::ServiceUnavailable(message, retry_after) => {
+                let message = format!("Service unavailable: {}", message);
+                let mut response: HttpResponse =
+                    Response::<()>::new(StatusCode::SERVICE_UNAVAILABLE, &message).into();
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+
+                response
+            }
             ServiceError::MissingToken => {
                 Response::<()>::new(StatusCode::UNAUTHORIZED, "Missing token header").into()
             }
@@ -0,0 +1,40 @@
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use chrono::TimeZone;
+
+/// The ETag of a file version, its content hash.
+pub fn etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+/// Formats a timestamp the way `Last-Modified` expects it.
+pub fn http_date(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// The content hashes listed in `If-Match`, `None` when the header is absent or is `*`.
+pub fn if_match(request: &HttpRequest) -> Option<Vec<String>> {
+    let value = request.headers().get(header::IF_MATCH)?.to_str().ok()?;
+
+    if value.trim() == "*" {
+        return None;
+    }
+
+    let tags = value
+        .split(',')
+        .map(|tag| {
+            tag.trim()
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .to_string()
+        })
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    Some(tags)
+}
@@ -1,4 +1,5 @@
 pub mod errors;
+pub mod headers;
 pub mod payloads;
 pub mod responses;
 pub mod types;
@@ -1,4 +1,13 @@
+use crate::archive::stream::ArchiveFormat;
+use crate::audit::models::AuditAction;
+use crate::jobs::models::Queue;
+use crate::storage::diff::{DiffFormat, Granularity};
+use crate::storage::previews::PreviewSize;
+use crate::sync::journal::ChangeKind;
+use crate::webhooks::models::Event;
 use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
 
 #[derive(Deserialize)]
 pub struct RegistrationPayload {
@@ -13,3 +22,231 @@ pub struct LoginPayload {
     pub password: String,
     pub device_id: String,
 }
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    pub folder: Option<String>,
+    /// Unpacks uploaded archives into the folder instead of storing them as they are.
+    #[serde(default)]
+    pub extract: bool,
+}
+
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    pub version: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    /// Defaults to the version before `to`.
+    pub from: Option<usize>,
+    /// Defaults to the latest version.
+    pub to: Option<usize>,
+    #[serde(default)]
+    pub granularity: Granularity,
+    #[serde(default)]
+    pub format: DiffFormat,
+    /// Unchanged lines or words shown around each change.
+    pub context: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateQuery {
+    /// Keeps stale changes as conflicted copies instead of refusing them.
+    #[serde(default)]
+    pub sync: bool,
+}
+
+#[derive(Deserialize)]
+pub struct LockQuery {
+    /// Length of the lease in seconds.
+    pub duration: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct PresignPayload {
+    /// `GET` to download the file, `PUT` to upload a new version.
+    pub method: String,
+    /// Lifetime of the URL in seconds.
+    pub expires_in: Option<u32>,
+    /// Only accepts requests from this address.
+    pub ip: Option<IpAddr>,
+    /// Largest upload the URL accepts, in bytes.
+    pub max_size: Option<u64>,
+    /// Serves images without their location and identifying metadata.
+    #[serde(default)]
+    pub strip_metadata: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PrivacyPayload {
+    pub strip_shared_metadata: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ActivityQuery {
+    pub before: Option<String>,
+    pub limit: Option<usize>,
+    pub kind: Option<ChangeKind>,
+    /// Leaves out the changes made from the requesting device.
+    #[serde(default)]
+    pub other_devices: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RecentQuery {
+    /// Only the files used from this device, `current` for the requesting device.
+    pub device: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    /// Unix timestamps bounding the entries, both inclusive.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Restricts the whole log to the entries of one user. Administrators only.
+    pub user: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    pub queue: Option<Queue>,
+}
+
+#[derive(Deserialize)]
+pub struct GcQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize)]
+pub struct QuotaPayload {
+    /// Quota in bytes, `null` to fall back to the default quota.
+    pub quota: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    #[serde(default)]
+    pub size: PreviewSize,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveQuery {
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+#[derive(Deserialize)]
+pub struct MovePayload {
+    pub name: Option<String>,
+    pub folder: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CopyPayload {
+    pub folder: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FolderCopyPayload {
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub folder: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct FolderPayload {
+    pub path: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MetadataPayload {
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub set_attributes: BTreeMap<String, String>,
+    pub remove_attributes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MetadataQuery {
+    /// Comma separated tags.
+    pub tags: Option<String>,
+    /// Comma separated `key=value` pairs.
+    pub attributes: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchPayload {
+    /// Applies either every operation or none of them. Otherwise failing operations are
+    /// skipped and the others are still applied together.
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+    pub operations: Vec<BatchOperation>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    Move {
+        file: String,
+        folder: String,
+    },
+    Copy {
+        file: String,
+        folder: Option<String>,
+        name: Option<String>,
+    },
+    Delete {
+        file: String,
+    },
+    Tag {
+        file: String,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    Rename {
+        file: String,
+        name: String,
+    },
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct WebhookPayload {
+    pub url: String,
+    pub events: BTreeSet<Event>,
+    /// Only notifies file events within this folder.
+    pub folder: Option<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
@@ -1,3 +1,11 @@
+use crate::archive::extract::ExtractionReport;
+use crate::jobs::models::Job;
+use crate::jwt::presigned::Grant;
+use crate::storage::copy::FolderCopyReport;
+use crate::storage::locks::FileLock;
+use crate::storage::models::{FileMetadata, Folder};
+use crate::storage::scrubber::ScrubStatus;
+use crate::sync::journal::Change;
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -9,3 +17,50 @@ pub struct RegistrationResponse {
 pub struct LoginResponse {
     pub token: String,
 }
+
+#[derive(Serialize)]
+pub struct ScrubResponse {
+    pub status: Option<ScrubStatus>,
+    pub corrupted_blobs: Vec<String>,
+    pub corrupted_files: Vec<FileMetadata>,
+}
+
+#[derive(Serialize)]
+pub struct FileListing {
+    #[serde(flatten)]
+    pub file: FileMetadata,
+    pub lock: Option<FileLock>,
+}
+
+#[derive(Serialize)]
+pub struct MetadataQueryResponse {
+    pub files: Vec<FileMetadata>,
+    pub folders: Vec<Folder>,
+}
+
+/// Small archives are extracted before responding, larger ones by a background job.
+#[derive(Serialize)]
+pub struct ExtractionResponse {
+    pub archive: String,
+    pub report: Option<ExtractionReport>,
+    pub job: Option<Job>,
+}
+
+#[derive(Serialize)]
+pub struct FolderCopyResponse {
+    pub report: Option<FolderCopyReport>,
+    pub job: Option<Job>,
+}
+
+#[derive(Serialize)]
+pub struct ChangesResponse {
+    pub changes: Vec<Change>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PresignedUrl {
+    pub url: String,
+    #[serde(flatten)]
+    pub grant: Grant,
+}
@@ -0,0 +1,476 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{MAX_ARCHIVE_ENTRIES, MAX_COMPRESSION_RATIO, MAX_EXTRACTED_SIZE};
+use crate::jobs::models::Job;
+use crate::jobs::tasks::Task;
+use crate::jobs::{queue, store};
+use crate::redis::client::RedisClient;
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
+use crate::storage::models::{File, FileMetadata};
+use crate::storage::{content, files, gc, previews};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Recognises an archive from its magic bytes, whatever its name claims.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+            Some(ArchiveKind::Zip)
+        } else if data.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveKind::TarGz)
+        } else if data.len() > 262 && &data[257..262] == b"ustar" {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EntryResult {
+    pub path: String,
+    pub file: Option<FileMetadata>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ExtractionReport {
+    pub archive: String,
+    pub folder: String,
+    pub extracted: usize,
+    pub failed: usize,
+    pub entries: Vec<EntryResult>,
+    /// Set when the archive itself could not be read to the end.
+    pub error: Option<String>,
+}
+
+enum Message {
+    Total(u64),
+    Entry(String, Result<(String, Vec<u8>), String>),
+}
+
+/// Unpacks uploaded archives into a user's folders, one stored file per archive entry.
+pub struct Extractor {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+    owner: String,
+    device_id: String,
+}
+
+impl Extractor {
+    pub fn new(
+        redis: Arc<RedisClient>,
+        blobs: Arc<dyn BlobStore>,
+        owner: String,
+        device_id: String,
+    ) -> Self {
+        Self {
+            redis,
+            blobs,
+            owner,
+            device_id,
+        }
+    }
+
+    /// Queues the extraction of the archive, reporting progress and the final report through a
+    /// job. The archive itself is stored as a blob the job holds on to until it completes.
+    pub async fn enqueue(self, archive: File, folder: String) -> Result<Job, ServiceError> {
+        let hash = content::hash_data(&archive.data);
+        let task = Task::ExtractArchive {
+            device_id: self.device_id.clone(),
+            folder,
+            name: archive.name,
+            hash: hash.clone(),
+        };
+        let job = Job::new(self.owner.clone(), task);
+
+        gc::acquire_upload(&self.redis, &hash).await?;
+        let stored = content::store_content(self.blobs.as_ref(), &hash, archive.data).await;
+        let held = match stored {
+            Ok(()) => queue::hold_hash(&self.redis, &job).await,
+            Err(error) => Err(ServiceError::InternalServerError(
+                "Failed to store the archive".to_string(),
+                Some(error),
+            )),
+        };
+        gc::release_upload(&self.redis, &hash).await?;
+        held?;
+
+        queue::enqueue(&self.redis, job).await
+    }
+
+    /// Runs an extraction queued by `enqueue`.
+    pub async fn run_job(
+        &self,
+        name: &str,
+        hash: &str,
+        folder: &str,
+        job: &mut Job,
+    ) -> Result<ExtractionReport, ServiceError> {
+        let data = content::read_content(self.blobs.as_ref(), hash)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to read the archive".to_string(),
+                    Some(error),
+                )
+            })?;
+
+        let archive = File::from_bytes(name.to_string(), data);
+        self.extract(archive, folder, Some(job)).await
+    }
+
+    pub async fn extract(
+        &self,
+        archive: File,
+        folder: &str,
+        mut job: Option<&mut Job>,
+    ) -> Result<ExtractionReport, ServiceError> {
+        let folder = files::normalize_folder(folder)?;
+        let kind = ArchiveKind::detect(&archive.data).ok_or_else(|| {
+            ServiceError::BadRequest(format!("{} is not a ZIP or tar archive", archive.name))
+        })?;
+
+        // Archive readers are synchronous, so entries are decoded on a blocking thread and
+        // handed over one at a time. At most one decoded entry waits in memory.
+        let (sender, mut receiver) = mpsc::channel(1);
+        let data = archive.data;
+        let reader = tokio::task::spawn_blocking(move || read_archive(kind, data, sender));
+
+        let mut report = ExtractionReport {
+            archive: archive.name,
+            folder: folder.clone(),
+            extracted: 0,
+            failed: 0,
+            entries: Vec::new(),
+            error: None,
+        };
+        let mut total = None;
+
+        while let Some(message) = receiver.recv().await {
+            let (path, entry) = match message {
+                Message::Total(count) => {
+                    total = Some(count);
+                    continue;
+                }
+                Message::Entry(path, entry) => (path, entry),
+            };
+
+            let result = match entry {
+                Ok((relative, data)) => self.store_entry(&folder, &relative, data).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(file) => {
+                    report.extracted += 1;
+                    report.entries.push(EntryResult {
+                        path,
+                        file: Some(file),
+                        error: None,
+                    });
+                }
+                Err(error) => {
+                    report.failed += 1;
+                    report.entries.push(EntryResult {
+                        path,
+                        file: None,
+                        error: Some(error),
+                    });
+                }
+            }
+
+            if let Some(job) = job.as_deref_mut() {
+                job.progress(report.entries.len() as u64, total);
+                store::save_job(&self.redis, job).await?;
+            }
+        }
+
+        report.error = match reader.await {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(error),
+            Err(error) => Some(format!("The archive reader crashed: {}", error)),
+        };
+
+        Ok(report)
+    }
+
+    async fn store_entry(
+        &self,
+        folder: &str,
+        relative: &str,
+        data: Vec<u8>,
+    ) -> Result<FileMetadata, String> {
+        let (parent, name) = match relative.rsplit_once('/') {
+            Some((parent, name)) => (format!("{}/{}", folder, parent), name),
+            None => (folder.to_string(), relative),
+        };
+
+        let file = files::create_file(
+            &self.redis,
+            self.blobs.as_ref(),
+            &self.owner,
+            &self.device_id,
+            &parent,
+            File::from_bytes(name.to_string(), data),
+        )
+        .await
+        .map_err(|error| error.to_string())?;
+
+        previews::enqueue_generation(&self.redis, &self.owner, &file.hash, &file.content_type)
+            .await
+            .map_err(|error| error.to_string())?;
+        indexer::enqueue_indexing(&self.redis, &file)
+            .await
+            .map_err(|error| error.to_string())?;
+
+        Ok(file)
+    }
+}
+
+/// Turns an entry name into a path relative to the extraction folder. Absolute paths are made
+/// relative, anything escaping the folder is refused.
+pub fn sanitize_path(name: &str) -> Result<String, String> {
+    let name = name.replace('\\', "/");
+    let mut segments = Vec::new();
+
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return Err("The entry path escapes the extraction folder".to_string()),
+            segment if segment.contains(':') || segment.contains('\0') => {
+                return Err("The entry path contains forbidden characters".to_string())
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        return Err("The entry has no name".to_string());
+    }
+
+    Ok(segments.join("/"))
+}
+
+/// Keeps track of how much an archive has expanded, to stop zip bombs before they fill memory.
+struct Budget {
+    archive_size: u64,
+    extracted: u64,
+    entries: usize,
+}
+
+impl Budget {
+    fn new(archive_size: u64) -> Self {
+        Self {
+            archive_size,
+            extracted: 0,
+            entries: 0,
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<(), String> {
+        self.entries += 1;
+
+        if self.entries > MAX_ARCHIVE_ENTRIES {
+            return Err(format!(
+                "The archive has more than {} entries",
+                MAX_ARCHIVE_ENTRIES
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Reads an entry without ever holding more than the remaining budget.
+    fn read(&mut self, reader: impl Read) -> Result<Vec<u8>, String> {
+        let remaining = *MAX_EXTRACTED_SIZE - self.extracted;
+
+        let mut data = Vec::new();
+        reader
+            .take(remaining + 1)
+            .read_to_end(&mut data)
+            .map_err(|error| format!("Failed to read the entry: {}", error))?;
+
+        self.extracted += data.len() as u64;
+        if self.extracted > *MAX_EXTRACTED_SIZE {
+            return Err(format!(
+                "The archive expands to more than {} bytes",
+                *MAX_EXTRACTED_SIZE
+            ));
+        }
+        if self.extracted > self.archive_size.max(1) * MAX_COMPRESSION_RATIO {
+            return Err(format!(
+                "The archive expands more than {} times its size",
+                MAX_COMPRESSION_RATIO
+            ));
+        }
+
+        Ok(data)
+    }
+}
+
+/// Decodes the archive entry by entry. Errors about a single entry are sent along with it,
+/// errors that make the rest of the archive unreadable end the extraction.
+fn read_archive(
+    kind: ArchiveKind,
+    data: Vec<u8>,
+    sender: mpsc::Sender<Message>,
+) -> Result<(), String> {
+    let mut budget = Budget::new(data.len() as u64);
+
+    match kind {
+        ArchiveKind::Zip => read_zip(data, &mut budget, &sender),
+        ArchiveKind::Tar => read_tar(Cursor::new(data), &mut budget, &sender),
+        ArchiveKind::TarGz => read_tar(GzDecoder::new(Cursor::new(data)), &mut budget, &sender),
+    }
+}
+
+fn read_zip(
+    data: Vec<u8>,
+    budget: &mut Budget,
+    sender: &mpsc::Sender<Message>,
+) -> Result<(), String> {
+    let mut archive = ::zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|error| format!("The archive is invalid: {}", error))?;
+
+    let files = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .count();
+    let _ = sender.blocking_send(Message::Total(files as u64));
+
+    for index in 0..archive.len() {
+        budget.next_entry()?;
+
+        let (path, entry) = match archive.by_index(index) {
+            Ok(file) if file.is_dir() => continue,
+            Ok(file) => {
+                let path = file.name().to_string();
+                let declared_ratio = file.size() / file.compressed_size().max(1);
+
+                let entry = match sanitize_path(&path) {
+                    Ok(_) if declared_ratio > MAX_COMPRESSION_RATIO => Err(format!(
+                        "The entry expands more than {} times its size",
+                        MAX_COMPRESSION_RATIO
+                    )),
+                    Ok(relative) => Ok((relative, budget.read(file)?)),
+                    Err(error) => Err(error),
+                };
+
+                (path, entry)
+            }
+            Err(error) => (
+                format!("#{}", index),
+                Err(format!("Failed to read the entry: {}", error)),
+            ),
+        };
+
+        if sender.blocking_send(Message::Entry(path, entry)).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_tar(
+    reader: impl Read,
+    budget: &mut Budget,
+    sender: &mpsc::Sender<Message>,
+) -> Result<(), String> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive
+        .entries()
+        .map_err(|error| format!("The archive is invalid: {}", error))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|error| format!("The archive is invalid: {}", error))?;
+        budget.next_entry()?;
+
+        let path = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            continue;
+        }
+
+        let result = match sanitize_path(&path) {
+            // Links could point anywhere, only the content of regular files is kept.
+            Ok(_) if !kind.is_file() => Err("Only regular files can be extracted".to_string()),
+            Ok(relative) => Ok((relative, budget.read(entry)?)),
+            Err(error) => Err(error),
+        };
+
+        if sender.blocking_send(Message::Entry(path, result)).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_entry_paths() {
+        assert_eq!(sanitize_path("docs/report.txt").unwrap(), "docs/report.txt");
+        assert_eq!(sanitize_path("/etc/passwd").unwrap(), "etc/passwd");
+        assert_eq!(sanitize_path("./a//b/./c").unwrap(), "a/b/c");
+        assert_eq!(sanitize_path("dir\\file.txt").unwrap(), "dir/file.txt");
+
+        for name in [
+            "../escape.txt",
+            "docs/../../escape.txt",
+            "..\\escape.txt",
+            "C:/windows/system.ini",
+            "nul\0byte",
+            "",
+            "/",
+            "./.",
+        ] {
+            assert!(sanitize_path(name).is_err(), "{:?} should be refused", name);
+        }
+    }
+
+    #[test]
+    fn caps_the_compression_ratio() {
+        let mut budget = Budget::new(10);
+        let data = budget.read(&[0u8; 600][..]).unwrap();
+        assert_eq!(data.len(), 600);
+
+        // The ratio is checked against everything extracted so far, not each entry alone.
+        assert!(budget.read(&[0u8; 400][..]).is_ok());
+        assert!(budget.read(&[0u8; 1][..]).is_err());
+    }
+
+    #[test]
+    fn stops_reading_entries_past_the_total_size() {
+        let mut budget = Budget::new(*MAX_EXTRACTED_SIZE);
+        budget.extracted = *MAX_EXTRACTED_SIZE - 10;
+
+        // An endless entry is cut off right after the remaining budget.
+        assert!(budget.read(std::io::repeat(0)).is_err());
+        assert_eq!(budget.extracted, *MAX_EXTRACTED_SIZE + 1);
+    }
+
+    #[test]
+    fn caps_the_number_of_entries() {
+        let mut budget = Budget::new(0);
+        for _ in 0..MAX_ARCHIVE_ENTRIES {
+            budget.next_entry().unwrap();
+        }
+
+        assert!(budget.next_entry().is_err());
+    }
+}
@@ -0,0 +1,30 @@
+use serde::Serialize;
+use std::io::Cursor;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub is_dir: bool,
+}
+
+/// Lists the entries of a ZIP archive from its central directory, without decompressing any of
+/// them.
+pub fn list_zip(data: &[u8]) -> Result<Vec<ZipEntry>, ::zip::result::ZipError> {
+    let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+    let mut entries = Vec::with_capacity(archive.len());
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+
+        entries.push(ZipEntry {
+            name: entry.name().to_string(),
+            size: entry.size(),
+            compressed_size: entry.compressed_size(),
+            is_dir: entry.is_dir(),
+        });
+    }
+
+    Ok(entries)
+}
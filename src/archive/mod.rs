@@ -0,0 +1,4 @@
+pub mod extract;
+pub mod listing;
+pub mod stream;
+pub mod zip_stream;
@@ -0,0 +1,148 @@
+use crate::archive::zip_stream::ZipStreamWriter;
+use crate::storage::blob::BlobStore;
+use crate::storage::content;
+use actix_web::web::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::channel::mpsc;
+use futures::SinkExt;
+use serde::Deserialize;
+use std::io;
+use std::sync::Arc;
+
+/// Chunks waiting to be sent to the client. Keeps a slow client from pulling whole folders into
+/// memory ahead of the network.
+const CHANNEL_CAPACITY: usize = 4;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+pub struct ArchiveEntry {
+    pub path: String,
+    pub hash: String,
+    pub modified: i64,
+}
+
+enum ArchiveWriter {
+    Zip(ZipStreamWriter),
+    TarGz(tar::Builder<GzEncoder<Vec<u8>>>),
+}
+
+impl ArchiveWriter {
+    fn new(format: ArchiveFormat) -> Self {
+        match format {
+            ArchiveFormat::Zip => ArchiveWriter::Zip(ZipStreamWriter::new()),
+            ArchiveFormat::TarGz => ArchiveWriter::TarGz(tar::Builder::new(GzEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            ))),
+        }
+    }
+
+    /// Appends an entry and returns the archive bytes produced so far.
+    fn add(&mut self, path: &str, data: &[u8], modified: i64) -> io::Result<Vec<u8>> {
+        match self {
+            ArchiveWriter::Zip(writer) => {
+                writer.add(path, data, modified)?;
+                Ok(writer.take_output())
+            }
+            ArchiveWriter::TarGz(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(modified.max(0) as u64);
+
+                builder.append_data(&mut header, path, data)?;
+                Ok(std::mem::take(builder.get_mut().get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            ArchiveWriter::Zip(mut writer) => {
+                writer.finish();
+                Ok(writer.take_output())
+            }
+            ArchiveWriter::TarGz(builder) => builder.into_inner()?.finish(),
+        }
+    }
+}
+
+/// Builds an archive of the given entries in the background and streams it chunk by chunk.
+/// Nothing is staged on disk: each blob is read, appended and handed to the client before the
+/// next one is read.
+pub fn spawn_archive(
+    blobs: Arc<dyn BlobStore>,
+    format: ArchiveFormat,
+    entries: Vec<ArchiveEntry>,
+) -> mpsc::Receiver<io::Result<Bytes>> {
+    let (mut sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        if let Err(error) = write_archive(blobs.as_ref(), format, entries, &mut sender).await {
+            log::warn!("Failed to stream an archive: {}", error);
+            let _ = sender.send(Err(error)).await;
+        }
+    });
+
+    receiver
+}
+
+async fn write_archive(
+    blobs: &dyn BlobStore,
+    format: ArchiveFormat,
+    entries: Vec<ArchiveEntry>,
+    sender: &mut mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<()> {
+    let mut writer = ArchiveWriter::new(format);
+
+    for entry in entries {
+        let data = content::read_content(blobs, &entry.hash)
+            .await
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+
+        // Compression is CPU bound, so the writer moves to a blocking thread for each entry.
+        let (returned, chunk) = tokio::task::spawn_blocking(move || {
+            let chunk = writer.add(&entry.path, &data, entry.modified);
+            (writer, chunk)
+        })
+        .await
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        writer = returned;
+
+        if sender.send(Ok(Bytes::from(chunk?))).await.is_err() {
+            // The client went away, there is no one left to build the archive for.
+            return Ok(());
+        }
+    }
+
+    let chunk = tokio::task::spawn_blocking(move || writer.finish())
+        .await
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))??;
+    let _ = sender.send(Ok(Bytes::from(chunk))).await;
+
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_DEFLATE: u16 = 8;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const MAX_U16: u64 = 0xFFFF;
+const MAX_U32: u64 = 0xFFFF_FFFF;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+    time: u16,
+    date: u16,
+}
+
+/// Writes a ZIP archive front to back without ever seeking, so it can be streamed as it is
+/// produced. Entries are compressed in memory first, which lets every local header carry the
+/// final sizes and CRC. ZIP64 records are used as soon as a size, an offset or the number of
+/// entries outgrows the classic format.
+///
+/// Output accumulates in an internal buffer, drained with `take_output`.
+pub struct ZipStreamWriter {
+    output: Vec<u8>,
+    written: u64,
+    entries: Vec<Entry>,
+}
+
+impl ZipStreamWriter {
+    pub fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            written: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8], modified: i64) -> std::io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let (time, date) = dos_date_time(modified);
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            compressed_size: compressed.len() as u64,
+            size: data.len() as u64,
+            offset: self.written,
+            time,
+            date,
+        };
+
+        let zip64 = entry.size >= MAX_U32 || entry.compressed_size >= MAX_U32;
+        let mut header = Vec::with_capacity(64 + name.len());
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(
+            &mut header,
+            if zip64 {
+                VERSION_ZIP64
+            } else {
+                VERSION_DEFAULT
+            },
+        );
+        put_u16(&mut header, FLAG_UTF8);
+        put_u16(&mut header, METHOD_DEFLATE);
+        put_u16(&mut header, entry.time);
+        put_u16(&mut header, entry.date);
+        put_u32(&mut header, entry.crc);
+
+        if zip64 {
+            put_u32(&mut header, MAX_U32 as u32);
+            put_u32(&mut header, MAX_U32 as u32);
+            put_u16(&mut header, name.len() as u16);
+            put_u16(&mut header, 20);
+            header.extend_from_slice(name.as_bytes());
+            put_u16(&mut header, ZIP64_EXTRA_ID);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, entry.size);
+            put_u64(&mut header, entry.compressed_size);
+        } else {
+            put_u32(&mut header, entry.compressed_size as u32);
+            put_u32(&mut header, entry.size as u32);
+            put_u16(&mut header, name.len() as u16);
+            put_u16(&mut header, 0);
+            header.extend_from_slice(name.as_bytes());
+        }
+
+        self.write(&header);
+        self.write(&compressed);
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Writes the central directory. No entry can be added afterwards.
+    pub fn finish(&mut self) {
+        let directory_offset = self.written;
+        let mut directory = Vec::new();
+
+        for entry in &self.entries {
+            let mut extra = Vec::new();
+            if entry.size >= MAX_U32 {
+                put_u64(&mut extra, entry.size);
+            }
+            if entry.compressed_size >= MAX_U32 {
+                put_u64(&mut extra, entry.compressed_size);
+            }
+            if entry.offset >= MAX_U32 {
+                put_u64(&mut extra, entry.offset);
+            }
+
+            let version = if extra.is_empty() {
+                VERSION_DEFAULT
+            } else {
+                VERSION_ZIP64
+            };
+            put_u32(&mut directory, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut directory, version);
+            put_u16(&mut directory, version);
+            put_u16(&mut directory, FLAG_UTF8);
+            put_u16(&mut directory, METHOD_DEFLATE);
+            put_u16(&mut directory, entry.time);
+            put_u16(&mut directory, entry.date);
+            put_u32(&mut directory, entry.crc);
+            put_u32(&mut directory, entry.compressed_size.min(MAX_U32) as u32);
+            put_u32(&mut directory, entry.size.min(MAX_U32) as u32);
+            put_u16(&mut directory, entry.name.len() as u16);
+            put_u16(
+                &mut directory,
+                if extra.is_empty() {
+                    0
+                } else {
+                    extra.len() as u16 + 4
+                },
+            );
+            put_u16(&mut directory, 0); // comment length
+            put_u16(&mut directory, 0); // disk number
+            put_u16(&mut directory, 0); // internal attributes
+            put_u32(&mut directory, 0); // external attributes
+            put_u32(&mut directory, entry.offset.min(MAX_U32) as u32);
+            directory.extend_from_slice(entry.name.as_bytes());
+
+            if !extra.is_empty() {
+                put_u16(&mut directory, ZIP64_EXTRA_ID);
+                put_u16(&mut directory, extra.len() as u16);
+                directory.extend_from_slice(&extra);
+            }
+        }
+
+        let directory_size = directory.len() as u64;
+        let count = self.entries.len() as u64;
+        self.write(&directory);
+
+        let mut end = Vec::new();
+        if count >= MAX_U16 || directory_size >= MAX_U32 || directory_offset >= MAX_U32 {
+            let zip64_end_offset = self.written;
+
+            put_u32(&mut end, ZIP64_END_SIGNATURE);
+            put_u64(&mut end, 44);
+            put_u16(&mut end, VERSION_ZIP64);
+            put_u16(&mut end, VERSION_ZIP64);
+            put_u32(&mut end, 0);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, count);
+            put_u64(&mut end, count);
+            put_u64(&mut end, directory_size);
+            put_u64(&mut end, directory_offset);
+
+            put_u32(&mut end, ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, zip64_end_offset);
+            put_u32(&mut end, 1);
+        }
+
+        put_u32(&mut end, END_SIGNATURE);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, count.min(MAX_U16) as u16);
+        put_u16(&mut end, count.min(MAX_U16) as u16);
+        put_u32(&mut end, directory_size.min(MAX_U32) as u32);
+        put_u32(&mut end, directory_offset.min(MAX_U32) as u32);
+        put_u16(&mut end, 0);
+
+        self.write(&end);
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.output.extend_from_slice(data);
+        self.written += data.len() as u64;
+    }
+}
+
+impl Default for ZipStreamWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dos_date_time(timestamp: i64) -> (u16, u16) {
+    use chrono::{Datelike, TimeZone, Timelike};
+
+    let time = chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(chrono::Utc::now);
+
+    // DOS dates start in 1980.
+    let year = (time.year().max(1980) - 1980) as u16;
+    let date = (year << 9) | ((time.month() as u16) << 5) | time.day() as u16;
+    let clock =
+        ((time.hour() as u16) << 11) | ((time.minute() as u16) << 5) | (time.second() as u16 / 2);
+
+    (clock, date)
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn read_u64(data: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    fn zip64_end(data: &[u8]) -> usize {
+        let signature = ZIP64_END_SIGNATURE.to_le_bytes();
+        data.windows(4)
+            .rposition(|window| window == signature)
+            .expect("no ZIP64 end record")
+    }
+
+    #[test]
+    fn round_trips_more_entries_than_the_classic_format_counts() {
+        let count = MAX_U16 as usize + 10;
+        let mut writer = ZipStreamWriter::new();
+        for index in 0..count {
+            writer
+                .add(&format!("{}.txt", index), index.to_string().as_bytes(), 0)
+                .unwrap();
+        }
+        writer.finish();
+        let output = writer.take_output();
+
+        assert_eq!(read_u64(&output, zip64_end(&output) + 24), count as u64);
+
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), count);
+        let mut content = String::new();
+        archive
+            .by_name("65540.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "65540");
+    }
+
+    #[test]
+    fn records_offsets_past_4_gib() {
+        let mut writer = ZipStreamWriter::new();
+        // Stands in for the 5 GiB of entries already streamed to the client.
+        writer.written = 5 << 30;
+        writer.add("late.txt", b"content", 0).unwrap();
+        writer.finish();
+        let output = writer.take_output();
+
+        let directory = output
+            .windows(4)
+            .position(|window| window == CENTRAL_HEADER_SIGNATURE.to_le_bytes())
+            .unwrap();
+
+        // The classic end record defers to the ZIP64 one for the directory offset.
+        let end = output.len() - 22;
+        assert_eq!(output[end + 16..end + 20], [0xFF; 4]);
+        let offset = read_u64(&output, zip64_end(&output) + 48);
+        assert_eq!(offset, (5 << 30) + directory as u64);
+
+        // The central header carries the entry offset in its ZIP64 extra field, after the
+        // fixed fields, the name and the extra field header.
+        assert_eq!(output[directory + 42..directory + 46], [0xFF; 4]);
+        assert_eq!(read_u64(&output, directory + 46 + 8 + 4), 5 << 30);
+    }
+
+    #[test]
+    #[ignore = "compresses more than 4 GiB"]
+    fn round_trips_entries_past_4_gib() {
+        let data = vec![0u8; MAX_U32 as usize + 10];
+        let mut writer = ZipStreamWriter::new();
+        writer.add("large.bin", &data, 0).unwrap();
+        writer.add("small.txt", b"after", 0).unwrap();
+        writer.finish();
+        drop(data);
+
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(writer.take_output())).unwrap();
+        let mut large = archive.by_name("large.bin").unwrap();
+        assert_eq!(large.size(), MAX_U32 + 10);
+        let copied = std::io::copy(&mut large, &mut std::io::sink()).unwrap();
+        assert_eq!(copied, MAX_U32 + 10);
+        drop(large);
+
+        let mut small = String::new();
+        archive
+            .by_name("small.txt")
+            .unwrap()
+            .read_to_string(&mut small)
+            .unwrap();
+        assert_eq!(small, "after");
+    }
+}
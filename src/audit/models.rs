@@ -0,0 +1,101 @@
+use crate::jwt::models::Claims;
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    TokenRefreshed,
+    Upload,
+    Download,
+    Delete,
+    /// A pre-signed URL handed out for a file.
+    Share,
+    AccessKeyCreated,
+    AccessKeyDeleted,
+    /// Anything done through the administration endpoints.
+    Admin,
+}
+
+/// Who did something, and from where.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Actor {
+    pub username: String,
+    pub device_id: String,
+    pub ip: Option<String>,
+}
+
+impl Actor {
+    pub fn new(username: &str, device_id: &str, request: &HttpRequest) -> Self {
+        Self {
+            username: username.to_string(),
+            device_id: device_id.to_string(),
+            ip: request.peer_addr().map(|address| address.ip().to_string()),
+        }
+    }
+
+    pub fn from_claims(claims: &Claims, request: &HttpRequest) -> Self {
+        Self::new(&claims.username, &claims.device_id, request)
+    }
+}
+
+/// An entry of the audit log. Each entry carries the hash of the one before it, so altering or
+/// removing an entry breaks the chain from there on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    /// Position of the entry in the stream it was read from, usable as a cursor.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub sequence: u64,
+    pub timestamp: i64,
+    pub action: AuditAction,
+    pub actor: Actor,
+    /// What the action was done to, e.g. `file:<id>`.
+    pub target: Option<String>,
+    pub detail: Option<String>,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChainHead {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `since` to get the entries that follow.
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct VerificationReport {
+    pub checked: u64,
+    pub valid: bool,
+    /// The sequence number of the first entry that does not fit the chain.
+    pub broken_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+/// Narrows down the entries read from the log. Times are Unix timestamps, both inclusive.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        !matches!(self.action, Some(action) if action != entry.action)
+            && !matches!(&self.target, Some(target) if entry.target.as_ref() != Some(target))
+            && !matches!(self.from, Some(from) if entry.timestamp < from)
+            && !matches!(self.to, Some(to) if entry.timestamp > to)
+    }
+}
@@ -0,0 +1,498 @@
+use crate::api::utils::errors::ServiceError;
+use crate::audit::models::{
+    Actor, AuditAction, AuditEntry, AuditFilter, AuditPage, ChainHead, VerificationReport,
+};
+use crate::constants::{
+    AUDIT_BATCH_SIZE, AUDIT_SECRET, MAX_AUDIT_ATTEMPTS, UNKNOWN_LOGIN_AUDIT_LIMIT,
+    UNKNOWN_LOGIN_AUDIT_WINDOW,
+};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::sync::journal;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The previous hash of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Counts the attempts made within the current window, starting a new window when none is open.
+const COUNT_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+fn serialize<T: serde::Serialize>(value: &T) -> Result<String, ServiceError> {
+    serde_json::to_string(value).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })
+}
+
+/// The hash of an entry, over everything it records including the hash of its predecessor. It
+/// is keyed with the server's secret, so whoever can write to Redis cannot rebuild the chain
+/// after altering it.
+fn hash_entry(entry: &AuditEntry) -> Result<String, ServiceError> {
+    let mut unsealed = entry.clone();
+    unsealed.id = String::new();
+    unsealed.hash = String::new();
+
+    let mut mac = HmacSha256::new_from_slice(&AUDIT_SECRET).expect("HMAC accepts keys of any size");
+    mac.update(serialize(&unsealed)?.as_bytes());
+
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Records an action that has already happened. Failing to audit it does not undo it, so the
+/// error is logged instead of failing the request.
+pub async fn record(
+    redis: &RedisClient,
+    actor: &Actor,
+    action: AuditAction,
+    target: Option<String>,
+    detail: Option<String>,
+) {
+    if let Err(error) = append(redis, actor, action, target, detail).await {
+        log::error!("Failed to record {:?} in the audit log: {}", action, error);
+    }
+}
+
+/// Records a failed login to an account that does not exist. Anyone can make those, so only
+/// the first few from an address are recorded in each window to keep them from flooding the log.
+pub async fn record_unknown_login(redis: &RedisClient, actor: &Actor, username: &str) {
+    let key = RedisKey::UnknownLogins(actor.ip.clone().unwrap_or_default());
+    let count: Result<u32, ServiceError> = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(COUNT_SCRIPT)
+                .arg(1)
+                .arg(key.to_string())
+                .arg(UNKNOWN_LOGIN_AUDIT_WINDOW),
+        )
+        .await;
+
+    let detail = match count {
+        Ok(count) if count > UNKNOWN_LOGIN_AUDIT_LIMIT => return,
+        Ok(count) if count == UNKNOWN_LOGIN_AUDIT_LIMIT => format!(
+            "Unknown account, further attempts from this address are not recorded for {} seconds",
+            UNKNOWN_LOGIN_AUDIT_WINDOW
+        ),
+        _ => "Unknown account".to_string(),
+    };
+    let target = Some(format!("user:{}", username));
+
+    record(redis, actor, AuditAction::LoginFailed, target, Some(detail)).await;
+}
+
+/// Appends an entry to the log, and to the log of the acting user when there is one. Entries
+/// are chained under a watch on the head of the chain, so concurrent writers take turns, up to
+/// `MAX_AUDIT_ATTEMPTS` times.
+async fn append(
+    redis: &RedisClient,
+    actor: &Actor,
+    action: AuditAction,
+    target: Option<String>,
+    detail: Option<String>,
+) -> Result<AuditEntry, ServiceError> {
+    for _ in 0..MAX_AUDIT_ATTEMPTS {
+        let head: Option<String> = redis
+            .execute(redis::cmd("GET").arg(RedisKey::AuditHead.to_string()))
+            .await?;
+        let (sequence, previous_hash) = match &head {
+            Some(head) => {
+                let head: ChainHead = serde_json::from_str(head).map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to deserialize the data".to_string(),
+                        Some(error.into()),
+                    )
+                })?;
+                (head.sequence + 1, head.hash)
+            }
+            None => (1, GENESIS_HASH.to_string()),
+        };
+
+        let mut entry = AuditEntry {
+            id: String::new(),
+            sequence,
+            timestamp: chrono::Utc::now().timestamp(),
+            action,
+            actor: actor.clone(),
+            target: target.clone(),
+            detail: detail.clone(),
+            previous_hash,
+            hash: String::new(),
+        };
+        entry.hash = hash_entry(&entry)?;
+
+        let value = serialize(&entry)?;
+        let next_head = serialize(&ChainHead {
+            sequence,
+            hash: entry.hash.clone(),
+        })?;
+
+        let mut pipeline = redis::pipe();
+        pipeline
+            .cmd("XADD")
+            .arg(RedisKey::AuditLog.to_string())
+            .arg("*")
+            .arg("entry")
+            .arg(&value)
+            .ignore();
+        if !actor.username.is_empty() {
+            pipeline
+                .cmd("XADD")
+                .arg(RedisKey::UserAuditLog(actor.username.clone()).to_string())
+                .arg("*")
+                .arg("entry")
+                .arg(&value)
+                .ignore();
+        }
+        pipeline
+            .cmd("SET")
+            .arg(RedisKey::AuditHead.to_string())
+            .arg(&next_head)
+            .ignore();
+
+        if redis
+            .async_compare_and_exec(&[(RedisKey::AuditHead, head)], &mut pipeline)
+            .await?
+        {
+            return Ok(entry);
+        }
+    }
+
+    Err(ServiceError::InternalServerError(
+        "Too many concurrent writes to the audit log".to_string(),
+        None,
+    ))
+}
+
+/// Reads up to `limit` entries matching `filter`, oldest first, from the whole log or from the
+/// log of a single user. The returned cursor points after the last entry looked at, matching or
+/// not, and an empty page means the end of the log was reached.
+pub async fn query(
+    redis: &RedisClient,
+    username: Option<&str>,
+    since: Option<&str>,
+    filter: &AuditFilter,
+    limit: usize,
+) -> Result<AuditPage, ServiceError> {
+    let key = || match username {
+        Some(username) => RedisKey::UserAuditLog(username.to_string()),
+        None => RedisKey::AuditLog,
+    };
+
+    let mut start = match (since, filter.from) {
+        (Some(since), _) => journal::next_id(since)?,
+        (None, Some(from)) => format!("{}-0", from.max(0) * 1000),
+        (None, None) => "-".to_string(),
+    };
+
+    let mut entries = Vec::new();
+    let mut cursor = since.map(str::to_string);
+    loop {
+        let batch = read(redis, key(), &start, AUDIT_BATCH_SIZE).await?;
+        let exhausted = batch.len() < AUDIT_BATCH_SIZE;
+
+        for entry in batch {
+            if matches!(filter.to, Some(to) if entry.timestamp > to) {
+                return Ok(AuditPage { entries, cursor });
+            }
+
+            start = journal::next_id(&entry.id)?;
+            cursor = Some(entry.id.clone());
+            if filter.matches(&entry) {
+                entries.push(entry);
+            }
+            if entries.len() == limit {
+                return Ok(AuditPage { entries, cursor });
+            }
+        }
+
+        if exhausted {
+            return Ok(AuditPage { entries, cursor });
+        }
+    }
+}
+
+async fn read(
+    redis: &RedisClient,
+    key: RedisKey,
+    start: &str,
+    count: usize,
+) -> Result<Vec<AuditEntry>, ServiceError> {
+    let raw = redis.async_xrange(key, start, count).await?;
+
+    let mut entries = Vec::with_capacity(raw.len());
+    for (id, fields) in raw {
+        let value = fields
+            .chunks(2)
+            .find(|pair| pair[0] == "entry")
+            .and_then(|pair| pair.get(1));
+
+        let mut entry: AuditEntry = match value.map(|value| serde_json::from_str(value)) {
+            Some(Ok(entry)) => entry,
+            _ => {
+                return Err(ServiceError::InternalServerError(
+                    format!("Audit entry {} is unreadable", id),
+                    None,
+                ))
+            }
+        };
+        entry.id = id;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Walks the log up to its head and checks that every entry still hashes to what it recorded,
+/// follows the entry before it and that none is missing. Entries appended during the walk are
+/// past the head read at its start, and left for the next verification.
+pub async fn verify(redis: &RedisClient) -> Result<VerificationReport, ServiceError> {
+    let head: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::AuditHead.to_string()))
+        .await?;
+    let head = match head {
+        Some(head) => Some(serde_json::from_str::<ChainHead>(&head).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to deserialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?),
+        None => None,
+    };
+    let last = head.as_ref().map_or(0, |head| head.sequence);
+
+    let mut report = VerificationReport::default();
+    if head.is_none() && !read(redis, RedisKey::AuditLog, "-", 1).await?.is_empty() {
+        report.broken_at = Some(1);
+        report.reason = Some("The head of the log is missing".to_string());
+        return Ok(report);
+    }
+
+    let mut previous = ChainHead {
+        sequence: 0,
+        hash: GENESIS_HASH.to_string(),
+    };
+    let mut start = "-".to_string();
+
+    'walk: while previous.sequence < last {
+        let batch = read(redis, RedisKey::AuditLog, &start, AUDIT_BATCH_SIZE).await?;
+        let exhausted = batch.len() < AUDIT_BATCH_SIZE;
+
+        for entry in batch {
+            start = journal::next_id(&entry.id)?;
+            report.checked += 1;
+
+            let reason = check(&previous, &entry)?;
+            if reason.is_some() {
+                report.broken_at = Some(entry.sequence);
+                report.reason = reason;
+                return Ok(report);
+            }
+
+            previous = ChainHead {
+                sequence: entry.sequence,
+                hash: entry.hash,
+            };
+            if previous.sequence == last {
+                break 'walk;
+            }
+        }
+
+        if exhausted {
+            break;
+        }
+    }
+
+    // Entries cut off the end of the stream leave the head ahead of the last entry.
+    let intact = match head {
+        Some(head) => head.sequence == previous.sequence && head.hash == previous.hash,
+        None => previous.sequence == 0,
+    };
+    if !intact {
+        report.broken_at = Some(previous.sequence + 1);
+        report.reason = Some("Entries are missing from the end of the log".to_string());
+        return Ok(report);
+    }
+
+    report.valid = true;
+    Ok(report)
+}
+
+/// Why an entry does not fit the chain after `previous`, if it does not.
+fn check(previous: &ChainHead, entry: &AuditEntry) -> Result<Option<String>, ServiceError> {
+    let reason = if entry.sequence != previous.sequence + 1 {
+        Some(format!(
+            "Expected entry {}, found {}",
+            previous.sequence + 1,
+            entry.sequence
+        ))
+    } else if entry.previous_hash != previous.hash {
+        Some("The entry does not follow the one before it".to_string())
+    } else if entry.hash != hash_entry(entry)? {
+        Some("The entry was altered".to_string())
+    } else {
+        None
+    };
+
+    Ok(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use sha2::Digest;
+
+    fn entry(previous: &ChainHead) -> AuditEntry {
+        let mut entry = AuditEntry {
+            id: String::new(),
+            sequence: previous.sequence + 1,
+            timestamp: 0,
+            action: AuditAction::Login,
+            actor: Actor {
+                username: "alice".to_string(),
+                device_id: "test-device".to_string(),
+                ip: None,
+            },
+            target: Some("user:alice".to_string()),
+            detail: None,
+            previous_hash: previous.hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = hash_entry(&entry).unwrap();
+
+        entry
+    }
+
+    fn genesis() -> ChainHead {
+        ChainHead {
+            sequence: 0,
+            hash: GENESIS_HASH.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_chained_entries() {
+        let first = entry(&genesis());
+        let head = ChainHead {
+            sequence: first.sequence,
+            hash: first.hash.clone(),
+        };
+
+        assert_eq!(check(&genesis(), &first).unwrap(), None);
+        assert_eq!(check(&head, &entry(&head)).unwrap(), None);
+    }
+
+    #[test]
+    fn detects_altered_entries() {
+        let mut altered = entry(&genesis());
+        altered.detail = Some("Nothing happened".to_string());
+
+        let reason = check(&genesis(), &altered).unwrap();
+        assert_eq!(reason.as_deref(), Some("The entry was altered"));
+    }
+
+    #[test]
+    fn detects_entries_rehashed_without_the_secret() {
+        let mut forged = entry(&genesis());
+        forged.detail = Some("Nothing happened".to_string());
+        let mut unsealed = forged.clone();
+        unsealed.hash = String::new();
+        forged.hash = hex::encode(Sha256::digest(serialize(&unsealed).unwrap()));
+
+        let reason = check(&genesis(), &forged).unwrap();
+        assert_eq!(reason.as_deref(), Some("The entry was altered"));
+    }
+
+    #[test]
+    fn detects_missing_and_reordered_entries() {
+        let first = entry(&genesis());
+        let head = ChainHead {
+            sequence: first.sequence,
+            hash: first.hash.clone(),
+        };
+        let second = entry(&head);
+
+        let reason = check(&genesis(), &second).unwrap();
+        assert_eq!(reason.as_deref(), Some("Expected entry 1, found 2"));
+
+        let mut relinked = entry(&genesis());
+        relinked.previous_hash = second.hash;
+        relinked.hash = hash_entry(&relinked).unwrap();
+        let reason = check(&genesis(), &relinked).unwrap();
+        assert_eq!(
+            reason.as_deref(),
+            Some("The entry does not follow the one before it")
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn verifies_the_recorded_log() {
+        let redis = testing::redis();
+        let actor = Actor {
+            username: testing::username(),
+            device_id: "test-device".to_string(),
+            ip: None,
+        };
+
+        append(&redis, &actor, AuditAction::Login, None, None)
+            .await
+            .unwrap();
+        let entry = append(&redis, &actor, AuditAction::Logout, None, None)
+            .await
+            .unwrap();
+
+        let report = verify(&redis).await.unwrap();
+        assert!(report.valid, "{:?}", report.reason);
+        assert!(report.checked >= entry.sequence);
+
+        redis
+            .async_del(RedisKey::UserAuditLog(actor.username))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn limits_unknown_logins_per_address() {
+        let redis = testing::redis();
+        let actor = Actor {
+            username: String::new(),
+            device_id: "test-device".to_string(),
+            ip: Some(testing::username()),
+        };
+
+        for _ in 0..UNKNOWN_LOGIN_AUDIT_LIMIT + 5 {
+            record_unknown_login(&redis, &actor, "nobody").await;
+        }
+
+        let key = RedisKey::UnknownLogins(actor.ip.clone().unwrap());
+        let count: u32 = redis
+            .execute(redis::cmd("GET").arg(key.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(count, UNKNOWN_LOGIN_AUDIT_LIMIT + 5);
+
+        let filter = AuditFilter::default();
+        let page = query(&redis, None, None, &filter, usize::MAX)
+            .await
+            .unwrap();
+        let recorded = page
+            .entries
+            .iter()
+            .filter(|entry| entry.actor.ip == actor.ip)
+            .count();
+        assert_eq!(recorded, UNKNOWN_LOGIN_AUDIT_LIMIT as usize);
+
+        redis.async_del(key).await.unwrap();
+    }
+}
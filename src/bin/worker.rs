@@ -0,0 +1,28 @@
+use doc_storage::constants::JOB_WORKERS;
+use doc_storage::jobs::models::Queue;
+use doc_storage::jobs::worker::{self, WorkerContext, WorkerPool};
+use doc_storage::redis::client::RedisClient;
+use doc_storage::storage::layout;
+use std::env;
+use std::sync::Arc;
+
+/// Runs job workers outside the server. The search index can only be opened by one process,
+/// so standalone workers only serve the default and webhook queues unless told otherwise.
+#[tokio::main]
+async fn main() {
+    env::set_var("RUST_LOG", "debug");
+    env_logger::init();
+
+    let redis =
+        Arc::new(RedisClient::from_env().expect("Failed to connect to Redis. Is it running?"));
+    let blobs = layout::from_env().expect("Failed to open the blob store");
+
+    let context = WorkerContext {
+        redis,
+        blobs,
+        search: None,
+    };
+    let queues = worker::queues_from_env(&[Queue::Default, Queue::Webhook]);
+
+    WorkerPool::new(context, queues, *JOB_WORKERS).run().await;
+}
@@ -0,0 +1,3 @@
+pub mod protocol;
+pub mod room;
+pub mod session;
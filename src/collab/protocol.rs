@@ -0,0 +1,111 @@
+//! The y-websocket wire format. Every message starts with its type, sync messages follow with
+//! their step and a length-prefixed payload. Integers are lib0 variable-length integers.
+
+const MESSAGE_SYNC: u64 = 0;
+const MESSAGE_AWARENESS: u64 = 1;
+
+const SYNC_STEP1: u64 = 0;
+const SYNC_STEP2: u64 = 1;
+const SYNC_UPDATE: u64 = 2;
+
+pub enum Message {
+    /// A state vector, answered with the updates the sender is missing.
+    SyncStep1(Vec<u8>),
+    /// The updates the receiver was missing.
+    SyncStep2(Vec<u8>),
+    Update(Vec<u8>),
+    /// Cursors and presence, relayed to the other peers as they are.
+    Awareness,
+    /// Messages the server has no use for, e.g. auth and awareness queries.
+    Other,
+}
+
+pub fn decode(data: &[u8]) -> Result<Message, String> {
+    let mut reader = Reader { data, position: 0 };
+
+    match reader.read_number()? {
+        MESSAGE_SYNC => {
+            let step = reader.read_number()?;
+            let payload = reader.read_buffer()?.to_vec();
+
+            match step {
+                SYNC_STEP1 => Ok(Message::SyncStep1(payload)),
+                SYNC_STEP2 => Ok(Message::SyncStep2(payload)),
+                SYNC_UPDATE => Ok(Message::Update(payload)),
+                step => Err(format!("Unknown sync step {}", step)),
+            }
+        }
+        MESSAGE_AWARENESS => Ok(Message::Awareness),
+        _ => Ok(Message::Other),
+    }
+}
+
+pub fn sync_step1(state_vector: &[u8]) -> Vec<u8> {
+    sync_message(SYNC_STEP1, state_vector)
+}
+
+pub fn sync_step2(update: &[u8]) -> Vec<u8> {
+    sync_message(SYNC_STEP2, update)
+}
+
+pub fn update(update: &[u8]) -> Vec<u8> {
+    sync_message(SYNC_UPDATE, update)
+}
+
+fn sync_message(step: u64, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(payload.len() + 12);
+    write_number(&mut message, MESSAGE_SYNC);
+    write_number(&mut message, step);
+    write_number(&mut message, payload.len() as u64);
+    message.extend_from_slice(payload);
+
+    message
+}
+
+fn write_number(buffer: &mut Vec<u8>, mut number: u64) {
+    while number >= 0x80 {
+        buffer.push((number as u8 & 0x7f) | 0x80);
+        number >>= 7;
+    }
+    buffer.push(number as u8);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_number(&mut self) -> Result<u64, String> {
+        let mut number = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| "Unexpected end of message".to_string())?;
+            self.position += 1;
+
+            number |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(number);
+            }
+        }
+
+        Err("Integer overflow".to_string())
+    }
+
+    fn read_buffer(&mut self) -> Result<&'a [u8], String> {
+        let length = self.read_number()? as usize;
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| "Unexpected end of message".to_string())?;
+
+        let buffer = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,545 @@
+use crate::api::utils::errors::ServiceError;
+use crate::collab::protocol::{self, Message};
+use crate::constants::{COLLAB_SAVE_INTERVAL, COLLAB_STATE_TTL, MAX_COLLAB_DOCUMENT_SIZE};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
+use crate::storage::models::{File, FileMetadata};
+use crate::storage::{content, files, locks, previews};
+use crate::sync::conflict;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+/// The device collaborative edits are saved as.
+pub const COLLAB_DEVICE: &str = "collab";
+
+/// Name of the shared text clients edit, `doc.getText("content")` in Yjs.
+pub const TEXT_NAME: &str = "content";
+
+/// Origin of frames received from other server instances.
+const REMOTE: usize = usize::MAX;
+
+lazy_static::lazy_static!(
+    /// Tags what this instance publishes, so it can skip its own messages.
+    static ref INSTANCE_ID: [u8; 16] = *uuid::Uuid::new_v4().as_bytes();
+);
+
+/// A message for the peers of a room, tagged with the peer it came from.
+#[derive(Clone)]
+pub struct Frame {
+    pub origin: usize,
+    pub data: Arc<Vec<u8>>,
+}
+
+/// The CRDT state shared by every instance, along with the hash of the text it was saved at.
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    hash: String,
+    update: String,
+}
+
+/// The open documents of this instance.
+pub struct Rooms {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+    rooms: tokio::sync::Mutex<HashMap<String, Arc<Room>>>,
+    next_peer: AtomicUsize,
+}
+
+impl Rooms {
+    pub fn new(redis: Arc<RedisClient>, blobs: Arc<dyn BlobStore>) -> Self {
+        Self {
+            redis,
+            blobs,
+            rooms: tokio::sync::Mutex::new(HashMap::new()),
+            next_peer: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds a peer to the room of a file, opening the room if it is the first one.
+    pub async fn join(&self, file: &FileMetadata) -> Result<(Arc<Room>, usize), ServiceError> {
+        let mut rooms = self.rooms.lock().await;
+
+        let room = match rooms.get(&file.id) {
+            Some(room) => room.clone(),
+            None => {
+                let room = Arc::new(Room::open(self, file).await?);
+                tokio::spawn(room.clone().run());
+                rooms.insert(file.id.clone(), room.clone());
+                room
+            }
+        };
+        room.peers.fetch_add(1, Ordering::SeqCst);
+
+        Ok((room, self.next_peer.fetch_add(1, Ordering::SeqCst)))
+    }
+
+    /// Removes a peer. The last one out closes the room, which saves it one final time.
+    pub async fn leave(&self, room: &Arc<Room>) {
+        let mut rooms = self.rooms.lock().await;
+
+        if room.peers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            rooms.remove(&room.file_id);
+            room.closed.notify_one();
+        }
+    }
+}
+
+/// A document being edited on this instance. Peers exchange updates through the room, other
+/// instances through Redis pub/sub, and the text is saved as a new version every
+/// `COLLAB_SAVE_INTERVAL` seconds while it changes.
+pub struct Room {
+    file_id: String,
+    doc: Mutex<Doc>,
+    /// The hash of the version the text was loaded from or last saved as.
+    base: Mutex<String>,
+    dirty: AtomicBool,
+    peers: AtomicUsize,
+    frames: broadcast::Sender<Frame>,
+    closed: Notify,
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+}
+
+impl Room {
+    async fn open(rooms: &Rooms, file: &FileMetadata) -> Result<Self, ServiceError> {
+        let doc = load(&rooms.redis, rooms.blobs.as_ref(), file).await?;
+        let (frames, _) = broadcast::channel(256);
+
+        Ok(Self {
+            file_id: file.id.clone(),
+            doc: Mutex::new(doc),
+            base: Mutex::new(file.hash.clone()),
+            dirty: AtomicBool::new(false),
+            peers: AtomicUsize::new(0),
+            frames,
+            closed: Notify::new(),
+            redis: rooms.redis.clone(),
+            blobs: rooms.blobs.clone(),
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Frame> {
+        self.frames.subscribe()
+    }
+
+    /// The first message of the server, asking the peer for the updates it is missing.
+    pub fn greeting(&self) -> Vec<u8> {
+        let doc = self.doc.lock().unwrap();
+        let state_vector = doc.transact().state_vector().encode_v1();
+
+        protocol::sync_step1(&state_vector)
+    }
+
+    /// The whole document as a single update, for peers that fell behind.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let doc = self.doc.lock().unwrap();
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+
+        protocol::update(&update)
+    }
+
+    /// Handles a message of a peer, returning the reply meant for that peer only.
+    pub async fn receive(&self, peer: usize, data: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        match protocol::decode(&data)? {
+            Message::SyncStep1(state_vector) => {
+                let state_vector = StateVector::decode_v1(&state_vector)
+                    .map_err(|_| "Invalid state vector".to_string())?;
+                let doc = self.doc.lock().unwrap();
+                let update = doc.transact().encode_diff_v1(&state_vector);
+
+                Ok(Some(protocol::sync_step2(&update)))
+            }
+            Message::SyncStep2(update) | Message::Update(update) => {
+                self.apply(&update)?;
+                self.dirty.store(true, Ordering::SeqCst);
+                self.relay(peer, protocol::update(&update)).await;
+
+                Ok(None)
+            }
+            Message::Awareness => {
+                self.relay(peer, data).await;
+                Ok(None)
+            }
+            Message::Other => Ok(None),
+        }
+    }
+
+    /// Refuses updates that could take the text past `MAX_COLLAB_DOCUMENT_SIZE`. An update
+    /// carries all the text it inserts, so its size bounds the growth and nothing has to be
+    /// rolled back once applied.
+    fn apply(&self, update: &[u8]) -> Result<(), String> {
+        let decoded = Update::decode_v1(update).map_err(|_| "Invalid update".to_string())?;
+        let doc = self.doc.lock().unwrap();
+
+        let text = doc.get_or_insert_text(TEXT_NAME);
+        let length = text.len(&doc.transact()) as usize;
+        if length + update.len() > MAX_COLLAB_DOCUMENT_SIZE {
+            return Err(format!(
+                "The document would exceed {} bytes",
+                MAX_COLLAB_DOCUMENT_SIZE
+            ));
+        }
+
+        doc.transact_mut().apply_update(decoded);
+
+        Ok(())
+    }
+
+    /// Passes a frame on to the other local peers and to the other instances.
+    async fn relay(&self, origin: usize, data: Vec<u8>) {
+        let mut message = INSTANCE_ID.to_vec();
+        message.extend_from_slice(&data);
+
+        // Nobody listening is fine, the frame has nowhere to go then.
+        let _ = self.frames.send(Frame {
+            origin,
+            data: Arc::new(data),
+        });
+
+        let channel = RedisKey::CollabUpdates(self.file_id.clone());
+        if let Err(error) = self.redis.async_publish(channel, &message).await {
+            log::error!(
+                "Failed to publish an update of file {}: {}",
+                self.file_id,
+                error
+            );
+        }
+    }
+
+    /// Applies what other instances publish, until the last peer leaves.
+    async fn run(self: Arc<Self>) {
+        let channel = RedisKey::CollabUpdates(self.file_id.clone());
+        let mut pubsub = match self.redis.async_subscribe(channel).await {
+            Ok(pubsub) => Some(pubsub),
+            Err(error) => {
+                log::error!(
+                    "Collaboration on file {} stays local: {}",
+                    self.file_id,
+                    error
+                );
+                None
+            }
+        };
+        let mut messages: Pin<Box<dyn Stream<Item = redis::Msg> + Send + '_>> = match &mut pubsub {
+            Some(pubsub) => Box::pin(pubsub.on_message()),
+            None => Box::pin(futures::stream::pending()),
+        };
+
+        let mut interval = tokio::time::interval(Duration::from_secs(COLLAB_SAVE_INTERVAL));
+        loop {
+            tokio::select! {
+                Some(message) = messages.next() => self.receive_remote(message.get_payload_bytes()),
+                _ = interval.tick() => {
+                    if let Err(error) = self.save().await {
+                        log::error!("Failed to save file {}: {}", self.file_id, error);
+                    }
+                }
+                _ = self.closed.notified() => break,
+            }
+        }
+        drop(messages);
+
+        if let Err(error) = self.save().await {
+            log::error!("Failed to save file {}: {}", self.file_id, error);
+        }
+    }
+
+    fn receive_remote(&self, message: &[u8]) {
+        if message.len() < INSTANCE_ID.len() || message[..INSTANCE_ID.len()] == *INSTANCE_ID {
+            return;
+        }
+
+        let data = message[INSTANCE_ID.len()..].to_vec();
+        let applied = match protocol::decode(&data) {
+            Ok(Message::Update(update)) => self.apply(&update),
+            Ok(Message::Awareness) => Ok(()),
+            Ok(_) => return,
+            Err(error) => Err(error),
+        };
+
+        match applied {
+            Ok(()) => {
+                let _ = self.frames.send(Frame {
+                    origin: REMOTE,
+                    data: Arc::new(data),
+                });
+            }
+            Err(error) => log::warn!("Dropped an update of file {}: {}", self.file_id, error),
+        }
+    }
+
+    /// Stores the text as a new version when it changed, along with the CRDT state. Every
+    /// instance editing the file saves it, the content hash keeps them from adding the same
+    /// version twice.
+    async fn save(&self) -> Result<(), ServiceError> {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let (text, state) = {
+            let doc = self.doc.lock().unwrap();
+            let text = doc.get_or_insert_text(TEXT_NAME);
+            let txn = doc.transact();
+
+            (
+                text.get_string(&txn),
+                txn.encode_state_as_update_v1(&StateVector::default()),
+            )
+        };
+
+        let result = self.persist(text.into_bytes(), state).await;
+        if result.is_err() {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+
+        result
+    }
+
+    async fn persist(&self, data: Vec<u8>, state: Vec<u8>) -> Result<(), ServiceError> {
+        let hash = content::hash_data(&data);
+        let mut file = files::get_file(&self.redis, &self.file_id).await?;
+
+        let base = self.base.lock().unwrap().clone();
+        if file.hash != base && !self.saved_elsewhere(&file).await? {
+            return self.reload(file, data).await;
+        }
+
+        if file.hash != hash {
+            locks::check_writable(&self.redis, &file.id, &file.owner, COLLAB_DEVICE).await?;
+
+            let base = file.hash.clone();
+            let upload = File {
+                name: file.name.clone(),
+                size: data.len(),
+                data: data.clone(),
+                declared_type: Some(file.content_type.clone()),
+            };
+            let added = files::add_file_version(
+                &self.redis,
+                self.blobs.as_ref(),
+                &mut file,
+                COLLAB_DEVICE,
+                upload,
+                Some(&base),
+            )
+            .await;
+
+            match added {
+                Ok(()) => {}
+                Err(ServiceError::PreconditionFailed(_)) => {
+                    let file = files::get_file(&self.redis, &self.file_id).await?;
+                    return self.reload(file, data).await;
+                }
+                Err(error) => return Err(error),
+            }
+
+            indexer::enqueue_indexing(&self.redis, &file).await?;
+        }
+
+        *self.base.lock().unwrap() = hash.clone();
+
+        let state = SavedState {
+            hash,
+            update: base64::encode(state),
+        };
+        save_state(&self.redis, &self.file_id, &state).await
+    }
+
+    /// Whether the current version was saved from this document by another instance, which
+    /// shares the CRDT state with this one.
+    async fn saved_elsewhere(&self, file: &FileMetadata) -> Result<bool, ServiceError> {
+        let saved: Option<String> = self
+            .redis
+            .execute(redis::cmd("GET").arg(RedisKey::CollabState(file.id.clone()).to_string()))
+            .await?;
+
+        Ok(saved
+            .and_then(|saved| serde_json::from_str::<SavedState>(&saved).ok())
+            .map(|saved| saved.hash == file.hash)
+            .unwrap_or(false))
+    }
+
+    /// The file changed outside the room. What the peers wrote is kept as a conflicted copy,
+    /// and the text is replaced with the new version through a regular edit, so every peer
+    /// gets it. Only one instance does so for a given version, the others get the edit.
+    async fn reload(&self, file: FileMetadata, data: Vec<u8>) -> Result<(), ServiceError> {
+        let claim = RedisKey::CollabReload(file.id.clone(), file.hash.clone());
+        if self
+            .redis
+            .async_set_nx_ex(claim, "1", COLLAB_STATE_TTL)
+            .await?
+        {
+            let upload = File {
+                name: file.name.clone(),
+                size: data.len(),
+                data,
+                declared_type: Some(file.content_type.clone()),
+            };
+            let copy = conflict::create_conflicted_copy(
+                &self.redis,
+                self.blobs.as_ref(),
+                &file,
+                COLLAB_DEVICE,
+                upload,
+            )
+            .await?;
+            previews::enqueue_generation(&self.redis, &copy.owner, &copy.hash, &copy.content_type)
+                .await?;
+            indexer::enqueue_indexing(&self.redis, &copy).await?;
+            log::info!(
+                "File {} changed while being edited, kept the edits as {}",
+                file.id,
+                copy.id
+            );
+
+            let content = content::read_content(self.blobs.as_ref(), &file.hash)
+                .await
+                .map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to read the file".to_string(),
+                        Some(error),
+                    )
+                })?;
+            let (update, state) = self.replace_text(&String::from_utf8_lossy(&content));
+            self.relay(REMOTE, protocol::update(&update)).await;
+
+            let state = SavedState {
+                hash: file.hash.clone(),
+                update: base64::encode(state),
+            };
+            save_state(&self.redis, &self.file_id, &state).await?;
+        }
+
+        *self.base.lock().unwrap() = file.hash;
+
+        Ok(())
+    }
+
+    /// Replaces the whole text, returning the update to send to the peers along with the new
+    /// state.
+    fn replace_text(&self, content: &str) -> (Vec<u8>, Vec<u8>) {
+        let doc = self.doc.lock().unwrap();
+        let text = doc.get_or_insert_text(TEXT_NAME);
+        let before = doc.transact().state_vector();
+        {
+            let mut txn = doc.transact_mut();
+            let length = text.len(&txn);
+            text.remove_range(&mut txn, 0, length);
+            text.insert(&mut txn, 0, content);
+        }
+
+        let txn = doc.transact();
+        (
+            txn.encode_diff_v1(&before),
+            txn.encode_state_as_update_v1(&StateVector::default()),
+        )
+    }
+}
+
+/// Restores the shared CRDT state, or builds it from the file when the file changed since
+/// it was saved. Building it from scratch on every instance would duplicate the text once
+/// the states merge, so only the first instance gets to store it.
+async fn load(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    file: &FileMetadata,
+) -> Result<Doc, ServiceError> {
+    loop {
+        let key = RedisKey::CollabState(file.id.clone());
+        let current: Option<String> = redis
+            .execute(redis::cmd("GET").arg(key.to_string()))
+            .await?;
+
+        let saved = current
+            .as_deref()
+            .and_then(|value| serde_json::from_str::<SavedState>(value).ok())
+            .filter(|saved| saved.hash == file.hash);
+        if let Some(doc) = saved.and_then(|saved| restore(&saved.update)) {
+            return Ok(doc);
+        }
+
+        let data = content::read_content(blobs, &file.hash)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to read the file".to_string(),
+                    Some(error),
+                )
+            })?;
+        let doc = Doc::new();
+        {
+            let text = doc.get_or_insert_text(TEXT_NAME);
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, &String::from_utf8_lossy(&data));
+        }
+
+        let state = SavedState {
+            hash: file.hash.clone(),
+            update: base64::encode(
+                doc.transact()
+                    .encode_state_as_update_v1(&StateVector::default()),
+            ),
+        };
+        let mut pipeline = redis::pipe();
+        pipeline
+            .cmd("SET")
+            .arg(key.to_string())
+            .arg(serialize(&state)?)
+            .arg("EX")
+            .arg(COLLAB_STATE_TTL);
+
+        if redis
+            .async_compare_and_exec(&[(key, current)], &mut pipeline)
+            .await?
+        {
+            return Ok(doc);
+        }
+    }
+}
+
+fn restore(update: &str) -> Option<Doc> {
+    let update = Update::decode_v1(&base64::decode(update).ok()?).ok()?;
+
+    let doc = Doc::new();
+    doc.get_or_insert_text(TEXT_NAME);
+    doc.transact_mut().apply_update(update);
+
+    Some(doc)
+}
+
+async fn save_state(
+    redis: &RedisClient,
+    file_id: &str,
+    state: &SavedState,
+) -> Result<(), ServiceError> {
+    redis
+        .execute::<()>(
+            redis::cmd("SET")
+                .arg(RedisKey::CollabState(file_id.to_string()).to_string())
+                .arg(serialize(state)?)
+                .arg("EX")
+                .arg(COLLAB_STATE_TTL),
+        )
+        .await
+}
+
+fn serialize(state: &SavedState) -> Result<String, ServiceError> {
+    serde_json::to_string(state).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })
+}
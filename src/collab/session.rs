@@ -0,0 +1,50 @@
+use crate::collab::room::{Room, Rooms};
+use actix_ws::{Message, MessageStream, Session};
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Syncs one WebSocket client with its room until either side hangs up.
+pub async fn run(
+    rooms: Arc<Rooms>,
+    room: Arc<Room>,
+    peer: usize,
+    mut session: Session,
+    mut stream: MessageStream,
+) {
+    let mut frames = room.subscribe();
+
+    if session.binary(room.greeting()).await.is_ok() {
+        loop {
+            let sent = tokio::select! {
+                message = stream.next() => match message {
+                    Some(Ok(Message::Binary(data))) => match room.receive(peer, data.to_vec()).await {
+                        Ok(Some(reply)) => session.binary(reply).await,
+                        Ok(None) => Ok(()),
+                        Err(error) => {
+                            log::warn!("Closing a collaboration session: {}", error);
+                            break;
+                        }
+                    },
+                    Some(Ok(Message::Ping(data))) => session.pong(&data).await,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => Ok(()),
+                },
+                frame = frames.recv() => match frame {
+                    Ok(frame) if frame.origin != peer => session.binary(frame.data.to_vec()).await,
+                    Ok(_) => Ok(()),
+                    // Frames were dropped, the whole document brings the peer up to date.
+                    Err(RecvError::Lagged(_)) => session.binary(room.snapshot()).await,
+                    Err(RecvError::Closed) => break,
+                },
+            };
+
+            if sent.is_err() {
+                break;
+            }
+        }
+    }
+
+    let _ = session.close(None).await;
+    rooms.leave(&room).await;
+}
@@ -1,7 +1,14 @@
+use crate::scan::scanner::{self, OversizePolicy, ScanPolicy, Scanner};
+use crate::storage::quota::QuotaPolicy;
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::sync::Arc;
 
 pub const BASE_ROUTE: &str = "/api/v1";
 pub const IGNORED_AUTH_ROUTES: [&str; 2] = ["auth/register", "auth/login"];
+pub const DAV_ROUTE: &str = "/dav";
+pub const S3_ROUTE: &str = "/s3";
+/// Protocol facades that authenticate requests themselves, the way their clients expect.
+pub const SELF_AUTHENTICATED_ROUTES: [&str; 2] = [DAV_ROUTE, S3_ROUTE];
 
 lazy_static::lazy_static!(
     pub static ref HEADER: Header = Header::new(Algorithm::RS256);
@@ -14,7 +21,124 @@ lazy_static::lazy_static!(
         .expect("Failed to load private key. Is it present?");
     pub static ref DECODING_KEY: DecodingKey = DecodingKey::from_rsa_pem(include_bytes!("../public.pem"))
         .expect("Failed to load public key. Is it present?");
+    /// Signs pre-signed URLs. Every instance has to share it to accept the same URLs, and
+    /// pre-signing is disabled without one.
+    pub static ref PRESIGN_SECRET: Option<Vec<u8>> = std::env::var("DOC_STORAGE_PRESIGN_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(String::into_bytes)
+        .or_else(|| cfg!(test).then(|| b"test-presign-secret".to_vec()));
+    /// Keys the hashes chaining the audit log. It has to be kept out of Redis, or whoever can
+    /// write there could rebuild the chain, so the server refuses to start without it.
+    pub static ref AUDIT_SECRET: Vec<u8> = match std::env::var("DOC_STORAGE_AUDIT_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ if cfg!(test) => b"test-audit-secret".to_vec(),
+        _ => panic!("DOC_STORAGE_AUDIT_SECRET must be set to the key of the audit log"),
+    };
 );
 
 pub const ISSUER: &str = "doc-storage-authenticator";
 pub const EXPIRATION_TIME: usize = 60 * 60 * 6; // 6 hours
+
+pub const PENDING_BLOB_TTL: u32 = 60 * 60; // 1 hour
+pub const GC_LOCK_TTL: u32 = 5 * 60; // 5 minutes, renewed while the collection runs
+pub const GC_CLAIM_TTL: u32 = 60 * 60; // 1 hour
+pub const GC_RETRY_AFTER: u64 = 30; // 30 seconds
+pub const PREVIEW_PENDING_TTL: u32 = 60 * 60; // 1 hour
+pub const JOB_TTL: u32 = 60 * 60 * 24; // 1 day
+pub const JOB_LEASE_TTL: u32 = 60; // 1 minute
+pub const JOB_POLL_INTERVAL: u64 = 1; // 1 second
+pub const JOB_MAINTENANCE_INTERVAL: u64 = 5; // 5 seconds
+pub const SCHEDULER_TICK: u64 = 5; // 5 seconds
+pub const SCHEDULER_LEADER_TTL: u32 = 30; // 30 seconds
+pub const SCHEDULED_TASK_LOCK_TTL: u32 = 60; // 1 minute
+pub const JOB_MAX_ATTEMPTS: u32 = 5;
+pub const JOB_RETRY_DELAY: i64 = 10; // 10 seconds, doubled on every attempt
+pub const MAX_JOB_RETRY_DELAY: i64 = 60 * 60; // 1 hour
+pub const LOCK_TTL: u32 = 60 * 15; // 15 minutes
+pub const MAX_LOCK_TTL: u32 = 60 * 60 * 24; // 1 day
+pub const MULTIPART_UPLOAD_TTL: u32 = 60 * 60 * 24 * 7; // 7 days
+pub const MAX_REQUEST_SKEW: i64 = 60 * 15; // 15 minutes
+pub const PRESIGNED_URL_TTL: u32 = 60 * 15; // 15 minutes
+pub const MAX_PRESIGNED_URL_TTL: u32 = 60 * 60 * 24 * 7; // 7 days
+pub const COLLAB_SAVE_INTERVAL: u64 = 30; // 30 seconds
+pub const COLLAB_STATE_TTL: u32 = 60 * 60 * 24 * 7; // 7 days
+pub const WEBHOOK_DELIVERY_TTL: u32 = 60 * 60 * 24 * 7; // 7 days
+pub const WEBHOOK_TIMEOUT: u64 = 10; // 10 seconds
+
+pub const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+pub const MAX_COMPRESSION_RATIO: u64 = 100;
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+pub const BACKGROUND_COPY_FILES: usize = 100;
+pub const JOURNAL_LENGTH: usize = 100_000;
+pub const MAX_ACCESS_KEYS: usize = 10;
+pub const MAX_WEBHOOKS: usize = 10;
+pub const WEBHOOK_DELIVERY_LOG_LENGTH: usize = 100;
+pub const AUDIT_BATCH_SIZE: usize = 1000;
+pub const MAX_AUDIT_ENTRIES: usize = 1000;
+pub const MAX_AUDIT_ATTEMPTS: usize = 100;
+pub const UNKNOWN_LOGIN_AUDIT_LIMIT: u32 = 10;
+pub const UNKNOWN_LOGIN_AUDIT_WINDOW: u32 = 60; // 1 minute
+pub const ACTIVITY_BATCH_SIZE: usize = 1000;
+pub const MAX_ACTIVITY_ENTRIES: usize = 1000;
+pub const RECENT_FILES_LENGTH: usize = 100;
+pub const MAX_MULTIPART_PARTS: u32 = 10_000;
+pub const MAX_COLLAB_DOCUMENT_SIZE: usize = 10 * 1024 * 1024; // 10 MiB
+pub const MAX_DIFF_SIZE: usize = 2 * 1024 * 1024; // 2 MiB
+pub const MAX_DAV_BODY_SIZE: usize = 1024 * 1024; // 1 MiB
+pub const MAX_S3_BODY_SIZE: usize = 1024 * 1024; // 1 MiB
+pub const MULTIPART_OVERHEAD: u64 = 64 * 1024; // 64 KiB
+pub const DIFF_TIMEOUT: u64 = 5; // 5 seconds
+pub const SCAN_TIMEOUT: u64 = 60; // 1 minute
+pub const MAX_SCAN_ATTEMPTS: u32 = 5;
+pub const MAX_SAVE_ATTEMPTS: usize = 10;
+
+lazy_static::lazy_static!(
+    pub static ref GC_GRACE_PERIOD: i64 = std::env::var("DOC_STORAGE_GC_GRACE_PERIOD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60 * 60 * 24);
+    pub static ref SCRUB_RATE: u64 = std::env::var("DOC_STORAGE_SCRUB_RATE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(10 * 1024 * 1024); // 10 MiB per second
+    pub static ref ADMINS: Vec<String> = std::env::var("DOC_STORAGE_ADMINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|username| username.trim().to_string())
+        .filter(|username| !username.is_empty())
+        .collect();
+    pub static ref DEFAULT_QUOTA: Option<u64> = std::env::var("DOC_STORAGE_DEFAULT_QUOTA")
+        .ok()
+        .and_then(|value| value.parse().ok());
+    pub static ref QUOTA_POLICY: QuotaPolicy = QuotaPolicy::from_name(
+        &std::env::var("DOC_STORAGE_QUOTA_POLICY").unwrap_or_default(),
+    );
+    pub static ref SCANNER: Option<Arc<dyn Scanner>> = scanner::from_env();
+    pub static ref SCAN_POLICY: ScanPolicy = ScanPolicy::from_name(
+        &std::env::var("DOC_STORAGE_SCAN_POLICY").unwrap_or_default(),
+    );
+    pub static ref OVERSIZE_POLICY: OversizePolicy = OversizePolicy::from_name(
+        &std::env::var("DOC_STORAGE_SCAN_OVERSIZE_POLICY").unwrap_or_default(),
+    );
+    pub static ref JOB_WORKERS: usize = std::env::var("DOC_STORAGE_JOB_WORKERS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(4);
+    pub static ref MAX_EXTRACTED_SIZE: u64 = std::env::var("DOC_STORAGE_MAX_EXTRACTED_SIZE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(4 * 1024 * 1024 * 1024); // 4 GiB
+    pub static ref MAX_DAV_UPLOAD_SIZE: usize = std::env::var("DOC_STORAGE_MAX_DAV_UPLOAD_SIZE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1024 * 1024 * 1024); // 1 GiB
+    pub static ref MAX_S3_UPLOAD_SIZE: usize = std::env::var("DOC_STORAGE_MAX_S3_UPLOAD_SIZE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1024 * 1024 * 1024); // 1 GiB
+    pub static ref BACKGROUND_EXTRACTION_SIZE: usize = std::env::var("DOC_STORAGE_BACKGROUND_EXTRACTION_SIZE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(16 * 1024 * 1024); // 16 MiB
+);
@@ -0,0 +1,75 @@
+use crate::api::utils::errors::ServiceError;
+use crate::jwt::models::Claims;
+use crate::jwt::token;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::user::models::User;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// The device WebDAV clients act as when they log in with a password.
+pub const DAV_DEVICE: &str = "webdav";
+
+/// Authenticates a WebDAV request. File managers only speak Basic auth, so the password may be
+/// the account password or an API token; API tokens are accepted as Bearer tokens as well.
+pub async fn authenticate(
+    request: &HttpRequest,
+    redis: &RedisClient,
+) -> Result<Claims, ServiceError> {
+    let header = request
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .ok_or(ServiceError::MissingToken)?;
+
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        return token::decode_token(token).map_err(|_| ServiceError::InvalidToken);
+    }
+
+    let credentials = header
+        .strip_prefix("Basic ")
+        .and_then(|encoded| base64::decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or(ServiceError::MissingToken)?;
+    let (username, password) = credentials
+        .split_once(':')
+        .ok_or(ServiceError::InvalidToken)?;
+
+    if let Ok(claims) = token::decode_token(password) {
+        if claims.username != username {
+            return Err(ServiceError::InvalidToken);
+        }
+
+        return Ok(claims);
+    }
+
+    if !redis
+        .async_exists(RedisKey::Account(username.to_string()))
+        .await?
+    {
+        return Err(ServiceError::InvalidToken);
+    }
+
+    let user = redis
+        .d_async_get::<User>(RedisKey::Account(username.to_string()))
+        .await?;
+    let valid = user
+        .verify_password(password.to_string())
+        .map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to verify the password".to_string(),
+                Some(error.into()),
+            )
+        })?;
+
+    if !valid {
+        return Err(ServiceError::InvalidToken);
+    }
+
+    Ok(Claims::new(username.to_string(), DAV_DEVICE.to_string()))
+}
+
+/// Asks the client for credentials, which file managers only do on a Basic challenge.
+pub fn challenge() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header(("WWW-Authenticate", "Basic realm=\"doc-storage\""))
+        .finish()
+}
@@ -0,0 +1,732 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::headers;
+use crate::api::utils::payloads::MetadataPayload;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::constants::{DAV_ROUTE, MAX_DAV_BODY_SIZE, MAX_DAV_UPLOAD_SIZE, MAX_LOCK_TTL};
+use crate::dav::xml::{self, Properties};
+use crate::dav::{auth, paths};
+use crate::jwt::models::Claims;
+use crate::redis::client::RedisClient;
+use crate::scan::status;
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
+use crate::storage::copy::{Copier, FolderCopy};
+use crate::storage::locks::{self, FileLock};
+use crate::storage::models::{File, FileMetadata, Folder};
+use crate::storage::{content, files, folders, metadata, previews};
+use crate::sync::journal::{self, Change, ChangeKind};
+use crate::sync::recent;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use futures::stream::TryStreamExt;
+use std::sync::Arc;
+
+const ALLOWED_METHODS: &str =
+    "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE, MKCOL, MOVE, COPY, LOCK, UNLOCK";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// WebDAV (RFC 4918) access to the files of the authenticated user, for clients such as file
+/// managers, `cadaver` or `rclone`. Every method goes through a single handler since most of
+/// them are not HTTP methods actix knows about.
+pub fn register_endpoints() -> Scope {
+    Scope::new(DAV_ROUTE).default_service(web::to(handle_request))
+}
+
+enum Resource {
+    File(FileMetadata),
+    Folder(Folder),
+}
+
+pub async fn handle_request(
+    request: HttpRequest,
+    payload: web::Payload,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, ServiceError> {
+    let claims = match auth::authenticate(&request, &redis).await {
+        Ok(claims) => claims,
+        Err(error @ ServiceError::InternalServerError(..)) => return Err(error),
+        Err(_) => return Ok(auth::challenge()),
+    };
+
+    let dav = Dav {
+        redis: redis.get_ref().clone(),
+        blobs: blobs.get_ref().clone(),
+        actor: Actor::from_claims(&claims, &request),
+        claims,
+    };
+    let path = paths::from_request(request.path())?;
+
+    match request.method().as_str() {
+        "OPTIONS" => Ok(HttpResponse::Ok()
+            .insert_header(("DAV", "1, 2"))
+            .insert_header(("Allow", ALLOWED_METHODS))
+            .insert_header(("MS-Author-Via", "DAV"))
+            .finish()),
+        "PROPFIND" => dav.propfind(&request, &path).await,
+        "GET" | "HEAD" => dav.get(&request, &path).await,
+        "PUT" => dav.put(&request, &path, payload).await,
+        "DELETE" => dav.delete(&request, &path).await,
+        "MKCOL" => dav.mkcol(&path, payload).await,
+        "MOVE" => dav.transfer(&request, &path, false).await,
+        "COPY" => dav.transfer(&request, &path, true).await,
+        "LOCK" => dav.lock(&request, &path, payload).await,
+        "UNLOCK" => dav.unlock(&request, &path).await,
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+struct Dav {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+    claims: Claims,
+    actor: Actor,
+}
+
+impl Dav {
+    async fn propfind(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+    ) -> Result<HttpResponse, ServiceError> {
+        let resource = self.resolve(path).await?.ok_or_else(|| not_found(path))?;
+
+        let mut properties = match &resource {
+            Resource::File(file) => {
+                let lock = locks::get_lock(&self.redis, &file.id).await?;
+                vec![Properties::of_file(path, file, lock)]
+            }
+            Resource::Folder(folder) => vec![Properties::of_folder(folder)],
+        };
+
+        // An infinite depth is served as depth 1, walking whole trees on every request of a
+        // file manager would be too costly.
+        let depth = header(request, "Depth").unwrap_or("infinity");
+        if let (Resource::Folder(folder), false) = (&resource, depth == "0") {
+            properties.extend(self.children(folder).await?);
+        }
+
+        Ok(HttpResponse::build(StatusCode::MULTI_STATUS)
+            .content_type(XML_CONTENT_TYPE)
+            .body(xml::multistatus(&properties)))
+    }
+
+    async fn get(&self, request: &HttpRequest, path: &str) -> Result<HttpResponse, ServiceError> {
+        let file = match self.resolve(path).await? {
+            Some(Resource::File(file)) => file,
+            Some(Resource::Folder(_)) => return Ok(method_not_allowed()),
+            None => return Err(not_found(path)),
+        };
+
+        status::check_download(&self.redis, &file.hash).await?;
+        let data = content::read_content(self.blobs.as_ref(), &file.hash)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to read the file".to_string(),
+                    Some(error),
+                )
+            })?;
+        if request.method() == Method::GET {
+            self.audit(AuditAction::Download, &file).await;
+            recent::touch(&self.redis, &file.owner, &self.actor.device_id, &file.id).await;
+        }
+
+        Ok(HttpResponse::Ok()
+            .content_type(file.content_type.as_str())
+            .insert_header(("ETag", headers::etag(&file.hash)))
+            .insert_header(("Last-Modified", headers::http_date(file.updated_at)))
+            .body(data))
+    }
+
+    async fn put(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, ServiceError> {
+        let (folder, name) = paths::split(path).ok_or_else(|| {
+            ServiceError::Forbidden("The root folder cannot be written to".to_string())
+        })?;
+
+        if self.find_folder(path).await?.is_some() {
+            return Ok(method_not_allowed());
+        }
+        if self.find_folder(&folder).await?.is_none() {
+            return Err(missing_parent());
+        }
+
+        let data = read_body(payload, *MAX_DAV_UPLOAD_SIZE).await?;
+        let upload = File {
+            name,
+            size: data.len(),
+            data,
+            declared_type: header(request, "Content-Type").map(str::to_string),
+        };
+
+        let (status, file) = match self.find_file(path).await? {
+            Some(mut file) => {
+                self.check_lock(request, &file).await?;
+                files::add_file_version(
+                    &self.redis,
+                    self.blobs.as_ref(),
+                    &mut file,
+                    &self.claims.device_id,
+                    upload,
+                    None,
+                )
+                .await?;
+
+                (StatusCode::NO_CONTENT, file)
+            }
+            None => {
+                let file = files::create_file(
+                    &self.redis,
+                    self.blobs.as_ref(),
+                    &self.claims.username,
+                    &self.claims.device_id,
+                    &folder,
+                    upload,
+                )
+                .await?;
+
+                (StatusCode::CREATED, file)
+            }
+        };
+
+        previews::enqueue_generation(&self.redis, &file.owner, &file.hash, &file.content_type)
+            .await?;
+        indexer::enqueue_indexing(&self.redis, &file).await?;
+        self.audit(AuditAction::Upload, &file).await;
+
+        Ok(HttpResponse::build(status)
+            .insert_header(("ETag", headers::etag(&file.hash)))
+            .finish())
+    }
+
+    async fn delete(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+    ) -> Result<HttpResponse, ServiceError> {
+        match self.resolve(path).await? {
+            Some(Resource::File(file)) => {
+                self.check_lock(request, &file).await?;
+                self.delete_file(file).await?;
+            }
+            Some(Resource::Folder(folder)) if folder.path == "/" => {
+                return Err(ServiceError::Forbidden(
+                    "The root folder cannot be deleted".to_string(),
+                ))
+            }
+            Some(Resource::Folder(folder)) => self.delete_tree(request, &folder).await?,
+            None => return Err(not_found(path)),
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn mkcol(&self, path: &str, payload: web::Payload) -> Result<HttpResponse, ServiceError> {
+        if !read_body(payload, MAX_DAV_BODY_SIZE).await?.is_empty() {
+            return Err(ServiceError::UnsupportedMediaType(
+                "MKCOL requests cannot have a body".to_string(),
+            ));
+        }
+
+        let (parent, _) = match paths::split(path) {
+            Some(split) => split,
+            None => return Ok(method_not_allowed()),
+        };
+        if self.resolve(path).await?.is_some() {
+            return Ok(method_not_allowed());
+        }
+        if self.find_folder(&parent).await?.is_none() {
+            return Err(missing_parent());
+        }
+
+        folders::ensure_folder(&self.redis, &self.claims.username, path).await?;
+
+        Ok(HttpResponse::Created().finish())
+    }
+
+    /// Handles both `MOVE` and `COPY`, which only differ in what happens to the source.
+    async fn transfer(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+        copy: bool,
+    ) -> Result<HttpResponse, ServiceError> {
+        let destination = header(request, "Destination").ok_or_else(|| {
+            ServiceError::BadRequest("The Destination header is missing".to_string())
+        })?;
+        let destination = paths::from_destination(destination)?;
+        let overwrite = header(request, "Overwrite")
+            .map(|overwrite| !overwrite.eq_ignore_ascii_case("F"))
+            .unwrap_or(true);
+
+        let source = self.resolve(path).await?.ok_or_else(|| not_found(path))?;
+        let (folder, name) = paths::split(&destination).ok_or_else(|| {
+            ServiceError::Forbidden("The root folder cannot be replaced".to_string())
+        })?;
+
+        // Either way, replacing the destination would destroy the source.
+        if folders::is_within(path, &destination) || folders::is_within(&destination, path) {
+            return Err(ServiceError::Forbidden(
+                "The source and the destination overlap".to_string(),
+            ));
+        }
+        if self.find_folder(&folder).await?.is_none() {
+            return Err(missing_parent());
+        }
+
+        // Everything that could refuse the request is checked before the destination is
+        // replaced, so a refused MOVE or COPY leaves both sides as they were.
+        match &source {
+            Resource::File(file) if !copy => self.check_lock(request, file).await?,
+            Resource::Folder(folder) if !copy => self.check_tree_locks(request, folder).await?,
+            _ => {}
+        }
+
+        let existing = self.resolve(&destination).await?;
+        match &existing {
+            Some(_) if !overwrite => {
+                return Err(ServiceError::PreconditionFailed(
+                    "The destination already exists".to_string(),
+                ))
+            }
+            Some(Resource::File(file)) => self.check_lock(request, file).await?,
+            Some(Resource::Folder(folder)) => self.check_tree_locks(request, folder).await?,
+            None => {}
+        }
+
+        let status = match existing {
+            Some(Resource::File(file)) => {
+                self.delete_file(file).await?;
+                StatusCode::NO_CONTENT
+            }
+            Some(Resource::Folder(folder)) => {
+                self.delete_tree(request, &folder).await?;
+                StatusCode::NO_CONTENT
+            }
+            None => StatusCode::CREATED,
+        };
+
+        match (source, copy) {
+            (Resource::File(file), true) => {
+                self.copier().copy_file(&file, &folder, &name).await?;
+            }
+            (Resource::File(file), false) => self.move_file(file, folder, name).await?,
+            (Resource::Folder(source), true) => {
+                match self.copier().copy_folder(source, &destination).await? {
+                    FolderCopy::Done(report) if !report.failed.is_empty() => {
+                        return Err(ServiceError::InternalServerError(
+                            format!("{} files could not be copied", report.failed.len()),
+                            None,
+                        ))
+                    }
+                    FolderCopy::Done(_) => {}
+                    FolderCopy::Started(_) => return Ok(HttpResponse::Accepted().finish()),
+                }
+            }
+            (Resource::Folder(source), false) => {
+                self.move_tree(request, &source, &destination).await?
+            }
+        }
+
+        Ok(HttpResponse::build(status).finish())
+    }
+
+    async fn lock(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, ServiceError> {
+        let duration = timeout(request);
+        let submitted = submitted_tokens(request, "If");
+
+        // A lock request without a body refreshes a lock the client already holds.
+        if read_body(payload, MAX_DAV_BODY_SIZE).await?.is_empty() {
+            let file = self.find_file(path).await?.ok_or_else(|| not_found(path))?;
+            match locks::get_lock(&self.redis, &file.id).await? {
+                Some(lock) if submitted.contains(&xml::lock_token(&lock)) => {}
+                _ => {
+                    return Err(ServiceError::PreconditionFailed(
+                        "No lock of this file was submitted".to_string(),
+                    ))
+                }
+            }
+
+            let lock = locks::renew(
+                &self.redis,
+                &file.id,
+                &self.claims.username,
+                &self.claims.device_id,
+                duration,
+            )
+            .await?;
+
+            return Ok(lock_response(StatusCode::OK, &lock, path));
+        }
+
+        let (status, file) = match self.resolve(path).await? {
+            Some(Resource::File(file)) => (StatusCode::OK, file),
+            // Only files can be checked out.
+            Some(Resource::Folder(_)) => return Ok(method_not_allowed()),
+            None => {
+                // Locking an unmapped path reserves it with an empty file, which the client
+                // fills with a PUT afterwards.
+                let (folder, name) = paths::split(path).ok_or_else(|| not_found(path))?;
+                if self.find_folder(&folder).await?.is_none() {
+                    return Err(missing_parent());
+                }
+
+                let file = files::create_file(
+                    &self.redis,
+                    self.blobs.as_ref(),
+                    &self.claims.username,
+                    &self.claims.device_id,
+                    &folder,
+                    File::from_bytes(name, Vec::new()),
+                )
+                .await?;
+
+                (StatusCode::CREATED, file)
+            }
+        };
+
+        // Clients of the same user share the WebDAV device, so holding the lease is not
+        // enough to take the lock again, the token is.
+        if let Some(lock) = locks::get_lock(&self.redis, &file.id).await? {
+            if !submitted.contains(&xml::lock_token(&lock)) {
+                return Err(locks::locked(&lock));
+            }
+        }
+
+        let lock = locks::acquire(
+            &self.redis,
+            &file.id,
+            &self.claims.username,
+            &self.claims.device_id,
+            duration,
+        )
+        .await?;
+
+        Ok(lock_response(status, &lock, path))
+    }
+
+    async fn unlock(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+    ) -> Result<HttpResponse, ServiceError> {
+        let token = submitted_tokens(request, "Lock-Token")
+            .pop()
+            .ok_or_else(|| {
+                ServiceError::BadRequest("The Lock-Token header is missing".to_string())
+            })?;
+        let file = self.find_file(path).await?.ok_or_else(|| not_found(path))?;
+
+        match locks::get_lock(&self.redis, &file.id).await? {
+            Some(lock) if xml::lock_token(&lock) == token => {
+                locks::release(
+                    &self.redis,
+                    &file.id,
+                    &self.claims.username,
+                    &self.claims.device_id,
+                )
+                .await?;
+
+                Ok(HttpResponse::NoContent().finish())
+            }
+            _ => Err(ServiceError::Conflict(
+                "The lock token does not match a lock of this file".to_string(),
+            )),
+        }
+    }
+
+    async fn resolve(&self, path: &str) -> Result<Option<Resource>, ServiceError> {
+        if let Some(folder) = self.find_folder(path).await? {
+            return Ok(Some(Resource::Folder(folder)));
+        }
+
+        Ok(self.find_file(path).await?.map(Resource::File))
+    }
+
+    async fn find_folder(&self, path: &str) -> Result<Option<Folder>, ServiceError> {
+        folders::find_folder(&self.redis, &self.claims.username, path).await
+    }
+
+    async fn find_file(&self, path: &str) -> Result<Option<FileMetadata>, ServiceError> {
+        match paths::split(path) {
+            Some((folder, name)) => {
+                files::find_file(&self.redis, &self.claims.username, &folder, &name).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn files_within(&self, root: &str) -> Result<Vec<FileMetadata>, ServiceError> {
+        let files = files::list_files(&self.redis, &self.claims.username)
+            .await?
+            .into_iter()
+            .filter(|file| folders::is_within(root, &file.folder))
+            .collect();
+
+        Ok(files)
+    }
+
+    /// The folders below `root`, and `root` itself, parents first.
+    async fn folders_within(&self, root: &str) -> Result<Vec<Folder>, ServiceError> {
+        let folders = folders::list_folders(&self.redis, &self.claims.username)
+            .await?
+            .into_iter()
+            .filter(|folder| folders::is_within(root, &folder.path))
+            .collect();
+
+        Ok(folders)
+    }
+
+    async fn children(&self, folder: &Folder) -> Result<Vec<Properties>, ServiceError> {
+        let mut children = folders::list_folders(&self.redis, &self.claims.username)
+            .await?
+            .iter()
+            .filter(|child| {
+                paths::split(&child.path).map(|(parent, _)| parent) == Some(folder.path.clone())
+            })
+            .map(Properties::of_folder)
+            .collect::<Vec<_>>();
+
+        let files = files::list_files(&self.redis, &self.claims.username)
+            .await?
+            .into_iter()
+            .filter(|file| file.folder == folder.path)
+            .collect::<Vec<_>>();
+        let ids = files.iter().map(|file| file.id.clone()).collect::<Vec<_>>();
+        let mut held = locks::get_locks(&self.redis, &ids).await?;
+
+        for file in files {
+            let path = paths::join(&file.folder, &file.name);
+            let lock = held.remove(&file.id);
+            children.push(Properties::of_file(&path, &file, lock));
+        }
+
+        Ok(children)
+    }
+
+    /// Writes to a locked file must come from its holder and, as RFC 4918 requires, carry the
+    /// lock token in the `If` header.
+    async fn check_lock(
+        &self,
+        request: &HttpRequest,
+        file: &FileMetadata,
+    ) -> Result<(), ServiceError> {
+        match locks::get_lock(&self.redis, &file.id).await? {
+            Some(lock)
+                if !lock.is_held_by(&self.claims.username, &self.claims.device_id)
+                    || !submitted_tokens(request, "If").contains(&xml::lock_token(&lock)) =>
+            {
+                Err(locks::locked(&lock))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_file(&self, file: FileMetadata) -> Result<(), ServiceError> {
+        files::delete_file(&self.redis, &file, &self.claims.device_id).await?;
+        indexer::enqueue_removal(&self.redis, &file).await?;
+        self.audit(AuditAction::Delete, &file).await;
+
+        Ok(())
+    }
+
+    async fn audit(&self, action: AuditAction, file: &FileMetadata) {
+        let target = Some(format!("file:{}", file.id));
+        let detail = Some("Over WebDAV".to_string());
+        store::record(&self.redis, &self.actor, action, target, detail).await;
+    }
+
+    /// Fails if any file below the folder is locked by someone else.
+    async fn check_tree_locks(
+        &self,
+        request: &HttpRequest,
+        folder: &Folder,
+    ) -> Result<(), ServiceError> {
+        for file in self.files_within(&folder.path).await? {
+            self.check_lock(request, &file).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a folder with everything below it. Nothing is deleted if any file is locked.
+    async fn delete_tree(
+        &self,
+        request: &HttpRequest,
+        folder: &Folder,
+    ) -> Result<(), ServiceError> {
+        let files = self.files_within(&folder.path).await?;
+        for file in &files {
+            self.check_lock(request, file).await?;
+        }
+
+        for file in files {
+            self.delete_file(file).await?;
+        }
+        for folder in self.folders_within(&folder.path).await?.iter().rev() {
+            folders::delete_folder(&self.redis, folder).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn move_file(
+        &self,
+        file: FileMetadata,
+        folder: String,
+        name: String,
+    ) -> Result<(), ServiceError> {
+        files::validate_name(&name)?;
+        let file = files::update_file(&self.redis, &file.id, &file.owner, |file| {
+            file.folder = folder.clone();
+            file.name = name.clone();
+            file.updated_at = chrono::Utc::now().timestamp();
+
+            Ok(())
+        })
+        .await?;
+        indexer::enqueue_move(&self.redis, &file).await?;
+
+        let change = Change::new(ChangeKind::Moved, &file, &self.claims.device_id);
+        journal::record(&self.redis, &file.owner, change).await
+    }
+
+    /// Folders are identified by their path, so moving one recreates it and its subfolders at
+    /// the destination before moving the files over.
+    async fn move_tree(
+        &self,
+        request: &HttpRequest,
+        source: &Folder,
+        destination: &str,
+    ) -> Result<(), ServiceError> {
+        let files = self.files_within(&source.path).await?;
+        for file in &files {
+            self.check_lock(request, file).await?;
+        }
+
+        let folders = self.folders_within(&source.path).await?;
+        for folder in &folders {
+            let path = folders::relocate(&source.path, destination, &folder.path);
+            let mut moved =
+                folders::ensure_folder(&self.redis, &self.claims.username, &path).await?;
+            let payload = MetadataPayload {
+                add_tags: folder.tags.iter().cloned().collect(),
+                set_attributes: folder.attributes.clone(),
+                ..MetadataPayload::default()
+            };
+
+            metadata::apply_changes(
+                &self.redis,
+                &self.claims.username,
+                &metadata::folder_member(&moved.id),
+                &mut moved.tags,
+                &mut moved.attributes,
+                &payload,
+            )
+            .await?;
+            folders::save_folder(&self.redis, &moved).await?;
+        }
+
+        for file in files {
+            let folder = folders::relocate(&source.path, destination, &file.folder);
+            let name = file.name.clone();
+            self.move_file(file, folder, name).await?;
+        }
+        for folder in folders.iter().rev() {
+            folders::delete_folder(&self.redis, folder).await?;
+        }
+
+        Ok(())
+    }
+
+    fn copier(&self) -> Copier {
+        Copier::new(
+            self.redis.clone(),
+            self.claims.username.clone(),
+            self.claims.device_id.clone(),
+        )
+    }
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The lock tokens listed in a header, e.g. `If: (<opaquelocktoken:...>)`.
+fn submitted_tokens(request: &HttpRequest, name: &str) -> Vec<String> {
+    header(request, name)
+        .unwrap_or_default()
+        .split('<')
+        .filter_map(|part| part.split_once('>'))
+        .map(|(token, _)| token.trim().to_string())
+        .filter(|token| token.starts_with("opaquelocktoken:"))
+        .collect()
+}
+
+/// The lease requested in the `Timeout` header. Clients may list several, the first one
+/// understood wins.
+fn timeout(request: &HttpRequest) -> Option<u32> {
+    header(request, "Timeout")?
+        .split(',')
+        .map(str::trim)
+        .find_map(|timeout| match timeout.strip_prefix("Second-") {
+            _ if timeout.eq_ignore_ascii_case("Infinite") => Some(MAX_LOCK_TTL),
+            Some(seconds) => seconds.parse().ok(),
+            None => None,
+        })
+}
+
+/// Reads the whole body into memory, refusing it as soon as it grows past `limit` bytes.
+async fn read_body(mut payload: web::Payload, limit: usize) -> Result<Vec<u8>, ServiceError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = payload
+        .try_next()
+        .await
+        .map_err(|error| ServiceError::BadRequest(format!("Failed to read the body: {}", error)))?
+    {
+        if data.len() + chunk.len() > limit {
+            return Err(ServiceError::BadRequest(format!(
+                "The body is larger than {} bytes",
+                limit
+            )));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+fn lock_response(status: StatusCode, lock: &FileLock, path: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header(("Lock-Token", format!("<{}>", xml::lock_token(lock))))
+        .content_type(XML_CONTENT_TYPE)
+        .body(xml::lock_discovery(lock, &paths::href(path, false)))
+}
+
+fn method_not_allowed() -> HttpResponse {
+    HttpResponse::MethodNotAllowed()
+        .insert_header(("Allow", ALLOWED_METHODS))
+        .finish()
+}
+
+fn not_found(path: &str) -> ServiceError {
+    ServiceError::NotFound(format!("{} does not exist", path))
+}
+
+fn missing_parent() -> ServiceError {
+    ServiceError::Conflict("The parent folder does not exist".to_string())
+}
@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod handler;
+pub mod paths;
+pub mod xml;
@@ -0,0 +1,133 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::DAV_ROUTE;
+use crate::storage::files;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// Characters escaped in hrefs, everything but unreserved characters and the separator.
+const HREF: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}')
+    .add(b'&')
+    .add(b'+');
+
+/// Turns a request path below the WebDAV root into a path of the user's namespace.
+pub fn from_request(path: &str) -> Result<String, ServiceError> {
+    let path = path.strip_prefix(DAV_ROUTE).unwrap_or(path);
+    let path = percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|_| ServiceError::BadRequest("The path is not valid UTF-8".to_string()))?;
+
+    files::normalize_folder(&path)
+}
+
+/// Reads the `Destination` header of `MOVE` and `COPY`, which may be an absolute URL.
+pub fn from_destination(destination: &str) -> Result<String, ServiceError> {
+    let path = match destination.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or("/"),
+        None => destination,
+    };
+
+    if path != DAV_ROUTE && !path.starts_with(&format!("{}/", DAV_ROUTE)) {
+        return Err(ServiceError::BadRequest(
+            "The destination is outside of the WebDAV root".to_string(),
+        ));
+    }
+
+    from_request(path)
+}
+
+/// Splits a path into its parent folder and its name, `None` for the root.
+pub fn split(path: &str) -> Option<(String, String)> {
+    let (parent, name) = path.rsplit_once('/')?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let parent = if parent.is_empty() { "/" } else { parent };
+    Some((parent.to_string(), name.to_string()))
+}
+
+pub fn join(folder: &str, name: &str) -> String {
+    match folder {
+        "/" => format!("/{}", name),
+        folder => format!("{}/{}", folder, name),
+    }
+}
+
+/// The href of a resource in multistatus responses. Collections end with a slash.
+pub fn href(path: &str, collection: bool) -> String {
+    let encoded = utf8_percent_encode(path, HREF).to_string();
+
+    match (collection, path) {
+        (true, "/") => format!("{}/", DAV_ROUTE),
+        (true, _) => format!("{}{}/", DAV_ROUTE, encoded),
+        (false, _) => format!("{}{}", DAV_ROUTE, encoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_request_paths() {
+        assert_eq!(from_request("/dav").unwrap(), "/");
+        assert_eq!(from_request("/dav/").unwrap(), "/");
+        assert_eq!(
+            from_request("/dav/My%20Documents/report.txt").unwrap(),
+            "/My Documents/report.txt"
+        );
+        assert_eq!(from_request("/dav//a/./b/").unwrap(), "/a/b");
+        assert!(from_request("/dav/a/../../etc").is_err());
+        assert!(from_request("/dav/%FF").is_err());
+    }
+
+    #[test]
+    fn reads_destinations_inside_the_root_only() {
+        assert_eq!(
+            from_destination("https://example.com/dav/a%20b/c.txt").unwrap(),
+            "/a b/c.txt"
+        );
+        assert_eq!(from_destination("/dav/c.txt").unwrap(), "/c.txt");
+        assert!(from_destination("https://example.com/files/c.txt").is_err());
+        assert!(from_destination("/davx/c.txt").is_err());
+        assert!(from_destination("https://example.com").is_err());
+    }
+
+    #[test]
+    fn splits_and_joins_paths() {
+        assert_eq!(
+            split("/a/b.txt"),
+            Some(("/a".to_string(), "b.txt".to_string()))
+        );
+        assert_eq!(
+            split("/b.txt"),
+            Some(("/".to_string(), "b.txt".to_string()))
+        );
+        assert_eq!(split("/"), None);
+
+        assert_eq!(join("/", "b.txt"), "/b.txt");
+        assert_eq!(join("/a", "b.txt"), "/a/b.txt");
+    }
+
+    #[test]
+    fn encodes_hrefs() {
+        assert_eq!(href("/", true), "/dav/");
+        assert_eq!(href("/a b", true), "/dav/a%20b/");
+        assert_eq!(href("/a/50%+#1.txt", false), "/dav/a/50%25%2B%231.txt");
+        assert_eq!(href("/é.txt", false), "/dav/%C3%A9.txt");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::api::utils::headers;
+use crate::dav::paths;
+use crate::storage::locks::FileLock;
+use crate::storage::models::{FileMetadata, Folder};
+use crate::utils::xml::escape;
+use chrono::TimeZone;
+
+/// The properties of a file or folder reported by `PROPFIND`.
+pub struct Properties {
+    pub href: String,
+    pub name: String,
+    pub collection: bool,
+    pub size: usize,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub created_at: i64,
+    pub modified_at: i64,
+    pub lock: Option<FileLock>,
+}
+
+impl Properties {
+    pub fn of_file(path: &str, file: &FileMetadata, lock: Option<FileLock>) -> Self {
+        Self {
+            href: paths::href(path, false),
+            name: file.name.clone(),
+            collection: false,
+            size: file.size,
+            content_type: Some(file.content_type.clone()),
+            etag: Some(headers::etag(&file.hash)),
+            created_at: file.created_at,
+            modified_at: file.updated_at,
+            lock,
+        }
+    }
+
+    pub fn of_folder(folder: &Folder) -> Self {
+        let name = paths::split(&folder.path)
+            .map(|(_, name)| name)
+            .unwrap_or_default();
+
+        Self {
+            href: paths::href(&folder.path, true),
+            name,
+            collection: true,
+            size: 0,
+            content_type: None,
+            etag: None,
+            created_at: folder.created_at,
+            modified_at: folder.created_at,
+            lock: None,
+        }
+    }
+}
+
+pub fn multistatus(resources: &[Properties]) -> String {
+    let mut body = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+    );
+
+    for resource in resources {
+        body.push_str("<D:response>\n");
+        body.push_str(&format!("<D:href>{}</D:href>\n", escape(&resource.href)));
+        body.push_str("<D:propstat>\n<D:prop>\n");
+        body.push_str(&format!(
+            "<D:displayname>{}</D:displayname>\n",
+            escape(&resource.name)
+        ));
+        body.push_str(&format!(
+            "<D:creationdate>{}</D:creationdate>\n",
+            iso_date(resource.created_at)
+        ));
+        body.push_str(&format!(
+            "<D:getlastmodified>{}</D:getlastmodified>\n",
+            headers::http_date(resource.modified_at)
+        ));
+
+        if resource.collection {
+            body.push_str("<D:resourcetype><D:collection/></D:resourcetype>\n");
+        } else {
+            body.push_str("<D:resourcetype/>\n");
+            body.push_str(&format!(
+                "<D:getcontentlength>{}</D:getcontentlength>\n",
+                resource.size
+            ));
+        }
+        if let Some(content_type) = &resource.content_type {
+            body.push_str(&format!(
+                "<D:getcontenttype>{}</D:getcontenttype>\n",
+                escape(content_type)
+            ));
+        }
+        if let Some(etag) = &resource.etag {
+            body.push_str(&format!("<D:getetag>{}</D:getetag>\n", escape(etag)));
+        }
+
+        body.push_str(
+            "<D:supportedlock><D:lockentry><D:lockscope><D:exclusive/></D:lockscope>\
+             <D:locktype><D:write/></D:locktype></D:lockentry></D:supportedlock>\n",
+        );
+        match &resource.lock {
+            Some(lock) => body.push_str(&format!(
+                "<D:lockdiscovery>{}</D:lockdiscovery>\n",
+                active_lock(lock, &resource.href)
+            )),
+            None => body.push_str("<D:lockdiscovery/>\n"),
+        }
+
+        body.push_str("</D:prop>\n<D:status>HTTP/1.1 200 OK</D:status>\n</D:propstat>\n");
+        body.push_str("</D:response>\n");
+    }
+
+    body.push_str("</D:multistatus>\n");
+    body
+}
+
+/// The body of a `LOCK` response.
+pub fn lock_discovery(lock: &FileLock, href: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:prop xmlns:D=\"DAV:\">\
+         <D:lockdiscovery>{}</D:lockdiscovery></D:prop>\n",
+        active_lock(lock, href)
+    )
+}
+
+pub fn lock_token(lock: &FileLock) -> String {
+    format!("opaquelocktoken:{}", lock.token)
+}
+
+fn active_lock(lock: &FileLock, href: &str) -> String {
+    let remaining = (lock.expires_at - chrono::Utc::now().timestamp()).max(0);
+
+    format!(
+        "<D:activelock><D:locktype><D:write/></D:locktype>\
+         <D:lockscope><D:exclusive/></D:lockscope><D:depth>0</D:depth>\
+         <D:owner>{} ({})</D:owner><D:timeout>Second-{}</D:timeout>\
+         <D:locktoken><D:href>{}</D:href></D:locktoken>\
+         <D:lockroot><D:href>{}</D:href></D:lockroot></D:activelock>",
+        escape(&lock.username),
+        escape(&lock.device_id),
+        remaining,
+        lock_token(lock),
+        escape(href)
+    )
+}
+
+fn iso_date(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
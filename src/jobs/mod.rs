@@ -0,0 +1,5 @@
+pub mod models;
+pub mod queue;
+pub mod store;
+pub mod tasks;
+pub mod worker;
@@ -0,0 +1,151 @@
+use crate::constants::{JOB_MAX_ATTEMPTS, JOB_RETRY_DELAY, MAX_JOB_RETRY_DELAY};
+use crate::jobs::tasks::Task;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    /// Failed, waiting for its next attempt.
+    Retrying,
+    Completed,
+    /// Failed for good, kept in the dead-letter queue.
+    Failed,
+}
+
+/// Jobs are split by the resources they need. Only the server process holds the search index,
+/// so indexing jobs have a queue of their own. Webhook deliveries wait on other people's
+/// servers and get one too, so a slow endpoint never holds up the rest.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Queue {
+    Default,
+    Index,
+    Webhook,
+}
+
+impl Queue {
+    pub const ALL: [Queue; 3] = [Queue::Default, Queue::Index, Queue::Webhook];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Queue::Default => "default",
+            Queue::Index => "index",
+            Queue::Webhook => "webhook",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Queue::ALL.into_iter().find(|queue| queue.name() == name)
+    }
+}
+
+/// A long running task whose progress can be polled by its owner.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Job {
+    pub id: String,
+    pub owner: String,
+    pub kind: String,
+    pub queue: Queue,
+    pub task: Task,
+    pub state: JobState,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    /// When a retrying job runs again.
+    pub retry_at: Option<i64>,
+    pub processed: u64,
+    pub total: Option<u64>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Job {
+    pub fn new(owner: String, task: Task) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        let max_attempts = match task.is_retryable() {
+            true => JOB_MAX_ATTEMPTS,
+            false => 1,
+        };
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner,
+            kind: task.kind().to_string(),
+            queue: task.queue(),
+            task,
+            state: JobState::Queued,
+            attempts: 0,
+            max_attempts,
+            retry_at: None,
+            processed: 0,
+            total: None,
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, JobState::Completed | JobState::Failed)
+    }
+
+    /// Records a failed attempt. Returns whether the job will be attempted again.
+    pub fn retry(&mut self, error: String) -> bool {
+        if self.attempts >= self.max_attempts {
+            self.fail(error);
+            return false;
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let delay = JOB_RETRY_DELAY
+            .saturating_mul(1 << self.attempts.saturating_sub(1).min(20))
+            .min(MAX_JOB_RETRY_DELAY);
+
+        self.state = JobState::Retrying;
+        self.error = Some(error);
+        self.retry_at = Some(now + delay);
+        self.updated_at = now;
+        true
+    }
+
+    /// Puts a dead job back in line with a fresh set of attempts.
+    pub fn requeue(&mut self) {
+        self.state = JobState::Queued;
+        self.attempts = 0;
+        self.retry_at = None;
+        self.error = None;
+        self.updated_at = chrono::Utc::now().timestamp();
+    }
+
+    pub fn progress(&mut self, processed: u64, total: Option<u64>) {
+        self.state = JobState::Running;
+        self.processed = processed;
+        self.total = total;
+        self.updated_at = chrono::Utc::now().timestamp();
+    }
+
+    pub fn complete<T: Serialize>(&mut self, result: &T) {
+        match serde_json::to_value(result) {
+            Ok(result) => {
+                self.state = JobState::Completed;
+                self.result = Some(result);
+                self.error = None;
+            }
+            Err(error) => {
+                self.state = JobState::Failed;
+                self.error = Some(format!("Failed to serialize the result: {}", error));
+            }
+        }
+        self.updated_at = chrono::Utc::now().timestamp();
+    }
+
+    pub fn fail(&mut self, error: String) {
+        self.state = JobState::Failed;
+        self.error = Some(error);
+        self.updated_at = chrono::Utc::now().timestamp();
+    }
+}
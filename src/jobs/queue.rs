@@ -0,0 +1,405 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::JOB_LEASE_TTL;
+use crate::jobs::models::{Job, JobState, Queue};
+use crate::jobs::store;
+use crate::redis::client::{RedisClient, RedisKey};
+use serde::Serialize;
+
+/// Moves the oldest queued job to the processing list and leases it to the caller, in one step
+/// so a job is never in processing without a lease while its worker is alive.
+const RESERVE_SCRIPT: &str = r#"
+local id = redis.call('RPOPLPUSH', KEYS[1], KEYS[2])
+if id then
+    redis.call('SET', ARGV[1] .. id .. ARGV[2], ARGV[3], 'EX', ARGV[4])
+end
+return id
+"#;
+
+/// Extends a lease, provided the given worker still holds it.
+const RENEW_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+    return 0
+end
+redis.call('EXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Takes a job off the processing list and files it according to its outcome: ARGV[2] is
+/// `retry` (scheduled at ARGV[3]), `dead` or `done`.
+const FINISH_SCRIPT: &str = r#"
+redis.call('LREM', KEYS[1], 0, ARGV[1])
+redis.call('DEL', KEYS[4])
+if ARGV[2] == 'retry' then
+    redis.call('ZADD', KEYS[2], ARGV[3], ARGV[1])
+elseif ARGV[2] == 'dead' then
+    redis.call('LPUSH', KEYS[3], ARGV[1])
+end
+return 1
+"#;
+
+/// Queues the retries that are due.
+const PROMOTE_SCRIPT: &str = r#"
+local ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+for _, id in ipairs(ids) do
+    redis.call('ZREM', KEYS[1], id)
+    redis.call('LPUSH', KEYS[2], id)
+end
+return #ids
+"#;
+
+/// Puts back the jobs whose worker stopped renewing its lease. They are queued at the front,
+/// having already waited their turn once.
+const RECOVER_SCRIPT: &str = r#"
+local ids = redis.call('LRANGE', KEYS[1], 0, -1)
+local recovered = 0
+for _, id in ipairs(ids) do
+    if redis.call('EXISTS', ARGV[1] .. id .. ARGV[2]) == 0 then
+        redis.call('LREM', KEYS[1], 0, id)
+        redis.call('RPUSH', KEYS[2], id)
+        recovered = recovered + 1
+    end
+end
+return recovered
+"#;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueueStats {
+    pub queue: Queue,
+    pub queued: usize,
+    pub running: usize,
+    pub retrying: usize,
+    pub dead: usize,
+}
+
+/// The parts around the job id in `RedisKey::JobLease`, for scripts building the key.
+fn lease_key_parts() -> (String, String) {
+    let marker = "{id}";
+    let key = RedisKey::JobLease(marker.to_string()).to_string();
+    let (prefix, suffix) = key.split_once(marker).unwrap_or((&key, ""));
+
+    (prefix.to_string(), suffix.to_string())
+}
+
+/// Saves a new job and queues it.
+pub async fn enqueue(redis: &RedisClient, job: Job) -> Result<Job, ServiceError> {
+    store::save_job(redis, &job).await?;
+    redis
+        .execute::<i64>(
+            redis::cmd("LPUSH")
+                .arg(RedisKey::JobQueue(job.queue.name().to_string()).to_string())
+                .arg(&job.id),
+        )
+        .await?;
+
+    Ok(job)
+}
+
+/// Leases the next job of `queue` to `worker`, if any is waiting.
+pub async fn reserve(
+    redis: &RedisClient,
+    queue: Queue,
+    worker: &str,
+) -> Result<Option<Job>, ServiceError> {
+    let (prefix, suffix) = lease_key_parts();
+    let id: Option<String> = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(RESERVE_SCRIPT)
+                .arg(2)
+                .arg(RedisKey::JobQueue(queue.name().to_string()).to_string())
+                .arg(RedisKey::JobProcessing(queue.name().to_string()).to_string())
+                .arg(prefix)
+                .arg(suffix)
+                .arg(worker)
+                .arg(JOB_LEASE_TTL),
+        )
+        .await?;
+
+    let id = match id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    match store::get_job(redis, &id).await? {
+        Some(job) => Ok(Some(job)),
+        None => {
+            // The record is gone, e.g. discarded by an administrator while queued.
+            log::warn!("Dropping job {} which no longer exists", id);
+            finish_raw(redis, queue, &id, "done", 0).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Extends the lease of `worker` on a job. Returns false once the lease expired, after which
+/// the job may have been recovered and leased to another worker.
+pub async fn renew_lease(
+    redis: &RedisClient,
+    id: &str,
+    worker: &str,
+) -> Result<bool, ServiceError> {
+    let renewed: i64 = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(RENEW_SCRIPT)
+                .arg(1)
+                .arg(RedisKey::JobLease(id.to_string()).to_string())
+                .arg(worker)
+                .arg(JOB_LEASE_TTL),
+        )
+        .await?;
+
+    Ok(renewed == 1)
+}
+
+/// Saves a job after an attempt and files it as completed, retrying or dead.
+pub async fn finish(redis: &RedisClient, job: &Job) -> Result<(), ServiceError> {
+    store::save_job(redis, job).await?;
+
+    let (outcome, retry_at) = match job.state {
+        JobState::Retrying => ("retry", job.retry_at.unwrap_or_default()),
+        JobState::Failed => ("dead", 0),
+        _ => ("done", 0),
+    };
+    finish_raw(redis, job.queue, &job.id, outcome, retry_at).await?;
+
+    if job.state == JobState::Completed {
+        release_hashes(redis, job).await?;
+    }
+
+    Ok(())
+}
+
+async fn finish_raw(
+    redis: &RedisClient,
+    queue: Queue,
+    id: &str,
+    outcome: &str,
+    retry_at: i64,
+) -> Result<(), ServiceError> {
+    let name = queue.name().to_string();
+
+    redis
+        .execute::<i64>(
+            redis::cmd("EVAL")
+                .arg(FINISH_SCRIPT)
+                .arg(4)
+                .arg(RedisKey::JobProcessing(name.clone()).to_string())
+                .arg(RedisKey::JobRetries(name.clone()).to_string())
+                .arg(RedisKey::JobDeadLetters(name).to_string())
+                .arg(RedisKey::JobLease(id.to_string()).to_string())
+                .arg(id)
+                .arg(outcome)
+                .arg(retry_at),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Queues the due retries and recovers the jobs of dead workers. Safe to run from every
+/// process at once.
+pub async fn maintain(redis: &RedisClient, queue: Queue) -> Result<(), ServiceError> {
+    let name = queue.name().to_string();
+
+    let promoted: i64 = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(PROMOTE_SCRIPT)
+                .arg(2)
+                .arg(RedisKey::JobRetries(name.clone()).to_string())
+                .arg(RedisKey::JobQueue(name.clone()).to_string())
+                .arg(chrono::Utc::now().timestamp()),
+        )
+        .await?;
+
+    let (prefix, suffix) = lease_key_parts();
+    let recovered: i64 = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(RECOVER_SCRIPT)
+                .arg(2)
+                .arg(RedisKey::JobProcessing(name.clone()).to_string())
+                .arg(RedisKey::JobQueue(name.clone()).to_string())
+                .arg(prefix)
+                .arg(suffix),
+        )
+        .await?;
+
+    if promoted > 0 || recovered > 0 {
+        log::info!(
+            "Queue {}: {} retries queued, {} abandoned jobs recovered",
+            name,
+            promoted,
+            recovered
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn stats(redis: &RedisClient) -> Result<Vec<QueueStats>, ServiceError> {
+    let mut stats = Vec::with_capacity(Queue::ALL.len());
+
+    for queue in Queue::ALL {
+        let name = queue.name().to_string();
+
+        stats.push(QueueStats {
+            queue,
+            queued: length(redis, RedisKey::JobQueue(name.clone())).await?,
+            running: length(redis, RedisKey::JobProcessing(name.clone())).await?,
+            retrying: redis
+                .execute(redis::cmd("ZCARD").arg(RedisKey::JobRetries(name.clone()).to_string()))
+                .await?,
+            dead: length(redis, RedisKey::JobDeadLetters(name)).await?,
+        });
+    }
+
+    Ok(stats)
+}
+
+async fn length(redis: &RedisClient, key: RedisKey) -> Result<usize, ServiceError> {
+    redis.execute(redis::cmd("LLEN").arg(key.to_string())).await
+}
+
+/// The jobs that ran out of attempts, most recent first.
+pub async fn dead_letters(redis: &RedisClient, queue: Queue) -> Result<Vec<Job>, ServiceError> {
+    let ids: Vec<String> = redis
+        .execute(
+            redis::cmd("LRANGE")
+                .arg(RedisKey::JobDeadLetters(queue.name().to_string()).to_string())
+                .arg(0)
+                .arg(-1),
+        )
+        .await?;
+
+    let mut jobs = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(job) = store::get_job(redis, &id).await? {
+            jobs.push(job);
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// Takes a job out of the dead-letter queue, failing if it is not there.
+async fn take_dead(redis: &RedisClient, id: &str) -> Result<Job, ServiceError> {
+    let missing = || ServiceError::NotFound(format!("Job {} is not in a dead-letter queue", id));
+    let job = store::get_job(redis, id).await?.ok_or_else(missing)?;
+
+    let removed: i64 = redis
+        .execute(
+            redis::cmd("LREM")
+                .arg(RedisKey::JobDeadLetters(job.queue.name().to_string()).to_string())
+                .arg(0)
+                .arg(id),
+        )
+        .await?;
+
+    match removed {
+        0 => Err(missing()),
+        _ => Ok(job),
+    }
+}
+
+/// Gives a dead job a fresh set of attempts.
+pub async fn retry_dead(redis: &RedisClient, id: &str) -> Result<Job, ServiceError> {
+    let mut job = take_dead(redis, id).await?;
+    job.requeue();
+
+    enqueue(redis, job).await
+}
+
+pub async fn discard_dead(redis: &RedisClient, id: &str) -> Result<Job, ServiceError> {
+    let job = take_dead(redis, id).await?;
+
+    redis.async_del(RedisKey::Job(job.id.clone())).await?;
+    release_hashes(redis, &job).await?;
+
+    Ok(job)
+}
+
+/// Keeps the blob a job needs away from the garbage collector until the job completes or is
+/// discarded.
+pub async fn hold_hash(redis: &RedisClient, job: &Job) -> Result<(), ServiceError> {
+    if let Some(hash) = job.task.held_hash() {
+        redis
+            .async_sadd(RedisKey::JobHashes, &held_member(job, hash))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn release_hashes(redis: &RedisClient, job: &Job) -> Result<(), ServiceError> {
+    if let Some(hash) = job.task.held_hash() {
+        redis
+            .async_srem(RedisKey::JobHashes, &held_member(job, hash))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Holds are per job, so two jobs needing the same blob do not release each other's.
+fn held_member(job: &Job, hash: &str) -> String {
+    format!("{}:{}", job.id, hash)
+}
+
+pub async fn held_hashes(redis: &RedisClient) -> Result<Vec<String>, ServiceError> {
+    let members = redis.async_smembers(RedisKey::JobHashes).await?;
+
+    Ok(members
+        .into_iter()
+        .filter_map(|member| member.split_once(':').map(|(_, hash)| hash.to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::tasks::Task;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn recovers_jobs_of_workers_that_stopped() {
+        let redis = testing::redis();
+        let task = Task::DeliverWebhook {
+            delivery_id: testing::hash(),
+        };
+        let job = enqueue(&redis, Job::new(testing::username(), task))
+            .await
+            .unwrap();
+
+        let reserved = reserve(&redis, Queue::Webhook, "first").await.unwrap();
+        assert_eq!(reserved.unwrap().id, job.id);
+        assert!(renew_lease(&redis, &job.id, "first").await.unwrap());
+
+        // The lease runs out while the worker is stuck.
+        redis
+            .async_del(RedisKey::JobLease(job.id.clone()))
+            .await
+            .unwrap();
+        maintain(&redis, Queue::Webhook).await.unwrap();
+
+        let reserved = reserve(&redis, Queue::Webhook, "second").await.unwrap();
+        assert_eq!(reserved.unwrap().id, job.id);
+        assert!(!renew_lease(&redis, &job.id, "first").await.unwrap());
+        assert!(renew_lease(&redis, &job.id, "second").await.unwrap());
+
+        let mut job = store::get_job(&redis, &job.id).await.unwrap().unwrap();
+        job.complete(&());
+        finish(&redis, &job).await.unwrap();
+        assert_eq!(
+            length(
+                &redis,
+                RedisKey::JobProcessing(Queue::Webhook.name().to_string())
+            )
+            .await
+            .unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::JOB_TTL;
+use crate::jobs::models::{Job, JobState};
+use crate::redis::client::{RedisClient, RedisKey};
+
+/// Saves a job. Completed jobs expire a while after they finished, the others are kept until
+/// they complete or an administrator discards them.
+pub async fn save_job(redis: &RedisClient, job: &Job) -> Result<(), ServiceError> {
+    redis
+        .s_async_set(RedisKey::Job(job.id.clone()), job)
+        .await?;
+
+    if job.state == JobState::Completed {
+        redis
+            .async_expire(RedisKey::Job(job.id.clone()), JOB_TTL)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_job(redis: &RedisClient, id: &str) -> Result<Option<Job>, ServiceError> {
+    let value: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::Job(id.to_string()).to_string()))
+        .await?;
+
+    value
+        .map(|value| {
+            serde_json::from_str(&value).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })
+        })
+        .transpose()
+}
+
+pub async fn get_owned_job(
+    redis: &RedisClient,
+    id: &str,
+    username: &str,
+) -> Result<Job, ServiceError> {
+    match get_job(redis, id).await? {
+        Some(job) if job.owner == username => Ok(job),
+        _ => Err(ServiceError::NotFound(format!("Job {} does not exist", id))),
+    }
+}
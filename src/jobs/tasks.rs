@@ -0,0 +1,149 @@
+use crate::archive::extract::Extractor;
+use crate::jobs::models::{Job, Queue};
+use crate::jobs::worker::WorkerContext;
+use crate::search::indexer;
+use crate::storage::copy::Copier;
+use crate::storage::models::{FileMetadata, Folder};
+use crate::storage::previews;
+use crate::webhooks::delivery;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The work a job carries out, with everything needed to carry it out in any process.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Task {
+    IndexFile {
+        file: FileMetadata,
+    },
+    MoveInIndex {
+        file: FileMetadata,
+    },
+    RemoveFromIndex {
+        owner: String,
+        file_id: String,
+    },
+    GeneratePreviews {
+        hash: String,
+        content_type: String,
+    },
+    CopyFolder {
+        device_id: String,
+        source: Folder,
+        destination: String,
+    },
+    ExtractArchive {
+        device_id: String,
+        folder: String,
+        name: String,
+        /// The archive, stored as a blob for the duration of the job.
+        hash: String,
+    },
+    DeliverWebhook {
+        delivery_id: String,
+    },
+}
+
+impl Task {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Task::IndexFile { .. } => "index",
+            Task::MoveInIndex { .. } => "reindex",
+            Task::RemoveFromIndex { .. } => "unindex",
+            Task::GeneratePreviews { .. } => "previews",
+            Task::CopyFolder { .. } => "copy",
+            Task::ExtractArchive { .. } => "extract",
+            Task::DeliverWebhook { .. } => "webhook",
+        }
+    }
+
+    pub fn queue(&self) -> Queue {
+        match self {
+            Task::IndexFile { .. } | Task::MoveInIndex { .. } | Task::RemoveFromIndex { .. } => {
+                Queue::Index
+            }
+            Task::DeliverWebhook { .. } => Queue::Webhook,
+            _ => Queue::Default,
+        }
+    }
+
+    /// Copies and extractions create files as they go, so running them again after a partial
+    /// attempt would duplicate those. They are dead-lettered instead of retried.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, Task::CopyFolder { .. } | Task::ExtractArchive { .. })
+    }
+
+    /// The blob the job needs kept until it completes.
+    pub fn held_hash(&self) -> Option<&str> {
+        match self {
+            Task::ExtractArchive { hash, .. } => Some(hash),
+            _ => None,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        context: &WorkerContext,
+        job: &mut Job,
+    ) -> Result<serde_json::Value, anyhow::Error> {
+        match self {
+            Task::IndexFile { file } => {
+                let search = context.search()?;
+                indexer::index_file(search, context.blobs.as_ref(), file.clone()).await?;
+                Ok(serde_json::Value::Null)
+            }
+            Task::MoveInIndex { file } => {
+                indexer::move_file(context.search()?, file.clone()).await?;
+                Ok(serde_json::Value::Null)
+            }
+            Task::RemoveFromIndex { owner, file_id } => {
+                let search = context.search()?;
+                indexer::remove_file(search, owner.clone(), file_id.clone()).await?;
+                Ok(serde_json::Value::Null)
+            }
+            Task::GeneratePreviews { hash, content_type } => {
+                previews::generate(context.blobs.as_ref(), hash, content_type).await?;
+                Ok(serde_json::Value::Null)
+            }
+            Task::CopyFolder {
+                device_id,
+                source,
+                destination,
+            } => {
+                let copier = Copier::new(
+                    Arc::clone(&context.redis),
+                    job.owner.clone(),
+                    device_id.clone(),
+                );
+                let report = copier
+                    .run_job(source, destination, job)
+                    .await
+                    .map_err(|error| anyhow::anyhow!("{}", error))?;
+
+                Ok(serde_json::to_value(report)?)
+            }
+            Task::ExtractArchive {
+                device_id,
+                folder,
+                name,
+                hash,
+            } => {
+                let extractor = Extractor::new(
+                    Arc::clone(&context.redis),
+                    Arc::clone(&context.blobs),
+                    job.owner.clone(),
+                    device_id.clone(),
+                );
+                let report = extractor
+                    .run_job(name, hash, folder, job)
+                    .await
+                    .map_err(|error| anyhow::anyhow!("{}", error))?;
+
+                Ok(serde_json::to_value(report)?)
+            }
+            Task::DeliverWebhook { delivery_id } => {
+                delivery::deliver(&context.redis, delivery_id, job).await
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::constants::{JOB_LEASE_TTL, JOB_MAINTENANCE_INTERVAL, JOB_POLL_INTERVAL};
+use crate::jobs::models::{Job, Queue};
+use crate::jobs::{queue, store};
+use crate::redis::client::RedisClient;
+use crate::search::index::SearchIndex;
+use crate::storage::blob::BlobStore;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What jobs get to work with. Only processes holding the search index can run indexing jobs.
+pub struct WorkerContext {
+    pub redis: Arc<RedisClient>,
+    pub blobs: Arc<dyn BlobStore>,
+    pub search: Option<Arc<SearchIndex>>,
+}
+
+impl WorkerContext {
+    pub fn search(&self) -> Result<Arc<SearchIndex>, anyhow::Error> {
+        self.search
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("This worker has no search index"))
+    }
+}
+
+/// A set of workers taking jobs from the given queues, in the server process or in the
+/// standalone worker binary.
+pub struct WorkerPool {
+    context: Arc<WorkerContext>,
+    queues: Vec<Queue>,
+    workers: usize,
+}
+
+impl WorkerPool {
+    pub fn new(context: WorkerContext, queues: Vec<Queue>, workers: usize) -> Self {
+        let queues = queues
+            .into_iter()
+            .filter(|queue| {
+                let usable = *queue != Queue::Index || context.search.is_some();
+                if !usable {
+                    log::warn!("Not serving the index queue without a search index");
+                }
+                usable
+            })
+            .collect();
+
+        Self {
+            context: Arc::new(context),
+            queues,
+            workers,
+        }
+    }
+
+    /// Starts the workers and keeps the queues maintained, forever.
+    pub async fn run(self) {
+        if self.workers == 0 || self.queues.is_empty() {
+            log::info!("No job workers configured");
+            return;
+        }
+
+        let names = self.queues.iter().map(Queue::name).collect::<Vec<_>>();
+        log::info!(
+            "Starting {} job workers on queues {}",
+            self.workers,
+            names.join(", ")
+        );
+
+        let instance = uuid::Uuid::new_v4();
+        for index in 0..self.workers {
+            let name = format!("{}:{}", instance, index);
+            tokio::spawn(work(self.context.clone(), self.queues.clone(), name));
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(JOB_MAINTENANCE_INTERVAL));
+        loop {
+            interval.tick().await;
+
+            for queue in &self.queues {
+                if let Err(error) = queue::maintain(&self.context.redis, *queue).await {
+                    log::error!("Failed to maintain queue {}: {}", queue.name(), error);
+                }
+            }
+        }
+    }
+}
+
+/// The queues listed in `DOC_STORAGE_JOB_QUEUES`, or `default` when it is unset.
+pub fn queues_from_env(default: &[Queue]) -> Vec<Queue> {
+    match std::env::var("DOC_STORAGE_JOB_QUEUES") {
+        Ok(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| Queue::from_name(name).unwrap_or_else(|| panic!("Unknown queue {}", name)))
+            .collect(),
+        Err(_) => default.to_vec(),
+    }
+}
+
+async fn work(context: Arc<WorkerContext>, queues: Vec<Queue>, name: String) {
+    loop {
+        let mut idle = true;
+
+        for queue in &queues {
+            match queue::reserve(&context.redis, *queue, &name).await {
+                Ok(Some(job)) => {
+                    idle = false;
+                    run_job(&context, job, &name).await;
+                }
+                Ok(None) => {}
+                Err(error) => log::error!("Failed to reserve a job: {}", error),
+            }
+        }
+
+        if idle {
+            tokio::time::sleep(Duration::from_secs(JOB_POLL_INTERVAL)).await;
+        }
+    }
+}
+
+async fn run_job(context: &WorkerContext, mut job: Job, worker: &str) {
+    job.attempts += 1;
+
+    // Only happens to jobs recovered from a worker that died while running them.
+    if job.attempts > job.max_attempts {
+        job.fail("The job was interrupted".to_string());
+    } else {
+        job.progress(job.processed, job.total);
+        if let Err(error) = store::save_job(&context.redis, &job).await {
+            log::error!("Failed to save the state of job {}: {}", job.id, error);
+        }
+
+        let id = job.id.clone();
+        let renew = async {
+            let period = Duration::from_secs(JOB_LEASE_TTL as u64 / 3);
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match queue::renew_lease(&context.redis, &id, worker).await {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(error) => log::warn!("Failed to renew the lease of job {}: {}", id, error),
+                }
+            }
+        };
+
+        let task = job.task.clone();
+        let result = tokio::select! {
+            result = task.run(context, &mut job) => result,
+            _ = renew => {
+                // The job was recovered and is someone else's now, or will be soon.
+                log::warn!("Lost the lease of job {}, stopping it", job.id);
+                return;
+            }
+        };
+
+        match result {
+            Ok(value) => job.complete(&value),
+            Err(error) => {
+                let error = error.to_string();
+                match job.retry(error.clone()) {
+                    true => log::warn!("Job {} failed, retrying: {}", job.id, error),
+                    false => log::error!("Job {} failed for good: {}", job.id, error),
+                }
+            }
+        }
+    }
+
+    if let Err(error) = queue::finish(&context.redis, &job).await {
+        log::error!("Failed to save the state of job {}: {}", job.id, error);
+    }
+}
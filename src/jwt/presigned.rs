@@ -0,0 +1,309 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{
+    MAX_PRESIGNED_URL_TTL, MULTIPART_OVERHEAD, PRESIGNED_URL_TTL, PRESIGN_SECRET,
+};
+use actix_web::http::{header, Method};
+use actix_web::{web, HttpRequest};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// The device requests made through a pre-signed URL act as.
+pub const PRESIGNED_DEVICE: &str = "presigned";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The query parameters of a pre-signed URL, all covered by its signature.
+const SIGNED_PARAMETERS: [&str; 6] = ["user", "expires", "ip", "max_size", "strip", "signature"];
+
+/// What a pre-signed URL allows: one method on one path, for one user, until it expires.
+#[derive(Clone, Debug, Serialize)]
+pub struct Grant {
+    #[serde(skip)]
+    pub username: String,
+    pub method: String,
+    pub path: String,
+    pub expires_at: i64,
+    pub ip: Option<IpAddr>,
+    pub max_size: Option<u64>,
+    /// Serves images without their location and identifying metadata.
+    pub strip_metadata: bool,
+}
+
+#[derive(Deserialize)]
+struct SignedQuery {
+    user: String,
+    expires: i64,
+    ip: Option<IpAddr>,
+    max_size: Option<u64>,
+    #[serde(default)]
+    strip: bool,
+    signature: String,
+}
+
+impl Grant {
+    pub fn new(
+        username: &str,
+        method: Method,
+        path: String,
+        expires_in: Option<u32>,
+        ip: Option<IpAddr>,
+        max_size: Option<u64>,
+        strip_metadata: bool,
+    ) -> Result<Self, ServiceError> {
+        secret()?;
+        if method != Method::GET && method != Method::PUT {
+            return Err(ServiceError::BadRequest(
+                "Only GET and PUT requests can be pre-signed".to_string(),
+            ));
+        }
+        if method != Method::PUT && max_size.is_some() {
+            return Err(ServiceError::BadRequest(
+                "A maximum size only applies to uploads".to_string(),
+            ));
+        }
+        if method != Method::GET && strip_metadata {
+            return Err(ServiceError::BadRequest(
+                "Removing metadata only applies to downloads".to_string(),
+            ));
+        }
+
+        let expires_in = expires_in
+            .unwrap_or(PRESIGNED_URL_TTL)
+            .clamp(1, MAX_PRESIGNED_URL_TTL);
+
+        Ok(Self {
+            username: username.to_string(),
+            method: method.to_string(),
+            path,
+            expires_at: chrono::Utc::now().timestamp() + expires_in as i64,
+            ip,
+            max_size,
+            strip_metadata,
+        })
+    }
+
+    /// The URL carrying the grant and its signature, relative to the server root.
+    pub fn url(&self) -> Result<String, ServiceError> {
+        let mut url = format!(
+            "{}?user={}&expires={}",
+            self.path,
+            utf8_percent_encode(&self.username, NON_ALPHANUMERIC),
+            self.expires_at
+        );
+        if let Some(ip) = self.ip {
+            url.push_str(&format!(
+                "&ip={}",
+                utf8_percent_encode(&ip.to_string(), NON_ALPHANUMERIC)
+            ));
+        }
+        if let Some(max_size) = self.max_size {
+            url.push_str(&format!("&max_size={}", max_size));
+        }
+        if self.strip_metadata {
+            url.push_str("&strip=true");
+        }
+
+        Ok(format!(
+            "{}&signature={}",
+            url,
+            hex::encode(self.mac()?.finalize().into_bytes())
+        ))
+    }
+
+    /// Refuses uploads announced as larger than the URL allows before any of the body is read.
+    /// The body also holds the multipart framing, which is allowed for on top of the limit.
+    pub fn check_content_length(&self, request: &HttpRequest) -> Result<(), ServiceError> {
+        let length = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        match (self.max_size, length) {
+            (Some(max_size), Some(length)) if length > max_size + MULTIPART_OVERHEAD => {
+                Err(ServiceError::BadRequest(format!(
+                    "The upload exceeds the {} bytes allowed",
+                    max_size
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn mac(&self) -> Result<HmacSha256, ServiceError> {
+        let ip = self.ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let max_size = self
+            .max_size
+            .map(|max_size| max_size.to_string())
+            .unwrap_or_default();
+        let payload = [
+            self.method.as_str(),
+            self.path.as_str(),
+            self.username.as_str(),
+            &self.expires_at.to_string(),
+            &ip,
+            &max_size,
+            if self.strip_metadata { "strip" } else { "" },
+        ]
+        .join("\n");
+
+        let mut mac = HmacSha256::new_from_slice(secret()?).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        Ok(mac)
+    }
+}
+
+/// The key signing URLs. Without one, URLs can neither be signed nor accepted.
+fn secret() -> Result<&'static [u8], ServiceError> {
+    PRESIGN_SECRET.as_deref().ok_or_else(|| {
+        ServiceError::Forbidden(
+            "Pre-signed URLs are disabled, DOC_STORAGE_PRESIGN_SECRET is not set".to_string(),
+        )
+    })
+}
+
+/// Verifies the signature a request carries in its query string. Returns `None` when the
+/// request is not pre-signed, so the caller can fall back to other credentials.
+///
+/// The IP binding is checked against the peer address, a reverse proxy in front of the server
+/// therefore needs to keep the client address.
+pub fn verify(request: &HttpRequest) -> Result<Option<Grant>, ServiceError> {
+    if !request.query_string().contains("signature=") {
+        return Ok(None);
+    }
+
+    // Anything in the query that the signature does not cover, such as `version`, could
+    // otherwise be changed to reach more than the URL was issued for.
+    let parameters = web::Query::<HashMap<String, String>>::from_query(request.query_string())
+        .map_err(|_| ServiceError::InvalidToken)?;
+    if let Some(name) = parameters
+        .keys()
+        .find(|name| !SIGNED_PARAMETERS.contains(&name.as_str()))
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "Pre-signed URLs cannot carry the '{}' parameter",
+            name
+        )));
+    }
+
+    let query = web::Query::<SignedQuery>::from_query(request.query_string())
+        .map_err(|_| ServiceError::InvalidToken)?
+        .into_inner();
+    let grant = Grant {
+        username: query.user,
+        method: request.method().to_string(),
+        path: request.path().to_string(),
+        expires_at: query.expires,
+        ip: query.ip,
+        max_size: query.max_size,
+        strip_metadata: query.strip,
+    };
+
+    let signature = hex::decode(&query.signature).map_err(|_| ServiceError::InvalidToken)?;
+    grant
+        .mac()?
+        .verify_slice(&signature)
+        .map_err(|_| ServiceError::InvalidToken)?;
+
+    if grant.expires_at < chrono::Utc::now().timestamp() {
+        return Err(ServiceError::ExpiredToken);
+    }
+    if let Some(ip) = grant.ip {
+        let peer = request.peer_addr().map(|address| address.ip());
+        if peer != Some(ip) {
+            return Err(ServiceError::Forbidden(
+                "The URL is bound to another address".to_string(),
+            ));
+        }
+    }
+
+    Ok(Some(grant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const PEER: &str = "203.0.113.7:50000";
+
+    fn grant(ip: Option<IpAddr>) -> Grant {
+        let path = "/files/some-id".to_string();
+        Grant::new("alice", Method::GET, path, None, ip, None, false).unwrap()
+    }
+
+    fn request(url: &str) -> HttpRequest {
+        TestRequest::get()
+            .uri(url)
+            .peer_addr(PEER.parse().unwrap())
+            .to_http_request()
+    }
+
+    #[test]
+    fn accepts_signed_urls() {
+        let grant = grant(None);
+        let verified = verify(&request(&grant.url().unwrap())).unwrap().unwrap();
+
+        assert_eq!(verified.username, "alice");
+        assert_eq!(verified.path, grant.path);
+        assert!(verify(&request("/files/some-id")).unwrap().is_none());
+    }
+
+    #[test]
+    fn refuses_tampered_urls() {
+        let url = grant(None).url().unwrap();
+
+        for tampered in [
+            url.replace("user=alice", "user=mallory"),
+            url.replace("/files/some-id", "/files/other-id"),
+            url.replace("expires=", "expires=9"),
+            format!("{}&max_size=1", url),
+        ] {
+            assert!(
+                matches!(verify(&request(&tampered)), Err(ServiceError::InvalidToken)),
+                "{} should be refused",
+                tampered
+            );
+        }
+
+        let put = TestRequest::put().uri(&url).to_http_request();
+        assert!(matches!(verify(&put), Err(ServiceError::InvalidToken)));
+    }
+
+    #[test]
+    fn refuses_unsigned_parameters() {
+        let url = format!("{}&version=1", grant(None).url().unwrap());
+
+        assert!(matches!(
+            verify(&request(&url)),
+            Err(ServiceError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_expired_urls() {
+        let mut grant = grant(None);
+        grant.expires_at = chrono::Utc::now().timestamp() - 1;
+
+        assert!(matches!(
+            verify(&request(&grant.url().unwrap())),
+            Err(ServiceError::ExpiredToken)
+        ));
+    }
+
+    #[test]
+    fn refuses_other_addresses() {
+        let peer = PEER.parse::<std::net::SocketAddr>().unwrap().ip();
+        assert!(verify(&request(&grant(Some(peer)).url().unwrap())).is_ok());
+
+        let other = "198.51.100.1".parse().unwrap();
+        assert!(matches!(
+            verify(&request(&grant(Some(other)).url().unwrap())),
+            Err(ServiceError::Forbidden(_))
+        ));
+    }
+}
@@ -1,8 +1,23 @@
 pub mod api;
+pub mod archive;
+pub mod audit;
+pub mod collab;
 pub mod constants;
+pub mod dav;
+pub mod jobs;
 pub mod jwt;
+pub mod media;
+pub mod metrics;
 pub mod middleware;
 pub mod redis;
+pub mod s3;
+pub mod scan;
+pub mod scheduler;
+pub mod search;
 pub mod storage;
+pub mod sync;
+#[cfg(test)]
+pub mod testing;
 pub mod user;
 pub mod utils;
+pub mod webhooks;
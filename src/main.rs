@@ -3,8 +3,17 @@ use actix_web::rt::System;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer};
 use doc_storage::api::handler::endpoints;
+use doc_storage::collab::room::Rooms;
+use doc_storage::constants::{AUDIT_SECRET, JOB_WORKERS, PRESIGN_SECRET};
+use doc_storage::dav;
+use doc_storage::jobs::models::Queue;
+use doc_storage::jobs::worker::{self, WorkerContext, WorkerPool};
 use doc_storage::middleware::auth::AuthenticationMiddleware;
 use doc_storage::redis::client::RedisClient;
+use doc_storage::s3;
+use doc_storage::scheduler::runner::Scheduler;
+use doc_storage::search::index::SearchIndex;
+use doc_storage::storage::layout;
 use std::env;
 use std::sync::Arc;
 use tokio::runtime::Builder;
@@ -18,17 +27,44 @@ fn main() -> std::io::Result<()> {
         .parse::<usize>()
         .unwrap();
 
-    log::info!("Starting server with {} worker threads...", worker_threads);
-
-    System::with_tokio_rt(|| {
+    let system = System::with_tokio_rt(|| {
         Builder::new_multi_thread()
             .thread_name("doc-storage-worker")
             .worker_threads(worker_threads)
             .enable_all()
             .build()
             .expect("Failed to create Tokio runtime")
-    })
-    .block_on(async_bootstrap(worker_threads))
+    });
+
+    match env::args().nth(1).as_deref() {
+        Some("rebuild") => system.block_on(async_rebuild()),
+        _ => {
+            log::info!("Starting server with {} worker threads...", worker_threads);
+            system.block_on(async_bootstrap(worker_threads))
+        }
+    }
+}
+
+async fn async_rebuild() -> std::io::Result<()> {
+    let blobs = layout::from_env().expect("Failed to open the blob store");
+
+    log::info!("Rebuilding storage redundancy...");
+    let report = blobs
+        .rebuild()
+        .await
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))?;
+
+    log::info!(
+        "Rebuild finished: {} blobs scanned, {} copies restored, {} unrecoverable",
+        report.scanned,
+        report.restored,
+        report.unrecoverable.len()
+    );
+    for key in report.unrecoverable {
+        log::error!("Blob {} could not be recovered", key);
+    }
+
+    Ok(())
 }
 
 async fn async_bootstrap(worker_threads: usize) -> std::io::Result<()> {
@@ -36,14 +72,31 @@ async fn async_bootstrap(worker_threads: usize) -> std::io::Result<()> {
     let port = env::var("DOC_STORAGE_PORT").unwrap_or_else(|_| "8080".to_string());
     let address = format!("{}:{}", host, port);
 
-    let redis_host = env::var("REDIS_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let redis_port = env::var("REDIS_PORT").unwrap_or_else(|_| "6379".to_string());
-    let redis_password = env::var("REDIS_PASSWORD").unwrap_or_else(|_| "nullptr-rs".to_string());
-    let redis_address = format!("redis://:{}@{}:{}", redis_password, redis_host, redis_port);
+    lazy_static::initialize(&AUDIT_SECRET);
+    if PRESIGN_SECRET.is_none() {
+        log::warn!("DOC_STORAGE_PRESIGN_SECRET is not set, pre-signed URLs are disabled");
+    }
 
-    let redis = Arc::new(
-        RedisClient::new(redis_address).expect("Failed to connect to Redis. Is it running?"),
-    );
+    let redis =
+        Arc::new(RedisClient::from_env().expect("Failed to connect to Redis. Is it running?"));
+
+    let blobs = layout::from_env().expect("Failed to open the blob store");
+
+    let index_path = env::var("DOC_STORAGE_INDEX_PATH").unwrap_or_else(|_| "./index".to_string());
+    let search = Arc::new(SearchIndex::new(index_path).expect("Failed to open the search index"));
+
+    let rooms = Arc::new(Rooms::new(redis.clone(), blobs.clone()));
+
+    let scheduler = Arc::new(Scheduler::new(redis.clone(), blobs.clone()));
+    tokio::spawn(scheduler.clone().run_forever());
+
+    let context = WorkerContext {
+        redis: redis.clone(),
+        blobs: blobs.clone(),
+        search: Some(search.clone()),
+    };
+    let queues = worker::queues_from_env(&Queue::ALL);
+    tokio::spawn(WorkerPool::new(context, queues, *JOB_WORKERS).run());
 
     log::info!("Starting server on {}...", &address);
 
@@ -53,7 +106,13 @@ async fn async_bootstrap(worker_threads: usize) -> std::io::Result<()> {
             .wrap(Compress::default())
             .wrap(AuthenticationMiddleware::new())
             .app_data(Data::new(redis.clone()))
+            .app_data(Data::new(blobs.clone()))
+            .app_data(Data::new(search.clone()))
+            .app_data(Data::new(rooms.clone()))
+            .app_data(Data::new(scheduler.clone()))
             .service(endpoints::register_endpoints())
+            .service(dav::handler::register_endpoints())
+            .service(s3::handler::register_endpoints())
     })
     .workers(worker_threads)
     .bind(address)?
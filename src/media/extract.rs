@@ -0,0 +1,224 @@
+use crate::media::jpeg;
+use crate::storage::content_type;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+/// Attributes extracted from the content of a file start with this prefix, so they don't
+/// collide with the ones users set.
+pub const ATTRIBUTE_PREFIX: &str = "media.";
+
+const MAX_VALUE_LENGTH: usize = 256;
+
+/// IPTC datasets of the application record (2).
+const IPTC_DATE_CREATED: u8 = 55;
+const IPTC_TIME_CREATED: u8 = 60;
+const IPTC_LOCATION: [u8; 5] = [90, 92, 95, 100, 101];
+
+pub fn is_media_attribute(key: &str) -> bool {
+    key.starts_with(ATTRIBUTE_PREFIX)
+}
+
+/// Reads the capture date, camera and dimensions of an image from its EXIF, XMP and IPTC
+/// metadata, in that order of preference. Coordinates are never stored, only whether the
+/// image has a location.
+pub fn extract(content_type: &str, data: &[u8]) -> BTreeMap<String, String> {
+    let mut attributes = BTreeMap::new();
+    if !content_type::essence(content_type).starts_with("image/") {
+        return attributes;
+    }
+
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok();
+    let xmp = xmp_packet(data);
+    let iptc = iptc_datasets(data);
+
+    let captured_at = exif
+        .as_ref()
+        .and_then(|exif| exif_date(exif, exif::Tag::DateTimeOriginal))
+        .or_else(|| {
+            exif.as_ref()
+                .and_then(|exif| exif_date(exif, exif::Tag::DateTime))
+        })
+        .or_else(|| xmp.and_then(|xmp| xmp_value(xmp, "exif:DateTimeOriginal")))
+        .or_else(|| xmp.and_then(|xmp| xmp_value(xmp, "photoshop:DateCreated")))
+        .or_else(|| xmp.and_then(|xmp| xmp_value(xmp, "xmp:CreateDate")))
+        .or_else(|| iptc_date(&iptc));
+
+    let make = exif
+        .as_ref()
+        .and_then(|exif| exif_text(exif, exif::Tag::Make))
+        .or_else(|| xmp.and_then(|xmp| xmp_value(xmp, "tiff:Make")));
+    let model = exif
+        .as_ref()
+        .and_then(|exif| exif_text(exif, exif::Tag::Model))
+        .or_else(|| xmp.and_then(|xmp| xmp_value(xmp, "tiff:Model")));
+    // Models usually repeat the make, e.g. "Canon" and "Canon EOS 5D".
+    let camera = match (make, model) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+
+    let has_location = exif
+        .as_ref()
+        .map(|exif| {
+            exif.get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY)
+                .is_some()
+        })
+        .unwrap_or(false)
+        || xmp
+            .map(|xmp| xmp.contains("exif:GPSLatitude"))
+            .unwrap_or(false)
+        || iptc
+            .iter()
+            .any(|(dataset, _)| IPTC_LOCATION.contains(dataset));
+
+    let dimensions = image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+
+    let mut set = |key: &str, value: String| {
+        let value = value.trim();
+        if !value.is_empty() {
+            let value = value.chars().take(MAX_VALUE_LENGTH).collect();
+            attributes.insert(format!("{}{}", ATTRIBUTE_PREFIX, key), value);
+        }
+    };
+    if let Some(captured_at) = captured_at {
+        set("captured_at", captured_at);
+    }
+    if let Some(camera) = camera {
+        set("camera", camera);
+    }
+    if let Some((width, height)) = dimensions {
+        set("width", width.to_string());
+        set("height", height.to_string());
+    }
+    if has_location {
+        set("has_location", "true".to_string());
+    }
+
+    attributes
+}
+
+fn exif_text(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string()),
+        _ => None,
+    }
+}
+
+fn exif_date(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    let value = match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values.first()?.clone(),
+        _ => return None,
+    };
+    let date = exif::DateTime::from_ascii(&value).ok()?;
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.year, date.month, date.day, date.hour, date.minute, date.second
+    ))
+}
+
+/// The XMP packet embedded in the file, wherever the format keeps it.
+fn xmp_packet(data: &[u8]) -> Option<&str> {
+    let start = find(data, b"<x:xmpmeta")?;
+    let end = start + find(&data[start..], b"</x:xmpmeta>")?;
+
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+/// Reads a simple property, written either as an attribute or as an element.
+fn xmp_value(packet: &str, name: &str) -> Option<String> {
+    let attribute = format!("{}=\"", name);
+    if let Some(start) = packet.find(&attribute) {
+        let value = &packet[start + attribute.len()..];
+        return value.split('"').next().map(str::to_string);
+    }
+
+    let element = format!("<{}>", name);
+    let start = packet.find(&element)?;
+    let value = &packet[start + element.len()..];
+    value.split('<').next().map(str::to_string)
+}
+
+/// The datasets of the IPTC application record, which JPEGs keep in a Photoshop resource.
+fn iptc_datasets(data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let resources = jpeg::segments(data).and_then(|(segments, _)| {
+        segments
+            .into_iter()
+            .filter(|segment| segment.marker == jpeg::APP13)
+            .map(|segment| segment.payload(data))
+            .find_map(|payload| payload.strip_prefix(b"Photoshop 3.0\0"))
+    });
+
+    let mut datasets = Vec::new();
+    if let Some(iim) = resources.and_then(iptc_resource) {
+        let mut position = 0;
+        while position + 5 <= iim.len() && iim[position] == 0x1c {
+            let (record, dataset) = (iim[position + 1], iim[position + 2]);
+            let length = u16::from_be_bytes([iim[position + 3], iim[position + 4]]) as usize;
+            // Extended lengths only occur for large binary datasets.
+            if length & 0x8000 != 0 || position + 5 + length > iim.len() {
+                break;
+            }
+
+            if record == 2 {
+                datasets.push((dataset, iim[position + 5..position + 5 + length].to_vec()));
+            }
+            position += 5 + length;
+        }
+    }
+
+    datasets
+}
+
+/// Finds the IPTC-NAA resource (0x0404) among the image resource blocks.
+fn iptc_resource(resources: &[u8]) -> Option<&[u8]> {
+    let mut position = 0;
+
+    while resources.get(position..position + 4)? == b"8BIM" {
+        let id = u16::from_be_bytes([*resources.get(position + 4)?, *resources.get(position + 5)?]);
+        // The name is a Pascal string padded to an even length.
+        let name_length = *resources.get(position + 6)? as usize;
+        let size_at = position + 6 + (name_length + 2) / 2 * 2;
+        let size =
+            u32::from_be_bytes(resources.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let data = resources.get(size_at + 4..size_at + 4 + size)?;
+
+        if id == 0x0404 {
+            return Some(data);
+        }
+        position = size_at + 4 + (size + 1) / 2 * 2;
+    }
+
+    None
+}
+
+fn iptc_date(datasets: &[(u8, Vec<u8>)]) -> Option<String> {
+    let value = |number: u8| {
+        datasets
+            .iter()
+            .find(|(dataset, _)| *dataset == number)
+            .map(|(_, value)| String::from_utf8_lossy(value).to_string())
+    };
+
+    let date = value(IPTC_DATE_CREATED).filter(|date| date.len() == 8 && date.is_ascii())?;
+    let mut result = format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8]);
+    if let Some(time) = value(IPTC_TIME_CREATED).filter(|time| time.len() >= 6 && time.is_ascii()) {
+        result.push_str(&format!("T{}:{}:{}", &time[0..2], &time[2..4], &time[4..6]));
+    }
+
+    Some(result)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,124 @@
+pub const APP0: u8 = 0xe0;
+pub const APP1: u8 = 0xe1;
+pub const APP2: u8 = 0xe2;
+pub const APP13: u8 = 0xed;
+pub const APP14: u8 = 0xee;
+pub const COM: u8 = 0xfe;
+
+const SOS: u8 = 0xda;
+const EOI: u8 = 0xd9;
+
+/// A marker segment, from its `0xFF` byte to the end of its payload.
+pub struct Segment {
+    pub marker: u8,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Segment {
+    /// The payload, after the marker and the length.
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        data.get(self.start + 4..self.end).unwrap_or_default()
+    }
+}
+
+/// The segments before the image data, and the offset the image data starts at.
+pub fn segments(data: &[u8]) -> Option<(Vec<Segment>, usize)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    let mut position = 2;
+    loop {
+        if *data.get(position)? != 0xff {
+            return None;
+        }
+
+        match *data.get(position + 1)? {
+            // Fill bytes may precede a marker.
+            0xff => position += 1,
+            SOS | EOI => return Some((segments, position)),
+            _ => {
+                let segment = segment_at(data, position)?;
+                position = segment.end;
+                segments.push(segment);
+            }
+        }
+    }
+}
+
+/// The segments from the first scan to the end of the image, which is where the `EOI` marker
+/// is. A scan segment also spans the entropy-coded data following its header, so anything
+/// appended after the image is left out.
+pub fn image_segments(data: &[u8], start: usize) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut position = start;
+    loop {
+        if *data.get(position)? != 0xff {
+            return None;
+        }
+
+        match *data.get(position + 1)? {
+            0xff => position += 1,
+            EOI => {
+                segments.push(Segment {
+                    marker: EOI,
+                    start: position,
+                    end: position + 2,
+                });
+                return Some(segments);
+            }
+            SOS => {
+                let mut segment = segment_at(data, position)?;
+                segment.end = entropy_end(data, segment.end)?;
+                position = segment.end;
+                segments.push(segment);
+            }
+            _ => {
+                let segment = segment_at(data, position)?;
+                position = segment.end;
+                segments.push(segment);
+            }
+        }
+    }
+}
+
+fn segment_at(data: &[u8], position: usize) -> Option<Segment> {
+    let marker = *data.get(position + 1)?;
+    if let 0x01 | 0xd0..=0xd7 = marker {
+        return Some(Segment {
+            marker,
+            start: position,
+            end: position + 2,
+        });
+    }
+
+    let length = u16::from_be_bytes([*data.get(position + 2)?, *data.get(position + 3)?]);
+    let end = position + 2 + length as usize;
+    if length < 2 || end > data.len() {
+        return None;
+    }
+
+    Some(Segment {
+        marker,
+        start: position,
+        end,
+    })
+}
+
+/// Entropy-coded data runs up to the first marker that is neither a stuffed `0xFF` byte nor
+/// a restart marker.
+fn entropy_end(data: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        position += data
+            .get(position..)?
+            .iter()
+            .position(|byte| *byte == 0xff)?;
+
+        match *data.get(position + 1)? {
+            0x00 | 0xd0..=0xd7 => position += 2,
+            _ => return Some(position),
+        }
+    }
+}
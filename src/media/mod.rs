@@ -0,0 +1,3 @@
+pub mod extract;
+pub mod jpeg;
+pub mod strip;
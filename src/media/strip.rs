@@ -0,0 +1,374 @@
+use crate::api::utils::errors::ServiceError;
+use crate::media::jpeg;
+use crate::storage::blob::BlobStore;
+use crate::storage::{content, content_type};
+use std::io::Cursor;
+use std::sync::Arc;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+/// Text, timestamp and EXIF chunks. Color profiles and the image itself are kept.
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// Color profiles are the only APP2 segments kept, others hold FlashPix or MPF data.
+const ICC_PROFILE: &[u8] = b"ICC_PROFILE\0";
+
+/// Application extensions of GIF images that are kept: animation loops and color profiles.
+const GIF_APPLICATIONS: [&[u8; 11]; 3] = [b"NETSCAPE2.0", b"ANIMEXTS1.0", b"ICCRGBG1012"];
+
+/// Flags of the VP8X chunk announcing EXIF and XMP metadata.
+const WEBP_EXIF_FLAG: u8 = 0x08;
+const WEBP_XMP_FLAG: u8 = 0x04;
+
+/// Bumped whenever stripping removes more, so copies made before are made again.
+pub fn stripped_key(hash: &str) -> String {
+    format!("{}.stripped2", hash)
+}
+
+/// Serves a copy of an image without its EXIF, XMP and IPTC metadata, stored next to the
+/// original once it has been made.
+pub async fn stripped_copy(
+    blobs: Arc<dyn BlobStore>,
+    hash: &str,
+    content_type: &str,
+) -> Result<Vec<u8>, ServiceError> {
+    if let Ok(data) = blobs.get(&stripped_key(hash)).await {
+        return Ok(data);
+    }
+
+    let data = content::read_content(blobs.as_ref(), hash)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to read the file".to_string(), Some(error))
+        })?;
+
+    let essence = content_type::essence(content_type);
+    let stripped = tokio::task::spawn_blocking(move || strip(&essence, data))
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to remove the metadata".to_string(),
+                Some(error.into()),
+            )
+        })?
+        .ok_or_else(|| {
+            ServiceError::UnsupportedMediaType(format!(
+                "Metadata cannot be removed from {} files",
+                content_type
+            ))
+        })?;
+
+    if let Err(error) = blobs.put(&stripped_key(hash), &stripped).await {
+        log::warn!("Failed to store the stripped copy of {}: {}", hash, error);
+    }
+    // Left behind by versions that kept trailing data and GIF metadata.
+    let outdated = format!("{}.stripped", hash);
+    if let Err(error) = blobs.delete(&outdated).await {
+        log::warn!("Failed to remove {}: {}", outdated, error);
+    }
+
+    Ok(stripped)
+}
+
+/// Removes the metadata of an image without re-encoding it. Returns `None` for formats
+/// whose metadata cannot be removed, so they are never served with it by mistake.
+pub fn strip(content_type: &str, data: Vec<u8>) -> Option<Vec<u8>> {
+    match content_type {
+        "image/jpeg" => strip_jpeg(&data),
+        "image/png" => strip_png(&data),
+        "image/webp" => strip_webp(&data),
+        "image/gif" => strip_gif(&data),
+        // There is no place for camera or location data in the format.
+        "image/bmp" => Some(data),
+        _ => None,
+    }
+}
+
+/// Drops every application segment but JFIF, the color profile and the Adobe color transform,
+/// along with comments. The orientation is written back in a minimal EXIF segment so the
+/// image still displays upright. Anything appended after the end of the image is dropped too.
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    let (segments, image_data) = jpeg::segments(data)?;
+    let image = jpeg::image_segments(data, image_data)?;
+    let orientation = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+                .value
+                .get_uint(0)
+        })
+        .filter(|orientation| (2..=8).contains(orientation));
+
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(&data[..2]);
+
+    let mut segments = segments.into_iter().peekable();
+    while let Some(segment) = segments.next_if(|segment| segment.marker == jpeg::APP0) {
+        stripped.extend_from_slice(&data[segment.start..segment.end]);
+    }
+    if let Some(orientation) = orientation {
+        stripped.extend_from_slice(&orientation_segment(orientation as u16));
+    }
+
+    for segment in segments.chain(image) {
+        if !is_jpeg_metadata(&segment, data) {
+            stripped.extend_from_slice(&data[segment.start..segment.end]);
+        }
+    }
+
+    Some(stripped)
+}
+
+fn is_jpeg_metadata(segment: &jpeg::Segment, data: &[u8]) -> bool {
+    match segment.marker {
+        jpeg::COM => true,
+        jpeg::APP2 => !segment.payload(data).starts_with(ICC_PROFILE),
+        jpeg::APP14 => false,
+        marker => (jpeg::APP0..=0xef).contains(&marker),
+    }
+}
+
+/// An APP1 segment holding a big-endian TIFF structure with a single IFD entry.
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut payload = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+    payload.extend_from_slice(&1u16.to_be_bytes());
+    // Tag 0x0112 (Orientation), type SHORT, one value, padded to four bytes.
+    payload.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+    payload.extend_from_slice(&orientation.to_be_bytes());
+    payload.extend_from_slice(&[0x00, 0x00]);
+    // No further IFD.
+    payload.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+
+    let mut segment = vec![0xff, jpeg::APP1];
+    segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    segment.extend_from_slice(&payload);
+    segment
+}
+
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut position = PNG_SIGNATURE.len();
+    while position < data.len() {
+        let length = u32::from_be_bytes(data.get(position..position + 4)?.try_into().ok()?);
+        let kind = data.get(position + 4..position + 8)?;
+        // Length, type, data and CRC.
+        let end = position.checked_add(12 + length as usize)?;
+        let chunk = data.get(position..end)?;
+
+        if !PNG_METADATA_CHUNKS
+            .iter()
+            .any(|metadata| metadata[..] == *kind)
+        {
+            stripped.extend_from_slice(chunk);
+        }
+        position = end;
+    }
+
+    Some(stripped)
+}
+
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(..4)? != b"RIFF" || data.get(8..12)? != b"WEBP" {
+        return None;
+    }
+
+    let mut stripped = data[..12].to_vec();
+    let mut position = 12;
+    while position < data.len() {
+        let kind = data.get(position..position + 4)?;
+        let size = u32::from_le_bytes(data.get(position + 4..position + 8)?.try_into().ok()?);
+        // Chunks are padded to an even size.
+        let end = position.checked_add(8 + (size as usize + 1) / 2 * 2)?;
+        let chunk = data.get(position..end.min(data.len()))?;
+
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if chunk.len() > 8 => {
+                let start = stripped.len();
+                stripped.extend_from_slice(chunk);
+                stripped[start + 8] &= !(WEBP_EXIF_FLAG | WEBP_XMP_FLAG);
+            }
+            _ => stripped.extend_from_slice(chunk),
+        }
+        position = end;
+    }
+
+    let size = (stripped.len() - 8) as u32;
+    stripped[4..8].copy_from_slice(&size.to_le_bytes());
+
+    Some(stripped)
+}
+
+/// Drops comments and application extensions such as XMP packets, keeping the blocks that
+/// make up the image. Anything after the trailer is dropped too.
+fn strip_gif(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(..6)? != b"GIF87a" && data.get(..6)? != b"GIF89a" {
+        return None;
+    }
+
+    // Header, logical screen descriptor and global color table.
+    let flags = *data.get(10)?;
+    let mut position = 13 + color_table_size(flags);
+    let mut stripped = data.get(..position)?.to_vec();
+
+    loop {
+        match *data.get(position)? {
+            // Image descriptor, local color table, LZW code size and image data.
+            0x2c => {
+                let flags = *data.get(position + 9)?;
+                let end = sub_blocks_end(data, position + 10 + color_table_size(flags) + 1)?;
+                stripped.extend_from_slice(&data[position..end]);
+                position = end;
+            }
+            0x21 => {
+                let label = *data.get(position + 1)?;
+                let end = sub_blocks_end(data, position + 2)?;
+                let keep = match label {
+                    // Comment.
+                    0xfe => false,
+                    // Application, identified by the first sub-block.
+                    0xff => {
+                        let identifier = data.get(position + 3..position + 14)?;
+                        GIF_APPLICATIONS
+                            .iter()
+                            .any(|application| application[..] == *identifier)
+                    }
+                    _ => true,
+                };
+
+                if keep {
+                    stripped.extend_from_slice(&data[position..end]);
+                }
+                position = end;
+            }
+            0x3b => {
+                stripped.push(0x3b);
+                return Some(stripped);
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn color_table_size(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        return 0;
+    }
+
+    3 << ((flags & 0x07) + 1)
+}
+
+/// Skips data sub-blocks, each starting with its size, up to and including the empty one
+/// that ends them.
+fn sub_blocks_end(data: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let size = *data.get(position)? as usize;
+        position += 1 + size;
+
+        if size == 0 {
+            return Some(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    /// Two scans with a stuffed byte and a restart marker, and the given segments between them.
+    fn jpeg(header: &[Vec<u8>], between: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8];
+        data.extend(segment(jpeg::APP0, b"JFIF\0\x01\x01"));
+        data.extend(header.concat());
+        data.extend(segment(0xdb, &[0; 65]));
+        data.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+        data.extend_from_slice(&[0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56]);
+        data.extend(between.concat());
+        data.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+        data.extend_from_slice(&[0x78, 0x9a]);
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    }
+
+    fn contains(data: &[u8], part: &[u8]) -> bool {
+        data.windows(part.len()).any(|window| window == part)
+    }
+
+    fn xmp() -> Vec<u8> {
+        segment(jpeg::APP1, b"http://ns.adobe.com/xap/1.0/\0<x/>")
+    }
+
+    #[test]
+    fn strips_jpeg_metadata() {
+        let icc = segment(jpeg::APP2, b"ICC_PROFILE\0\x01\x01profile");
+        let data = jpeg(
+            &[
+                segment(jpeg::APP1, b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0"),
+                icc.clone(),
+                segment(jpeg::APP2, b"FPXR\0flashpix"),
+                segment(jpeg::APP13, b"Photoshop 3.0\0iptc"),
+                segment(jpeg::COM, b"a comment"),
+            ],
+            &[xmp()],
+        );
+
+        assert_eq!(strip_jpeg(&data).unwrap(), jpeg(&[icc], &[]));
+    }
+
+    #[test]
+    fn drops_data_after_the_end_of_the_image() {
+        let data = jpeg(&[], &[xmp()]);
+        let mut appended = data.clone();
+        appended.extend_from_slice(b"hidden\xff\xd9");
+
+        let stripped = strip_jpeg(&appended).unwrap();
+        assert!(stripped.ends_with(&[0xff, 0xd9]));
+        assert!(!contains(&stripped, b"hidden"));
+        assert!(!contains(&stripped, b"http://ns.adobe.com/xap/1.0/"));
+    }
+
+    #[test]
+    fn keeps_the_orientation_of_jpeg_images() {
+        let data = jpeg(&[orientation_segment(6)], &[]);
+        let stripped = strip_jpeg(&data).unwrap();
+
+        assert!(contains(&stripped, &orientation_segment(6)));
+    }
+
+    #[test]
+    fn refuses_truncated_jpeg_images() {
+        let data = jpeg(&[], &[]);
+        assert!(strip_jpeg(&data[..data.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn strips_gif_comments_and_xmp() {
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        data.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        let netscape = b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00".to_vec();
+        let image = b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00".to_vec();
+
+        let mut gif = data.clone();
+        gif.extend_from_slice(&netscape);
+        gif.extend_from_slice(b"\x21\xff\x0bXMP DataXMP\x05<x/>\x01\x00");
+        gif.extend_from_slice(b"\x21\xfe\x09a comment\x00");
+        gif.extend_from_slice(&image);
+        gif.extend_from_slice(b"\x3bhidden");
+
+        data.extend(netscape);
+        data.extend(image);
+        data.push(0x3b);
+        assert_eq!(strip("image/gif", gif).unwrap(), data);
+    }
+}
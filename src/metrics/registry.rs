@@ -0,0 +1,87 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    value: AtomicU64,
+}
+
+impl Metric {
+    pub const fn counter(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "counter",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub const fn gauge(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "gauge",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+pub static SCRUB_BLOBS_SCANNED: Metric = Metric::counter(
+    "doc_storage_scrub_blobs_scanned_total",
+    "Blobs re-hashed by the integrity scrubber",
+);
+pub static SCRUB_BYTES_SCANNED: Metric = Metric::counter(
+    "doc_storage_scrub_bytes_scanned_total",
+    "Bytes read by the integrity scrubber",
+);
+pub static SCRUB_CORRUPTIONS: Metric = Metric::counter(
+    "doc_storage_scrub_corruptions_total",
+    "Blobs found missing or not matching their hash",
+);
+pub static SCRUB_REPAIRS: Metric = Metric::counter(
+    "doc_storage_scrub_repairs_total",
+    "Corrupted blobs restored from a replica",
+);
+pub static CORRUPTED_BLOBS: Metric = Metric::gauge(
+    "doc_storage_corrupted_blobs",
+    "Blobs currently flagged as corrupted",
+);
+
+static METRICS: [&Metric; 5] = [
+    &SCRUB_BLOBS_SCANNED,
+    &SCRUB_BYTES_SCANNED,
+    &SCRUB_CORRUPTIONS,
+    &SCRUB_REPAIRS,
+    &CORRUPTED_BLOBS,
+];
+
+/// Renders every metric in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut output = String::new();
+
+    for metric in METRICS {
+        let _ = writeln!(output, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(output, "# TYPE {} {}", metric.name, metric.kind);
+        let _ = writeln!(output, "{} {}", metric.name, metric.get());
+    }
+
+    output
+}
@@ -1,12 +1,15 @@
 use crate::api::utils::errors::ServiceError;
 use crate::conditional_return;
-use crate::constants::{BASE_ROUTE, IGNORED_AUTH_ROUTES};
+use crate::constants::{BASE_ROUTE, IGNORED_AUTH_ROUTES, SELF_AUTHENTICATED_ROUTES};
+use crate::jwt::models::Claims;
+use crate::jwt::presigned::{self, PRESIGNED_DEVICE};
 use crate::jwt::token;
 use actix_web::body::MessageBody;
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
-use actix_web::HttpMessage;
+use actix_web::{web, HttpMessage};
 use futures::future::{ready, Future, Ready};
 use jsonwebtoken::errors::ErrorKind;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
@@ -59,16 +62,38 @@ where
         let bypass_auth = IGNORED_AUTH_ROUTES.iter().any(|route| {
             req.path()
                 .starts_with(format!("{}/{}", BASE_ROUTE, route).as_str())
+        }) || SELF_AUTHENTICATED_ROUTES.iter().any(|route| {
+            req.path() == *route || req.path().starts_with(format!("{}/", route).as_str())
         });
 
+        // Pre-signed URLs stand in for the Authorization header, for the single request they
+        // were signed for.
+        if !bypass_auth && !req.headers().contains_key("Authorization") {
+            let grant = presigned::verify(req.request());
+            conditional_return!(grant.is_err(), self.failure(grant.err().unwrap()));
+
+            if let Some(grant) = grant.unwrap() {
+                let claims = Claims::new(grant.username.clone(), PRESIGNED_DEVICE.to_string());
+                req.extensions_mut().insert(claims);
+                req.extensions_mut().insert(grant);
+
+                return Box::pin(self.service.call(req));
+            }
+        }
+
         if !bypass_auth {
-            let auth_header = req.headers().get("Authorization");
+            let auth_header = req
+                .headers()
+                .get("Authorization")
+                .and_then(|header| header.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| websocket_token(&req).map(|token| format!("Bearer {}", token)));
             conditional_return!(
                 auth_header.is_none(),
                 self.failure(ServiceError::MissingToken)
             );
 
-            let auth_header = auth_header.unwrap().to_str().unwrap();
+            let auth_header = auth_header.unwrap();
             conditional_return!(
                 auth_header.is_empty() || !auth_header.starts_with("Bearer"),
                 self.failure(ServiceError::MissingToken)
@@ -115,3 +140,16 @@ where
         Box::pin(async move { Err(error.into()) })
     }
 }
+
+/// Browsers cannot set headers on WebSocket handshakes, they pass the token in the query.
+fn websocket_token(req: &ServiceRequest) -> Option<String> {
+    let upgrade = req.headers().get("Upgrade")?.to_str().ok()?;
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return None;
+    }
+
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()?
+        .into_inner()
+        .remove("access_token")
+}
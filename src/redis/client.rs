@@ -11,6 +11,55 @@ pub enum RedisKey {
     Base,
     Account(String),
     Session(String),
+    File(String),
+    UserFiles(String),
+    FilePath(String, String),
+    FilePathsIndexed(String),
+    PendingBlob(String),
+    GcLock,
+    CorruptedBlobs,
+    ScrubStatus,
+    PreviewPending(String),
+    Folder(String),
+    UserFolders(String),
+    Tag(String, String),
+    Attribute(String, String, String),
+    Usage(String),
+    RefCount(String, String),
+    Job(String),
+    Journal(String),
+    FileLock(String),
+    AccessKey(String),
+    UserAccessKeys(String),
+    MultipartUpload(String),
+    MultipartParts(String),
+    CollabState(String),
+    CollabUpdates(String),
+    CollabReload(String, String),
+    ScanResult(String),
+    ScanPending,
+    ScanPendingIndexed,
+    Quarantine,
+    JobQueue(String),
+    JobProcessing(String),
+    JobRetries(String),
+    JobDeadLetters(String),
+    JobLease(String),
+    JobHashes,
+    SchedulerLeader,
+    ScheduledTask(String),
+    ScheduledTaskLock(String),
+    ScheduleCursor(String),
+    Webhook(String),
+    UserWebhooks(String),
+    WebhookDelivery(String),
+    WebhookDeliveries(String),
+    AuditLog,
+    AuditHead,
+    UnknownLogins(String),
+    UserAuditLog(String),
+    RecentFiles(String),
+    RecentDeviceFiles(String, String),
     Other(String),
 }
 
@@ -26,6 +75,15 @@ impl RedisClient {
         Ok(RedisClient { client })
     }
 
+    /// Connects to the instance configured with `REDIS_HOST`, `REDIS_PORT` and `REDIS_PASSWORD`.
+    pub fn from_env() -> Result<RedisClient, anyhow::Error> {
+        let host = std::env::var("REDIS_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+        let port = std::env::var("REDIS_PORT").unwrap_or_else(|_| "6379".to_string());
+        let password = std::env::var("REDIS_PASSWORD").unwrap_or_else(|_| "nullptr-rs".to_string());
+
+        Self::new(format!("redis://:{}@{}:{}", password, host, port))
+    }
+
     pub async fn execute_raw<T: FromRedisValue>(
         &self,
         cmd: &mut redis::Cmd,
@@ -53,6 +111,62 @@ impl RedisClient {
         })
     }
 
+    /// Runs `pipeline` inside MULTI/EXEC, provided every watched key still holds the value it
+    /// was read with. Returns `false` without applying anything when one of them changed, in
+    /// which case the caller should read the keys again and start over.
+    pub async fn async_compare_and_exec(
+        &self,
+        expected: &[(RedisKey, Option<String>)],
+        pipeline: &mut redis::Pipeline,
+    ) -> Result<bool, ServiceError> {
+        self.compare_and_exec(expected, pipeline)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to interact with the database".to_string(),
+                    Some(error),
+                )
+            })
+    }
+
+    async fn compare_and_exec(
+        &self,
+        expected: &[(RedisKey, Option<String>)],
+        pipeline: &mut redis::Pipeline,
+    ) -> Result<bool, anyhow::Error> {
+        let mut connection = self.client.get_async_connection().await?;
+
+        let keys = expected
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        if !keys.is_empty() {
+            redis::cmd("WATCH")
+                .arg(&keys)
+                .query_async::<_, ()>(&mut connection)
+                .await?;
+        }
+
+        for (key, value) in keys.iter().zip(expected.iter().map(|(_, value)| value)) {
+            let current: Option<String> = redis::cmd("GET")
+                .arg(key)
+                .query_async(&mut connection)
+                .await?;
+
+            if current != *value {
+                redis::cmd("UNWATCH")
+                    .query_async::<_, ()>(&mut connection)
+                    .await?;
+                return Ok(false);
+            }
+        }
+
+        let result: Option<Vec<redis::Value>> =
+            pipeline.atomic().query_async(&mut connection).await?;
+
+        Ok(result.is_some())
+    }
+
     pub async fn async_get(&self, key: RedisKey) -> Result<String, ServiceError> {
         self.execute(redis::cmd("GET").arg(key.to_string())).await
     }
@@ -73,6 +187,18 @@ impl RedisClient {
         Ok(result)
     }
 
+    pub async fn async_mget(
+        &self,
+        keys: Vec<RedisKey>,
+    ) -> Result<Vec<Option<String>>, ServiceError> {
+        let mut cmd = redis::cmd("MGET");
+        for key in keys {
+            cmd.arg(key.to_string());
+        }
+
+        self.execute(&mut cmd).await
+    }
+
     pub async fn async_set(&self, key: RedisKey, value: &str) -> Result<String, ServiceError> {
         self.execute(
             redis::cmd("SET")
@@ -110,6 +236,209 @@ impl RedisClient {
         self.execute(redis::cmd("EXPIRE").arg(key.to_string()).arg(seconds))
             .await
     }
+
+    pub async fn async_set_nx_ex(
+        &self,
+        key: RedisKey,
+        value: &str,
+        seconds: u32,
+    ) -> Result<bool, ServiceError> {
+        let result: Option<String> = self
+            .execute(
+                redis::cmd("SET")
+                    .arg(key.to_string())
+                    .arg(value.to_string())
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(seconds),
+            )
+            .await?;
+
+        Ok(result.is_some())
+    }
+
+    pub async fn async_sadd(&self, key: RedisKey, member: &str) -> Result<bool, ServiceError> {
+        self.execute(redis::cmd("SADD").arg(key.to_string()).arg(member))
+            .await
+    }
+
+    pub async fn async_srem(&self, key: RedisKey, member: &str) -> Result<bool, ServiceError> {
+        self.execute(redis::cmd("SREM").arg(key.to_string()).arg(member))
+            .await
+    }
+
+    pub async fn async_sismember(&self, key: RedisKey, member: &str) -> Result<bool, ServiceError> {
+        self.execute(redis::cmd("SISMEMBER").arg(key.to_string()).arg(member))
+            .await
+    }
+
+    pub async fn async_smembers(&self, key: RedisKey) -> Result<Vec<String>, ServiceError> {
+        self.execute(redis::cmd("SMEMBERS").arg(key.to_string()))
+            .await
+    }
+
+    pub async fn async_sinter(&self, keys: Vec<RedisKey>) -> Result<Vec<String>, ServiceError> {
+        let mut cmd = redis::cmd("SINTER");
+        for key in keys {
+            cmd.arg(key.to_string());
+        }
+
+        self.execute(&mut cmd).await
+    }
+
+    pub async fn async_hset(
+        &self,
+        key: RedisKey,
+        field: &str,
+        value: &str,
+    ) -> Result<bool, ServiceError> {
+        self.execute(
+            redis::cmd("HSET")
+                .arg(key.to_string())
+                .arg(field)
+                .arg(value),
+        )
+        .await
+    }
+
+    pub async fn async_hvals(&self, key: RedisKey) -> Result<Vec<String>, ServiceError> {
+        self.execute(redis::cmd("HVALS").arg(key.to_string())).await
+    }
+
+    /// Appends an entry to a stream, trimming it to roughly `max_length` entries.
+    pub async fn async_xadd(
+        &self,
+        key: RedisKey,
+        field: &str,
+        value: &str,
+        max_length: usize,
+    ) -> Result<String, ServiceError> {
+        self.execute(
+            redis::cmd("XADD")
+                .arg(key.to_string())
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(max_length)
+                .arg("*")
+                .arg(field)
+                .arg(value),
+        )
+        .await
+    }
+
+    pub async fn async_xrange(
+        &self,
+        key: RedisKey,
+        start: &str,
+        count: usize,
+    ) -> Result<Vec<(String, Vec<String>)>, ServiceError> {
+        self.execute(
+            redis::cmd("XRANGE")
+                .arg(key.to_string())
+                .arg(start)
+                .arg("+")
+                .arg("COUNT")
+                .arg(count),
+        )
+        .await
+    }
+
+    pub async fn async_xrevrange(
+        &self,
+        key: RedisKey,
+        end: &str,
+        count: usize,
+    ) -> Result<Vec<(String, Vec<String>)>, ServiceError> {
+        self.execute(
+            redis::cmd("XREVRANGE")
+                .arg(key.to_string())
+                .arg(end)
+                .arg("-")
+                .arg("COUNT")
+                .arg(count),
+        )
+        .await
+    }
+
+    /// Members of a sorted set with their scores, highest score first, skipping the first
+    /// `start` of them.
+    pub async fn async_zrevrange(
+        &self,
+        key: RedisKey,
+        start: usize,
+        count: usize,
+    ) -> Result<Vec<(String, i64)>, ServiceError> {
+        self.execute(
+            redis::cmd("ZREVRANGE")
+                .arg(key.to_string())
+                .arg(start)
+                .arg((start + count) as isize - 1)
+                .arg("WITHSCORES"),
+        )
+        .await
+    }
+
+    pub async fn async_zrem(&self, key: RedisKey, members: &[String]) -> Result<(), ServiceError> {
+        self.execute(redis::cmd("ZREM").arg(key.to_string()).arg(members))
+            .await
+    }
+
+    pub async fn async_publish(
+        &self,
+        channel: RedisKey,
+        message: &[u8],
+    ) -> Result<usize, ServiceError> {
+        self.execute(redis::cmd("PUBLISH").arg(channel.to_string()).arg(message))
+            .await
+    }
+
+    /// Opens a dedicated connection subscribed to `channel`.
+    pub async fn async_subscribe(
+        &self,
+        channel: RedisKey,
+    ) -> Result<redis::aio::PubSub, ServiceError> {
+        let subscribe = async {
+            let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+            pubsub.subscribe(channel.to_string()).await?;
+
+            Ok::<_, redis::RedisError>(pubsub)
+        };
+
+        subscribe.await.map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to subscribe to the channel".to_string(),
+                Some(error.into()),
+            )
+        })
+    }
+
+    pub async fn async_scan(&self, pattern: RedisKey) -> Result<Vec<String>, ServiceError> {
+        let pattern = pattern.to_string();
+        let mut keys = Vec::new();
+        let mut cursor = 0u64;
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = self
+                .execute(
+                    redis::cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(1000),
+                )
+                .await?;
+
+            keys.extend(batch);
+            cursor = next;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
 }
 
 impl Display for RedisKey {
@@ -118,6 +447,104 @@ impl Display for RedisKey {
             RedisKey::Base => write!(f, "doc_storage"),
             RedisKey::Account(username) => write!(f, "{}:account:{}", RedisKey::Base, username),
             RedisKey::Session(session_id) => write!(f, "{}:session:{}", RedisKey::Base, session_id),
+            RedisKey::File(file_id) => write!(f, "{}:file:{}", RedisKey::Base, file_id),
+            RedisKey::UserFiles(username) => write!(f, "{}:files:{}", RedisKey::Base, username),
+            RedisKey::FilePath(username, path) => {
+                write!(f, "{}:path:{}:{}", RedisKey::Base, username, path)
+            }
+            RedisKey::FilePathsIndexed(username) => {
+                write!(f, "{}:paths_indexed:{}", RedisKey::Base, username)
+            }
+            RedisKey::PendingBlob(hash) => write!(f, "{}:pending_blob:{}", RedisKey::Base, hash),
+            RedisKey::GcLock => write!(f, "{}:gc:lock", RedisKey::Base),
+            RedisKey::CorruptedBlobs => write!(f, "{}:scrub:corrupted", RedisKey::Base),
+            RedisKey::ScrubStatus => write!(f, "{}:scrub:status", RedisKey::Base),
+            RedisKey::PreviewPending(hash) => {
+                write!(f, "{}:preview:{}:pending", RedisKey::Base, hash)
+            }
+            RedisKey::Folder(folder_id) => write!(f, "{}:folder:{}", RedisKey::Base, folder_id),
+            RedisKey::UserFolders(username) => write!(f, "{}:folders:{}", RedisKey::Base, username),
+            RedisKey::Tag(username, tag) => {
+                write!(f, "{}:tag:{}:{}", RedisKey::Base, username, tag)
+            }
+            RedisKey::Attribute(username, key, value) => write!(
+                f,
+                "{}:attribute:{}:{}={}",
+                RedisKey::Base,
+                username,
+                key,
+                value
+            ),
+            RedisKey::Usage(username) => write!(f, "{}:usage:{}", RedisKey::Base, username),
+            RedisKey::RefCount(username, hash) => {
+                write!(f, "{}:refcount:{}:{}", RedisKey::Base, username, hash)
+            }
+            RedisKey::Job(job_id) => write!(f, "{}:job:{}", RedisKey::Base, job_id),
+            RedisKey::FileLock(file_id) => write!(f, "{}:lock:{}", RedisKey::Base, file_id),
+            RedisKey::Journal(username) => write!(f, "{}:journal:{}", RedisKey::Base, username),
+            RedisKey::AccessKey(key_id) => write!(f, "{}:access_key:{}", RedisKey::Base, key_id),
+            RedisKey::UserAccessKeys(username) => {
+                write!(f, "{}:access_keys:{}", RedisKey::Base, username)
+            }
+            RedisKey::MultipartUpload(upload_id) => {
+                write!(f, "{}:multipart:{}", RedisKey::Base, upload_id)
+            }
+            RedisKey::MultipartParts(upload_id) => {
+                write!(f, "{}:multipart:{}:parts", RedisKey::Base, upload_id)
+            }
+            RedisKey::CollabState(file_id) => {
+                write!(f, "{}:collab:{}:state", RedisKey::Base, file_id)
+            }
+            RedisKey::CollabUpdates(file_id) => {
+                write!(f, "{}:collab:{}:updates", RedisKey::Base, file_id)
+            }
+            RedisKey::CollabReload(file_id, hash) => {
+                write!(f, "{}:collab:{}:reload:{}", RedisKey::Base, file_id, hash)
+            }
+            RedisKey::ScanResult(hash) => write!(f, "{}:scan:{}", RedisKey::Base, hash),
+            RedisKey::ScanPending => write!(f, "{}:scan:pending", RedisKey::Base),
+            RedisKey::ScanPendingIndexed => write!(f, "{}:scan:pending:indexed", RedisKey::Base),
+            RedisKey::Quarantine => write!(f, "{}:scan:quarantine", RedisKey::Base),
+            RedisKey::JobQueue(queue) => write!(f, "{}:queue:{}", RedisKey::Base, queue),
+            RedisKey::JobProcessing(queue) => {
+                write!(f, "{}:queue:{}:processing", RedisKey::Base, queue)
+            }
+            RedisKey::JobRetries(queue) => write!(f, "{}:queue:{}:retries", RedisKey::Base, queue),
+            RedisKey::JobDeadLetters(queue) => {
+                write!(f, "{}:queue:{}:dead", RedisKey::Base, queue)
+            }
+            RedisKey::JobLease(job_id) => write!(f, "{}:job:{}:lease", RedisKey::Base, job_id),
+            RedisKey::JobHashes => write!(f, "{}:queue:hashes", RedisKey::Base),
+            RedisKey::SchedulerLeader => write!(f, "{}:scheduler:leader", RedisKey::Base),
+            RedisKey::ScheduledTask(task) => write!(f, "{}:scheduler:{}", RedisKey::Base, task),
+            RedisKey::ScheduledTaskLock(task) => {
+                write!(f, "{}:scheduler:{}:lock", RedisKey::Base, task)
+            }
+            RedisKey::ScheduleCursor(task) => {
+                write!(f, "{}:scheduler:{}:cursor", RedisKey::Base, task)
+            }
+            RedisKey::Webhook(webhook_id) => write!(f, "{}:webhook:{}", RedisKey::Base, webhook_id),
+            RedisKey::UserWebhooks(username) => {
+                write!(f, "{}:webhooks:{}", RedisKey::Base, username)
+            }
+            RedisKey::WebhookDelivery(delivery_id) => {
+                write!(f, "{}:webhook_delivery:{}", RedisKey::Base, delivery_id)
+            }
+            RedisKey::WebhookDeliveries(webhook_id) => {
+                write!(f, "{}:webhook:{}:deliveries", RedisKey::Base, webhook_id)
+            }
+            RedisKey::AuditLog => write!(f, "{}:audit", RedisKey::Base),
+            RedisKey::AuditHead => write!(f, "{}:audit:head", RedisKey::Base),
+            RedisKey::UnknownLogins(ip) => {
+                write!(f, "{}:audit:unknown_logins:{}", RedisKey::Base, ip)
+            }
+            RedisKey::UserAuditLog(username) => {
+                write!(f, "{}:audit:user:{}", RedisKey::Base, username)
+            }
+            RedisKey::RecentFiles(username) => write!(f, "{}:recent:{}", RedisKey::Base, username),
+            RedisKey::RecentDeviceFiles(username, device_id) => {
+                write!(f, "{}:recent:{}:{}", RedisKey::Base, username, device_id)
+            }
             RedisKey::Other(key) => write!(f, "{}:{}", RedisKey::Base, key),
         }
     }
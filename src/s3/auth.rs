@@ -0,0 +1,452 @@
+use crate::constants::MAX_REQUEST_SKEW;
+use crate::redis::client::RedisClient;
+use crate::s3::errors::S3Error;
+use crate::user::access_keys::{self, AccessKey};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest};
+use futures::stream::TryStreamExt;
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// SigV4 encodes everything but unreserved characters.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+const UNRESERVED_PATH: &AsciiSet = &UNRESERVED.remove(b'/');
+
+/// How the body of a signed request is protected, from `x-amz-content-sha256`.
+pub enum Payload {
+    Unsigned,
+    Hashed(String),
+    /// An `aws-chunked` body. Chunks are signed one after the other, starting from the
+    /// signature of the request, unless the client opted out of signing them.
+    Chunked(Option<ChunkSigner>),
+}
+
+pub struct ChunkSigner {
+    key: Vec<u8>,
+    date: String,
+    scope: String,
+    seed: String,
+}
+
+pub struct SignedRequest {
+    pub key: AccessKey,
+    pub payload: Payload,
+}
+
+/// Checks the AWS Signature Version 4 of a request against the secret of its access key.
+pub async fn verify(request: &HttpRequest, redis: &RedisClient) -> Result<SignedRequest, S3Error> {
+    let authorization = header(request, "Authorization")
+        .ok_or_else(|| S3Error::access_denied("Anonymous requests are not allowed"))?;
+    let fields = authorization
+        .strip_prefix(ALGORITHM)
+        .ok_or_else(|| malformed("Only AWS Signature Version 4 is supported".to_string()))?;
+
+    let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+    for field in fields.split(',') {
+        match field.trim().split_once('=') {
+            Some(("Credential", value)) => credential = Some(value),
+            Some(("SignedHeaders", value)) => signed_headers = Some(value),
+            Some(("Signature", value)) => signature = Some(value),
+            _ => {}
+        }
+    }
+    let (credential, signed_headers, signature) = match (credential, signed_headers, signature) {
+        (Some(credential), Some(headers), Some(signature)) => (credential, headers, signature),
+        _ => {
+            return Err(malformed(
+                "The authorization header is incomplete".to_string(),
+            ))
+        }
+    };
+
+    let (key_id, scope) = credential
+        .split_once('/')
+        .ok_or_else(|| malformed("The credential is malformed".to_string()))?;
+    let (date, region, service) = match scope.split('/').collect::<Vec<_>>()[..] {
+        [date, region, service, "aws4_request"] => (date, region, service),
+        _ => return Err(malformed("The credential scope is malformed".to_string())),
+    };
+
+    let amz_date = header(request, "x-amz-date")
+        .ok_or_else(|| S3Error::access_denied("The x-amz-date header is missing"))?;
+    let timestamp = chrono::NaiveDateTime::parse_from_str(amz_date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| S3Error::access_denied("The x-amz-date header is malformed"))?
+        .timestamp();
+    if (chrono::Utc::now().timestamp() - timestamp).abs() > MAX_REQUEST_SKEW {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "The difference between the request time and the server's time is too large",
+        ));
+    }
+    if !amz_date.starts_with(date) {
+        return Err(malformed(
+            "The credential date does not match x-amz-date".to_string(),
+        ));
+    }
+
+    let key = access_keys::get_key(redis, key_id).await?.ok_or_else(|| {
+        S3Error::new(
+            StatusCode::FORBIDDEN,
+            "InvalidAccessKeyId",
+            "The access key does not exist",
+        )
+    })?;
+
+    let content_sha256 = header(request, "x-amz-content-sha256")
+        .ok_or_else(|| S3Error::invalid_argument("The x-amz-content-sha256 header is missing"))?;
+    let string_to_sign = string_to_sign(request, signed_headers, content_sha256, amz_date, scope);
+
+    let signing_key = signing_key(&key.secret, date, region, service);
+    if !verify_signature(&signing_key, &string_to_sign, signature) {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "SignatureDoesNotMatch",
+            "The request signature does not match",
+        ));
+    }
+
+    let payload = match content_sha256 {
+        "UNSIGNED-PAYLOAD" => Payload::Unsigned,
+        "STREAMING-UNSIGNED-PAYLOAD-TRAILER" => Payload::Chunked(None),
+        "STREAMING-AWS4-HMAC-SHA256-PAYLOAD" | "STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER" => {
+            Payload::Chunked(Some(ChunkSigner {
+                key: signing_key,
+                date: amz_date.to_string(),
+                scope: scope.to_string(),
+                seed: signature.to_string(),
+            }))
+        }
+        hash if hash.len() == 64 && hex::decode(hash).is_ok() => {
+            Payload::Hashed(hash.to_lowercase())
+        }
+        _ => {
+            return Err(S3Error::invalid_argument(
+                "The x-amz-content-sha256 header is invalid",
+            ))
+        }
+    };
+
+    Ok(SignedRequest { key, payload })
+}
+
+/// Reads the body of a signed request, checking it against its signature. Bodies larger than
+/// `limit` are refused as soon as they go past it, `aws-chunked` ones leaving room for the
+/// framing of their chunks.
+pub async fn read_body(
+    mut body: web::Payload,
+    payload: &Payload,
+    limit: usize,
+) -> Result<Vec<u8>, S3Error> {
+    let read_limit = match payload {
+        Payload::Chunked(_) => limit + limit / 64 + 1024,
+        _ => limit,
+    };
+
+    let mut data = Vec::new();
+    while let Some(chunk) = body.try_next().await.map_err(|error| {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "IncompleteBody",
+            format!("Failed to read the body: {}", error),
+        )
+    })? {
+        if data.len() + chunk.len() > read_limit {
+            return Err(S3Error::entity_too_large(limit));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    match payload {
+        Payload::Unsigned => Ok(data),
+        Payload::Hashed(hash) if hex::encode(Sha256::digest(&data)) == *hash => Ok(data),
+        Payload::Hashed(_) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The body does not match x-amz-content-sha256",
+        )),
+        Payload::Chunked(signer) => match decode_chunks(&data, signer.as_ref())? {
+            data if data.len() > limit => Err(S3Error::entity_too_large(limit)),
+            data => Ok(data),
+        },
+    }
+}
+
+/// Decodes an `aws-chunked` body: `<hex size>[;chunk-signature=<signature>]\r\n<data>\r\n`
+/// repeated, ending with an empty chunk. Trailers after the last chunk are ignored.
+fn decode_chunks(mut rest: &[u8], signer: Option<&ChunkSigner>) -> Result<Vec<u8>, S3Error> {
+    let incomplete = || {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "IncompleteBody",
+            "The chunked body is malformed",
+        )
+    };
+
+    let mut data = Vec::new();
+    let mut previous = signer.map(|signer| signer.seed.clone());
+    loop {
+        let end = rest
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(incomplete)?;
+        let header = std::str::from_utf8(&rest[..end]).map_err(|_| incomplete())?;
+        rest = &rest[end + 2..];
+
+        let (size, extensions) = header.split_once(';').unwrap_or((header, ""));
+        let size = usize::from_str_radix(size.trim(), 16).map_err(|_| incomplete())?;
+        if rest.len() < size {
+            return Err(incomplete());
+        }
+        let (chunk, remaining) = rest.split_at(size);
+        rest = remaining;
+
+        if let (Some(signer), Some(previous)) = (signer, previous.as_mut()) {
+            let signature = extensions
+                .split(';')
+                .find_map(|extension| extension.trim().strip_prefix("chunk-signature="))
+                .ok_or_else(incomplete)?;
+
+            let string_to_sign = format!(
+                "{}-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+                ALGORITHM,
+                signer.date,
+                signer.scope,
+                previous,
+                EMPTY_SHA256,
+                hex::encode(Sha256::digest(chunk))
+            );
+            if !verify_signature(&signer.key, &string_to_sign, signature) {
+                return Err(S3Error::new(
+                    StatusCode::FORBIDDEN,
+                    "SignatureDoesNotMatch",
+                    "A chunk signature does not match",
+                ));
+            }
+            *previous = signature.to_string();
+        }
+
+        if size == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(chunk);
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(incomplete)?;
+    }
+}
+
+/// What the client signed: the canonical form of the request, hashed, along with its time
+/// and credential scope.
+fn string_to_sign(
+    request: &HttpRequest,
+    signed_headers: &str,
+    content_sha256: &str,
+    amz_date: &str,
+    scope: &str,
+) -> String {
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method().as_str(),
+        canonical_path(request.uri().path()),
+        canonical_query(request.query_string()),
+        canonical_headers(request, signed_headers),
+        signed_headers,
+        content_sha256
+    );
+
+    format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    )
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let mut key = format!("AWS4{}", secret).into_bytes();
+
+    for part in [date, region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes());
+    }
+
+    key
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Compares in constant time, so signatures cannot be guessed byte by byte.
+fn verify_signature(key: &[u8], string_to_sign: &str, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(string_to_sign.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn canonical_path(path: &str) -> String {
+    let decoded = percent_decode_str(path).decode_utf8_lossy();
+    utf8_percent_encode(&decoded, UNRESERVED_PATH).to_string()
+}
+
+fn canonical_query(query: &str) -> String {
+    let mut pairs = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (encode(key), encode(value))
+        })
+        .collect::<Vec<_>>();
+    pairs.sort();
+
+    pairs
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn encode(value: &str) -> String {
+    let decoded = percent_decode_str(value).decode_utf8_lossy();
+    utf8_percent_encode(&decoded, UNRESERVED).to_string()
+}
+
+fn canonical_headers(request: &HttpRequest, signed_headers: &str) -> String {
+    signed_headers
+        .split(';')
+        .map(|name| {
+            let value = request
+                .headers()
+                .get_all(name)
+                .filter_map(|value| value.to_str().ok())
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            format!("{}:{}\n", name, value)
+        })
+        .collect()
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn malformed(message: String) -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        "AuthorizationHeaderMalformed",
+        message,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    // The examples from the AWS documentation of Signature Version 4 for S3.
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const DATE: &str = "20130524T000000Z";
+    const SCOPE: &str = "20130524/us-east-1/s3/aws4_request";
+
+    fn key() -> Vec<u8> {
+        signing_key(SECRET, "20130524", "us-east-1", "s3")
+    }
+
+    #[test]
+    fn verifies_a_signed_request() {
+        let request = TestRequest::get()
+            .uri("/test.txt")
+            .insert_header(("Host", "examplebucket.s3.amazonaws.com"))
+            .insert_header(("Range", "bytes=0-9"))
+            .insert_header(("x-amz-content-sha256", EMPTY_SHA256))
+            .insert_header(("x-amz-date", DATE))
+            .to_http_request();
+        let signed = string_to_sign(
+            &request,
+            "host;range;x-amz-content-sha256;x-amz-date",
+            EMPTY_SHA256,
+            DATE,
+            SCOPE,
+        );
+
+        let signature = "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41";
+        assert!(verify_signature(&key(), &signed, signature));
+        assert!(!verify_signature(
+            &key(),
+            &signed,
+            &signature.replace('f', "e")
+        ));
+        assert!(!verify_signature(&key(), &signed, "not hex"));
+    }
+
+    #[test]
+    fn encodes_the_query_canonically() {
+        assert_eq!(
+            canonical_query("prefix=a%20b&list-type=2&marker="),
+            "list-type=2&marker=&prefix=a%20b"
+        );
+        assert_eq!(canonical_path("/bucket/a b+c"), "/bucket/a%20b%2Bc");
+    }
+
+    #[test]
+    fn verifies_signed_chunks() {
+        let signer = ChunkSigner {
+            key: key(),
+            date: DATE.to_string(),
+            scope: SCOPE.to_string(),
+            seed: "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9".to_string(),
+        };
+
+        let mut body = Vec::new();
+        for (size, signature) in [
+            (
+                65536,
+                "ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648",
+            ),
+            (
+                1024,
+                "0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497",
+            ),
+            (
+                0,
+                "b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9",
+            ),
+        ] {
+            body.extend_from_slice(
+                format!("{:x};chunk-signature={}\r\n", size, signature).as_bytes(),
+            );
+            body.extend(vec![b'a'; size]);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        let data = decode_chunks(&body, Some(&signer)).unwrap();
+        assert_eq!(data.len(), 65536 + 1024);
+
+        // Swapping a byte of the first chunk breaks its signature.
+        body[100] = b'b';
+        assert!(decode_chunks(&body, Some(&signer)).is_err());
+        assert!(decode_chunks(&body, None).is_ok());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::api::utils::errors::ServiceError;
+use crate::s3::xml;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::fmt::{Debug, Display, Formatter};
+
+/// An error in the shape S3 clients expect: an XML body with a machine readable code.
+pub struct S3Error {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn access_denied(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    pub fn entity_too_large(limit: usize) -> Self {
+        Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "EntityTooLarge",
+            format!("The body is larger than {} bytes", limit),
+        )
+    }
+
+    pub fn no_such_bucket(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            format!("The bucket {} does not exist", bucket),
+        )
+    }
+
+    pub fn no_such_key(key: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            format!("The key {} does not exist", key),
+        )
+    }
+
+    pub fn no_such_upload(upload: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchUpload",
+            format!("The upload {} does not exist", upload),
+        )
+    }
+
+    pub fn not_implemented() -> Self {
+        Self::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "This operation is not supported",
+        )
+    }
+}
+
+impl From<ServiceError> for S3Error {
+    fn from(error: ServiceError) -> Self {
+        let (status, code) = match &error {
+            ServiceError::InternalServerError(_, _) => {
+                log::error!("S3 request failed: {}", error);
+                (StatusCode::INTERNAL_SERVER_ERROR, "InternalError")
+            }
+            ServiceError::BadRequest(_) => (StatusCode::BAD_REQUEST, "InvalidRequest"),
+            ServiceError::NotFound(_) => (StatusCode::NOT_FOUND, "NoSuchKey"),
+            ServiceError::Forbidden(_) => (StatusCode::FORBIDDEN, "AccessDenied"),
+            ServiceError::Conflict(_) => (StatusCode::CONFLICT, "OperationAborted"),
+            ServiceError::PreconditionFailed(_) => {
+                (StatusCode::PRECONDITION_FAILED, "PreconditionFailed")
+            }
+            ServiceError::Locked(_) => (StatusCode::CONFLICT, "OperationAborted"),
+            ServiceError::UnsupportedMediaType(_) => (StatusCode::BAD_REQUEST, "InvalidRequest"),
+            ServiceError::InsufficientStorage(_) => (StatusCode::FORBIDDEN, "QuotaExceeded"),
+            ServiceError::NotScanned(_) => (StatusCode::CONFLICT, "OperationAborted"),
+            ServiceError::ServiceUnavailable(_, _) => (StatusCode::SERVICE_UNAVAILABLE, "SlowDown"),
+            _ => (StatusCode::FORBIDDEN, "AccessDenied"),
+        };
+
+        Self::new(status, code, error.to_string())
+    }
+}
+
+impl Display for S3Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl Debug for S3Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl ResponseError for S3Error {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .content_type(xml::CONTENT_TYPE)
+            .body(xml::error(self.code, &self.message))
+    }
+}
@@ -0,0 +1,705 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::headers;
+use crate::audit::models::{Actor, AuditAction};
+use crate::audit::store;
+use crate::constants::{MAX_MULTIPART_PARTS, MAX_S3_BODY_SIZE, MAX_S3_UPLOAD_SIZE, S3_ROUTE};
+use crate::redis::client::RedisClient;
+use crate::s3::auth::{self, Payload};
+use crate::s3::errors::S3Error;
+use crate::s3::xml::{self, Continuation, Object, ObjectListing};
+use crate::scan::status;
+use crate::search::indexer;
+use crate::storage::blob::BlobStore;
+use crate::storage::models::{File, FileMetadata, Folder};
+use crate::storage::multipart::{self, MultipartUpload};
+use crate::storage::{content, files, folders, locks, previews};
+use crate::sync::recent;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use percent_encoding::percent_decode_str;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The device S3 clients act as.
+pub const S3_DEVICE: &str = "s3";
+
+const MAX_KEYS: usize = 1000;
+
+/// A subset of the S3 REST API with path-style addressing, so S3 tools can be pointed at
+/// `<host>/s3`. Every top-level folder of the user is a bucket.
+pub fn register_endpoints() -> Scope {
+    Scope::new(S3_ROUTE).default_service(web::to(handle_request))
+}
+
+pub async fn handle_request(
+    request: HttpRequest,
+    payload: web::Payload,
+    redis: web::Data<Arc<RedisClient>>,
+    blobs: web::Data<Arc<dyn BlobStore>>,
+) -> Result<HttpResponse, S3Error> {
+    let signed = auth::verify(&request, &redis).await?;
+    let s3 = S3 {
+        redis: redis.get_ref().clone(),
+        blobs: blobs.get_ref().clone(),
+        owner: signed.key.username.clone(),
+        actor: Actor::new(&signed.key.username, S3_DEVICE, &request),
+        access_key: signed.key.id.clone(),
+    };
+
+    let path = request.path().strip_prefix(S3_ROUTE).unwrap_or_default();
+    let path = percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|_| S3Error::invalid_argument("The path is not valid UTF-8"))?;
+    let path = path.trim_start_matches('/');
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+
+    let query = web::Query::<HashMap<String, String>>::from_query(request.query_string())
+        .map_err(|error| S3Error::invalid_argument(error.to_string()))?
+        .into_inner();
+
+    match (request.method().as_str(), bucket.is_empty(), key.is_empty()) {
+        ("GET", true, _) => s3.list_buckets().await,
+        ("PUT", false, true) => s3.create_bucket(bucket).await,
+        ("HEAD", false, true) => {
+            s3.bucket(bucket).await?;
+            Ok(HttpResponse::Ok().finish())
+        }
+        ("GET", false, true) if query.contains_key("location") => {
+            s3.bucket(bucket).await?;
+            Ok(xml_response(xml::bucket_location()))
+        }
+        ("GET", false, true) => s3.list_objects(bucket, &query).await,
+        ("GET" | "HEAD", false, false) => s3.get_object(&request, bucket, key).await,
+        ("PUT", false, false) if query.contains_key("uploadId") => {
+            s3.upload_part(bucket, key, &query, payload, &signed.payload)
+                .await
+        }
+        ("PUT", false, false) if request.headers().contains_key("x-amz-copy-source") => {
+            Err(S3Error::not_implemented())
+        }
+        ("PUT", false, false) => {
+            s3.put_object(&request, bucket, key, payload, &signed.payload)
+                .await
+        }
+        ("POST", false, false) if query.contains_key("uploads") => {
+            s3.create_multipart_upload(&request, bucket, key).await
+        }
+        ("POST", false, false) if query.contains_key("uploadId") => {
+            s3.complete_multipart_upload(bucket, key, &query, payload, &signed.payload)
+                .await
+        }
+        ("DELETE", false, false) if query.contains_key("uploadId") => {
+            s3.abort_multipart_upload(bucket, key, &query).await
+        }
+        ("DELETE", false, false) => s3.delete_object(bucket, key).await,
+        _ => Err(S3Error::not_implemented()),
+    }
+}
+
+struct S3 {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+    owner: String,
+    actor: Actor,
+    access_key: String,
+}
+
+impl S3 {
+    async fn list_buckets(&self) -> Result<HttpResponse, S3Error> {
+        let buckets = folders::list_folders(&self.redis, &self.owner)
+            .await?
+            .into_iter()
+            .filter(|folder| folder.path != "/" && !folder.path[1..].contains('/'))
+            .collect::<Vec<_>>();
+
+        Ok(xml_response(xml::list_buckets(&self.owner, &buckets)))
+    }
+
+    async fn create_bucket(&self, bucket: &str) -> Result<HttpResponse, S3Error> {
+        files::validate_name(bucket).map_err(|_| {
+            S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidBucketName",
+                format!("{} is not a valid bucket name", bucket),
+            )
+        })?;
+
+        let path = format!("/{}", bucket);
+        if folders::find_folder(&self.redis, &self.owner, &path)
+            .await?
+            .is_some()
+        {
+            return Err(S3Error::new(
+                StatusCode::CONFLICT,
+                "BucketAlreadyOwnedByYou",
+                format!("The bucket {} already exists", bucket),
+            ));
+        }
+        folders::ensure_folder(&self.redis, &self.owner, &path).await?;
+
+        Ok(HttpResponse::Ok()
+            .insert_header(("Location", path))
+            .finish())
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<HttpResponse, S3Error> {
+        let folder = self.bucket(bucket).await?;
+
+        let v2 = query.get("list-type").map(String::as_str) == Some("2");
+        let prefix = query.get("prefix").cloned().unwrap_or_default();
+        let delimiter = query
+            .get("delimiter")
+            .filter(|delimiter| !delimiter.is_empty())
+            .cloned();
+        let max_keys = match query.get("max-keys") {
+            Some(max_keys) => max_keys
+                .parse::<usize>()
+                .map_err(|_| S3Error::invalid_argument("max-keys must be a number"))?
+                .min(MAX_KEYS),
+            None => MAX_KEYS,
+        };
+        let token = match query.get("continuation-token").filter(|_| v2) {
+            Some(token) => Some(decode_token(token)?),
+            None => None,
+        };
+        let start_after = match v2 {
+            true => query.get("start-after").cloned(),
+            false => query.get("marker").cloned(),
+        };
+        let start = token.or_else(|| start_after.clone());
+
+        let objects = self
+            .objects(&folder)
+            .await?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .filter(|(key, _)| start.as_ref().map(|start| key > start).unwrap_or(true))
+            .collect::<Vec<_>>();
+
+        let mut listing = ObjectListing {
+            bucket: bucket.to_string(),
+            prefix: prefix.clone(),
+            delimiter: delimiter.clone(),
+            max_keys,
+            url_encoded: query.get("encoding-type").map(String::as_str) == Some("url"),
+            objects: Vec::new(),
+            common_prefixes: Vec::new(),
+            truncated: false,
+            continuation: Continuation::V1 {
+                marker: String::new(),
+                next_marker: None,
+            },
+        };
+
+        // Keys sharing a prefix up to the delimiter are rolled up into a single common
+        // prefix, which counts as one key.
+        let mut last = None;
+        let mut index = 0;
+        while index < objects.len() {
+            if listing.objects.len() + listing.common_prefixes.len() == max_keys {
+                listing.truncated = true;
+                break;
+            }
+
+            let key = &objects[index].0;
+            let common = delimiter.as_ref().and_then(|delimiter| {
+                key[prefix.len()..]
+                    .find(delimiter.as_str())
+                    .map(|end| key[..prefix.len() + end + delimiter.len()].to_string())
+            });
+
+            match common {
+                Some(common) => {
+                    while index < objects.len() && objects[index].0.starts_with(&common) {
+                        last = Some(objects[index].0.clone());
+                        index += 1;
+                    }
+                    listing.common_prefixes.push(common);
+                }
+                None => {
+                    let (key, object) = &objects[index];
+                    last = Some(key.clone());
+                    listing.objects.push(Object {
+                        key: object.key.clone(),
+                        size: object.size,
+                        etag: object.etag.clone(),
+                        modified: object.modified,
+                    });
+                    index += 1;
+                }
+            }
+        }
+
+        let next = last.filter(|_| listing.truncated);
+        listing.continuation = match v2 {
+            true => Continuation::V2 {
+                token: query.get("continuation-token").cloned(),
+                next_token: next.map(|key| base64::encode(key.as_bytes())),
+                start_after,
+            },
+            false => Continuation::V1 {
+                marker: start_after.unwrap_or_default(),
+                next_marker: next,
+            },
+        };
+
+        Ok(xml_response(xml::list_objects(&listing)))
+    }
+
+    /// The objects of a bucket by key. Empty folders are listed as zero-byte keys ending with
+    /// a slash, the directory markers S3 tools create themselves.
+    async fn objects(&self, bucket: &Folder) -> Result<BTreeMap<String, Object>, ServiceError> {
+        let root = &bucket.path;
+        let mut objects: BTreeMap<String, Object> = BTreeMap::new();
+
+        for file in files::list_files(&self.redis, &self.owner).await? {
+            if !folders::is_within(root, &file.folder) {
+                continue;
+            }
+
+            let relative = file.folder[root.len()..].trim_start_matches('/');
+            let key = match relative {
+                "" => file.name.clone(),
+                relative => format!("{}/{}", relative, file.name),
+            };
+
+            // Names are not unique within a folder, the most recently changed file wins.
+            if let Some(existing) = objects.get(&key) {
+                if existing.modified > file.updated_at {
+                    continue;
+                }
+            }
+            objects.insert(
+                key.clone(),
+                Object {
+                    key,
+                    size: file.size,
+                    etag: headers::etag(&file.hash),
+                    modified: file.updated_at,
+                },
+            );
+        }
+
+        let empty = headers::etag(&content::hash_data(&[]));
+        for folder in folders::list_folders(&self.redis, &self.owner).await? {
+            if folder.path == *root || !folders::is_within(root, &folder.path) {
+                continue;
+            }
+
+            let marker = format!("{}/", &folder.path[root.len() + 1..]);
+            if !objects.keys().any(|key| key.starts_with(&marker)) {
+                objects.insert(
+                    marker.clone(),
+                    Object {
+                        key: marker,
+                        size: 0,
+                        etag: empty.clone(),
+                        modified: folder.created_at,
+                    },
+                );
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn get_object(
+        &self,
+        request: &HttpRequest,
+        bucket: &str,
+        key: &str,
+    ) -> Result<HttpResponse, S3Error> {
+        self.bucket(bucket).await?;
+        let file = self
+            .object(bucket, key)
+            .await?
+            .ok_or_else(|| S3Error::no_such_key(key))?;
+
+        status::check_download(&self.redis, &file.hash).await?;
+        let data = content::read_content(self.blobs.as_ref(), &file.hash)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to read the file".to_string(),
+                    Some(error),
+                )
+            })?;
+        if request.method() == "GET" {
+            self.audit(AuditAction::Download, &file).await;
+            recent::touch(&self.redis, &file.owner, &self.actor.device_id, &file.id).await;
+        }
+
+        let mut response = HttpResponse::Ok();
+        response
+            .content_type(file.content_type.as_str())
+            .insert_header(("ETag", headers::etag(&file.hash)))
+            .insert_header(("Last-Modified", headers::http_date(file.updated_at)))
+            .insert_header(("Accept-Ranges", "bytes"));
+
+        let range = request
+            .headers()
+            .get("Range")
+            .and_then(|range| range.to_str().ok());
+        match range.map(|range| parse_range(range, data.len())) {
+            Some(Some((start, end))) => Ok(response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, data.len()),
+                ))
+                .body(data[start..=end].to_vec())),
+            Some(None) => Err(S3Error::new(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "InvalidRange",
+                "The requested range is not satisfiable",
+            )),
+            None => Ok(response.body(data)),
+        }
+    }
+
+    async fn put_object(
+        &self,
+        request: &HttpRequest,
+        bucket: &str,
+        key: &str,
+        body: web::Payload,
+        payload: &Payload,
+    ) -> Result<HttpResponse, S3Error> {
+        self.bucket(bucket).await?;
+        let data = auth::read_body(body, payload, *MAX_S3_UPLOAD_SIZE).await?;
+
+        if key.ends_with('/') {
+            let path = files::normalize_folder(&format!("/{}/{}", bucket, key))?;
+            folders::ensure_folder(&self.redis, &self.owner, &path).await?;
+
+            return Ok(HttpResponse::Ok()
+                .insert_header(("ETag", headers::etag(&content::hash_data(&[]))))
+                .finish());
+        }
+
+        let content_type = request
+            .headers()
+            .get("Content-Type")
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(str::to_string);
+        let file = self.write_object(bucket, key, data, content_type).await?;
+
+        Ok(HttpResponse::Ok()
+            .insert_header(("ETag", headers::etag(&file.hash)))
+            .finish())
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<HttpResponse, S3Error> {
+        self.bucket(bucket).await?;
+
+        if key.ends_with('/') {
+            let path = files::normalize_folder(&format!("/{}/{}", bucket, key))?;
+            if let Some(folder) = folders::find_folder(&self.redis, &self.owner, &path).await? {
+                self.delete_marker(&folder).await?;
+            }
+        } else if let Some(file) = self.object(bucket, key).await? {
+            locks::check_writable(&self.redis, &file.id, &self.owner, S3_DEVICE).await?;
+            files::delete_file(&self.redis, &file, S3_DEVICE).await?;
+            indexer::enqueue_removal(&self.redis, &file).await?;
+            self.audit(AuditAction::Delete, &file).await;
+        }
+
+        // Deleting a key that does not exist succeeds, as in S3.
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Removes a folder deleted through its directory marker, unless something is inside.
+    async fn delete_marker(&self, folder: &Folder) -> Result<(), ServiceError> {
+        let has_files = files::list_files(&self.redis, &self.owner)
+            .await?
+            .iter()
+            .any(|file| folders::is_within(&folder.path, &file.folder));
+        let has_folders = folders::list_folders(&self.redis, &self.owner)
+            .await?
+            .iter()
+            .any(|child| {
+                child.path != folder.path && folders::is_within(&folder.path, &child.path)
+            });
+
+        if !has_files && !has_folders {
+            folders::delete_folder(&self.redis, folder).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        request: &HttpRequest,
+        bucket: &str,
+        key: &str,
+    ) -> Result<HttpResponse, S3Error> {
+        self.bucket(bucket).await?;
+        locate(bucket, key)?;
+
+        let content_type = request
+            .headers()
+            .get("Content-Type")
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(str::to_string);
+        let upload =
+            multipart::create_upload(&self.redis, &self.owner, bucket, key, content_type).await?;
+
+        Ok(xml_response(xml::initiate_multipart_upload(
+            bucket, key, &upload.id,
+        )))
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+        body: web::Payload,
+        payload: &Payload,
+    ) -> Result<HttpResponse, S3Error> {
+        let upload = self.upload(bucket, key, query).await?;
+        let number = query
+            .get("partNumber")
+            .and_then(|number| number.parse::<u32>().ok())
+            .filter(|number| (1..=MAX_MULTIPART_PARTS).contains(number))
+            .ok_or_else(|| {
+                S3Error::invalid_argument(format!(
+                    "Part numbers range from 1 to {}",
+                    MAX_MULTIPART_PARTS
+                ))
+            })?;
+
+        let data = auth::read_body(body, payload, *MAX_S3_UPLOAD_SIZE).await?;
+        let part =
+            multipart::add_part(&self.redis, self.blobs.as_ref(), &upload, number, data).await?;
+
+        Ok(HttpResponse::Ok()
+            .insert_header(("ETag", headers::etag(&part.hash)))
+            .finish())
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+        body: web::Payload,
+        payload: &Payload,
+    ) -> Result<HttpResponse, S3Error> {
+        let upload = self.upload(bucket, key, query).await?;
+
+        let malformed =
+            |message: String| S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", message);
+        let body = String::from_utf8(auth::read_body(body, payload, MAX_S3_BODY_SIZE).await?)
+            .map_err(|_| malformed("The body is not valid UTF-8".to_string()))?;
+        let requested = xml::completed_parts(&body).map_err(malformed)?;
+
+        if requested.is_empty() {
+            return Err(malformed("No parts were listed".to_string()));
+        }
+        if requested.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidPartOrder",
+                "Parts must be listed in ascending order",
+            ));
+        }
+
+        let stored = multipart::parts(&self.redis, &upload.id).await?;
+        let parts = requested
+            .iter()
+            .map(|(number, etag)| {
+                stored
+                    .get(number)
+                    .filter(|part| part.hash == *etag)
+                    .cloned()
+                    .ok_or_else(|| {
+                        S3Error::new(
+                            StatusCode::BAD_REQUEST,
+                            "InvalidPart",
+                            format!("Part {} was not uploaded", number),
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The object is assembled in memory, so it is held to the limit of a single upload.
+        if parts.iter().map(|part| part.size).sum::<usize>() > *MAX_S3_UPLOAD_SIZE {
+            return Err(S3Error::entity_too_large(*MAX_S3_UPLOAD_SIZE));
+        }
+        let data = multipart::assemble(self.blobs.as_ref(), &parts).await?;
+        let file = self
+            .write_object(bucket, key, data, upload.content_type.clone())
+            .await?;
+        multipart::remove_upload(&self.redis, &upload.id).await?;
+
+        Ok(xml_response(xml::complete_multipart_upload(
+            &format!("{}/{}/{}", S3_ROUTE, bucket, key),
+            bucket,
+            key,
+            &headers::etag(&file.hash),
+        )))
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<HttpResponse, S3Error> {
+        let upload = self.upload(bucket, key, query).await?;
+        multipart::remove_upload(&self.redis, &upload.id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn bucket(&self, bucket: &str) -> Result<Folder, S3Error> {
+        if bucket.is_empty() || bucket.contains('/') {
+            return Err(S3Error::no_such_bucket(bucket));
+        }
+
+        folders::find_folder(&self.redis, &self.owner, &format!("/{}", bucket))
+            .await?
+            .ok_or_else(|| S3Error::no_such_bucket(bucket))
+    }
+
+    async fn object(&self, bucket: &str, key: &str) -> Result<Option<FileMetadata>, S3Error> {
+        if key.ends_with('/') {
+            return Ok(None);
+        }
+
+        let (folder, name) = locate(bucket, key)?;
+        Ok(files::find_file(&self.redis, &self.owner, &folder, &name).await?)
+    }
+
+    async fn upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<MultipartUpload, S3Error> {
+        let id = query
+            .get("uploadId")
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        match multipart::get_owned_upload(&self.redis, id, &self.owner).await {
+            Ok(upload) if upload.bucket == bucket && upload.key == key => Ok(upload),
+            Ok(_) | Err(ServiceError::NotFound(_)) => Err(S3Error::no_such_upload(id)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Stores an object as a new file, or as a new version of the file already at its key.
+    async fn write_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: Vec<u8>,
+        content_type: Option<String>,
+    ) -> Result<FileMetadata, S3Error> {
+        let (folder, name) = locate(bucket, key)?;
+        let upload = File {
+            name: name.clone(),
+            size: data.len(),
+            data,
+            declared_type: content_type,
+        };
+
+        let file = match files::find_file(&self.redis, &self.owner, &folder, &name).await? {
+            Some(mut file) => {
+                locks::check_writable(&self.redis, &file.id, &self.owner, S3_DEVICE).await?;
+                files::add_file_version(
+                    &self.redis,
+                    self.blobs.as_ref(),
+                    &mut file,
+                    S3_DEVICE,
+                    upload,
+                    None,
+                )
+                .await?;
+                file
+            }
+            None => {
+                files::create_file(
+                    &self.redis,
+                    self.blobs.as_ref(),
+                    &self.owner,
+                    S3_DEVICE,
+                    &folder,
+                    upload,
+                )
+                .await?
+            }
+        };
+
+        previews::enqueue_generation(&self.redis, &file.owner, &file.hash, &file.content_type)
+            .await?;
+        indexer::enqueue_indexing(&self.redis, &file).await?;
+        self.audit(AuditAction::Upload, &file).await;
+
+        Ok(file)
+    }
+
+    async fn audit(&self, action: AuditAction, file: &FileMetadata) {
+        let target = Some(format!("file:{}", file.id));
+        let detail = Some(format!("Over S3 with access key {}", self.access_key));
+        store::record(&self.redis, &self.actor, action, target, detail).await;
+    }
+}
+
+/// Maps a key onto the folder and name of the file it is stored as.
+fn locate(bucket: &str, key: &str) -> Result<(String, String), S3Error> {
+    let path = files::normalize_folder(&format!("/{}/{}", bucket, key))
+        .map_err(|_| S3Error::invalid_argument(format!("{} is not a valid key", key)))?;
+
+    match path.rsplit_once('/') {
+        Some((folder, name)) if !folder.is_empty() && !name.is_empty() => {
+            Ok((folder.to_string(), name.to_string()))
+        }
+        _ => Err(S3Error::invalid_argument(format!(
+            "{} is not a valid key",
+            key
+        ))),
+    }
+}
+
+/// Parses a single `bytes=` range, `None` if it cannot be satisfied.
+fn parse_range(range: &str, length: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    if length == 0 {
+        return None;
+    }
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<usize>().ok()?.min(length);
+            (length - suffix, length - 1)
+        }
+        (start, "") => (start.parse().ok()?, length - 1),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<usize>().ok()?.min(length - 1),
+        ),
+    };
+
+    (start <= end && start < length).then_some((start, end))
+}
+
+fn decode_token(token: &str) -> Result<String, S3Error> {
+    base64::decode(token)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
+        .ok_or_else(|| S3Error::invalid_argument("The continuation token is invalid"))
+}
+
+fn xml_response(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(xml::CONTENT_TYPE)
+        .body(body)
+}
@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod errors;
+pub mod handler;
+pub mod xml;
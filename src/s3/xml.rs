@@ -0,0 +1,226 @@
+use crate::storage::models::Folder;
+use crate::utils::xml::escape;
+use chrono::TimeZone;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+pub const CONTENT_TYPE: &str = "application/xml";
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+const NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// Keys listed with `encoding-type=url` keep their separators.
+const KEY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+pub struct Object {
+    pub key: String,
+    pub size: usize,
+    pub etag: String,
+    pub modified: i64,
+}
+
+/// Where a listing starts and where the next page would, in either version of ListObjects.
+pub enum Continuation {
+    V1 {
+        marker: String,
+        next_marker: Option<String>,
+    },
+    V2 {
+        token: Option<String>,
+        next_token: Option<String>,
+        start_after: Option<String>,
+    },
+}
+
+pub struct ObjectListing {
+    pub bucket: String,
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub max_keys: usize,
+    pub url_encoded: bool,
+    pub objects: Vec<Object>,
+    pub common_prefixes: Vec<String>,
+    pub truncated: bool,
+    pub continuation: Continuation,
+}
+
+pub fn error(code: &str, message: &str) -> String {
+    format!(
+        "{}<Error><Code>{}</Code><Message>{}</Message><RequestId>{}</RequestId></Error>",
+        HEADER,
+        code,
+        escape(message),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+pub fn list_buckets(owner: &str, buckets: &[Folder]) -> String {
+    let mut body = format!(
+        "{}<ListAllMyBucketsResult xmlns=\"{}\"><Owner><ID>{}</ID><DisplayName>{}</DisplayName></Owner><Buckets>",
+        HEADER,
+        NAMESPACE,
+        escape(owner),
+        escape(owner)
+    );
+
+    for bucket in buckets {
+        body.push_str(&format!(
+            "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+            escape(bucket.path.trim_start_matches('/')),
+            iso_date(bucket.created_at)
+        ));
+    }
+
+    body.push_str("</Buckets></ListAllMyBucketsResult>");
+    body
+}
+
+pub fn list_objects(listing: &ObjectListing) -> String {
+    let key = |value: &str| match listing.url_encoded {
+        true => utf8_percent_encode(value, KEY).to_string(),
+        false => escape(value),
+    };
+
+    let mut body = format!(
+        "{}<ListBucketResult xmlns=\"{}\"><Name>{}</Name><Prefix>{}</Prefix><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        HEADER,
+        NAMESPACE,
+        escape(&listing.bucket),
+        key(&listing.prefix),
+        listing.max_keys,
+        listing.truncated
+    );
+
+    if let Some(delimiter) = &listing.delimiter {
+        body.push_str(&format!("<Delimiter>{}</Delimiter>", key(delimiter)));
+    }
+    if listing.url_encoded {
+        body.push_str("<EncodingType>url</EncodingType>");
+    }
+
+    match &listing.continuation {
+        Continuation::V1 {
+            marker,
+            next_marker,
+        } => {
+            body.push_str(&format!("<Marker>{}</Marker>", key(marker)));
+            if let Some(next_marker) = next_marker {
+                body.push_str(&format!("<NextMarker>{}</NextMarker>", key(next_marker)));
+            }
+        }
+        Continuation::V2 {
+            token,
+            next_token,
+            start_after,
+        } => {
+            body.push_str(&format!(
+                "<KeyCount>{}</KeyCount>",
+                listing.objects.len() + listing.common_prefixes.len()
+            ));
+            if let Some(token) = token {
+                body.push_str(&format!(
+                    "<ContinuationToken>{}</ContinuationToken>",
+                    escape(token)
+                ));
+            }
+            if let Some(next_token) = next_token {
+                body.push_str(&format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    escape(next_token)
+                ));
+            }
+            if let Some(start_after) = start_after {
+                body.push_str(&format!("<StartAfter>{}</StartAfter>", key(start_after)));
+            }
+        }
+    }
+
+    for object in &listing.objects {
+        body.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            key(&object.key),
+            iso_date(object.modified),
+            escape(&object.etag),
+            object.size
+        ));
+    }
+    for prefix in &listing.common_prefixes {
+        body.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            key(prefix)
+        ));
+    }
+
+    body.push_str("</ListBucketResult>");
+    body
+}
+
+pub fn bucket_location() -> String {
+    format!("{}<LocationConstraint xmlns=\"{}\"/>", HEADER, NAMESPACE)
+}
+
+pub fn initiate_multipart_upload(bucket: &str, key: &str, upload: &str) -> String {
+    format!(
+        "{}<InitiateMultipartUploadResult xmlns=\"{}\"><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+        HEADER,
+        NAMESPACE,
+        escape(bucket),
+        escape(key),
+        escape(upload)
+    )
+}
+
+pub fn complete_multipart_upload(location: &str, bucket: &str, key: &str, etag: &str) -> String {
+    format!(
+        "{}<CompleteMultipartUploadResult xmlns=\"{}\"><Location>{}</Location><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        HEADER,
+        NAMESPACE,
+        escape(location),
+        escape(bucket),
+        escape(key),
+        escape(etag)
+    )
+}
+
+/// Reads the part numbers and ETags of a CompleteMultipartUpload request, in their order.
+pub fn completed_parts(body: &str) -> Result<Vec<(u32, String)>, String> {
+    body.split("<Part>")
+        .skip(1)
+        .map(|part| {
+            let number = element(part, "PartNumber")
+                .and_then(|number| number.trim().parse().ok())
+                .ok_or_else(|| "A part has no valid number".to_string())?;
+            let etag = element(part, "ETag")
+                .map(|etag| {
+                    etag.replace("&quot;", "\"")
+                        .replace("&#34;", "\"")
+                        .trim()
+                        .trim_matches('"')
+                        .to_string()
+                })
+                .ok_or_else(|| format!("Part {} has no ETag", number))?;
+
+            Ok((number, etag))
+        })
+        .collect()
+}
+
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", name))? + start;
+
+    Some(&xml[start..end])
+}
+
+fn iso_date(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%dT%H:%M:%S.000Z")
+        .to_string()
+}
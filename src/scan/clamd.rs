@@ -0,0 +1,119 @@
+use crate::constants::SCAN_TIMEOUT;
+use crate::scan::scanner::{Scanner, Verdict};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+
+/// Size of the chunks the content is streamed in. clamd only limits the size of the whole
+/// stream, with `StreamMaxLength`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Streams content to a clamd daemon with the INSTREAM command.
+pub struct ClamdScanner {
+    address: String,
+    /// The `StreamMaxLength` of the daemon. Larger content is not sent at all, clamd would
+    /// refuse it anyway.
+    max_size: usize,
+}
+
+impl ClamdScanner {
+    pub fn new(address: String, max_size: usize) -> Self {
+        Self { address, max_size }
+    }
+
+    async fn instream<S>(mut stream: S, data: &[u8]) -> Result<Verdict, anyhow::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            stream
+                .write_all(&(chunk.len() as u32).to_be_bytes())
+                .await?;
+            stream.write_all(chunk).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+        stream.flush().await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        let reply = String::from_utf8_lossy(&reply);
+
+        parse_reply(reply.trim_end_matches('\0').trim())
+    }
+}
+
+#[async_trait]
+impl Scanner for ClamdScanner {
+    fn name(&self) -> &'static str {
+        "clamd"
+    }
+
+    async fn scan(&self, data: &[u8]) -> Result<Verdict, anyhow::Error> {
+        if data.len() > self.max_size {
+            return Ok(Verdict::TooLarge);
+        }
+
+        let scan = async {
+            match self.address.strip_prefix("unix:") {
+                Some(path) => Self::instream(UnixStream::connect(path).await?, data).await,
+                None => Self::instream(TcpStream::connect(&self.address).await?, data).await,
+            }
+        };
+
+        tokio::time::timeout(Duration::from_secs(SCAN_TIMEOUT), scan)
+            .await
+            .map_err(|_| anyhow::anyhow!("clamd did not answer in time"))?
+    }
+}
+
+/// Replies look like `stream: OK`, `stream: Eicar-Test-Signature FOUND` or
+/// `INSTREAM size limit exceeded. ERROR`.
+fn parse_reply(reply: &str) -> Result<Verdict, anyhow::Error> {
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+
+    if result == "OK" {
+        Ok(Verdict::Clean)
+    } else if result.starts_with("INSTREAM size limit exceeded") {
+        Ok(Verdict::TooLarge)
+    } else if let Some(signature) = result.strip_suffix("FOUND") {
+        Ok(Verdict::Infected(signature.trim().to_string()))
+    } else {
+        Err(anyhow::anyhow!(
+            "clamd failed to scan the content: {}",
+            result
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_replies() {
+        assert!(matches!(parse_reply("stream: OK"), Ok(Verdict::Clean)));
+        assert!(matches!(
+            parse_reply("stream: Eicar-Test-Signature FOUND"),
+            Ok(Verdict::Infected(signature)) if signature == "Eicar-Test-Signature"
+        ));
+        assert!(matches!(
+            parse_reply("INSTREAM size limit exceeded. ERROR"),
+            Ok(Verdict::TooLarge)
+        ));
+        assert!(parse_reply("stream: Can't allocate memory ERROR").is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_send_content_over_the_limit() {
+        // Nothing listens there, so sending anything would fail.
+        let scanner = ClamdScanner::new("unix:/nonexistent/clamd.sock".to_string(), 4);
+
+        assert!(matches!(
+            scanner.scan(b"12345").await,
+            Ok(Verdict::TooLarge)
+        ));
+        assert!(scanner.scan(b"1234").await.is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod clamd;
+pub mod scanner;
+pub mod status;
@@ -0,0 +1,102 @@
+use crate::scan::clamd::ClamdScanner;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::env;
+use std::sync::Arc;
+
+/// The EICAR test file, which every scanner reports as infected.
+const EICAR: &[u8] = br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+pub enum Verdict {
+    Clean,
+    /// Infected, with the name of the signature that matched.
+    Infected(String),
+    /// Larger than the scanner accepts.
+    TooLarge,
+}
+
+#[async_trait]
+pub trait Scanner: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn scan(&self, data: &[u8]) -> Result<Verdict, anyhow::Error>;
+}
+
+/// What happens to uploads found infected. Either way their content is quarantined and can
+/// no longer be downloaded.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanPolicy {
+    /// Refuses the upload.
+    Block,
+    /// Stores the file, flagged as infected.
+    Flag,
+}
+
+impl ScanPolicy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "flag" => ScanPolicy::Flag,
+            _ => ScanPolicy::Block,
+        }
+    }
+}
+
+/// What happens to content too large to be scanned. Either way it is not scanned again.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    /// Refuses the upload, and the download of content stored before.
+    Block,
+    /// Treats the content as clean.
+    Allow,
+}
+
+impl OversizePolicy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "allow" => OversizePolicy::Allow,
+            _ => OversizePolicy::Block,
+        }
+    }
+}
+
+/// Reports the EICAR test file and nothing else, for tests and development setups without
+/// a clamd daemon.
+pub struct FakeScanner;
+
+#[async_trait]
+impl Scanner for FakeScanner {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    async fn scan(&self, data: &[u8]) -> Result<Verdict, anyhow::Error> {
+        let infected = data.windows(EICAR.len()).any(|window| window == EICAR);
+
+        Ok(match infected {
+            true => Verdict::Infected("Eicar-Test-Signature".to_string()),
+            false => Verdict::Clean,
+        })
+    }
+}
+
+/// Builds the scanner selected by `DOC_STORAGE_SCANNER`: `clamd`, reached at
+/// `DOC_STORAGE_CLAMD_ADDRESS` (`host:port` or `unix:/path`) and accepting up to
+/// `DOC_STORAGE_CLAMD_MAX_SIZE` bytes, or `fake`. Scanning is disabled when it is unset.
+pub fn from_env() -> Option<Arc<dyn Scanner>> {
+    match env::var("DOC_STORAGE_SCANNER").unwrap_or_default().as_str() {
+        "clamd" => {
+            let address = env::var("DOC_STORAGE_CLAMD_ADDRESS")
+                .unwrap_or_else(|_| "127.0.0.1:3310".to_string());
+            // The default `StreamMaxLength` of clamd.
+            let max_size = env::var("DOC_STORAGE_CLAMD_MAX_SIZE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(25 * 1024 * 1024);
+            Some(Arc::new(ClamdScanner::new(address, max_size)))
+        }
+        "fake" => Some(Arc::new(FakeScanner)),
+        "" | "none" => None,
+        other => panic!("Unknown scanner {}", other),
+    }
+}
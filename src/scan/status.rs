@@ -0,0 +1,411 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{MAX_SCAN_ATTEMPTS, OVERSIZE_POLICY, SCANNER, SCAN_POLICY};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::scan::scanner::{OversizePolicy, ScanPolicy, Verdict};
+use crate::storage::blob::BlobStore;
+use crate::storage::models::FileMetadata;
+use crate::storage::{content, files};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanStatus {
+    Clean,
+    Infected,
+    /// The scanner could not be reached or gave up on the content.
+    Failed,
+    /// Larger than the scanner accepts, left to the oversize policy.
+    #[serde(rename = "too_large")]
+    TooLarge,
+}
+
+/// The outcome of scanning a piece of content, shared by every file holding it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScanResult {
+    pub hash: String,
+    pub status: ScanStatus,
+    pub signature: Option<String>,
+    pub scanner: String,
+    pub scanned_at: i64,
+    /// Failed scans in a row, so content that keeps failing is eventually given up on.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Infected content kept aside for administrators, even when the upload was refused.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuarantinedSample {
+    pub hash: String,
+    pub signature: String,
+    pub owner: String,
+    pub name: String,
+    pub size: usize,
+    pub quarantined_at: i64,
+}
+
+pub async fn get_result(
+    redis: &RedisClient,
+    hash: &str,
+) -> Result<Option<ScanResult>, ServiceError> {
+    let value: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::ScanResult(hash.to_string()).to_string()))
+        .await?;
+
+    value
+        .map(|value| {
+            serde_json::from_str(&value).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })
+        })
+        .transpose()
+}
+
+async fn save_result(redis: &RedisClient, result: &ScanResult) -> Result<(), ServiceError> {
+    redis
+        .s_async_set(RedisKey::ScanResult(result.hash.clone()), result)
+        .await?;
+
+    Ok(())
+}
+
+/// Runs the configured scanner over `data` and records the outcome. Scanner failures are
+/// recorded rather than returned, so they never fail the upload itself.
+pub async fn scan(
+    redis: &RedisClient,
+    hash: &str,
+    data: &[u8],
+) -> Result<Option<ScanResult>, ServiceError> {
+    let scanner = match SCANNER.as_ref() {
+        Some(scanner) => scanner,
+        None => return Ok(None),
+    };
+
+    let (status, signature) = match scanner.scan(data).await {
+        Ok(Verdict::Clean) => (ScanStatus::Clean, None),
+        Ok(Verdict::Infected(signature)) => (ScanStatus::Infected, Some(signature)),
+        Ok(Verdict::TooLarge) => (ScanStatus::TooLarge, None),
+        Err(error) => {
+            log::error!("Failed to scan {}: {}", hash, error);
+            (ScanStatus::Failed, None)
+        }
+    };
+
+    let result = record(redis, hash, scanner.name(), status, signature).await?;
+
+    Ok(Some(result))
+}
+
+/// Records the outcome of a scan, counting failures in a row.
+async fn record(
+    redis: &RedisClient,
+    hash: &str,
+    scanner: &str,
+    status: ScanStatus,
+    signature: Option<String>,
+) -> Result<ScanResult, ServiceError> {
+    let attempts = match (status, get_result(redis, hash).await?) {
+        (ScanStatus::Failed, Some(previous)) if previous.status == ScanStatus::Failed => {
+            previous.attempts + 1
+        }
+        (ScanStatus::Failed, _) => 1,
+        _ => 0,
+    };
+
+    let result = ScanResult {
+        hash: hash.to_string(),
+        status,
+        signature,
+        scanner: scanner.to_string(),
+        scanned_at: chrono::Utc::now().timestamp(),
+        attempts,
+    };
+    save_result(redis, &result).await?;
+    track_pending(redis, &result).await?;
+
+    Ok(result)
+}
+
+/// Keeps the content that failed to scan in the set the rescan works through, until it is
+/// scanned or given up on.
+async fn track_pending(redis: &RedisClient, result: &ScanResult) -> Result<(), ServiceError> {
+    if result.status == ScanStatus::Failed && result.attempts < MAX_SCAN_ATTEMPTS {
+        redis
+            .async_sadd(RedisKey::ScanPending, &result.hash)
+            .await?;
+    } else {
+        redis
+            .async_srem(RedisKey::ScanPending, &result.hash)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Scans an upload before it is stored. Infected content is quarantined and, under the
+/// blocking policy, refused. Content too large to be scanned is refused unless allowed.
+pub async fn check_upload(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    owner: &str,
+    name: &str,
+    hash: &str,
+    data: &[u8],
+) -> Result<(), ServiceError> {
+    // Scanned by the rescan once scanning is enabled.
+    if SCANNER.is_none() {
+        redis.async_sadd(RedisKey::ScanPending, hash).await?;
+        return Ok(());
+    }
+
+    let result = match get_result(redis, hash).await? {
+        Some(result) if result.status != ScanStatus::Failed => Some(result),
+        _ => scan(redis, hash, data).await?,
+    };
+
+    let signature = match result {
+        Some(ScanResult {
+            status: ScanStatus::Infected,
+            signature,
+            ..
+        }) => signature.unwrap_or_default(),
+        Some(ScanResult {
+            status: ScanStatus::TooLarge,
+            ..
+        }) => return check_oversized(),
+        _ => return Ok(()),
+    };
+
+    quarantine(redis, blobs, owner, name, hash, &signature, data.to_vec()).await?;
+
+    match *SCAN_POLICY {
+        ScanPolicy::Block => Err(infected(&signature)),
+        ScanPolicy::Flag => Ok(()),
+    }
+}
+
+/// Refuses to hand out content that is infected or has not been scanned successfully. Nothing
+/// is refused while scanning is disabled.
+pub async fn check_download(redis: &RedisClient, hash: &str) -> Result<(), ServiceError> {
+    if SCANNER.is_none() {
+        return Ok(());
+    }
+
+    match get_result(redis, hash).await? {
+        Some(result) if result.status == ScanStatus::Clean => Ok(()),
+        Some(result) if result.status == ScanStatus::Infected => {
+            Err(infected(&result.signature.unwrap_or_default()))
+        }
+        Some(result) if result.status == ScanStatus::TooLarge => check_oversized(),
+        Some(_) => Err(ServiceError::NotScanned(
+            "The file could not be scanned for malware yet".to_string(),
+        )),
+        None => Err(ServiceError::NotScanned(
+            "The file has not been scanned for malware".to_string(),
+        )),
+    }
+}
+
+fn check_oversized() -> Result<(), ServiceError> {
+    match *OVERSIZE_POLICY {
+        OversizePolicy::Allow => Ok(()),
+        OversizePolicy::Block => Err(ServiceError::NotScanned(
+            "The file is too large to be scanned for malware".to_string(),
+        )),
+    }
+}
+
+fn infected(signature: &str) -> ServiceError {
+    ServiceError::Infected(format!("The file contains malware ({})", signature))
+}
+
+async fn quarantine(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    owner: &str,
+    name: &str,
+    hash: &str,
+    signature: &str,
+    data: Vec<u8>,
+) -> Result<(), ServiceError> {
+    let sample = QuarantinedSample {
+        hash: hash.to_string(),
+        signature: signature.to_string(),
+        owner: owner.to_string(),
+        name: name.to_string(),
+        size: data.len(),
+        quarantined_at: chrono::Utc::now().timestamp(),
+    };
+    let value = serde_json::to_string(&sample).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })?;
+
+    // Recorded first, so the collector never sees the blob without its quarantine entry.
+    redis.async_hset(RedisKey::Quarantine, hash, &value).await?;
+    content::store_content(blobs, hash, data)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to quarantine the file".to_string(),
+                Some(error),
+            )
+        })
+}
+
+pub async fn list_quarantine(redis: &RedisClient) -> Result<Vec<QuarantinedSample>, ServiceError> {
+    let values = redis.async_hvals(RedisKey::Quarantine).await?;
+
+    let mut samples = values
+        .into_iter()
+        .filter_map(|value| serde_json::from_str::<QuarantinedSample>(&value).ok())
+        .collect::<Vec<_>>();
+    samples.sort_by_key(|sample| sample.quarantined_at);
+
+    Ok(samples)
+}
+
+/// The hashes of quarantined content, which the garbage collector keeps.
+pub async fn quarantined_hashes(redis: &RedisClient) -> Result<Vec<String>, ServiceError> {
+    redis
+        .execute(redis::cmd("HKEYS").arg(RedisKey::Quarantine.to_string()))
+        .await
+}
+
+/// Drops a sample from the quarantine, leaving its content to the garbage collector unless
+/// files still refer to it. Releasing it as a false positive also marks the content clean.
+pub async fn remove_from_quarantine(
+    redis: &RedisClient,
+    hash: &str,
+    release: bool,
+) -> Result<(), ServiceError> {
+    let removed: i64 = redis
+        .execute(
+            redis::cmd("HDEL")
+                .arg(RedisKey::Quarantine.to_string())
+                .arg(hash),
+        )
+        .await?;
+
+    if removed == 0 {
+        return Err(ServiceError::NotFound(format!(
+            "Content {} is not quarantined",
+            hash
+        )));
+    }
+
+    if release {
+        let result = ScanResult {
+            hash: hash.to_string(),
+            status: ScanStatus::Clean,
+            signature: None,
+            scanner: "admin".to_string(),
+            scanned_at: chrono::Utc::now().timestamp(),
+            attempts: 0,
+        };
+        save_result(redis, &result).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Default)]
+pub struct RescanReport {
+    pub scanned: usize,
+    pub clean: usize,
+    pub infected: usize,
+    pub failed: usize,
+    pub too_large: usize,
+    /// Content that failed `MAX_SCAN_ATTEMPTS` times in a row and is no longer retried.
+    pub given_up: usize,
+}
+
+/// Scans the stored content that has no verdict yet: uploaded while scanning was disabled, or
+/// while the scanner could not be reached. Content is retried up to `MAX_SCAN_ATTEMPTS` times.
+pub async fn rescan_pending(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+) -> Result<RescanReport, ServiceError> {
+    let mut report = RescanReport::default();
+    let scanner = match SCANNER.as_ref() {
+        Some(scanner) => scanner,
+        None => return Ok(report),
+    };
+
+    index_pending(redis).await?;
+
+    for hash in redis.async_smembers(RedisKey::ScanPending).await? {
+        match get_result(redis, &hash).await? {
+            Some(result) if result.status != ScanStatus::Failed => {
+                redis.async_srem(RedisKey::ScanPending, &hash).await?;
+                continue;
+            }
+            Some(result) if result.attempts >= MAX_SCAN_ATTEMPTS => {
+                report.given_up += 1;
+                redis.async_srem(RedisKey::ScanPending, &hash).await?;
+                continue;
+            }
+            _ => {}
+        }
+
+        let data = match content::read_content(blobs, &hash).await {
+            Ok(data) => data,
+            // Deleted since, there is nothing left to scan.
+            Err(_) if !blobs.exists(&hash).await.unwrap_or(true) => {
+                redis.async_srem(RedisKey::ScanPending, &hash).await?;
+                continue;
+            }
+            // Counted as a failed attempt, so a blob that stays unreadable is given up on
+            // like content the scanner keeps failing on.
+            Err(error) => {
+                log::error!("Failed to read {} for a rescan: {}", hash, error);
+                record(redis, &hash, scanner.name(), ScanStatus::Failed, None).await?;
+                report.failed += 1;
+                continue;
+            }
+        };
+
+        report.scanned += 1;
+        match scan(redis, &hash, &data).await?.map(|result| result.status) {
+            Some(ScanStatus::Clean) => report.clean += 1,
+            Some(ScanStatus::Infected) => report.infected += 1,
+            Some(ScanStatus::TooLarge) => report.too_large += 1,
+            _ => report.failed += 1,
+        }
+    }
+
+    Ok(report)
+}
+
+/// Content stored before the pending set existed is only in the files, so they are walked
+/// once to fill it.
+async fn index_pending(redis: &RedisClient) -> Result<(), ServiceError> {
+    if redis.async_exists(RedisKey::ScanPendingIndexed).await? {
+        return Ok(());
+    }
+
+    let files = files::all_files(redis).await?;
+    let hashes = files
+        .iter()
+        .flat_map(FileMetadata::hashes)
+        .map(str::to_string)
+        .collect::<BTreeSet<_>>();
+
+    for hash in hashes {
+        match get_result(redis, &hash).await? {
+            Some(result) if result.status != ScanStatus::Failed => {}
+            _ => {
+                redis.async_sadd(RedisKey::ScanPending, &hash).await?;
+            }
+        }
+    }
+
+    redis.async_set(RedisKey::ScanPendingIndexed, "1").await?;
+
+    Ok(())
+}
@@ -0,0 +1,373 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{SCHEDULED_TASK_LOCK_TTL, SCHEDULER_LEADER_TTL, SCHEDULER_TICK};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::scheduler::tasks::{self, MaintenanceTask};
+use crate::storage::blob::BlobStore;
+use chrono::{TimeZone, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Takes the leadership when nobody holds it, or extends it for its current holder.
+const LEAD_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current == ARGV[1] then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+if not current then
+    redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+/// Extends a lock, provided it is still held by the caller.
+pub const EXTEND_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Releases a lock, provided it is still held by the caller.
+pub const UNLOCK_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Schedule,
+    Manual,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// How the last run of a task went.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LastRun {
+    pub trigger: Trigger,
+    pub instance: String,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub duration_ms: u64,
+    pub outcome: RunOutcome,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ScheduledTask {
+    pub task: MaintenanceTask,
+    pub schedule: Option<String>,
+    pub next_run_at: Option<i64>,
+    /// The instance running the task right now, if any.
+    pub running_on: Option<String>,
+    pub last_run: Option<LastRun>,
+}
+
+/// Runs the maintenance tasks on their cron schedules. Every instance runs a scheduler, but
+/// only the one holding the leadership in Redis starts scheduled runs; another takes over
+/// within `SCHEDULER_LEADER_TTL` seconds when it goes away.
+pub struct Scheduler {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+    instance: String,
+    schedules: Vec<(MaintenanceTask, Option<(String, Schedule)>)>,
+}
+
+impl Scheduler {
+    pub fn new(redis: Arc<RedisClient>, blobs: Arc<dyn BlobStore>) -> Self {
+        let schedules = MaintenanceTask::ALL
+            .into_iter()
+            .map(|task| {
+                let schedule = task.expression().map(|expression| {
+                    let schedule = tasks::parse(&expression).unwrap_or_else(|error| {
+                        panic!("Invalid schedule for {}: {}", task.name(), error)
+                    });
+                    (expression, schedule)
+                });
+                (task, schedule)
+            })
+            .collect();
+
+        Self {
+            redis,
+            blobs,
+            instance: uuid::Uuid::new_v4().to_string(),
+            schedules,
+        }
+    }
+
+    pub async fn run_forever(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK));
+        let mut leading = false;
+
+        loop {
+            interval.tick().await;
+
+            match self.lead().await {
+                Ok(true) => {
+                    if !leading {
+                        log::info!("Instance {} now leads the scheduler", self.instance);
+                    }
+                    leading = true;
+
+                    if let Err(error) = self.start_due().await {
+                        log::error!("Failed to start scheduled tasks: {}", error);
+                    }
+                }
+                Ok(false) => leading = false,
+                Err(error) => {
+                    leading = false;
+                    log::error!("Failed to elect the scheduler leader: {}", error);
+                }
+            }
+        }
+    }
+
+    async fn lead(&self) -> Result<bool, ServiceError> {
+        let leading: i64 = self
+            .redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(LEAD_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::SchedulerLeader.to_string())
+                    .arg(&self.instance)
+                    .arg(SCHEDULER_LEADER_TTL),
+            )
+            .await?;
+
+        Ok(leading == 1)
+    }
+
+    /// Starts the tasks whose next occurrence has passed. Occurrences missed while no instance
+    /// was leading are run once, not once each.
+    async fn start_due(self: &Arc<Self>) -> Result<(), ServiceError> {
+        let now = Utc::now();
+
+        for (task, schedule) in &self.schedules {
+            let schedule = match schedule {
+                Some((_, schedule)) => schedule,
+                None => continue,
+            };
+
+            let cursor = RedisKey::ScheduleCursor(task.name().to_string());
+            let last: Option<i64> = self
+                .redis
+                .execute(redis::cmd("GET").arg(cursor.to_string()))
+                .await?;
+            let last = match last.and_then(|last| Utc.timestamp_opt(last, 0).single()) {
+                Some(last) => last,
+                None => {
+                    // Never scheduled before: start counting from now rather than catching up.
+                    self.redis
+                        .async_set(cursor, &now.timestamp().to_string())
+                        .await?;
+                    continue;
+                }
+            };
+
+            match schedule.after(&last).next() {
+                Some(next) if next <= now => {}
+                _ => continue,
+            }
+
+            self.redis
+                .async_set(cursor, &now.timestamp().to_string())
+                .await?;
+            match self.start(*task, Trigger::Schedule).await {
+                Err(ServiceError::Conflict(_)) => {
+                    log::warn!("Skipping {}, its previous run is not over", task.name())
+                }
+                result => result?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts a task in the background, unless it is already running somewhere.
+    pub async fn start(
+        self: &Arc<Self>,
+        task: MaintenanceTask,
+        trigger: Trigger,
+    ) -> Result<(), ServiceError> {
+        let lock = RedisKey::ScheduledTaskLock(task.name().to_string());
+        let acquired = self
+            .redis
+            .async_set_nx_ex(lock, &self.instance, SCHEDULED_TASK_LOCK_TTL)
+            .await?;
+
+        if !acquired {
+            return Err(ServiceError::Conflict(format!(
+                "Task {} is already running",
+                task.name()
+            )));
+        }
+
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = scheduler.execute(task, trigger).await {
+                log::error!("Failed to record the run of {}: {}", task.name(), error);
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn execute(&self, task: MaintenanceTask, trigger: Trigger) -> Result<(), ServiceError> {
+        log::info!("Running {} ({:?})", task.name(), trigger);
+        let started_at = Utc::now().timestamp();
+        let clock = Instant::now();
+
+        let lock = RedisKey::ScheduledTaskLock(task.name().to_string());
+        let renew = async {
+            let period = Duration::from_secs(SCHEDULED_TASK_LOCK_TTL as u64 / 3);
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match self.extend(&lock).await {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(error) => {
+                        log::warn!("Failed to extend the lock of {}: {}", task.name(), error)
+                    }
+                }
+            }
+        };
+
+        // Another instance may start the task once the lock is lost, so this run stops.
+        let result = tokio::select! {
+            result = task.run(self.redis.clone(), self.blobs.clone()) => result,
+            _ = renew => Err(ServiceError::Conflict(format!(
+                "Lost the lock of {} while running it",
+                task.name()
+            ))),
+        };
+
+        let (outcome, result, error) = match result {
+            Ok(result) => (RunOutcome::Succeeded, Some(result), None),
+            Err(error) => {
+                log::error!("Task {} failed: {}", task.name(), error);
+                (RunOutcome::Failed, None, Some(error.to_string()))
+            }
+        };
+        let run = LastRun {
+            trigger,
+            instance: self.instance.clone(),
+            started_at,
+            finished_at: Utc::now().timestamp(),
+            duration_ms: clock.elapsed().as_millis() as u64,
+            outcome,
+            result,
+            error,
+        };
+
+        self.redis
+            .s_async_set(RedisKey::ScheduledTask(task.name().to_string()), &run)
+            .await?;
+        self.redis
+            .execute::<i64>(
+                redis::cmd("EVAL")
+                    .arg(UNLOCK_SCRIPT)
+                    .arg(1)
+                    .arg(lock.to_string())
+                    .arg(&self.instance),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn extend(&self, lock: &RedisKey) -> Result<bool, ServiceError> {
+        let extended: i64 = self
+            .redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(EXTEND_SCRIPT)
+                    .arg(1)
+                    .arg(lock.to_string())
+                    .arg(&self.instance)
+                    .arg(SCHEDULED_TASK_LOCK_TTL),
+            )
+            .await?;
+
+        Ok(extended == 1)
+    }
+
+    pub async fn list(&self) -> Result<Vec<ScheduledTask>, ServiceError> {
+        let now = Utc::now();
+        let mut tasks = Vec::with_capacity(self.schedules.len());
+
+        for (task, schedule) in &self.schedules {
+            let name = task.name().to_string();
+            let running_on: Option<String> = self
+                .redis
+                .execute(
+                    redis::cmd("GET").arg(RedisKey::ScheduledTaskLock(name.clone()).to_string()),
+                )
+                .await?;
+            let last_run: Option<String> = self
+                .redis
+                .execute(redis::cmd("GET").arg(RedisKey::ScheduledTask(name).to_string()))
+                .await?;
+
+            tasks.push(ScheduledTask {
+                task: *task,
+                schedule: schedule.as_ref().map(|(expression, _)| expression.clone()),
+                next_run_at: schedule
+                    .as_ref()
+                    .and_then(|(_, schedule)| schedule.after(&now).next())
+                    .map(|next| next.timestamp()),
+                running_on,
+                last_run: last_run.and_then(|value| serde_json::from_str(&value).ok()),
+            });
+        }
+
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn runs_a_task_once_at_a_time() {
+        let redis = testing::redis();
+        let first = Arc::new(Scheduler::new(redis.clone(), testing::blobs()));
+        let second = Arc::new(Scheduler::new(redis.clone(), testing::blobs()));
+
+        // Holds the lock of the task as if the first instance were running it.
+        let lock = || RedisKey::ScheduledTaskLock(MaintenanceTask::Rebuild.name().to_string());
+        redis.async_del(lock()).await.unwrap();
+        assert!(redis
+            .async_set_nx_ex(lock(), &first.instance, SCHEDULED_TASK_LOCK_TTL)
+            .await
+            .unwrap());
+
+        let started = second
+            .start(MaintenanceTask::Rebuild, Trigger::Manual)
+            .await;
+        assert!(matches!(started, Err(ServiceError::Conflict(_))));
+        assert!(!second.extend(&lock()).await.unwrap());
+        assert!(first.extend(&lock()).await.unwrap());
+
+        redis.async_del(lock()).await.unwrap();
+    }
+}
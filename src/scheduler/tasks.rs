@@ -0,0 +1,155 @@
+use crate::api::utils::errors::ServiceError;
+use crate::redis::client::RedisClient;
+use crate::scan::status;
+use crate::storage::blob::BlobStore;
+use crate::storage::gc::GarbageCollector;
+use crate::storage::multipart;
+use crate::storage::scrubber::Scrubber;
+use cron::Schedule;
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Recurring maintenance, run by whichever instance currently leads the scheduler.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceTask {
+    Gc,
+    Scrub,
+    Rescan,
+    Uploads,
+    Rebuild,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 5] = [
+        MaintenanceTask::Gc,
+        MaintenanceTask::Scrub,
+        MaintenanceTask::Rescan,
+        MaintenanceTask::Uploads,
+        MaintenanceTask::Rebuild,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Gc => "gc",
+            MaintenanceTask::Scrub => "scrub",
+            MaintenanceTask::Rescan => "rescan",
+            MaintenanceTask::Uploads => "uploads",
+            MaintenanceTask::Rebuild => "rebuild",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MaintenanceTask::ALL
+            .into_iter()
+            .find(|task| task.name() == name)
+    }
+
+    /// Deleting blobs is left to administrators until they schedule it, and so is
+    /// rebuilding, which is only needed after a disk was replaced.
+    fn default_schedule(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Gc => "off",
+            MaintenanceTask::Scrub => "0 0 4 * * Sun",
+            MaintenanceTask::Rescan => "0 */15 * * * *",
+            MaintenanceTask::Uploads => "0 30 * * * *",
+            MaintenanceTask::Rebuild => "off",
+        }
+    }
+
+    /// The cron expression of the task, from `DOC_STORAGE_SCHEDULE_<NAME>`. `off` leaves the
+    /// task to manual runs.
+    pub fn expression(&self) -> Option<String> {
+        let variable = format!("DOC_STORAGE_SCHEDULE_{}", self.name().to_uppercase());
+        let expression =
+            std::env::var(variable).unwrap_or_else(|_| self.default_schedule().to_string());
+
+        match expression.trim() {
+            "" | "off" => None,
+            expression => Some(expression.to_string()),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        redis: Arc<RedisClient>,
+        blobs: Arc<dyn BlobStore>,
+    ) -> Result<serde_json::Value, ServiceError> {
+        let result = match self {
+            MaintenanceTask::Gc => {
+                serde_json::to_value(GarbageCollector::new(redis, blobs).run(false).await?)
+            }
+            MaintenanceTask::Scrub => {
+                serde_json::to_value(Scrubber::new(redis, blobs).scrub().await?)
+            }
+            MaintenanceTask::Rescan => {
+                serde_json::to_value(status::rescan_pending(&redis, blobs.as_ref()).await?)
+            }
+            MaintenanceTask::Uploads => {
+                serde_json::to_value(multipart::release_abandoned(&redis).await?)
+            }
+            MaintenanceTask::Rebuild => {
+                serde_json::to_value(blobs.rebuild().await.map_err(|error| {
+                    ServiceError::InternalServerError(
+                        "Failed to rebuild the storage".to_string(),
+                        Some(error),
+                    )
+                })?)
+            }
+        };
+
+        result.map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to serialize the data".to_string(),
+                Some(error.into()),
+            )
+        })
+    }
+}
+
+/// Parses a cron expression. The seconds field is optional, so the usual five-field
+/// expressions work as well.
+pub fn parse(expression: &str) -> Result<Schedule, cron::error::Error> {
+    match expression.split_whitespace().count() {
+        5 => Schedule::from_str(&format!("0 {}", expression)),
+        _ => Schedule::from_str(expression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_five_and_six_field_expressions() {
+        let five = parse("30 4 * * Sun").unwrap();
+        let six = parse("0 30 4 * * Sun").unwrap();
+        assert_eq!(
+            five.upcoming(chrono::Utc).next(),
+            six.upcoming(chrono::Utc).next()
+        );
+
+        assert!(parse("every day").is_err());
+        assert!(parse("61 * * * *").is_err());
+    }
+
+    #[test]
+    fn default_schedules_are_valid() {
+        for task in MaintenanceTask::ALL {
+            match task.default_schedule() {
+                "off" => {}
+                expression => assert!(parse(expression).is_ok(), "{}", task.name()),
+            }
+        }
+    }
+
+    #[test]
+    fn finds_tasks_by_name() {
+        for task in MaintenanceTask::ALL {
+            assert_eq!(MaintenanceTask::from_name(task.name()), Some(task));
+        }
+
+        assert_eq!(MaintenanceTask::from_name("unknown"), None);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::storage::content_type;
+use std::io::{Cursor, Read};
+
+const OFFICE_TYPES: [&str; 3] = [
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+];
+
+/// Extracts the searchable text of a document, or `None` when its format is not supported.
+pub async fn extract_text(
+    content_type: &str,
+    data: Vec<u8>,
+) -> Result<Option<String>, anyhow::Error> {
+    let essence = content_type::essence(content_type);
+
+    if essence == "application/pdf" {
+        extract_pdf(data).await.map(Some)
+    } else if OFFICE_TYPES.contains(&essence.as_str()) {
+        let text = tokio::task::spawn_blocking(move || extract_office(data)).await??;
+        Ok(Some(text))
+    } else if content_type::is_text(&essence) {
+        Ok(Some(String::from_utf8_lossy(&data).to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Extracts the text of a PDF with poppler's `pdftotext`.
+async fn extract_pdf(data: Vec<u8>) -> Result<String, anyhow::Error> {
+    let input = std::env::temp_dir().join(format!("doc-storage-{}.pdf", uuid::Uuid::new_v4()));
+    tokio::fs::write(&input, data).await?;
+
+    let output = tokio::process::Command::new("pdftotext")
+        .args(["-enc", "UTF-8"])
+        .arg(&input)
+        .arg("-")
+        .output()
+        .await;
+    let _ = tokio::fs::remove_file(&input).await;
+
+    match output {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        Ok(output) => Err(anyhow::anyhow!("pdftotext exited with {}", output.status)),
+        Err(error) => Err(anyhow::anyhow!("Failed to run pdftotext: {}", error)),
+    }
+}
+
+/// Extracts the text of Office Open XML documents: Word paragraphs, PowerPoint slides and
+/// Excel shared strings.
+fn extract_office(data: Vec<u8>) -> Result<String, anyhow::Error> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+
+    let mut parts = archive
+        .file_names()
+        .filter(|name| {
+            *name == "word/document.xml"
+                || *name == "xl/sharedStrings.xml"
+                || (name.starts_with("ppt/slides/slide") && name.ends_with(".xml"))
+        })
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    parts.sort();
+
+    let mut text = String::new();
+    for part in parts {
+        let mut xml = String::new();
+        archive.by_name(&part)?.read_to_string(&mut xml)?;
+
+        text.push_str(&strip_xml(&xml));
+        text.push('\n');
+    }
+
+    Ok(text)
+}
+
+/// Keeps the character data of an XML document, turning paragraph-like elements into line
+/// breaks.
+fn strip_xml(xml: &str) -> String {
+    let mut text = String::with_capacity(xml.len() / 4);
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = &rest[start + 1..end];
+
+        match tag {
+            "/w:p" | "/a:p" | "/si" | "w:br/" => text.push('\n'),
+            "w:tab/" => text.push(' '),
+            _ => {}
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    text
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,299 @@
+use crate::api::utils::errors::ServiceError;
+use crate::storage::content_type;
+use crate::storage::models::FileMetadata;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+const WRITER_MEMORY: usize = 15_000_000;
+
+#[derive(Clone, Copy)]
+struct SearchFields {
+    id: Field,
+    name: Field,
+    folder: Field,
+    ancestors: Field,
+    kind: Field,
+    body: Field,
+}
+
+struct UserIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub name: String,
+    pub folder: String,
+    pub score: f32,
+    pub snippet: String,
+}
+
+pub struct SearchFilter {
+    pub folder: Option<String>,
+    pub kind: Option<String>,
+    pub limit: usize,
+}
+
+/// Full-text index of file names and contents, with one tantivy index per user.
+pub struct SearchIndex {
+    root: PathBuf,
+    schema: Schema,
+    fields: SearchFields,
+    indexes: Mutex<HashMap<String, Arc<UserIndex>>>,
+}
+
+impl SearchIndex {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, anyhow::Error> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+
+        let mut builder = Schema::builder();
+        let fields = SearchFields {
+            id: builder.add_text_field("id", STRING | STORED),
+            name: builder.add_text_field("name", TEXT | STORED),
+            folder: builder.add_text_field("folder", STRING | STORED),
+            ancestors: builder.add_text_field("ancestors", STRING),
+            kind: builder.add_text_field("kind", STRING | STORED),
+            body: builder.add_text_field("body", TEXT | STORED),
+        };
+
+        Ok(Self {
+            root,
+            schema: builder.build(),
+            fields,
+            indexes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Adds or replaces a file in its owner's index.
+    pub fn index_file(&self, file: &FileMetadata, body: &str) -> Result<(), anyhow::Error> {
+        let index = self.user_index(&file.owner)?;
+        let mut writer = index.writer.lock().unwrap();
+
+        writer.delete_term(Term::from_field_text(self.fields.id, &file.id));
+        writer.add_document(self.document(file, body))?;
+        writer.commit()?;
+        index.reader.reload()?;
+
+        Ok(())
+    }
+
+    /// Re-indexes a file after a rename or a move, keeping the text extracted earlier. Files
+    /// that are not indexed yet are left to the indexer, which extracts their text.
+    pub fn move_file(&self, file: &FileMetadata) -> Result<(), anyhow::Error> {
+        let index = self.user_index(&file.owner)?;
+        let searcher = index.reader.searcher();
+
+        let query = TermQuery::new(
+            Term::from_field_text(self.fields.id, &file.id),
+            IndexRecordOption::Basic,
+        );
+        let body = match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+            Some((_, address)) => {
+                let document = searcher.doc::<TantivyDocument>(*address)?;
+                text(&document, self.fields.body)
+            }
+            None => return Ok(()),
+        };
+
+        self.index_file(file, &body)
+    }
+
+    pub fn remove_file(&self, owner: &str, id: &str) -> Result<(), anyhow::Error> {
+        let index = self.user_index(owner)?;
+        let mut writer = index.writer.lock().unwrap();
+
+        writer.delete_term(Term::from_field_text(self.fields.id, id));
+        writer.commit()?;
+        index.reader.reload()?;
+
+        Ok(())
+    }
+
+    pub fn search(
+        &self,
+        owner: &str,
+        query: &str,
+        filter: &SearchFilter,
+    ) -> Result<Vec<SearchHit>, ServiceError> {
+        let index = self.user_index(owner).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to open the search index".to_string(),
+                Some(error),
+            )
+        })?;
+
+        let parser = QueryParser::for_index(&index.index, vec![self.fields.name, self.fields.body]);
+        let parsed = parser.parse_query(query).map_err(|error| {
+            ServiceError::BadRequest(format!("Invalid search query: {}", error))
+        })?;
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, parsed.box_clone())];
+        if let Some(folder) = &filter.folder {
+            clauses.push((Occur::Must, self.term_query(self.fields.ancestors, folder)));
+        }
+        if let Some(kind) = &filter.kind {
+            clauses.push((Occur::Must, self.term_query(self.fields.kind, kind)));
+        }
+
+        self.collect(&index, &*parsed, &BooleanQuery::new(clauses), filter.limit)
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to search the index".to_string(),
+                    Some(error),
+                )
+            })
+    }
+
+    fn collect(
+        &self,
+        index: &UserIndex,
+        parsed: &dyn Query,
+        query: &dyn Query,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, anyhow::Error> {
+        let searcher = index.reader.searcher();
+        let snippets = SnippetGenerator::create(&searcher, parsed, self.fields.body)?;
+
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(query, &TopDocs::with_limit(limit))? {
+            let document = searcher.doc::<TantivyDocument>(address)?;
+
+            hits.push(SearchHit {
+                id: text(&document, self.fields.id),
+                name: text(&document, self.fields.name),
+                folder: text(&document, self.fields.folder),
+                score,
+                snippet: snippets.snippet_from_doc(&document).to_html(),
+            });
+        }
+
+        Ok(hits)
+    }
+
+    fn term_query(&self, field: Field, value: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_field_text(field, value),
+            IndexRecordOption::Basic,
+        ))
+    }
+
+    fn document(&self, file: &FileMetadata, body: &str) -> TantivyDocument {
+        let mut document = TantivyDocument::default();
+
+        document.add_text(self.fields.id, &file.id);
+        document.add_text(self.fields.name, &file.name);
+        document.add_text(self.fields.folder, &file.folder);
+        // Both the full type and its top-level type are indexed, so `image` matches too.
+        let kind = content_type::essence(&file.content_type);
+        if let Some((top_level, _)) = kind.split_once('/') {
+            document.add_text(self.fields.kind, top_level);
+        }
+        document.add_text(self.fields.kind, &kind);
+        document.add_text(self.fields.body, body);
+
+        // Every ancestor is indexed so a folder filter also matches its subfolders.
+        let mut ancestor = String::new();
+        document.add_text(self.fields.ancestors, "/");
+        for segment in file.folder.split('/').filter(|segment| !segment.is_empty()) {
+            ancestor = format!("{}/{}", ancestor, segment);
+            document.add_text(self.fields.ancestors, &ancestor);
+        }
+
+        document
+    }
+
+    fn user_index(&self, owner: &str) -> Result<Arc<UserIndex>, anyhow::Error> {
+        let mut indexes = self.indexes.lock().unwrap();
+
+        if let Some(index) = indexes.get(owner) {
+            return Ok(index.clone());
+        }
+
+        // Usernames are arbitrary strings, so the directory is named after their hash.
+        let directory = self
+            .root
+            .join(blake3::hash(owner.as_bytes()).to_hex().as_str());
+        std::fs::create_dir_all(&directory)?;
+
+        let index = Index::open_or_create(MmapDirectory::open(&directory)?, self.schema.clone())?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+
+        let index = Arc::new(UserIndex {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+        });
+        indexes.insert(owner.to_string(), index.clone());
+
+        Ok(index)
+    }
+}
+
+fn text(document: &TantivyDocument, field: Field) -> String {
+    document
+        .get_first(field)
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn index() -> SearchIndex {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        SearchIndex::new(root).unwrap()
+    }
+
+    fn filter() -> SearchFilter {
+        SearchFilter {
+            folder: None,
+            kind: None,
+            limit: 10,
+        }
+    }
+
+    #[test]
+    fn moves_keep_the_extracted_text() {
+        let index = index();
+        let mut file = testing::file(&testing::username(), &testing::hash());
+        index.index_file(&file, "quarterly figures").unwrap();
+
+        file.name = "renamed.txt".to_string();
+        index.move_file(&file).unwrap();
+
+        let hits = index.search(&file.owner, "quarterly", &filter()).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "renamed.txt");
+    }
+
+    #[test]
+    fn moves_leave_unindexed_files_alone() {
+        let index = index();
+        let mut file = testing::file(&testing::username(), &testing::hash());
+
+        file.name = "renamed.txt".to_string();
+        index.move_file(&file).unwrap();
+
+        let hits = index.search(&file.owner, "renamed", &filter()).unwrap();
+        assert!(hits.is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::api::utils::errors::ServiceError;
+use crate::jobs::models::Job;
+use crate::jobs::queue;
+use crate::jobs::tasks::Task;
+use crate::redis::client::RedisClient;
+use crate::search::extractor;
+use crate::search::index::SearchIndex;
+use crate::storage::blob::BlobStore;
+use crate::storage::content;
+use crate::storage::models::FileMetadata;
+use std::sync::Arc;
+
+/// Queues the indexing of a file, so uploads do not wait for its text to be extracted.
+pub async fn enqueue_indexing(
+    redis: &RedisClient,
+    file: &FileMetadata,
+) -> Result<(), ServiceError> {
+    let task = Task::IndexFile { file: file.clone() };
+    queue::enqueue(redis, Job::new(file.owner.clone(), task)).await?;
+
+    Ok(())
+}
+
+pub async fn enqueue_move(redis: &RedisClient, file: &FileMetadata) -> Result<(), ServiceError> {
+    let task = Task::MoveInIndex { file: file.clone() };
+    queue::enqueue(redis, Job::new(file.owner.clone(), task)).await?;
+
+    Ok(())
+}
+
+pub async fn enqueue_removal(redis: &RedisClient, file: &FileMetadata) -> Result<(), ServiceError> {
+    let task = Task::RemoveFromIndex {
+        owner: file.owner.clone(),
+        file_id: file.id.clone(),
+    };
+    queue::enqueue(redis, Job::new(file.owner.clone(), task)).await?;
+
+    Ok(())
+}
+
+/// Extracts the text of a file and indexes it. Files whose text cannot be extracted are still
+/// indexed by name and metadata.
+pub async fn index_file(
+    search: Arc<SearchIndex>,
+    blobs: &dyn BlobStore,
+    file: FileMetadata,
+) -> Result<(), anyhow::Error> {
+    let body = match content::read_content(blobs, &file.hash).await {
+        Ok(data) => extractor::extract_text(&file.content_type, data).await,
+        Err(error) => Err(error),
+    };
+
+    let body = body.unwrap_or_else(|error| {
+        log::warn!("Failed to extract the text of file {}: {}", file.id, error);
+        None
+    });
+
+    tokio::task::spawn_blocking(move || {
+        search.index_file(&file, body.as_deref().unwrap_or_default())
+    })
+    .await?
+}
+
+pub async fn move_file(search: Arc<SearchIndex>, file: FileMetadata) -> Result<(), anyhow::Error> {
+    tokio::task::spawn_blocking(move || search.move_file(&file)).await?
+}
+
+pub async fn remove_file(
+    search: Arc<SearchIndex>,
+    owner: String,
+    file_id: String,
+) -> Result<(), anyhow::Error> {
+    tokio::task::spawn_blocking(move || search.remove_file(&owner, &file_id)).await?
+}
@@ -0,0 +1,3 @@
+pub mod extractor;
+pub mod index;
+pub mod indexer;
@@ -0,0 +1,598 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::{BatchOperation, MetadataPayload};
+use crate::constants::{MAX_BATCH_OPERATIONS, QUOTA_POLICY};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::search::indexer;
+use crate::storage::models::FileMetadata;
+use crate::storage::quota::{self, QuotaPolicy};
+use crate::storage::{files, folders, locks, metadata};
+use crate::sync::journal::{self, Change, ChangeKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+/// How many times a batch is planned again after a concurrent change to one of its files.
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationStatus {
+    Applied,
+    Failed,
+    /// Valid on its own, but not applied because another operation of the batch failed.
+    Aborted,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OperationResult {
+    pub index: usize,
+    pub status: OperationStatus,
+    pub file: Option<FileMetadata>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchReport {
+    pub atomic: bool,
+    pub applied: usize,
+    pub failed: usize,
+    pub results: Vec<OperationResult>,
+}
+
+/// The state of every file touched by a batch, as it would be once the batch is applied.
+#[derive(Default)]
+struct Plan {
+    /// Raw values of the keys the plan was built from, checked again when committing.
+    expected: Vec<(RedisKey, Option<String>)>,
+    originals: HashMap<String, Option<FileMetadata>>,
+    files: HashMap<String, Option<FileMetadata>>,
+    /// Touched file ids, in the order they were first touched.
+    order: Vec<String>,
+    /// Reference counts of the blobs touched by the batch, as read and as planned.
+    references: HashMap<String, (i64, i64)>,
+    usage: u64,
+    quota: Option<u64>,
+    usage_delta: i64,
+}
+
+/// Applies a list of file operations as a single Redis transaction.
+///
+/// Operations are first played against an in-memory copy of the files they touch. The
+/// resulting writes are then committed with MULTI/EXEC, on the condition that none of the files
+/// changed in the meantime; otherwise the batch is planned again from fresh data.
+pub struct Batch {
+    redis: Arc<RedisClient>,
+    owner: String,
+    device_id: String,
+}
+
+impl Batch {
+    pub fn new(redis: Arc<RedisClient>, owner: String, device_id: String) -> Self {
+        Self {
+            redis,
+            owner,
+            device_id,
+        }
+    }
+
+    pub async fn run(
+        &self,
+        operations: &[BatchOperation],
+        atomic: bool,
+    ) -> Result<BatchReport, ServiceError> {
+        if operations.is_empty() || operations.len() > MAX_BATCH_OPERATIONS {
+            return Err(ServiceError::BadRequest(format!(
+                "A batch must contain between 1 and {} operations",
+                MAX_BATCH_OPERATIONS
+            )));
+        }
+
+        for _ in 0..MAX_ATTEMPTS {
+            let (plan, report) = self.plan(operations, atomic).await?;
+
+            if report.applied == 0 {
+                return Ok(report);
+            }
+
+            if self.commit(&plan).await? {
+                self.after_commit(&plan).await?;
+                return Ok(report);
+            }
+        }
+
+        Err(ServiceError::Conflict(
+            "The files of this batch kept changing, please try again".to_string(),
+        ))
+    }
+
+    async fn plan(
+        &self,
+        operations: &[BatchOperation],
+        atomic: bool,
+    ) -> Result<(Plan, BatchReport), ServiceError> {
+        let usage_key = RedisKey::Usage(self.owner.clone());
+        let usage: Option<String> = self
+            .redis
+            .execute(redis::cmd("GET").arg(usage_key.to_string()))
+            .await?;
+
+        let mut plan = Plan {
+            usage: usage
+                .as_deref()
+                .and_then(|usage| usage.parse().ok())
+                .unwrap_or_default(),
+            quota: quota::quota_for(&self.redis, &self.owner).await?,
+            expected: vec![(usage_key, usage)],
+            ..Plan::default()
+        };
+
+        let mut results = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.iter().enumerate() {
+            let (status, file, error) = match self.apply(&mut plan, operation).await? {
+                Ok(file) => (OperationStatus::Applied, Some(file), None),
+                Err(error) => (OperationStatus::Failed, None, Some(error)),
+            };
+
+            results.push(OperationResult {
+                index,
+                status,
+                file,
+                error,
+            });
+
+            if atomic && status == OperationStatus::Failed {
+                break;
+            }
+        }
+
+        let failed = results
+            .iter()
+            .filter(|result| result.status == OperationStatus::Failed)
+            .count();
+
+        if atomic && failed > 0 {
+            for result in results.iter_mut() {
+                if result.status == OperationStatus::Applied {
+                    result.status = OperationStatus::Aborted;
+                    result.file = None;
+                }
+            }
+            for index in results.len()..operations.len() {
+                results.push(OperationResult {
+                    index,
+                    status: OperationStatus::Aborted,
+                    file: None,
+                    error: None,
+                });
+            }
+        }
+
+        let applied = results
+            .iter()
+            .filter(|result| result.status == OperationStatus::Applied)
+            .count();
+
+        let report = BatchReport {
+            atomic,
+            applied,
+            failed,
+            results,
+        };
+
+        Ok((plan, report))
+    }
+
+    /// Plays a single operation against the plan. The outer error aborts the whole batch, the
+    /// inner one only fails this operation.
+    async fn apply(
+        &self,
+        plan: &mut Plan,
+        operation: &BatchOperation,
+    ) -> Result<Result<FileMetadata, String>, ServiceError> {
+        let id = match operation {
+            BatchOperation::Move { file, .. }
+            | BatchOperation::Copy { file, .. }
+            | BatchOperation::Delete { file }
+            | BatchOperation::Tag { file, .. }
+            | BatchOperation::Rename { file, .. } => file,
+        };
+
+        let mut file = match self.load(plan, id).await? {
+            Some(file) => file,
+            None => return Ok(Err(format!("File {} does not exist", id))),
+        };
+        let now = chrono::Utc::now().timestamp();
+
+        if !matches!(operation, BatchOperation::Copy { .. }) {
+            if let Some(lock) = locks::get_lock(&self.redis, &file.id).await? {
+                if !lock.is_held_by(&self.owner, &self.device_id) {
+                    return Ok(Err(format!(
+                        "File {} is locked by {} ({})",
+                        file.id, lock.username, lock.device_id
+                    )));
+                }
+            }
+        }
+
+        match operation {
+            BatchOperation::Move { folder, .. } => {
+                file.folder = match files::normalize_folder(folder) {
+                    Ok(folder) => folder,
+                    Err(error) => return Ok(Err(error.to_string())),
+                };
+                file.updated_at = now;
+            }
+            BatchOperation::Rename { name, .. } => {
+                if let Err(error) = files::validate_name(name) {
+                    return Ok(Err(error.to_string()));
+                }
+                file.name = name.clone();
+                file.updated_at = now;
+            }
+            BatchOperation::Tag { add, remove, .. } => {
+                let payload = MetadataPayload {
+                    add_tags: add.clone(),
+                    remove_tags: remove.clone(),
+                    ..MetadataPayload::default()
+                };
+                if let Err(error) = metadata::validate(&payload) {
+                    return Ok(Err(error.to_string()));
+                }
+
+                for tag in remove {
+                    file.tags.remove(tag);
+                }
+                file.tags.extend(add.iter().cloned());
+            }
+            BatchOperation::Delete { .. } => {
+                for version in &file.versions {
+                    let charge = self
+                        .reference(plan, &version.hash, version.size, -1)
+                        .await?;
+                    plan.usage_delta += charge;
+                }
+                plan.files.insert(file.id.clone(), None);
+
+                return Ok(Ok(file));
+            }
+            BatchOperation::Copy { folder, name, .. } => {
+                let folder = match folder.as_deref().map(files::normalize_folder) {
+                    Some(Ok(folder)) => folder,
+                    Some(Err(error)) => return Ok(Err(error.to_string())),
+                    None => file.folder.clone(),
+                };
+                let name = name.clone().unwrap_or_else(|| file.name.clone());
+                if let Err(error) = files::validate_name(&name) {
+                    return Ok(Err(error.to_string()));
+                }
+
+                let references = self.references(plan, &file.hash).await?;
+                let charge = charge(references, 1, file.size);
+                let usage = plan.usage as i64 + plan.usage_delta + charge;
+                if let Some(quota) = plan.quota.filter(|quota| usage > *quota as i64) {
+                    return Ok(Err(format!(
+                        "Copying this file would exceed the quota of {} bytes",
+                        quota
+                    )));
+                }
+                plan.usage_delta += self.reference(plan, &file.hash, file.size, 1).await?;
+
+                file = file.copy_as(folder, name, self.device_id.clone());
+                plan.originals.insert(file.id.clone(), None);
+                plan.order.push(file.id.clone());
+            }
+        }
+
+        plan.files.insert(file.id.clone(), Some(file.clone()));
+
+        Ok(Ok(file))
+    }
+
+    /// Returns how many versions of the owner's files point at `hash` according to the plan,
+    /// reading the count on first use.
+    async fn references(&self, plan: &mut Plan, hash: &str) -> Result<i64, ServiceError> {
+        if let Some((_, references)) = plan.references.get(hash) {
+            return Ok(*references);
+        }
+
+        let key = RedisKey::RefCount(self.owner.clone(), hash.to_string());
+        let raw: Option<String> = self
+            .redis
+            .execute(redis::cmd("GET").arg(key.to_string()))
+            .await?;
+        let references = raw
+            .as_deref()
+            .and_then(|raw| raw.parse().ok())
+            .unwrap_or_default();
+
+        plan.expected.push((key, raw));
+        plan.references
+            .insert(hash.to_string(), (references, references));
+
+        Ok(references)
+    }
+
+    /// Adds `delta` references to `hash` and returns the resulting change of usage.
+    async fn reference(
+        &self,
+        plan: &mut Plan,
+        hash: &str,
+        size: usize,
+        delta: i64,
+    ) -> Result<i64, ServiceError> {
+        let references = self.references(plan, hash).await?;
+
+        if let Some((_, planned)) = plan.references.get_mut(hash) {
+            *planned = references + delta;
+        }
+
+        Ok(charge(references, delta, size))
+    }
+
+    /// Returns a file as it currently stands in the plan, reading it on first use.
+    async fn load(&self, plan: &mut Plan, id: &str) -> Result<Option<FileMetadata>, ServiceError> {
+        if let Some(file) = plan.files.get(id) {
+            return Ok(file.clone());
+        }
+
+        let key = RedisKey::File(id.to_string());
+        let raw: Option<String> = self
+            .redis
+            .execute(redis::cmd("GET").arg(key.to_string()))
+            .await?;
+
+        let file = match &raw {
+            Some(raw) => Some(serde_json::from_str::<FileMetadata>(raw).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })?),
+            None => None,
+        };
+        // Files of other users are reported as missing, like everywhere else.
+        let file = file.filter(|file| file.owner == self.owner);
+
+        plan.expected.push((key, raw));
+        plan.originals.insert(id.to_string(), file.clone());
+        plan.files.insert(id.to_string(), file.clone());
+        plan.order.push(id.to_string());
+
+        Ok(file)
+    }
+
+    async fn commit(&self, plan: &Plan) -> Result<bool, ServiceError> {
+        let mut pipeline = redis::pipe();
+        let files_key = RedisKey::UserFiles(self.owner.clone()).to_string();
+
+        for id in &plan.order {
+            let original = plan.originals.get(id).cloned().flatten();
+            let current = plan.files.get(id).cloned().flatten();
+            let key = RedisKey::File(id.clone()).to_string();
+
+            match &current {
+                Some(file) => {
+                    let value = serde_json::to_string(file).map_err(|error| {
+                        ServiceError::InternalServerError(
+                            "Failed to serialize the data".to_string(),
+                            Some(error.into()),
+                        )
+                    })?;
+                    pipeline.cmd("SET").arg(&key).arg(value).ignore();
+                    pipeline.cmd("SADD").arg(&files_key).arg(id).ignore();
+                }
+                None if original.is_some() => {
+                    pipeline.cmd("DEL").arg(&key).ignore();
+                    pipeline.cmd("SREM").arg(&files_key).arg(id).ignore();
+                }
+                None => continue,
+            }
+
+            self.index_changes(&mut pipeline, id, original.as_ref(), current.as_ref());
+            files::queue_path_index(&mut pipeline, original.as_ref(), current.as_ref());
+        }
+
+        for (hash, (original, planned)) in &plan.references {
+            if original == planned {
+                continue;
+            }
+
+            let key = RedisKey::RefCount(self.owner.clone(), hash.clone()).to_string();
+            match *planned > 0 {
+                true => pipeline.cmd("SET").arg(&key).arg(*planned).ignore(),
+                false => pipeline.cmd("DEL").arg(&key).ignore(),
+            };
+        }
+
+        if plan.usage_delta != 0 {
+            let usage = (plan.usage as i64 + plan.usage_delta).max(0);
+            pipeline
+                .cmd("SET")
+                .arg(RedisKey::Usage(self.owner.clone()).to_string())
+                .arg(usage)
+                .ignore();
+        }
+
+        self.redis
+            .async_compare_and_exec(&plan.expected, &mut pipeline)
+            .await
+    }
+
+    /// Queues the updates of the tag and attribute indexes between two states of a file.
+    fn index_changes(
+        &self,
+        pipeline: &mut redis::Pipeline,
+        id: &str,
+        original: Option<&FileMetadata>,
+        current: Option<&FileMetadata>,
+    ) {
+        let empty_tags = BTreeSet::new();
+        let empty_attributes = BTreeMap::new();
+
+        let old = original
+            .map(|file| (&file.tags, &file.attributes))
+            .unwrap_or((&empty_tags, &empty_attributes));
+        let new = current
+            .map(|file| (&file.tags, &file.attributes))
+            .unwrap_or((&empty_tags, &empty_attributes));
+
+        let member = metadata::file_member(id);
+        metadata::queue_reindex(pipeline, &self.owner, &member, old, new);
+    }
+
+    /// Creates the destination folders, updates the search index and records the changes in the
+    /// journal once the batch is stored.
+    async fn after_commit(&self, plan: &Plan) -> Result<(), ServiceError> {
+        let mut created_folders = HashSet::new();
+
+        for id in &plan.order {
+            let original = plan.originals.get(id).cloned().flatten();
+            let current = plan.files.get(id).cloned().flatten();
+
+            match (original, current) {
+                (None, Some(file)) => {
+                    if created_folders.insert(file.folder.clone()) {
+                        folders::ensure_folder(&self.redis, &self.owner, &file.folder).await?;
+                    }
+                    self.record(ChangeKind::Copied, &file).await?;
+                    indexer::enqueue_indexing(&self.redis, &file).await?;
+                }
+                (Some(original), Some(file)) => {
+                    if original.folder == file.folder && original.name == file.name {
+                        continue;
+                    }
+                    if created_folders.insert(file.folder.clone()) {
+                        folders::ensure_folder(&self.redis, &self.owner, &file.folder).await?;
+                    }
+                    self.record(ChangeKind::Moved, &file).await?;
+                    indexer::enqueue_move(&self.redis, &file).await?;
+                }
+                (Some(original), None) => {
+                    self.record(ChangeKind::Deleted, &original).await?;
+                    indexer::enqueue_removal(&self.redis, &original).await?;
+                }
+                (None, None) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn record(&self, kind: ChangeKind, file: &FileMetadata) -> Result<(), ServiceError> {
+        let change = Change::new(kind, file, &self.device_id);
+        journal::record(&self.redis, &self.owner, change).await
+    }
+}
+
+/// The change of usage caused by adding `delta` references to a blob currently referenced
+/// `references` times, according to the quota policy.
+fn charge(references: i64, delta: i64, size: usize) -> i64 {
+    let size = size as i64;
+
+    match *QUOTA_POLICY {
+        QuotaPolicy::Logical => delta * size,
+        QuotaPolicy::Physical if references <= 0 && references + delta > 0 => size,
+        QuotaPolicy::Physical if references > 0 && references + delta <= 0 => -size,
+        QuotaPolicy::Physical => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn batch(owner: &str) -> Batch {
+        Batch::new(
+            testing::redis(),
+            owner.to_string(),
+            "test-device".to_string(),
+        )
+    }
+
+    fn rename(file: &str, name: &str) -> BatchOperation {
+        BatchOperation::Rename {
+            file: file.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn refuses_empty_and_oversized_batches() {
+        let batch = batch(&testing::username());
+
+        let result = batch.run(&[], true).await;
+        assert!(matches!(result, Err(ServiceError::BadRequest(_))));
+
+        let operations = (0..=MAX_BATCH_OPERATIONS)
+            .map(|_| rename("some-id", "name.txt"))
+            .collect::<Vec<_>>();
+        let result = batch.run(&operations, false).await;
+        assert!(matches!(result, Err(ServiceError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn atomic_batches_apply_all_or_nothing() {
+        let owner = testing::username();
+        let batch = batch(&owner);
+        let file = testing::file(&owner, &testing::hash());
+        files::save_file(&batch.redis, &file).await.unwrap();
+
+        let operations = [
+            rename(&file.id, "renamed.txt"),
+            BatchOperation::Delete {
+                file: "missing-id".to_string(),
+            },
+        ];
+
+        let report = batch.run(&operations, true).await.unwrap();
+        assert_eq!((report.applied, report.failed), (0, 1));
+        let statuses = report.results.iter().map(|result| result.status);
+        assert!(statuses.eq([OperationStatus::Aborted, OperationStatus::Failed]));
+        let stored = files::get_file(&batch.redis, &file.id).await.unwrap();
+        assert_eq!(stored.name, file.name);
+
+        let report = batch.run(&operations, false).await.unwrap();
+        assert_eq!((report.applied, report.failed), (1, 1));
+        let found = files::find_file(&batch.redis, &owner, "/", "renamed.txt").await;
+        assert_eq!(found.unwrap().map(|found| found.id), Some(file.id.clone()));
+
+        batch
+            .redis
+            .async_del(RedisKey::File(file.id))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn plans_are_not_committed_over_concurrent_changes() {
+        let owner = testing::username();
+        let batch = batch(&owner);
+        let file = testing::file(&owner, &testing::hash());
+        files::save_file(&batch.redis, &file).await.unwrap();
+
+        let (plan, report) = batch
+            .plan(&[rename(&file.id, "renamed.txt")], true)
+            .await
+            .unwrap();
+        assert_eq!(report.applied, 1);
+
+        // Another device committed a version after the batch was planned.
+        let mut newer = file.clone();
+        newer.add_version(testing::hash(), 4, "other-device".to_string());
+        files::save_file(&batch.redis, &newer).await.unwrap();
+
+        assert!(!batch.commit(&plan).await.unwrap());
+        let stored = files::get_file(&batch.redis, &file.id).await.unwrap();
+        assert_eq!((stored.name, stored.hash), (file.name, newer.hash));
+
+        batch
+            .redis
+            .async_del(RedisKey::File(file.id))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BlobInfo {
+    pub key: String,
+    pub size: u64,
+    pub modified: i64,
+}
+
+impl BlobInfo {
+    /// Derived blobs are stored as `<hash>.<suffix>`, so the content hash is everything
+    /// before the first dot.
+    pub fn content_hash(&self) -> &str {
+        self.key.split('.').next().unwrap_or(&self.key)
+    }
+}
+
+/// How many copies or shards of a blob are left, out of how many the layout keeps.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redundancy {
+    pub present: usize,
+    pub expected: usize,
+}
+
+impl Redundancy {
+    pub fn is_degraded(&self) -> bool {
+        self.present < self.expected
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RebuildReport {
+    pub scanned: usize,
+    pub restored: usize,
+    pub unrecoverable: Vec<String>,
+}
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), anyhow::Error>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error>;
+    async fn exists(&self, key: &str) -> Result<bool, anyhow::Error>;
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error>;
+    async fn list(&self) -> Result<Vec<BlobInfo>, anyhow::Error>;
+
+    /// Counts the copies of a blob. Stores without redundancy keep a single one.
+    async fn redundancy(&self, key: &str) -> Result<Redundancy, anyhow::Error> {
+        Ok(Redundancy {
+            present: self.exists(key).await? as usize,
+            expected: 1,
+        })
+    }
+
+    /// Returns every redundant copy of a blob the store can find, for repairs. Stores without
+    /// redundancy have nothing to offer.
+    async fn replicas(&self, _key: &str) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        Ok(Vec::new())
+    }
+
+    /// Rewrites every copy or shard of a blob that is missing or differs from `data`, which
+    /// the caller has verified, and returns how many were rewritten. Stores without
+    /// redundancy have nothing to compare against.
+    async fn restore(&self, _key: &str, _data: &[u8]) -> Result<usize, anyhow::Error> {
+        Ok(0)
+    }
+
+    /// Restores the configured redundancy, e.g. after a disk has been replaced. Only
+    /// copies that pass verification are copied from.
+    async fn rebuild(&self) -> Result<RebuildReport, anyhow::Error> {
+        Ok(RebuildReport::default())
+    }
+}
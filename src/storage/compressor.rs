@@ -10,8 +10,12 @@ pub async fn compress_data(data: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
 }
 
 pub async fn decompress_data(data: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
+    decompress(&data)
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
     let mut decoder = ZlibDecoder::new(Vec::new());
-    decoder.write_all(&data)?;
+    decoder.write_all(data)?;
     let decompressed_data = decoder.finish()?;
     Ok(decompressed_data)
 }
@@ -0,0 +1,50 @@
+use crate::storage::blob::BlobStore;
+use crate::storage::compressor;
+
+/// Inputs above this size are hashed on the rayon thread pool.
+const RAYON_THRESHOLD: usize = 1024 * 1024;
+
+pub fn hash_data(data: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new();
+
+    if data.len() >= RAYON_THRESHOLD {
+        hasher.update_rayon(data);
+    } else {
+        hasher.update(data);
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Whether a stored blob still holds the content its key names. Derived blobs are keyed
+/// `<hash>.<suffix>` and have no hash of their own to check, so they always pass.
+pub fn is_intact(key: &str, stored: &[u8]) -> bool {
+    if key.contains('.') {
+        return true;
+    }
+
+    match compressor::decompress(stored) {
+        Ok(data) => hash_data(&data) == key,
+        Err(_) => false,
+    }
+}
+
+/// Stores the data under its BLAKE3 hash. Content that is already stored with every copy the
+/// layout keeps is not written again.
+pub async fn store_content(
+    blobs: &dyn BlobStore,
+    hash: &str,
+    data: Vec<u8>,
+) -> Result<(), anyhow::Error> {
+    if blobs.redundancy(hash).await?.is_degraded() {
+        let compressed = compressor::compress_data(data).await?;
+        blobs.put(hash, &compressed).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn read_content(blobs: &dyn BlobStore, hash: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let compressed = blobs.get(hash).await?;
+    compressor::decompress_data(compressed).await
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Content types allowed and blocked for a user. Patterns are full types such as
+/// `application/pdf` or wildcards such as `image/*`. An empty allow list allows everything
+/// that is not blocked.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TypePolicy {
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    #[serde(default)]
+    pub blocked: Vec<String>,
+}
+
+impl TypePolicy {
+    pub fn permits(&self, content_type: &str) -> bool {
+        let matches = |pattern: &String| matches_pattern(pattern, content_type);
+
+        !self.blocked.iter().any(matches)
+            && (self.allowed.is_empty() || self.allowed.iter().any(matches))
+    }
+}
+
+/// Detects the content type from the magic bytes of the data, falling back to the file
+/// extension.
+pub fn detect(name: &str, data: &[u8]) -> String {
+    if let Some(kind) = infer::get(data) {
+        return kind.mime_type().to_string();
+    }
+
+    mime_guess::from_path(name)
+        .first_raw()
+        .unwrap_or(OCTET_STREAM)
+        .to_string()
+}
+
+/// Whether a client-declared type contradicts the detected one. Empty and generic
+/// declarations, such as the `application/octet-stream` browsers send for unknown files,
+/// never do.
+pub fn is_mismatch(declared: &str, detected: &str) -> bool {
+    let declared = essence(declared);
+    !declared.is_empty() && declared != OCTET_STREAM && declared != essence(detected)
+}
+
+pub fn is_text(content_type: &str) -> bool {
+    let content_type = essence(content_type);
+
+    content_type.starts_with("text/")
+        || matches!(
+            content_type.as_str(),
+            "application/json" | "application/xml" | "application/x-yaml"
+        )
+}
+
+/// The lowercased `type/subtype` part of a content type, without parameters.
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+fn matches_pattern(pattern: &str, content_type: &str) -> bool {
+    let pattern = essence(pattern);
+    let content_type = essence(content_type);
+
+    match pattern.strip_suffix("/*") {
+        Some(prefix) => content_type.split('/').next() == Some(prefix),
+        None => pattern == "*/*" || pattern == content_type,
+    }
+}
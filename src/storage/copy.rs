@@ -0,0 +1,239 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::MetadataPayload;
+use crate::constants::BACKGROUND_COPY_FILES;
+use crate::jobs::models::Job;
+use crate::jobs::tasks::Task;
+use crate::jobs::{queue, store};
+use crate::redis::client::RedisClient;
+use crate::search::indexer;
+use crate::storage::models::{FileMetadata, Folder};
+use crate::storage::{files, folders, gc, metadata, quota};
+use crate::sync::journal::{self, Change, ChangeKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CopyFailure {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FolderCopyReport {
+    pub source: String,
+    pub destination: String,
+    pub copied: usize,
+    pub failed: Vec<CopyFailure>,
+}
+
+/// Folder copies either finish before responding or, for large trees, run as a job.
+pub enum FolderCopy {
+    Done(FolderCopyReport),
+    Started(Box<Job>),
+}
+
+/// Copies files and folders within a user's namespace without touching their content: copies
+/// point at the same blobs, which gain a reference each.
+pub struct Copier {
+    redis: Arc<RedisClient>,
+    owner: String,
+    device_id: String,
+}
+
+impl Copier {
+    pub fn new(redis: Arc<RedisClient>, owner: String, device_id: String) -> Self {
+        Self {
+            redis,
+            owner,
+            device_id,
+        }
+    }
+
+    pub async fn copy_file(
+        &self,
+        source: &FileMetadata,
+        folder: &str,
+        name: &str,
+    ) -> Result<FileMetadata, ServiceError> {
+        files::validate_name(name)?;
+        let folder = files::normalize_folder(folder)?;
+
+        let mut copy = source.copy_as(folder, name.to_string(), self.device_id.clone());
+        let tags = std::mem::take(&mut copy.tags);
+        let attributes = std::mem::take(&mut copy.attributes);
+
+        // Keeps the collector away from the blob in case the source is deleted meanwhile.
+        gc::acquire_upload(&self.redis, &copy.hash).await?;
+        let result = self.save_copy(&mut copy, tags, attributes).await;
+        gc::release_upload(&self.redis, &copy.hash).await?;
+        result?;
+
+        indexer::enqueue_indexing(&self.redis, &copy).await?;
+
+        let change = Change::new(ChangeKind::Copied, &copy, &self.device_id);
+        journal::record(&self.redis, &self.owner, change).await?;
+
+        Ok(copy)
+    }
+
+    async fn save_copy(
+        &self,
+        copy: &mut FileMetadata,
+        tags: BTreeSet<String>,
+        attributes: BTreeMap<String, String>,
+    ) -> Result<(), ServiceError> {
+        quota::add_reference(&self.redis, &self.owner, &copy.hash, copy.size as u64).await?;
+
+        let result = self.save_metadata(copy, tags, attributes).await;
+        if result.is_err() {
+            quota::remove_reference(&self.redis, &self.owner, &copy.hash, copy.size as u64).await?;
+        }
+
+        result
+    }
+
+    async fn save_metadata(
+        &self,
+        copy: &mut FileMetadata,
+        tags: BTreeSet<String>,
+        attributes: BTreeMap<String, String>,
+    ) -> Result<(), ServiceError> {
+        let payload = MetadataPayload {
+            add_tags: tags.into_iter().collect(),
+            set_attributes: attributes,
+            ..MetadataPayload::default()
+        };
+        metadata::apply_changes(
+            &self.redis,
+            &self.owner,
+            &metadata::file_member(&copy.id),
+            &mut copy.tags,
+            &mut copy.attributes,
+            &payload,
+        )
+        .await?;
+
+        folders::ensure_folder(&self.redis, &self.owner, &copy.folder).await?;
+        files::save_file(&self.redis, copy).await
+    }
+
+    /// Copies a folder and everything below it to `destination`. Large trees are copied by a
+    /// background job.
+    pub async fn copy_folder(
+        self,
+        source: Folder,
+        destination: &str,
+    ) -> Result<FolderCopy, ServiceError> {
+        let destination = files::normalize_folder(destination)?;
+
+        // The destination is the path of the copy itself, which cannot be the root.
+        if source.path == "/"
+            || destination == "/"
+            || folders::is_within(&source.path, &destination)
+        {
+            return Err(ServiceError::BadRequest(
+                "A folder cannot be copied into itself".to_string(),
+            ));
+        }
+
+        let files = self.files_within(&source).await?;
+
+        if files.len() <= BACKGROUND_COPY_FILES {
+            let report = self.copy_tree(&source, &destination, files, None).await?;
+            return Ok(FolderCopy::Done(report));
+        }
+
+        let task = Task::CopyFolder {
+            device_id: self.device_id.clone(),
+            source,
+            destination,
+        };
+        let job = queue::enqueue(&self.redis, Job::new(self.owner.clone(), task)).await?;
+
+        Ok(FolderCopy::Started(Box::new(job)))
+    }
+
+    /// Runs a folder copy queued by `copy_folder`, reporting its progress through `job`.
+    pub async fn run_job(
+        &self,
+        source: &Folder,
+        destination: &str,
+        job: &mut Job,
+    ) -> Result<FolderCopyReport, ServiceError> {
+        let files = self.files_within(source).await?;
+
+        self.copy_tree(source, destination, files, Some(job)).await
+    }
+
+    async fn files_within(&self, source: &Folder) -> Result<Vec<FileMetadata>, ServiceError> {
+        let files = files::list_files(&self.redis, &self.owner)
+            .await?
+            .into_iter()
+            .filter(|file| folders::is_within(&source.path, &file.folder))
+            .collect();
+
+        Ok(files)
+    }
+
+    async fn copy_tree(
+        &self,
+        source: &Folder,
+        destination: &str,
+        files: Vec<FileMetadata>,
+        mut job: Option<&mut Job>,
+    ) -> Result<FolderCopyReport, ServiceError> {
+        let mut report = FolderCopyReport {
+            source: source.path.clone(),
+            destination: destination.to_string(),
+            copied: 0,
+            failed: Vec::new(),
+        };
+
+        // Folders first, so empty ones and their metadata are copied too.
+        for folder in folders::list_folders(&self.redis, &self.owner).await? {
+            if !folders::is_within(&source.path, &folder.path) {
+                continue;
+            }
+
+            let path = folders::relocate(&source.path, destination, &folder.path);
+            let mut copy = folders::ensure_folder(&self.redis, &self.owner, &path).await?;
+            let payload = MetadataPayload {
+                add_tags: folder.tags.into_iter().collect(),
+                set_attributes: folder.attributes,
+                ..MetadataPayload::default()
+            };
+
+            metadata::apply_changes(
+                &self.redis,
+                &self.owner,
+                &metadata::folder_member(&copy.id),
+                &mut copy.tags,
+                &mut copy.attributes,
+                &payload,
+            )
+            .await?;
+            folders::save_folder(&self.redis, &copy).await?;
+        }
+
+        let total = files.len() as u64;
+        for (index, file) in files.into_iter().enumerate() {
+            let folder = folders::relocate(&source.path, destination, &file.folder);
+
+            match self.copy_file(&file, &folder, &file.name).await {
+                Ok(_) => report.copied += 1,
+                Err(error) => report.failed.push(CopyFailure {
+                    file: file.id,
+                    error: error.to_string(),
+                }),
+            }
+
+            if let Some(job) = job.as_deref_mut() {
+                job.progress(index as u64 + 1, Some(total));
+                store::save_job(&self.redis, job).await?;
+            }
+        }
+
+        Ok(report)
+    }
+}
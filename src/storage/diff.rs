@@ -0,0 +1,314 @@
+use crate::constants::DIFF_TIMEOUT;
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, DiffOp, TextDiff};
+use std::time::Duration;
+
+pub const DEFAULT_CONTEXT: usize = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Line,
+    Word,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffFormat {
+    #[default]
+    Json,
+    Patch,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DiffChange {
+    pub kind: ChangeKind,
+    pub value: String,
+}
+
+/// A run of changes with its surrounding context. Starts are 1-based and counted in lines, or
+/// in words for word-level diffs.
+#[derive(Serialize, Clone, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_length: usize,
+    pub new_start: usize,
+    pub new_length: usize,
+    pub changes: Vec<DiffChange>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FileDiff {
+    pub from: usize,
+    pub to: usize,
+    pub granularity: Granularity,
+    /// Binary versions are only compared by hash, `hunks` is empty for them.
+    pub binary: bool,
+    pub identical: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Two versions of a document, as read from storage.
+pub struct Versions {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+    pub text: bool,
+}
+
+impl Versions {
+    /// Both versions as text, `None` if either one is binary.
+    fn texts(&self) -> Option<(&str, &str)> {
+        if !self.text {
+            return None;
+        }
+
+        let old = std::str::from_utf8(&self.old).ok()?;
+        let new = std::str::from_utf8(&self.new).ok()?;
+        if old.contains('\0') || new.contains('\0') {
+            return None;
+        }
+
+        Some((old, new))
+    }
+
+    pub fn diff(&self, granularity: Granularity, context: usize) -> FileDiff {
+        let mut result = FileDiff {
+            from: self.from,
+            to: self.to,
+            granularity,
+            binary: false,
+            identical: self.old == self.new,
+            insertions: 0,
+            deletions: 0,
+            hunks: Vec::new(),
+        };
+
+        let (old, new) = match self.texts() {
+            Some(texts) => texts,
+            None => {
+                result.binary = true;
+                return result;
+            }
+        };
+
+        let diff = text_diff(old, new, granularity);
+        for group in diff.grouped_ops(context) {
+            let mut hunk = hunk_header(&group);
+
+            for op in &group {
+                for change in diff.iter_changes(op) {
+                    let kind = match change.tag() {
+                        ChangeTag::Equal => ChangeKind::Equal,
+                        ChangeTag::Insert => {
+                            result.insertions += 1;
+                            ChangeKind::Insert
+                        }
+                        ChangeTag::Delete => {
+                            result.deletions += 1;
+                            ChangeKind::Delete
+                        }
+                    };
+
+                    hunk.changes.push(DiffChange {
+                        kind,
+                        value: change.value().to_string(),
+                    });
+                }
+            }
+
+            result.hunks.push(hunk);
+        }
+
+        result
+    }
+
+    /// The diff as patch text: a unified diff for lines, wdiff-style `[-removed-]{+added+}`
+    /// markup for words.
+    pub fn patch(&self, granularity: Granularity, context: usize) -> String {
+        let old_label = format!("a/{} (version {})", self.name, self.from);
+        let new_label = format!("b/{} (version {})", self.name, self.to);
+
+        let (old, new) = match self.texts() {
+            Some(texts) => texts,
+            None if self.old == self.new => return String::new(),
+            None => return format!("Binary files {} and {} differ\n", old_label, new_label),
+        };
+
+        let diff = text_diff(old, new, granularity);
+        if granularity == Granularity::Line {
+            return diff
+                .unified_diff()
+                .context_radius(context)
+                .header(&old_label, &new_label)
+                .to_string();
+        }
+
+        let mut patch = String::new();
+        for group in diff.grouped_ops(context) {
+            if patch.is_empty() {
+                patch.push_str(&format!("--- {}\n+++ {}\n", old_label, new_label));
+            }
+
+            let hunk = hunk_header(&group);
+            patch.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                hunk.old_start, hunk.old_length, hunk.new_start, hunk.new_length
+            ));
+
+            for op in &group {
+                for change in diff.iter_changes(op) {
+                    match change.tag() {
+                        ChangeTag::Equal => patch.push_str(change.value()),
+                        ChangeTag::Delete => patch.push_str(&format!("[-{}-]", change.value())),
+                        ChangeTag::Insert => patch.push_str(&format!("{{+{}+}}", change.value())),
+                    }
+                }
+            }
+
+            if !patch.ends_with('\n') {
+                patch.push('\n');
+            }
+        }
+
+        patch
+    }
+}
+
+/// Pathological inputs fall back to a coarser diff once the timeout is reached.
+fn text_diff<'a>(
+    old: &'a str,
+    new: &'a str,
+    granularity: Granularity,
+) -> TextDiff<'a, 'a, 'a, str> {
+    let mut config = TextDiff::configure();
+    config
+        .algorithm(Algorithm::Patience)
+        .timeout(Duration::from_secs(DIFF_TIMEOUT));
+
+    match granularity {
+        Granularity::Line => config.diff_lines(old, new),
+        Granularity::Word => config.diff_words(old, new),
+    }
+}
+
+fn hunk_header(group: &[DiffOp]) -> Hunk {
+    let (first, last) = match (group.first(), group.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            return Hunk {
+                old_start: 0,
+                old_length: 0,
+                new_start: 0,
+                new_length: 0,
+                changes: Vec::new(),
+            }
+        }
+    };
+
+    let old_length = last.old_range().end - first.old_range().start;
+    let new_length = last.new_range().end - first.new_range().start;
+
+    // Empty ranges point at the line before them, as in unified diffs.
+    Hunk {
+        old_start: first.old_range().start + (old_length > 0) as usize,
+        old_length,
+        new_start: first.new_range().start + (new_length > 0) as usize,
+        new_length,
+        changes: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(old: &str, new: &str) -> Versions {
+        Versions {
+            name: "notes.txt".to_string(),
+            from: 1,
+            to: 2,
+            old: old.as_bytes().to_vec(),
+            new: new.as_bytes().to_vec(),
+            text: true,
+        }
+    }
+
+    #[test]
+    fn diffs_lines() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+        let new = "one\ntwo\nthree\nfour\n5\nsix\nseven\neight\nnine\n";
+        let diff = versions(old, new).diff(Granularity::Line, 1);
+
+        assert!(!diff.binary && !diff.identical);
+        assert_eq!((diff.insertions, diff.deletions), (2, 1));
+        assert_eq!(diff.hunks.len(), 2);
+
+        let hunk = &diff.hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_length), (4, 3));
+        assert_eq!((hunk.new_start, hunk.new_length), (4, 3));
+        let kinds = hunk
+            .changes
+            .iter()
+            .map(|change| change.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                ChangeKind::Equal,
+                ChangeKind::Delete,
+                ChangeKind::Insert,
+                ChangeKind::Equal
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_unified_patches() {
+        let patch = versions("a\nb\nc\n", "a\nB\nc\n").patch(Granularity::Line, DEFAULT_CONTEXT);
+
+        assert_eq!(
+            patch,
+            "--- a/notes.txt (version 1)\n+++ b/notes.txt (version 2)\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn marks_changed_words() {
+        let versions = versions("the quick fox\n", "the slow fox\n");
+        let patch = versions.patch(Granularity::Word, DEFAULT_CONTEXT);
+
+        assert!(patch.contains("the [-quick-]{+slow+} fox"), "{}", patch);
+        let diff = versions.diff(Granularity::Word, DEFAULT_CONTEXT);
+        assert_eq!((diff.insertions, diff.deletions), (1, 1));
+    }
+
+    #[test]
+    fn compares_binary_versions_by_content_only() {
+        let mut binary = versions("same", "other\0");
+        let diff = binary.diff(Granularity::Line, DEFAULT_CONTEXT);
+        assert!(diff.binary && !diff.identical && diff.hunks.is_empty());
+        assert_eq!(
+            binary.patch(Granularity::Line, DEFAULT_CONTEXT),
+            "Binary files a/notes.txt (version 1) and b/notes.txt (version 2) differ\n"
+        );
+
+        binary.text = false;
+        binary.new = binary.old.clone();
+        assert!(binary.diff(Granularity::Line, DEFAULT_CONTEXT).identical);
+        assert!(binary.patch(Granularity::Line, DEFAULT_CONTEXT).is_empty());
+    }
+}
@@ -0,0 +1,410 @@
+use crate::storage::blob::{BlobInfo, BlobStore, RebuildReport, Redundancy};
+use crate::storage::content;
+use crate::storage::local::LocalBlobStore;
+use async_trait::async_trait;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::collections::BTreeMap;
+
+/// Every shard starts with the length of the original blob, so padding can be removed.
+const HEADER_SIZE: usize = 8;
+
+/// Splits every blob into `data_shards` data shards and `parity_shards` Reed-Solomon parity
+/// shards, one per disk. Any `data_shards` of them are enough to read the blob back.
+pub struct ErasureBlobStore {
+    disks: Vec<LocalBlobStore>,
+    codec: ReedSolomon,
+    data_shards: usize,
+}
+
+impl ErasureBlobStore {
+    pub fn new(
+        disks: Vec<LocalBlobStore>,
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<Self, anyhow::Error> {
+        if data_shards + parity_shards != disks.len() {
+            return Err(anyhow::anyhow!(
+                "Erasure coding with {}+{} shards needs exactly {} disks, {} configured",
+                data_shards,
+                parity_shards,
+                data_shards + parity_shards,
+                disks.len()
+            ));
+        }
+
+        let codec = ReedSolomon::new(data_shards, parity_shards)
+            .map_err(|error| anyhow::anyhow!("Invalid erasure coding layout: {:?}", error))?;
+
+        Ok(Self {
+            disks,
+            codec,
+            data_shards,
+        })
+    }
+
+    /// The size of every shard of a blob of `length` bytes, without its header.
+    fn shard_size(&self, length: usize) -> usize {
+        let shard_size = match length % self.data_shards {
+            0 => length / self.data_shards,
+            _ => length / self.data_shards + 1,
+        };
+        shard_size.max(1)
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let shard_size = self.shard_size(data.len());
+
+        let mut shards = (0..self.disks.len())
+            .map(|index| {
+                let start = (index * shard_size).min(data.len());
+                let end = ((index + 1) * shard_size).min(data.len());
+
+                let mut shard = vec![0u8; shard_size];
+                if index < self.data_shards {
+                    shard[..end - start].copy_from_slice(&data[start..end]);
+                }
+                shard
+            })
+            .collect::<Vec<_>>();
+
+        self.codec
+            .encode(&mut shards)
+            .map_err(|error| anyhow::anyhow!("Failed to encode shards: {:?}", error))?;
+
+        Ok(shards
+            .into_iter()
+            .map(|shard| {
+                let mut framed = (data.len() as u64).to_le_bytes().to_vec();
+                framed.extend_from_slice(&shard);
+                framed
+            })
+            .collect())
+    }
+
+    /// The blob length most shards agree on. A header only counts when its shard has the
+    /// size that length was encoded with, so a corrupted header can neither outvote the
+    /// others nor make decoding allocate more than the shards hold.
+    fn length(&self, shards: &[Option<Vec<u8>>]) -> Result<usize, anyhow::Error> {
+        let mut votes: BTreeMap<usize, usize> = BTreeMap::new();
+        for shard in shards.iter().flatten() {
+            match header(shard) {
+                Some(length) if shard.len() - HEADER_SIZE == self.shard_size(length) => {
+                    *votes.entry(length).or_default() += 1
+                }
+                _ => {}
+            }
+        }
+
+        votes
+            .into_iter()
+            .max_by_key(|(_, votes)| *votes)
+            .map(|(length, _)| length)
+            .ok_or_else(|| anyhow::anyhow!("No shard is readable"))
+    }
+
+    /// Reassembles a blob from whatever shards are available. Missing entries, and shards
+    /// whose header disagrees with the majority, are rebuilt in place.
+    fn decode(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>, anyhow::Error> {
+        let length = self.length(shards)?;
+        let shard_size = self.shard_size(length);
+
+        for shard in shards.iter_mut() {
+            let agrees = match shard {
+                Some(shard) => {
+                    header(shard) == Some(length) && shard.len() - HEADER_SIZE == shard_size
+                }
+                None => true,
+            };
+            if !agrees {
+                *shard = None;
+            }
+        }
+
+        let mut payloads = shards
+            .iter()
+            .map(|shard| shard.as_ref().map(|shard| shard[HEADER_SIZE..].to_vec()))
+            .collect::<Vec<_>>();
+
+        self.codec
+            .reconstruct(&mut payloads)
+            .map_err(|error| anyhow::anyhow!("Failed to reconstruct shards: {:?}", error))?;
+
+        let mut data = Vec::with_capacity(length);
+        for (index, payload) in payloads.into_iter().enumerate() {
+            let payload = payload.unwrap();
+
+            if index < self.data_shards {
+                data.extend_from_slice(&payload);
+            }
+
+            if shards[index].is_none() {
+                let mut framed = (length as u64).to_le_bytes().to_vec();
+                framed.extend_from_slice(&payload);
+                shards[index] = Some(framed);
+            }
+        }
+        data.truncate(length);
+
+        Ok(data)
+    }
+
+    async fn read_shards(&self, key: &str) -> Vec<Option<Vec<u8>>> {
+        let mut shards = Vec::with_capacity(self.disks.len());
+
+        for disk in &self.disks {
+            shards.push(disk.get(key).await.ok());
+        }
+
+        shards
+    }
+
+    /// Decodes the shards as they are, then once with each shard left out, in case one of
+    /// them rotted without going missing.
+    fn reconstructions(&self, shards: &[Option<Vec<u8>>]) -> Vec<Vec<u8>> {
+        let mut candidates = vec![shards.to_vec()];
+        for skipped in 0..shards.len() {
+            if shards[skipped].is_some() {
+                let mut candidate = shards.to_vec();
+                candidate[skipped] = None;
+                candidates.push(candidate);
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter_map(|mut candidate| self.decode(&mut candidate).ok())
+            .collect()
+    }
+}
+
+/// The blob length a shard claims, if it is long enough to have a header.
+fn header(shard: &[u8]) -> Option<usize> {
+    let header = shard.get(..HEADER_SIZE)?.try_into().ok()?;
+    usize::try_from(u64::from_le_bytes(header)).ok()
+}
+
+#[async_trait]
+impl BlobStore for ErasureBlobStore {
+    /// Shards left behind by a failed write would only waste space, so the ones this call
+    /// created are removed again. Shards that were already there are left alone.
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        let mut written = 0;
+        let mut created = Vec::new();
+
+        for (disk, shard) in self.disks.iter().zip(self.encode(data)?) {
+            let existed = disk.exists(key).await.unwrap_or(true);
+
+            match disk.put(key, &shard).await {
+                Ok(()) => {
+                    written += 1;
+                    if !existed {
+                        created.push(disk);
+                    }
+                }
+                Err(error) => log::warn!("Failed to write a shard of blob {}: {}", key, error),
+            }
+        }
+
+        if written < self.data_shards {
+            for disk in created {
+                if let Err(error) = disk.delete(key).await {
+                    log::warn!("Failed to remove a shard of blob {}: {}", key, error);
+                }
+            }
+
+            return Err(anyhow::anyhow!(
+                "Only {} shards of blob {} could be written",
+                written,
+                key
+            ));
+        }
+
+        if written < self.disks.len() {
+            log::warn!(
+                "Blob {} was written with {} of {} shards",
+                key,
+                written,
+                self.disks.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let mut shards = self.read_shards(key).await;
+        self.decode(&mut shards)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.redundancy(key).await?.present >= self.data_shards)
+    }
+
+    async fn redundancy(&self, key: &str) -> Result<Redundancy, anyhow::Error> {
+        let mut present = 0;
+        for disk in &self.disks {
+            if disk.exists(key).await? {
+                present += 1;
+            }
+        }
+
+        Ok(Redundancy {
+            present,
+            expected: self.disks.len(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        for disk in &self.disks {
+            disk.delete(key).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<BlobInfo>, anyhow::Error> {
+        let mut blobs: BTreeMap<String, BlobInfo> = BTreeMap::new();
+
+        for disk in &self.disks {
+            for blob in disk.list().await? {
+                match blobs.get_mut(&blob.key) {
+                    Some(entry) => {
+                        entry.size += blob.size;
+                        entry.modified = entry.modified.max(blob.modified);
+                    }
+                    None => {
+                        blobs.insert(blob.key.clone(), blob);
+                    }
+                }
+            }
+        }
+
+        Ok(blobs.into_values().collect())
+    }
+
+    /// A shard can rot without going missing, in which case decoding silently produces the
+    /// wrong content. Offer one reconstruction per shard with that shard left out, so the
+    /// caller can pick the one that matches the expected hash.
+    async fn replicas(&self, key: &str) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let shards = self.read_shards(key).await;
+        Ok(self.reconstructions(&shards).into_iter().skip(1).collect())
+    }
+
+    /// Parity shards are never read while the data shards are intact, so every shard is
+    /// compared against a fresh encoding of the verified content.
+    async fn restore(&self, key: &str, data: &[u8]) -> Result<usize, anyhow::Error> {
+        let mut rewritten = 0;
+
+        for (disk, shard) in self.disks.iter().zip(self.encode(data)?) {
+            match disk.get(key).await {
+                Ok(stored) if stored == shard => {}
+                _ => {
+                    disk.put(key, &shard).await?;
+                    rewritten += 1;
+                }
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    async fn rebuild(&self) -> Result<RebuildReport, anyhow::Error> {
+        let mut report = RebuildReport::default();
+
+        for blob in self.list().await? {
+            report.scanned += 1;
+
+            let shards = self.read_shards(&blob.key).await;
+            let source = self
+                .reconstructions(&shards)
+                .into_iter()
+                .find(|data| content::is_intact(&blob.key, data));
+
+            match source {
+                Some(source) => report.restored += self.restore(&blob.key, &source).await?,
+                None => report.unrecoverable.push(blob.key),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(root: &std::path::Path) -> ErasureBlobStore {
+        let disks = (0..3)
+            .map(|index| LocalBlobStore::new(root.join(index.to_string())).unwrap())
+            .collect();
+
+        ErasureBlobStore::new(disks, 2, 1).unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_writes_leave_no_shards_behind() {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        let store = store(&root);
+
+        // Blocks the last two disks, so only the first shard can be written.
+        for index in 1..3 {
+            let disk = root.join(index.to_string()).join("blobs");
+            std::fs::remove_dir_all(&disk).unwrap();
+            std::fs::write(&disk, b"").unwrap();
+        }
+
+        assert!(store.put("abcdef", b"some content").await.is_err());
+        assert!(!store.disks[0].exists("abcdef").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn counts_missing_shards() {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        let store = store(&root);
+
+        store.put("abcdef", b"some content").await.unwrap();
+        store.disks[2].delete("abcdef").await.unwrap();
+
+        let redundancy = store.redundancy("abcdef").await.unwrap();
+        assert_eq!(redundancy.present, 2);
+        assert!(redundancy.is_degraded());
+        assert_eq!(store.get("abcdef").await.unwrap(), b"some content");
+    }
+
+    #[tokio::test]
+    async fn outvotes_corrupted_headers() {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        let store = store(&root);
+        store.put("abcdef", b"some content").await.unwrap();
+
+        let mut shard = store.disks[0].get("abcdef").await.unwrap();
+        shard[..HEADER_SIZE].copy_from_slice(&u64::MAX.to_le_bytes());
+        store.disks[0].put("abcdef", &shard).await.unwrap();
+
+        assert_eq!(store.get("abcdef").await.unwrap(), b"some content");
+    }
+
+    #[tokio::test]
+    async fn rebuilds_rotten_parity_shards() {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        let store = store(&root);
+        let data = b"some content".to_vec();
+        let hash = content::hash_data(&data);
+        content::store_content(&store, &hash, data).await.unwrap();
+
+        let mut parity = store.disks[2].get(&hash).await.unwrap();
+        let last = parity.len() - 1;
+        parity[last] ^= 0xff;
+        store.disks[2].put(&hash, &parity).await.unwrap();
+
+        let report = store.rebuild().await.unwrap();
+        assert_eq!(report.restored, 1);
+        assert!(report.unrecoverable.is_empty());
+
+        // Losing a data shard now has to reconstruct from the repaired parity.
+        store.disks[0].delete(&hash).await.unwrap();
+        let stored = store.get(&hash).await.unwrap();
+        assert!(content::is_intact(&hash, &stored));
+    }
+}
@@ -0,0 +1,674 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::MAX_SAVE_ATTEMPTS;
+use crate::media::extract;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::scan::status;
+use crate::storage::blob::BlobStore;
+use crate::storage::content_type;
+use crate::storage::models::{File, FileMetadata, FileVersion};
+use crate::storage::{content, folders, gc, metadata, quota};
+use crate::sync::journal::{self, Change, ChangeKind};
+use crate::user::models::User;
+use std::collections::BTreeMap;
+
+/// Replaces a file, provided its current version is still the one the change was based on.
+const SAVE_IF_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if not current or cjson.decode(current).hash ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+"#;
+
+pub async fn get_file(redis: &RedisClient, id: &str) -> Result<FileMetadata, ServiceError> {
+    let exists = redis.async_exists(RedisKey::File(id.to_string())).await?;
+
+    if !exists {
+        return Err(missing_file(id));
+    }
+
+    redis.d_async_get(RedisKey::File(id.to_string())).await
+}
+
+/// Loads a file and makes sure it belongs to the given user. Files owned by someone else are
+/// reported as missing so their existence is not leaked.
+pub async fn get_owned_file(
+    redis: &RedisClient,
+    id: &str,
+    username: &str,
+) -> Result<FileMetadata, ServiceError> {
+    let file = get_file(redis, id).await?;
+
+    if file.owner != username {
+        return Err(missing_file(id));
+    }
+
+    Ok(file)
+}
+
+pub async fn save_file(redis: &RedisClient, file: &FileMetadata) -> Result<(), ServiceError> {
+    redis
+        .s_async_set(RedisKey::File(file.id.clone()), file)
+        .await?;
+    redis
+        .async_sadd(RedisKey::UserFiles(file.owner.clone()), &file.id)
+        .await?;
+    redis.async_sadd(path_key(file), &file.id).await?;
+
+    Ok(())
+}
+
+/// Files are indexed by their path, so they can be found by it without loading every file of
+/// their owner. Names are not unique within a folder, so a path can lead to several files.
+fn path_key(file: &FileMetadata) -> RedisKey {
+    RedisKey::FilePath(file.owner.clone(), path_of(&file.folder, &file.name))
+}
+
+fn path_of(folder: &str, name: &str) -> String {
+    format!("{}/{}", folder.trim_end_matches('/'), name)
+}
+
+/// Queues the updates of the path index between two states of a file.
+pub fn queue_path_index(
+    pipeline: &mut redis::Pipeline,
+    original: Option<&FileMetadata>,
+    current: Option<&FileMetadata>,
+) {
+    let moved = |file: &FileMetadata| match current {
+        Some(current) => current.folder != file.folder || current.name != file.name,
+        None => true,
+    };
+
+    if let Some(original) = original.filter(|original| moved(original)) {
+        let key = path_key(original).to_string();
+        pipeline.cmd("SREM").arg(key).arg(&original.id).ignore();
+    }
+    if let Some(current) = current {
+        let key = path_key(current).to_string();
+        pipeline.cmd("SADD").arg(key).arg(&current.id).ignore();
+    }
+}
+
+/// Applies `change` to a file as it is currently stored, and writes it back along with the
+/// updates of its tag and attribute indexes, only if nobody wrote it in the meantime. Otherwise
+/// the file is read again and the change applied anew, so concurrent writes such as new
+/// versions are never lost. Files owned by someone other than `username` are reported as
+/// missing.
+pub async fn update_file<F>(
+    redis: &RedisClient,
+    id: &str,
+    username: &str,
+    mut change: F,
+) -> Result<FileMetadata, ServiceError>
+where
+    F: FnMut(&mut FileMetadata) -> Result<(), ServiceError>,
+{
+    let key = RedisKey::File(id.to_string());
+    for _ in 0..MAX_SAVE_ATTEMPTS {
+        let current: Option<String> = redis
+            .execute(redis::cmd("GET").arg(key.to_string()))
+            .await?;
+        let current = match current {
+            Some(current) => current,
+            None => return Err(missing_file(id)),
+        };
+        let mut file: FileMetadata = serde_json::from_str(&current).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to deserialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?;
+        if file.owner != username {
+            return Err(missing_file(id));
+        }
+
+        let original = file.clone();
+        change(&mut file)?;
+        let value = serde_json::to_string(&file).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to serialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?;
+        let mut pipeline = redis::pipe();
+        pipeline.cmd("SET").arg(key.to_string()).arg(value).ignore();
+        let member = metadata::file_member(id);
+        let new = (&file.tags, &file.attributes);
+        let old = (&original.tags, &original.attributes);
+        metadata::queue_reindex(&mut pipeline, username, &member, old, new);
+        queue_path_index(&mut pipeline, Some(&original), Some(&file));
+
+        let expected = [(RedisKey::File(id.to_string()), Some(current))];
+        if redis
+            .async_compare_and_exec(&expected, &mut pipeline)
+            .await?
+        {
+            return Ok(file);
+        }
+    }
+
+    Err(ServiceError::Conflict(format!(
+        "File {} kept changing while it was updated",
+        id
+    )))
+}
+
+fn missing_file(id: &str) -> ServiceError {
+    ServiceError::NotFound(format!("File {} does not exist", id))
+}
+
+/// Writes the content of `file` to the blob store and records it as a new file.
+pub async fn create_file(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    owner: &str,
+    device_id: &str,
+    folder: &str,
+    file: File,
+) -> Result<FileMetadata, ServiceError> {
+    validate_name(&file.name)?;
+    let detected = detect_type(redis, owner, &file).await?;
+
+    let hash = content::hash_data(&file.data);
+    status::check_upload(redis, blobs, owner, &file.name, &hash, &file.data).await?;
+    let version = FileVersion::new(1, hash.clone(), file.size, device_id.to_string());
+    let mut metadata = FileMetadata::new(
+        owner.to_string(),
+        normalize_folder(folder)?,
+        file.name,
+        version,
+    );
+    metadata.set_content_type(detected, file.declared_type);
+    let attributes = metadata.attributes.clone();
+    let media = extract::extract(&metadata.content_type, &file.data);
+    set_media_attributes(&mut metadata, media);
+
+    quota::add_reference(redis, owner, &hash, file.size as u64).await?;
+    gc::acquire_upload(redis, &hash).await?;
+    let result = store_and_save(redis, blobs, &metadata, file.data).await;
+    gc::release_upload(redis, &hash).await?;
+    if result.is_err() {
+        quota::remove_reference(redis, owner, &hash, file.size as u64).await?;
+    }
+    result?;
+
+    let member = metadata::file_member(&metadata.id);
+    metadata::reindex_attributes(redis, owner, &member, &attributes, &metadata.attributes).await?;
+
+    let change = Change::new(ChangeKind::Created, &metadata, device_id);
+    journal::record(redis, owner, change).await?;
+
+    Ok(metadata)
+}
+
+/// Writes the content of `file` to the blob store and records it as the newest version of
+/// `metadata`.
+///
+/// With a `base`, the write only goes through if the file's current content still has that
+/// hash, so changes derived from an outdated version are refused instead of overwriting newer
+/// ones. Without one, the version is added on top of whatever the file holds when it is
+/// written, so versions other writers added meanwhile are kept.
+pub async fn add_file_version(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    metadata: &mut FileMetadata,
+    device_id: &str,
+    file: File,
+    base: Option<&str>,
+) -> Result<(), ServiceError> {
+    if let Some(base) = base.filter(|base| *base != metadata.hash) {
+        return Err(stale_version(base));
+    }
+
+    let detected = detect_type(redis, &metadata.owner, &file).await?;
+
+    let hash = content::hash_data(&file.data);
+    let owner = &metadata.owner;
+    status::check_upload(redis, blobs, owner, &metadata.name, &hash, &file.data).await?;
+    let size = file.size as u64;
+    let version = NewVersion {
+        media: extract::extract(&detected, &file.data),
+        hash: hash.clone(),
+        size: file.size,
+        detected,
+        declared: file.declared_type,
+    };
+    quota::add_reference(redis, &metadata.owner, &hash, size).await?;
+
+    gc::acquire_upload(redis, &hash).await?;
+    let result = store_version(redis, blobs, metadata, device_id, version, file.data, base).await;
+    gc::release_upload(redis, &hash).await?;
+    let attributes = match result {
+        Ok(attributes) => attributes,
+        Err(error) => {
+            quota::remove_reference(redis, &metadata.owner, &hash, size).await?;
+            return Err(error);
+        }
+    };
+
+    let member = metadata::file_member(&metadata.id);
+    let owner = &metadata.owner;
+    metadata::reindex_attributes(redis, owner, &member, &attributes, &metadata.attributes).await?;
+
+    let change = Change::new(ChangeKind::Updated, metadata, device_id);
+    journal::record(redis, &metadata.owner, change).await
+}
+
+/// What a new version records about its content.
+struct NewVersion {
+    hash: String,
+    size: usize,
+    detected: String,
+    declared: Option<String>,
+    media: BTreeMap<String, String>,
+}
+
+/// Stores the content of a new version, then adds it to the file with a compare-and-set against
+/// the version it was added to. Without a `base`, a file changed meanwhile is loaded again and
+/// the version added on top, up to `MAX_SAVE_ATTEMPTS` times. Returns the attributes the file
+/// had before, for reindexing.
+async fn store_version(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    metadata: &mut FileMetadata,
+    device_id: &str,
+    version: NewVersion,
+    data: Vec<u8>,
+    base: Option<&str>,
+) -> Result<BTreeMap<String, String>, ServiceError> {
+    content::store_content(blobs, &version.hash, data)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to store the file".to_string(), Some(error))
+        })?;
+    folders::ensure_folder(redis, &metadata.owner, &metadata.folder).await?;
+
+    let mut attempts = 1;
+    loop {
+        let previous = metadata.hash.clone();
+        let attributes = metadata.attributes.clone();
+        metadata.add_version(version.hash.clone(), version.size, device_id.to_string());
+        metadata.set_content_type(version.detected.clone(), version.declared.clone());
+        set_media_attributes(metadata, version.media.clone());
+
+        match save_file_if(redis, metadata, &previous).await {
+            Ok(()) => return Ok(attributes),
+            Err(ServiceError::PreconditionFailed(_))
+                if base.is_none() && attempts < MAX_SAVE_ATTEMPTS =>
+            {
+                attempts += 1;
+                *metadata = get_file(redis, &metadata.id).await?;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Replaces the attributes extracted from the previous content with those of the new one.
+fn set_media_attributes(metadata: &mut FileMetadata, media: BTreeMap<String, String>) {
+    metadata
+        .attributes
+        .retain(|key, _| !extract::is_media_attribute(key));
+    metadata.attributes.extend(media);
+}
+
+fn stale_version(base: &str) -> ServiceError {
+    ServiceError::PreconditionFailed(format!("The file has changed since version {}", base))
+}
+
+/// Detects the content type of an upload and checks it against the owner's type policy.
+async fn detect_type(
+    redis: &RedisClient,
+    owner: &str,
+    file: &File,
+) -> Result<String, ServiceError> {
+    let detected = content_type::detect(&file.name, &file.data);
+
+    let account = RedisKey::Account(owner.to_string());
+    if redis.async_exists(account).await? {
+        let user: User = redis
+            .d_async_get(RedisKey::Account(owner.to_string()))
+            .await?;
+
+        if !user.type_policy.permits(&detected) {
+            return Err(ServiceError::UnsupportedMediaType(format!(
+                "Files of type {} are not allowed",
+                detected
+            )));
+        }
+    }
+
+    Ok(detected)
+}
+
+async fn store_and_save(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    metadata: &FileMetadata,
+    data: Vec<u8>,
+) -> Result<(), ServiceError> {
+    content::store_content(blobs, &metadata.hash, data)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to store the file".to_string(), Some(error))
+        })?;
+
+    folders::ensure_folder(redis, &metadata.owner, &metadata.folder).await?;
+    save_file(redis, metadata).await
+}
+
+async fn save_file_if(
+    redis: &RedisClient,
+    file: &FileMetadata,
+    base: &str,
+) -> Result<(), ServiceError> {
+    let value = serde_json::to_string(file).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })?;
+
+    let saved: i64 = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(SAVE_IF_SCRIPT)
+                .arg(1)
+                .arg(RedisKey::File(file.id.clone()).to_string())
+                .arg(base)
+                .arg(value),
+        )
+        .await?;
+
+    match saved {
+        1 => Ok(()),
+        _ => Err(stale_version(base)),
+    }
+}
+
+pub async fn delete_file(
+    redis: &RedisClient,
+    file: &FileMetadata,
+    device_id: &str,
+) -> Result<(), ServiceError> {
+    metadata::clear_indexes(
+        redis,
+        &file.owner,
+        &metadata::file_member(&file.id),
+        &file.tags,
+        &file.attributes,
+    )
+    .await?;
+
+    redis.async_del(RedisKey::File(file.id.clone())).await?;
+    redis.async_del(RedisKey::FileLock(file.id.clone())).await?;
+    redis
+        .async_srem(RedisKey::UserFiles(file.owner.clone()), &file.id)
+        .await?;
+    redis.async_srem(path_key(file), &file.id).await?;
+
+    for version in &file.versions {
+        quota::remove_reference(redis, &file.owner, &version.hash, version.size as u64).await?;
+    }
+
+    let change = Change::new(ChangeKind::Deleted, file, device_id);
+    journal::record(redis, &file.owner, change).await?;
+
+    Ok(())
+}
+
+/// Lists the files of a user. Files deleted while the list is read are left out.
+pub async fn list_files(
+    redis: &RedisClient,
+    username: &str,
+) -> Result<Vec<FileMetadata>, ServiceError> {
+    let ids = redis
+        .async_smembers(RedisKey::UserFiles(username.to_string()))
+        .await?;
+
+    load_files(redis, ids).await
+}
+
+async fn load_files(
+    redis: &RedisClient,
+    ids: Vec<String>,
+) -> Result<Vec<FileMetadata>, ServiceError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let values = redis
+        .async_mget(ids.into_iter().map(RedisKey::File).collect())
+        .await?;
+
+    let mut files = Vec::with_capacity(values.len());
+    for value in values.into_iter().flatten() {
+        files.push(serde_json::from_str(&value).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to deserialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?);
+    }
+
+    Ok(files)
+}
+
+/// Finds a file by folder and name. Names are not unique within a folder, so the most
+/// recently changed file wins.
+pub async fn find_file(
+    redis: &RedisClient,
+    username: &str,
+    folder: &str,
+    name: &str,
+) -> Result<Option<FileMetadata>, ServiceError> {
+    index_paths(redis, username).await?;
+
+    let key = RedisKey::FilePath(username.to_string(), path_of(folder, name));
+    let ids = redis.async_smembers(key).await?;
+    let file = load_files(redis, ids)
+        .await?
+        .into_iter()
+        .filter(|file| file.owner == username && file.folder == folder && file.name == name)
+        .max_by_key(|file| file.updated_at);
+
+    Ok(file)
+}
+
+/// Files stored before the path index existed are only in the owner's set, so it is walked
+/// once to index them.
+async fn index_paths(redis: &RedisClient, username: &str) -> Result<(), ServiceError> {
+    let indexed = RedisKey::FilePathsIndexed(username.to_string());
+    if redis.async_exists(indexed).await? {
+        return Ok(());
+    }
+
+    for file in list_files(redis, username).await? {
+        redis.async_sadd(path_key(&file), &file.id).await?;
+    }
+    redis
+        .async_set(RedisKey::FilePathsIndexed(username.to_string()), "1")
+        .await?;
+
+    Ok(())
+}
+
+/// Walks every file stored in Redis, regardless of its owner.
+pub async fn all_files(redis: &RedisClient) -> Result<Vec<FileMetadata>, ServiceError> {
+    let keys = redis.async_scan(RedisKey::File("*".to_string())).await?;
+    let prefix = RedisKey::File(String::new()).to_string();
+
+    let mut files = Vec::with_capacity(keys.len());
+    for key in keys {
+        let id = key.trim_start_matches(&prefix).to_string();
+        let value: Option<String> = redis
+            .execute(redis::cmd("GET").arg(RedisKey::File(id).to_string()))
+            .await?;
+
+        // Files deleted since the scan are simply gone.
+        if let Some(value) = value {
+            files.push(serde_json::from_str(&value).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })?);
+        }
+    }
+
+    Ok(files)
+}
+
+pub fn normalize_folder(folder: &str) -> Result<String, ServiceError> {
+    let segments = folder
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>();
+
+    if segments.contains(&"..") {
+        return Err(ServiceError::BadRequest(
+            "Folder paths cannot contain '..'".to_string(),
+        ));
+    }
+
+    Ok(format!("/{}", segments.join("/")))
+}
+
+pub fn validate_name(name: &str) -> Result<(), ServiceError> {
+    if name.trim().is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(ServiceError::BadRequest(format!(
+            "'{}' is not a valid file name",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn stale_writes_are_refused() {
+        let redis = testing::redis();
+        let base = testing::hash();
+        let mut file = testing::file(&testing::username(), &base);
+        save_file(&redis, &file).await.unwrap();
+
+        // Another device committed a version first.
+        let mut newer = file.clone();
+        newer.add_version(testing::hash(), 4, "other-device".to_string());
+        save_file_if(&redis, &newer, &base).await.unwrap();
+
+        file.add_version(testing::hash(), 4, "test-device".to_string());
+        let result = save_file_if(&redis, &file, &base).await;
+        assert!(matches!(result, Err(ServiceError::PreconditionFailed(_))));
+
+        let stored = get_file(&redis, &file.id).await.unwrap();
+        assert_eq!(stored.hash, newer.hash);
+
+        redis.async_del(RedisKey::File(file.id)).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn updates_apply_to_the_stored_file() {
+        let redis = testing::redis();
+        let owner = testing::username();
+        let stale = testing::file(&owner, &testing::hash());
+        save_file(&redis, &stale).await.unwrap();
+
+        let mut newer = stale.clone();
+        newer.add_version(testing::hash(), 4, "other-device".to_string());
+        save_file_if(&redis, &newer, &stale.hash).await.unwrap();
+
+        let moved = update_file(&redis, &stale.id, &owner, |file| {
+            file.name = "moved.txt".to_string();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(moved.name, "moved.txt");
+        assert_eq!(moved.hash, newer.hash);
+        assert_eq!(get_file(&redis, &stale.id).await.unwrap().versions.len(), 2);
+
+        let result = update_file(&redis, &stale.id, "someone-else", |_| Ok(())).await;
+        assert!(matches!(result, Err(ServiceError::NotFound(_))));
+
+        redis.async_del(RedisKey::File(stale.id)).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn files_are_found_by_path() {
+        let redis = testing::redis();
+        let owner = testing::username();
+        let file = testing::file(&owner, &testing::hash());
+        save_file(&redis, &file).await.unwrap();
+
+        let found = find_file(&redis, &owner, &file.folder, &file.name).await;
+        assert_eq!(found.unwrap().map(|found| found.id), Some(file.id.clone()));
+
+        update_file(&redis, &file.id, &owner, |file| {
+            file.name = "moved.txt".to_string();
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let found = find_file(&redis, &owner, &file.folder, &file.name).await;
+        assert!(found.unwrap().is_none());
+        let found = find_file(&redis, &owner, &file.folder, "moved.txt").await;
+        assert_eq!(found.unwrap().map(|found| found.id), Some(file.id.clone()));
+
+        // A file removed without its index entries is skipped rather than failing lookups.
+        redis
+            .async_del(RedisKey::File(file.id.clone()))
+            .await
+            .unwrap();
+        let found = find_file(&redis, &owner, &file.folder, "moved.txt").await;
+        assert!(found.unwrap().is_none());
+        assert!(list_files(&redis, &owner).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn versions_without_a_base_go_on_top_of_newer_ones() {
+        let redis = testing::redis();
+        let blobs = testing::blobs();
+        let mut file = testing::file(&testing::username(), &testing::hash());
+        save_file(&redis, &file).await.unwrap();
+
+        // Another writer added a version after this one loaded the file.
+        let mut newer = file.clone();
+        newer.add_version(testing::hash(), 4, "other-device".to_string());
+        save_file_if(&redis, &newer, &file.hash).await.unwrap();
+
+        let upload = File {
+            name: file.name.clone(),
+            size: 4,
+            data: b"data".to_vec(),
+            declared_type: None,
+        };
+        add_file_version(
+            &redis,
+            blobs.as_ref(),
+            &mut file,
+            "test-device",
+            upload,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let stored = get_file(&redis, &file.id).await.unwrap();
+        assert_eq!(stored.versions.len(), 3);
+        assert_eq!(stored.versions[1].hash, newer.hash);
+        assert_eq!(stored.hash, content::hash_data(b"data"));
+
+        delete_file(&redis, &stored, "test-device").await.unwrap();
+    }
+}
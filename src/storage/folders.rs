@@ -0,0 +1,136 @@
+use crate::api::utils::errors::ServiceError;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::metadata;
+use crate::storage::models::Folder;
+
+pub async fn get_owned_folder(
+    redis: &RedisClient,
+    id: &str,
+    username: &str,
+) -> Result<Folder, ServiceError> {
+    let exists = redis.async_exists(RedisKey::Folder(id.to_string())).await?;
+
+    if !exists {
+        return Err(ServiceError::NotFound(format!(
+            "Folder {} does not exist",
+            id
+        )));
+    }
+
+    let folder: Folder = redis.d_async_get(RedisKey::Folder(id.to_string())).await?;
+    if folder.owner != username {
+        return Err(ServiceError::NotFound(format!(
+            "Folder {} does not exist",
+            id
+        )));
+    }
+
+    Ok(folder)
+}
+
+/// Moves `path` from under `source` to under `destination`.
+pub fn relocate(source: &str, destination: &str, path: &str) -> String {
+    format!("{}{}", destination, &path[source.len()..])
+}
+
+/// Whether `path` is `root` or lies below it.
+pub fn is_within(root: &str, path: &str) -> bool {
+    root == "/" || path == root || path.starts_with(&format!("{}/", root))
+}
+
+/// Returns the folder at `path` if it exists. The root always exists.
+pub async fn find_folder(
+    redis: &RedisClient,
+    owner: &str,
+    path: &str,
+) -> Result<Option<Folder>, ServiceError> {
+    if path == "/" {
+        return ensure_folder(redis, owner, path).await.map(Some);
+    }
+
+    let id = Folder::id_for(owner, path);
+    if !redis.async_exists(RedisKey::Folder(id.clone())).await? {
+        return Ok(None);
+    }
+
+    redis.d_async_get(RedisKey::Folder(id)).await.map(Some)
+}
+
+/// Returns the folder at `path`, creating it and its missing ancestors on first use.
+pub async fn ensure_folder(
+    redis: &RedisClient,
+    owner: &str,
+    path: &str,
+) -> Result<Folder, ServiceError> {
+    let mut current = String::new();
+    let mut paths = vec!["/".to_string()];
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        current = format!("{}/{}", current, segment);
+        paths.push(current.clone());
+    }
+
+    let mut folder = None;
+    for path in paths {
+        let id = Folder::id_for(owner, &path);
+
+        folder = Some(
+            match redis.async_exists(RedisKey::Folder(id.clone())).await? {
+                true => redis.d_async_get(RedisKey::Folder(id)).await?,
+                false => {
+                    let created = Folder::new(owner.to_string(), path);
+                    save_folder(redis, &created).await?;
+                    created
+                }
+            },
+        );
+    }
+
+    Ok(folder.unwrap())
+}
+
+pub async fn save_folder(redis: &RedisClient, folder: &Folder) -> Result<(), ServiceError> {
+    redis
+        .s_async_set(RedisKey::Folder(folder.id.clone()), folder)
+        .await?;
+    redis
+        .async_sadd(RedisKey::UserFolders(folder.owner.clone()), &folder.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes the folder record only, its files and subfolders are left to the caller.
+pub async fn delete_folder(redis: &RedisClient, folder: &Folder) -> Result<(), ServiceError> {
+    metadata::clear_indexes(
+        redis,
+        &folder.owner,
+        &metadata::folder_member(&folder.id),
+        &folder.tags,
+        &folder.attributes,
+    )
+    .await?;
+
+    redis.async_del(RedisKey::Folder(folder.id.clone())).await?;
+    redis
+        .async_srem(RedisKey::UserFolders(folder.owner.clone()), &folder.id)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn list_folders(
+    redis: &RedisClient,
+    username: &str,
+) -> Result<Vec<Folder>, ServiceError> {
+    let ids = redis
+        .async_smembers(RedisKey::UserFolders(username.to_string()))
+        .await?;
+
+    let mut folders = Vec::with_capacity(ids.len());
+    for id in ids {
+        folders.push(redis.d_async_get(RedisKey::Folder(id)).await?);
+    }
+    folders.sort_by(|a: &Folder, b: &Folder| a.path.cmp(&b.path));
+
+    Ok(folders)
+}
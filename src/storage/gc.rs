@@ -0,0 +1,400 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{
+    GC_CLAIM_TTL, GC_GRACE_PERIOD, GC_LOCK_TTL, GC_RETRY_AFTER, PENDING_BLOB_TTL,
+};
+use crate::jobs::queue;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::scan::status;
+use crate::scheduler::runner::{EXTEND_SCRIPT, UNLOCK_SCRIPT};
+use crate::storage::blob::{BlobInfo, BlobStore};
+use crate::storage::{files, multipart};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Registers an in-flight upload of `hash`. Fails (returns 0) while the collector is deleting
+/// the same blob, in which case the caller should retry.
+const ACQUIRE_UPLOAD_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == 'gc' then
+    return 0
+end
+redis.call('INCR', KEYS[1])
+redis.call('EXPIRE', KEYS[1], ARGV[1])
+return 1
+"#;
+
+const RELEASE_UPLOAD_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value == false or value == 'gc' then
+    return 0
+end
+if tonumber(value) <= 1 then
+    redis.call('DEL', KEYS[1])
+else
+    redis.call('DECR', KEYS[1])
+end
+return 1
+"#;
+
+#[derive(Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub scanned_blobs: usize,
+    pub live_hashes: usize,
+    pub orphaned: Vec<BlobInfo>,
+    pub skipped_in_flight: usize,
+    pub skipped_recent: usize,
+    pub reclaimed_bytes: u64,
+}
+
+pub struct GarbageCollector {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+}
+
+impl GarbageCollector {
+    pub fn new(redis: Arc<RedisClient>, blobs: Arc<dyn BlobStore>) -> Self {
+        Self { redis, blobs }
+    }
+
+    pub async fn run(&self, dry_run: bool) -> Result<GcReport, ServiceError> {
+        let token = uuid::Uuid::new_v4().to_string();
+        let locked = self
+            .redis
+            .async_set_nx_ex(RedisKey::GcLock, &token, GC_LOCK_TTL)
+            .await?;
+
+        if !locked {
+            return Err(ServiceError::BadRequest(
+                "A garbage collection is already running".to_string(),
+            ));
+        }
+
+        let renew = async {
+            let period = Duration::from_secs(GC_LOCK_TTL as u64 / 3);
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match self.extend(&token).await {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(error) => {
+                        log::warn!("Failed to extend the garbage collection lock: {}", error)
+                    }
+                }
+            }
+        };
+
+        // Another collection may start once the lock is lost, so this one stops.
+        let report = tokio::select! {
+            report = self.collect(dry_run) => report,
+            _ = renew => Err(ServiceError::Conflict(
+                "Lost the garbage collection lock while collecting".to_string(),
+            )),
+        };
+        self.redis
+            .execute::<i64>(
+                redis::cmd("EVAL")
+                    .arg(UNLOCK_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::GcLock.to_string())
+                    .arg(&token),
+            )
+            .await?;
+
+        report
+    }
+
+    async fn extend(&self, token: &str) -> Result<bool, ServiceError> {
+        let extended: i64 = self
+            .redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(EXTEND_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::GcLock.to_string())
+                    .arg(token)
+                    .arg(GC_LOCK_TTL),
+            )
+            .await?;
+
+        Ok(extended == 1)
+    }
+
+    async fn collect(&self, dry_run: bool) -> Result<GcReport, ServiceError> {
+        if !dry_run {
+            multipart::release_abandoned(&self.redis).await?;
+        }
+
+        // Snapshot the blobs before marking, so anything uploaded during the mark phase is
+        // either absent from the snapshot or protected by its pending marker.
+        let blobs = self.blobs.list().await.map_err(|error| {
+            ServiceError::InternalServerError("Failed to list blobs".to_string(), Some(error))
+        })?;
+        let live = self.mark().await?;
+
+        let mut report = GcReport {
+            dry_run,
+            scanned_blobs: blobs.len(),
+            live_hashes: live.len(),
+            orphaned: Vec::new(),
+            skipped_in_flight: 0,
+            skipped_recent: 0,
+            reclaimed_bytes: 0,
+        };
+        let cutoff = chrono::Utc::now().timestamp() - *GC_GRACE_PERIOD;
+
+        let mut candidates = Vec::new();
+        for blob in blobs {
+            if live.contains(blob.content_hash()) {
+                continue;
+            }
+
+            if blob.modified > cutoff {
+                report.skipped_recent += 1;
+                continue;
+            }
+
+            if !dry_run && !self.claim(&blob).await? {
+                report.skipped_in_flight += 1;
+                continue;
+            }
+
+            candidates.push(blob);
+        }
+
+        if dry_run {
+            report.reclaimed_bytes = candidates.iter().map(|blob| blob.size).sum();
+            report.orphaned = candidates;
+        } else {
+            self.sweep(candidates, &mut report).await?;
+        }
+
+        log::info!(
+            "Garbage collection finished (dry run: {}): {} orphaned blobs, {} bytes",
+            dry_run,
+            report.orphaned.len(),
+            report.reclaimed_bytes
+        );
+
+        Ok(report)
+    }
+
+    async fn mark(&self) -> Result<HashSet<String>, ServiceError> {
+        let mut live = HashSet::new();
+
+        for file in files::all_files(&self.redis).await? {
+            live.extend(file.hashes().map(str::to_string));
+        }
+        live.extend(multipart::pending_hashes(&self.redis).await?);
+        live.extend(status::quarantined_hashes(&self.redis).await?);
+        live.extend(queue::held_hashes(&self.redis).await?);
+
+        Ok(live)
+    }
+
+    /// Keeps uploads away from an orphaned blob until it has been swept. Fails while an upload
+    /// holds its hash.
+    async fn claim(&self, blob: &BlobInfo) -> Result<bool, ServiceError> {
+        self.redis
+            .async_set_nx_ex(
+                RedisKey::PendingBlob(blob.content_hash().to_string()),
+                "gc",
+                GC_CLAIM_TTL,
+            )
+            .await
+    }
+
+    /// Deletes the claimed blobs that are still orphaned, then lifts the claims.
+    async fn sweep(
+        &self,
+        candidates: Vec<BlobInfo>,
+        report: &mut GcReport,
+    ) -> Result<(), ServiceError> {
+        let result = self.sweep_claimed(&candidates, report).await;
+
+        for blob in &candidates {
+            self.redis
+                .async_del(RedisKey::PendingBlob(blob.content_hash().to_string()))
+                .await?;
+        }
+
+        result
+    }
+
+    /// An upload may have committed a file using one of the blobs between the first mark and
+    /// the claim, so everything is marked again while the claims keep new uploads out.
+    async fn sweep_claimed(
+        &self,
+        candidates: &[BlobInfo],
+        report: &mut GcReport,
+    ) -> Result<(), ServiceError> {
+        let live = self.mark().await?;
+
+        for blob in candidates {
+            if live.contains(blob.content_hash()) {
+                report.skipped_in_flight += 1;
+                continue;
+            }
+
+            self.blobs.delete(&blob.key).await.map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to delete a blob".to_string(),
+                    Some(error),
+                )
+            })?;
+
+            report.reclaimed_bytes += blob.size;
+            report.orphaned.push(blob.clone());
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks `hash` as being uploaded so the collector leaves it alone until the upload has
+/// committed its metadata. Every call must be paired with `release_upload`. A collection
+/// holds its claims until the sweep is over, so uploads of content it is about to delete are
+/// told to come back later rather than waiting for it.
+pub async fn acquire_upload(redis: &RedisClient, hash: &str) -> Result<(), ServiceError> {
+    for _ in 0..50 {
+        let acquired: i64 = redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(ACQUIRE_UPLOAD_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::PendingBlob(hash.to_string()).to_string())
+                    .arg(PENDING_BLOB_TTL),
+            )
+            .await?;
+
+        if acquired == 1 {
+            return Ok(());
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Err(ServiceError::ServiceUnavailable(
+        "The garbage collector is deleting this content, try again later".to_string(),
+        GC_RETRY_AFTER,
+    ))
+}
+
+pub async fn release_upload(redis: &RedisClient, hash: &str) -> Result<(), ServiceError> {
+    redis
+        .execute::<i64>(
+            redis::cmd("EVAL")
+                .arg(RELEASE_UPLOAD_SCRIPT)
+                .arg(1)
+                .arg(RedisKey::PendingBlob(hash.to_string()).to_string()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn report() -> GcReport {
+        GcReport {
+            dry_run: false,
+            scanned_blobs: 0,
+            live_hashes: 0,
+            orphaned: Vec::new(),
+            skipped_in_flight: 0,
+            skipped_recent: 0,
+            reclaimed_bytes: 0,
+        }
+    }
+
+    async fn orphan(gc: &GarbageCollector) -> BlobInfo {
+        let hash = testing::hash();
+        gc.blobs.put(&hash, b"data").await.unwrap();
+
+        let blob = BlobInfo {
+            key: hash,
+            size: 4,
+            modified: 0,
+        };
+        assert!(gc.claim(&blob).await.unwrap());
+
+        blob
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn sweep_deletes_orphaned_blobs() {
+        let gc = GarbageCollector::new(testing::redis(), testing::blobs());
+        let blob = orphan(&gc).await;
+
+        let mut report = report();
+        gc.sweep(vec![blob.clone()], &mut report).await.unwrap();
+
+        assert!(!gc.blobs.exists(&blob.key).await.unwrap());
+        let claim = RedisKey::PendingBlob(blob.key.clone());
+        assert!(!gc.redis.async_exists(claim).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn sweep_keeps_blobs_committed_after_the_mark() {
+        let gc = GarbageCollector::new(testing::redis(), testing::blobs());
+        let blob = orphan(&gc).await;
+
+        // An upload of the same content committed between the mark and the claim.
+        let file = testing::file(&testing::username(), &blob.key);
+        files::save_file(&gc.redis, &file).await.unwrap();
+
+        let mut report = report();
+        gc.sweep(vec![blob.clone()], &mut report).await.unwrap();
+
+        assert!(gc.blobs.exists(&blob.key).await.unwrap());
+        assert_eq!(report.skipped_in_flight, 1);
+        assert!(report.orphaned.is_empty());
+
+        gc.redis.async_del(RedisKey::File(file.id)).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn locks_belong_to_their_collection() {
+        let gc = GarbageCollector::new(testing::redis(), testing::blobs());
+        gc.redis
+            .async_set(RedisKey::GcLock, "another-collection")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            gc.run(true).await,
+            Err(ServiceError::BadRequest(_))
+        ));
+        assert!(!gc.extend("this-collection").await.unwrap());
+        assert!(gc.extend("another-collection").await.unwrap());
+
+        gc.redis.async_del(RedisKey::GcLock).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn claimed_blobs_hold_uploads_back() {
+        let gc = GarbageCollector::new(testing::redis(), testing::blobs());
+        let blob = orphan(&gc).await;
+
+        let upload = acquire_upload(&gc.redis, &blob.key).await;
+        assert!(matches!(
+            upload,
+            Err(ServiceError::ServiceUnavailable(_, GC_RETRY_AFTER))
+        ));
+
+        let mut report = report();
+        gc.sweep(vec![blob.clone()], &mut report).await.unwrap();
+        acquire_upload(&gc.redis, &blob.key).await.unwrap();
+        release_upload(&gc.redis, &blob.key).await.unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use crate::storage::blob::BlobStore;
+use crate::storage::erasure::ErasureBlobStore;
+use crate::storage::local::LocalBlobStore;
+use crate::storage::replicated::ReplicatedBlobStore;
+use std::env;
+use std::sync::Arc;
+
+/// Builds the blob store described by the environment:
+///
+/// - `DOC_STORAGE_LAYOUT`: `local` (default), `replicated` or `erasure`
+/// - `DOC_STORAGE_PATH`: directory of the `local` layout
+/// - `DOC_STORAGE_DISKS`: comma separated directories of the other layouts
+/// - `DOC_STORAGE_REPLICAS`: copies kept by `replicated`, defaults to one per disk
+/// - `DOC_STORAGE_DATA_SHARDS` / `DOC_STORAGE_PARITY_SHARDS`: shards used by `erasure`
+pub fn from_env() -> Result<Arc<dyn BlobStore>, anyhow::Error> {
+    let layout = env::var("DOC_STORAGE_LAYOUT").unwrap_or_else(|_| "local".to_string());
+
+    let store: Arc<dyn BlobStore> = match layout.as_str() {
+        "local" => {
+            let path = env::var("DOC_STORAGE_PATH").unwrap_or_else(|_| "./data".to_string());
+            Arc::new(LocalBlobStore::new(path)?)
+        }
+        "replicated" => {
+            let disks = disks()?;
+            let copies = parse_var("DOC_STORAGE_REPLICAS")?.unwrap_or(disks.len());
+            Arc::new(ReplicatedBlobStore::new(disks, copies)?)
+        }
+        "erasure" => {
+            let disks = disks()?;
+            let parity_shards = parse_var("DOC_STORAGE_PARITY_SHARDS")?.unwrap_or(2);
+            let data_shards = parse_var("DOC_STORAGE_DATA_SHARDS")?
+                .unwrap_or_else(|| disks.len().saturating_sub(parity_shards));
+            Arc::new(ErasureBlobStore::new(disks, data_shards, parity_shards)?)
+        }
+        other => return Err(anyhow::anyhow!("Unknown storage layout: {}", other)),
+    };
+
+    log::info!("Using the {} storage layout", layout);
+
+    Ok(store)
+}
+
+fn disks() -> Result<Vec<LocalBlobStore>, anyhow::Error> {
+    let disks = env::var("DOC_STORAGE_DISKS")
+        .map_err(|_| anyhow::anyhow!("DOC_STORAGE_DISKS must list the storage directories"))?;
+
+    disks
+        .split(',')
+        .map(str::trim)
+        .filter(|disk| !disk.is_empty())
+        .map(LocalBlobStore::new)
+        .collect()
+}
+
+fn parse_var(name: &str) -> Result<Option<usize>, anyhow::Error> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value.parse().map_err(|_| {
+            anyhow::anyhow!("{} must be a number, got {}", name, value)
+        })?)),
+        Err(_) => Ok(None),
+    }
+}
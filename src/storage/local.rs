@@ -0,0 +1,96 @@
+use crate::storage::blob::{BlobInfo, BlobStore};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, anyhow::Error> {
+        let root = root.as_ref().join("blobs");
+        std::fs::create_dir_all(&root)?;
+
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        let valid = key.len() > 2
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_');
+
+        if !valid || key.starts_with('.') {
+            return Err(anyhow::anyhow!("Invalid blob key: {}", key));
+        }
+
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        let path = self.path(key)?;
+        let parent = path.parent().unwrap();
+        fs::create_dir_all(parent).await?;
+
+        // Write to a temporary file first so readers never observe a partial blob.
+        let temp = parent.join(format!(".{}.{}", key, uuid::Uuid::new_v4()));
+        fs::write(&temp, data).await?;
+        fs::rename(&temp, &path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(fs::read(self.path(key)?).await?)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, anyhow::Error> {
+        Ok(fs::metadata(self.path(key)?).await.is_ok())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<BlobInfo>, anyhow::Error> {
+        let mut blobs = Vec::new();
+        let mut shards = fs::read_dir(&self.root).await?;
+
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut entries = fs::read_dir(shard.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let key = entry.file_name().to_string_lossy().to_string();
+                if key.starts_with('.') {
+                    continue;
+                }
+
+                let metadata = entry.metadata().await?;
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs() as i64)
+                    .unwrap_or_default();
+
+                blobs.push(BlobInfo {
+                    key,
+                    size: metadata.len(),
+                    modified,
+                });
+            }
+        }
+
+        Ok(blobs)
+    }
+}
@@ -0,0 +1,301 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{LOCK_TTL, MAX_LOCK_TTL};
+use crate::redis::client::{RedisClient, RedisKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Replaces a lease and its expiry, provided it is still the one with the given token.
+const RENEW_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if not current or cjson.decode(current).token ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2], 'XX', 'EX', ARGV[3])
+return 1
+"#;
+
+/// Deletes a lease, provided it is still the one with the given token.
+const RELEASE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if not current or cjson.decode(current).token ~= ARGV[1] then
+    return 0
+end
+redis.call('DEL', KEYS[1])
+return 1
+"#;
+
+/// An exclusive, time-limited lease on a file, held by one device of one user. Only the holder
+/// can write to the file until the lease is released or expires.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileLock {
+    pub file: String,
+    pub username: String,
+    pub device_id: String,
+    pub token: String,
+    pub acquired_at: i64,
+    pub expires_at: i64,
+}
+
+impl FileLock {
+    pub fn is_held_by(&self, username: &str, device_id: &str) -> bool {
+        self.username == username && self.device_id == device_id
+    }
+}
+
+fn lease_duration(duration: Option<u32>) -> u32 {
+    duration.unwrap_or(LOCK_TTL).clamp(1, MAX_LOCK_TTL)
+}
+
+pub async fn get_lock(
+    redis: &RedisClient,
+    file_id: &str,
+) -> Result<Option<FileLock>, ServiceError> {
+    let value: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::FileLock(file_id.to_string()).to_string()))
+        .await?;
+
+    value
+        .map(|value| {
+            serde_json::from_str(&value).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// The locks currently held on the given files, keyed by file id.
+pub async fn get_locks(
+    redis: &RedisClient,
+    file_ids: &[String],
+) -> Result<HashMap<String, FileLock>, ServiceError> {
+    if file_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let keys = file_ids
+        .iter()
+        .map(|id| RedisKey::FileLock(id.clone()))
+        .collect();
+    let values = redis.async_mget(keys).await?;
+
+    let locks = values
+        .into_iter()
+        .flatten()
+        .filter_map(|value| serde_json::from_str::<FileLock>(&value).ok())
+        .map(|lock| (lock.file.clone(), lock))
+        .collect();
+
+    Ok(locks)
+}
+
+/// Takes the lease on a file. Taking a lease already held by the same device renews it.
+pub async fn acquire(
+    redis: &RedisClient,
+    file_id: &str,
+    username: &str,
+    device_id: &str,
+    duration: Option<u32>,
+) -> Result<FileLock, ServiceError> {
+    let duration = lease_duration(duration);
+
+    loop {
+        let now = chrono::Utc::now().timestamp();
+        let lock = FileLock {
+            file: file_id.to_string(),
+            username: username.to_string(),
+            device_id: device_id.to_string(),
+            token: uuid::Uuid::new_v4().to_string(),
+            acquired_at: now,
+            expires_at: now + duration as i64,
+        };
+        let value = serde_json::to_string(&lock).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to serialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?;
+
+        let acquired = redis
+            .async_set_nx_ex(RedisKey::FileLock(file_id.to_string()), &value, duration)
+            .await?;
+        if acquired {
+            return Ok(lock);
+        }
+
+        match get_lock(redis, file_id).await? {
+            Some(current) if current.is_held_by(username, device_id) => {
+                return renew(redis, file_id, username, device_id, Some(duration)).await
+            }
+            Some(current) => return Err(locked(&current)),
+            // The lease expired in between, try again.
+            None => continue,
+        }
+    }
+}
+
+/// Extends the lease held by the given device. The lease is only replaced if it is still the
+/// one that was read, so a lease someone else took after this one expired is left alone.
+pub async fn renew(
+    redis: &RedisClient,
+    file_id: &str,
+    username: &str,
+    device_id: &str,
+    duration: Option<u32>,
+) -> Result<FileLock, ServiceError> {
+    let duration = lease_duration(duration);
+
+    loop {
+        let mut lock = held_lock(redis, file_id, username, device_id).await?;
+        lock.expires_at = chrono::Utc::now().timestamp() + duration as i64;
+        let value = serde_json::to_string(&lock).map_err(|error| {
+            ServiceError::InternalServerError(
+                "Failed to serialize the data".to_string(),
+                Some(error.into()),
+            )
+        })?;
+
+        let renewed: i64 = redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(RENEW_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::FileLock(file_id.to_string()).to_string())
+                    .arg(&lock.token)
+                    .arg(value)
+                    .arg(duration),
+            )
+            .await?;
+        if renewed == 1 {
+            return Ok(lock);
+        }
+    }
+}
+
+/// Gives the lease back. Only its holder can release it, administrators break it instead.
+pub async fn release(
+    redis: &RedisClient,
+    file_id: &str,
+    username: &str,
+    device_id: &str,
+) -> Result<(), ServiceError> {
+    loop {
+        let lock = held_lock(redis, file_id, username, device_id).await?;
+
+        let released: i64 = redis
+            .execute(
+                redis::cmd("EVAL")
+                    .arg(RELEASE_SCRIPT)
+                    .arg(1)
+                    .arg(RedisKey::FileLock(file_id.to_string()).to_string())
+                    .arg(&lock.token),
+            )
+            .await?;
+        if released == 1 {
+            return Ok(());
+        }
+    }
+}
+
+/// The lease on a file, provided the given device holds it.
+async fn held_lock(
+    redis: &RedisClient,
+    file_id: &str,
+    username: &str,
+    device_id: &str,
+) -> Result<FileLock, ServiceError> {
+    match get_lock(redis, file_id).await? {
+        Some(lock) if lock.is_held_by(username, device_id) => Ok(lock),
+        Some(lock) => Err(locked(&lock)),
+        None => Err(ServiceError::NotFound("The file is not locked".to_string())),
+    }
+}
+
+pub async fn break_lock(redis: &RedisClient, file_id: &str) -> Result<FileLock, ServiceError> {
+    let lock = get_lock(redis, file_id)
+        .await?
+        .ok_or_else(|| ServiceError::NotFound("The file is not locked".to_string()))?;
+
+    redis
+        .async_del(RedisKey::FileLock(file_id.to_string()))
+        .await?;
+
+    Ok(lock)
+}
+
+/// Fails with `423 Locked` if someone other than the given device holds a lease on the file.
+pub async fn check_writable(
+    redis: &RedisClient,
+    file_id: &str,
+    username: &str,
+    device_id: &str,
+) -> Result<(), ServiceError> {
+    match get_lock(redis, file_id).await? {
+        Some(lock) if !lock.is_held_by(username, device_id) => Err(locked(&lock)),
+        _ => Ok(()),
+    }
+}
+
+pub fn locked(lock: &FileLock) -> ServiceError {
+    ServiceError::Locked(format!(
+        "The file is locked by {} ({}) until {}",
+        lock.username, lock.device_id, lock.expires_at
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::time::Duration;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn expired_leases_cannot_be_renewed_or_released() {
+        let redis = testing::redis();
+        let owner = testing::username();
+        let file = testing::hash();
+
+        acquire(&redis, &file, &owner, "laptop", Some(1))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        let current = acquire(&redis, &file, &owner, "phone", Some(60))
+            .await
+            .unwrap();
+        let renewed = renew(&redis, &file, &owner, "laptop", Some(60)).await;
+        assert!(matches!(renewed, Err(ServiceError::Locked(_))));
+        let released = release(&redis, &file, &owner, "laptop").await;
+        assert!(matches!(released, Err(ServiceError::Locked(_))));
+
+        let lock = get_lock(&redis, &file).await.unwrap().unwrap();
+        assert_eq!(lock.token, current.token);
+
+        release(&redis, &file, &owner, "phone").await.unwrap();
+        assert!(get_lock(&redis, &file).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn renewing_keeps_the_token_and_extends_the_expiry() {
+        let redis = testing::redis();
+        let owner = testing::username();
+        let file = testing::hash();
+
+        let lock = acquire(&redis, &file, &owner, "laptop", Some(1))
+            .await
+            .unwrap();
+        let renewed = renew(&redis, &file, &owner, "laptop", Some(60))
+            .await
+            .unwrap();
+        assert_eq!(renewed.token, lock.token);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(get_lock(&redis, &file).await.unwrap().is_some());
+
+        release(&redis, &file, &owner, "laptop").await.unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+use crate::api::utils::errors::ServiceError;
+use crate::api::utils::payloads::MetadataPayload;
+use crate::redis::client::{RedisClient, RedisKey};
+use std::collections::{BTreeMap, BTreeSet};
+
+const MAX_TAG_LENGTH: usize = 64;
+const MAX_KEY_LENGTH: usize = 64;
+const MAX_VALUE_LENGTH: usize = 256;
+
+/// Tags and attributes are indexed in sets of members, so files and folders can be looked
+/// up by their metadata. Members are prefixed with the kind of entity they refer to.
+pub fn file_member(id: &str) -> String {
+    format!("file:{}", id)
+}
+
+pub fn folder_member(id: &str) -> String {
+    format!("folder:{}", id)
+}
+
+/// Applies the changes of `payload` to the given tags and attributes, keeping the secondary
+/// indexes of `owner` in sync.
+pub async fn apply_changes(
+    redis: &RedisClient,
+    owner: &str,
+    member: &str,
+    tags: &mut BTreeSet<String>,
+    attributes: &mut BTreeMap<String, String>,
+    payload: &MetadataPayload,
+) -> Result<(), ServiceError> {
+    validate(payload)?;
+
+    let (old_tags, old_attributes) = (tags.clone(), attributes.clone());
+    edit(tags, attributes, payload);
+
+    let mut pipeline = redis::pipe();
+    let old = (&old_tags, &old_attributes);
+    queue_reindex(&mut pipeline, owner, member, old, (tags, attributes));
+    redis.async_compare_and_exec(&[], &mut pipeline).await?;
+
+    Ok(())
+}
+
+/// Applies the changes of a validated `payload` to the given tags and attributes.
+pub fn edit(
+    tags: &mut BTreeSet<String>,
+    attributes: &mut BTreeMap<String, String>,
+    payload: &MetadataPayload,
+) {
+    for tag in &payload.remove_tags {
+        tags.remove(tag);
+    }
+    tags.extend(payload.add_tags.iter().cloned());
+
+    let removed = payload
+        .remove_attributes
+        .iter()
+        .chain(payload.set_attributes.keys());
+    for key in removed {
+        attributes.remove(key);
+    }
+    attributes.extend(payload.set_attributes.clone());
+}
+
+/// Queues the updates of the tag and attribute indexes of `owner` between two states of an
+/// entity, each given as its tags and attributes.
+pub fn queue_reindex(
+    pipeline: &mut redis::Pipeline,
+    owner: &str,
+    member: &str,
+    (old_tags, old_attributes): (&BTreeSet<String>, &BTreeMap<String, String>),
+    (new_tags, new_attributes): (&BTreeSet<String>, &BTreeMap<String, String>),
+) {
+    for tag in old_tags.difference(new_tags) {
+        let key = RedisKey::Tag(owner.to_string(), tag.clone());
+        pipeline
+            .cmd("SREM")
+            .arg(key.to_string())
+            .arg(member)
+            .ignore();
+    }
+    for tag in new_tags.difference(old_tags) {
+        let key = RedisKey::Tag(owner.to_string(), tag.clone());
+        pipeline
+            .cmd("SADD")
+            .arg(key.to_string())
+            .arg(member)
+            .ignore();
+    }
+
+    for (key, value) in old_attributes {
+        if new_attributes.get(key) != Some(value) {
+            let key = RedisKey::Attribute(owner.to_string(), key.clone(), value.clone());
+            pipeline
+                .cmd("SREM")
+                .arg(key.to_string())
+                .arg(member)
+                .ignore();
+        }
+    }
+    for (key, value) in new_attributes {
+        if old_attributes.get(key) != Some(value) {
+            let key = RedisKey::Attribute(owner.to_string(), key.clone(), value.clone());
+            pipeline
+                .cmd("SADD")
+                .arg(key.to_string())
+                .arg(member)
+                .ignore();
+        }
+    }
+}
+
+/// Drops an entity from every index it appears in, e.g. before it is deleted.
+pub async fn clear_indexes(
+    redis: &RedisClient,
+    owner: &str,
+    member: &str,
+    tags: &BTreeSet<String>,
+    attributes: &BTreeMap<String, String>,
+) -> Result<(), ServiceError> {
+    for tag in tags {
+        redis
+            .async_srem(RedisKey::Tag(owner.to_string(), tag.clone()), member)
+            .await?;
+    }
+
+    for (key, value) in attributes {
+        redis
+            .async_srem(
+                RedisKey::Attribute(owner.to_string(), key.clone(), value.clone()),
+                member,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Brings the attribute index in line after the attributes of an entity were replaced.
+pub async fn reindex_attributes(
+    redis: &RedisClient,
+    owner: &str,
+    member: &str,
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> Result<(), ServiceError> {
+    for (key, value) in old {
+        if new.get(key) != Some(value) {
+            redis
+                .async_srem(
+                    RedisKey::Attribute(owner.to_string(), key.clone(), value.clone()),
+                    member,
+                )
+                .await?;
+        }
+    }
+
+    for (key, value) in new {
+        if old.get(key) != Some(value) {
+            redis
+                .async_sadd(
+                    RedisKey::Attribute(owner.to_string(), key.clone(), value.clone()),
+                    member,
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the members carrying every given tag and attribute.
+pub async fn query(
+    redis: &RedisClient,
+    owner: &str,
+    tags: &[String],
+    attributes: &[(String, String)],
+) -> Result<Vec<String>, ServiceError> {
+    let mut keys = tags
+        .iter()
+        .map(|tag| RedisKey::Tag(owner.to_string(), tag.clone()))
+        .collect::<Vec<_>>();
+    keys.extend(
+        attributes
+            .iter()
+            .map(|(key, value)| RedisKey::Attribute(owner.to_string(), key.clone(), value.clone())),
+    );
+
+    if keys.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "At least one tag or attribute is required".to_string(),
+        ));
+    }
+
+    redis.async_sinter(keys).await
+}
+
+pub fn validate(payload: &MetadataPayload) -> Result<(), ServiceError> {
+    let invalid_tag = payload
+        .add_tags
+        .iter()
+        .find(|tag| tag.trim().is_empty() || tag.len() > MAX_TAG_LENGTH);
+    if let Some(tag) = invalid_tag {
+        return Err(ServiceError::BadRequest(format!(
+            "'{}' is not a valid tag",
+            tag
+        )));
+    }
+
+    let invalid_attribute = payload.set_attributes.iter().find(|(key, value)| {
+        key.trim().is_empty()
+            || key.contains('=')
+            || key.len() > MAX_KEY_LENGTH
+            || value.len() > MAX_VALUE_LENGTH
+    });
+    if let Some((key, _)) = invalid_attribute {
+        return Err(ServiceError::BadRequest(format!(
+            "'{}' is not a valid attribute",
+            key
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindexes_only_what_changed() {
+        let mut tags = BTreeSet::from(["draft".to_string(), "work".to_string()]);
+        let mut attributes = BTreeMap::from([("status".to_string(), "open".to_string())]);
+        let old = (tags.clone(), attributes.clone());
+
+        let payload = MetadataPayload {
+            add_tags: vec!["work".to_string(), "final".to_string()],
+            remove_tags: vec!["draft".to_string()],
+            set_attributes: BTreeMap::from([("status".to_string(), "done".to_string())]),
+            remove_attributes: Vec::new(),
+        };
+        edit(&mut tags, &mut attributes, &payload);
+        assert_eq!(
+            tags,
+            BTreeSet::from(["final".to_string(), "work".to_string()])
+        );
+        assert_eq!(attributes["status"], "done");
+
+        let mut pipeline = redis::pipe();
+        let new = (&tags, &attributes);
+        queue_reindex(&mut pipeline, "alice", "file:1", (&old.0, &old.1), new);
+        let commands = String::from_utf8_lossy(&pipeline.get_packed_pipeline()).into_owned();
+
+        assert!(commands.contains("doc_storage:tag:alice:draft"));
+        assert!(commands.contains("doc_storage:tag:alice:final"));
+        assert!(!commands.contains("doc_storage:tag:alice:work"));
+        assert_eq!(commands.matches("SREM").count(), 2);
+        assert_eq!(commands.matches("SADD").count(), 2);
+    }
+}
@@ -1,2 +1,21 @@
+pub mod batch;
+pub mod blob;
 pub mod compressor;
+pub mod content;
+pub mod content_type;
+pub mod copy;
+pub mod diff;
+pub mod erasure;
+pub mod files;
+pub mod folders;
+pub mod gc;
+pub mod layout;
+pub mod local;
+pub mod locks;
+pub mod metadata;
 pub mod models;
+pub mod multipart;
+pub mod previews;
+pub mod quota;
+pub mod replicated;
+pub mod scrubber;
@@ -1,4 +1,6 @@
-use serde::Serialize;
+use crate::storage::content_type;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Serialize, Debug, Clone)]
 pub struct File {
@@ -6,6 +8,65 @@ pub struct File {
     pub size: usize,
     #[serde(skip_serializing)]
     pub data: Vec<u8>,
+    #[serde(skip_serializing)]
+    pub declared_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileMetadata {
+    pub id: String,
+    pub owner: String,
+    pub name: String,
+    pub folder: String,
+    pub size: usize,
+    pub hash: String,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    #[serde(default)]
+    pub declared_type: Option<String>,
+    #[serde(default)]
+    pub type_mismatch: bool,
+    pub versions: Vec<FileVersion>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub corrupted: bool,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileVersion {
+    pub version: usize,
+    pub hash: String,
+    pub size: usize,
+    pub device_id: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub corrupted: bool,
+    /// Detected type of this version. Versions stored before types were recorded per version
+    /// have none and fall back to the type of the file.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Type the client declared, if it declared one.
+    #[serde(default)]
+    pub declared_type: Option<String>,
+    #[serde(default)]
+    pub type_mismatch: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Folder {
+    pub id: String,
+    pub owner: String,
+    pub path: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
 }
 
 impl File {
@@ -14,6 +75,7 @@ impl File {
             name,
             size,
             data: Vec::new(),
+            declared_type: None,
         }
     }
 
@@ -22,6 +84,153 @@ impl File {
             name,
             size: data.len(),
             data,
+            declared_type: None,
+        }
+    }
+}
+
+impl FileMetadata {
+    pub fn new(owner: String, folder: String, name: String, version: FileVersion) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner,
+            name,
+            folder,
+            size: version.size,
+            hash: version.hash.clone(),
+            content_type: content_type::OCTET_STREAM.to_string(),
+            declared_type: None,
+            type_mismatch: false,
+            created_at: version.created_at,
+            updated_at: version.created_at,
+            versions: vec![version],
+            corrupted: false,
+            tags: BTreeSet::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// A new file pointing at the current content of this one, without its history. The
+    /// content is shared, not duplicated: blobs are immutable, so changing either file adds a
+    /// version with a blob of its own and leaves the other untouched.
+    pub fn copy_as(&self, folder: String, name: String, device_id: String) -> Self {
+        let mut version = FileVersion::new(1, self.hash.clone(), self.size, device_id);
+        version.corrupted = self
+            .versions
+            .last()
+            .map(|version| version.corrupted)
+            .unwrap_or(false);
+        version.content_type = Some(self.content_type.clone());
+        version.declared_type = self.declared_type.clone();
+        version.type_mismatch = self.type_mismatch;
+
+        let mut copy = FileMetadata::new(self.owner.clone(), folder, name, version);
+        copy.content_type = self.content_type.clone();
+        copy.declared_type = self.declared_type.clone();
+        copy.type_mismatch = self.type_mismatch;
+        copy.corrupted = copy.versions[0].corrupted;
+        copy.tags = self.tags.clone();
+        copy.attributes = self.attributes.clone();
+
+        copy
+    }
+
+    pub fn add_version(&mut self, hash: String, size: usize, device_id: String) -> &FileVersion {
+        let version = FileVersion::new(self.versions.len() + 1, hash, size, device_id);
+
+        self.size = version.size;
+        self.hash = version.hash.clone();
+        self.updated_at = version.created_at;
+        self.versions.push(version);
+
+        self.versions.last().unwrap()
+    }
+
+    /// Records the detected type of the current version and the type the client declared. A
+    /// client that declared nothing never causes a mismatch.
+    pub fn set_content_type(&mut self, detected: String, declared: Option<String>) {
+        self.type_mismatch = declared
+            .as_deref()
+            .map(|declared| content_type::is_mismatch(declared, &detected))
+            .unwrap_or(false);
+        self.content_type = detected;
+        self.declared_type = declared;
+
+        if let Some(version) = self.versions.last_mut() {
+            version.content_type = Some(self.content_type.clone());
+            version.declared_type = self.declared_type.clone();
+            version.type_mismatch = self.type_mismatch;
         }
     }
+
+    /// The type of a version's content, which older versions may not share with the current one.
+    pub fn content_type_of<'a>(&'a self, version: &'a FileVersion) -> &'a str {
+        version
+            .content_type
+            .as_deref()
+            .unwrap_or(&self.content_type)
+    }
+
+    pub fn version(&self, version: Option<usize>) -> Option<&FileVersion> {
+        match version {
+            Some(version) => self.versions.iter().find(|v| v.version == version),
+            None => self.versions.last(),
+        }
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &str> {
+        self.versions.iter().map(|version| version.hash.as_str())
+    }
+
+    /// Flags every version stored under `hash` and returns whether anything changed.
+    pub fn set_corrupted(&mut self, hash: &str, corrupted: bool) -> bool {
+        let mut changed = false;
+
+        for version in self.versions.iter_mut().filter(|v| v.hash == hash) {
+            changed |= version.corrupted != corrupted;
+            version.corrupted = corrupted;
+        }
+
+        self.corrupted = self.versions.iter().any(|version| version.corrupted);
+        changed
+    }
+}
+
+fn default_content_type() -> String {
+    content_type::OCTET_STREAM.to_string()
+}
+
+impl FileVersion {
+    pub fn new(version: usize, hash: String, size: usize, device_id: String) -> Self {
+        Self {
+            version,
+            hash,
+            size,
+            device_id,
+            created_at: chrono::Utc::now().timestamp(),
+            corrupted: false,
+            content_type: None,
+            declared_type: None,
+            type_mismatch: false,
+        }
+    }
+}
+
+impl Folder {
+    pub fn new(owner: String, path: String) -> Self {
+        Self {
+            id: Folder::id_for(&owner, &path),
+            owner,
+            path,
+            created_at: chrono::Utc::now().timestamp(),
+            tags: BTreeSet::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Folders are addressed by path, so their id is derived from the owner and the path.
+    pub fn id_for(owner: &str, path: &str) -> String {
+        let hash = blake3::hash(format!("{}\0{}", owner, path).as_bytes());
+        hash.to_hex()[..32].to_string()
+    }
 }
@@ -0,0 +1,339 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::MULTIPART_UPLOAD_TTL;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::blob::BlobStore;
+use crate::storage::{content, gc, quota};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Stores a part under its number and returns the part it replaced, if any.
+const REPLACE_PART_SCRIPT: &str = r#"
+local previous = redis.call('HGET', KEYS[1], ARGV[1])
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+return previous
+"#;
+
+/// An object uploaded in parts. Parts are stored as blobs of their own until the upload is
+/// completed and assembled into a file. Abandoned uploads expire, after which the collector
+/// releases and reclaims their parts.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MultipartUpload {
+    pub id: String,
+    pub owner: String,
+    pub bucket: String,
+    pub key: String,
+    pub content_type: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Part {
+    pub number: u32,
+    pub hash: String,
+    pub size: usize,
+    /// The account the part is charged to. Parts stored before parts counted towards the
+    /// quota have none.
+    #[serde(default)]
+    pub owner: String,
+}
+
+pub async fn create_upload(
+    redis: &RedisClient,
+    owner: &str,
+    bucket: &str,
+    key: &str,
+    content_type: Option<String>,
+) -> Result<MultipartUpload, ServiceError> {
+    let upload = MultipartUpload {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        owner: owner.to_string(),
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        content_type,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+
+    redis
+        .s_async_set(RedisKey::MultipartUpload(upload.id.clone()), &upload)
+        .await?;
+    redis
+        .async_expire(
+            RedisKey::MultipartUpload(upload.id.clone()),
+            MULTIPART_UPLOAD_TTL,
+        )
+        .await?;
+
+    Ok(upload)
+}
+
+pub async fn get_owned_upload(
+    redis: &RedisClient,
+    id: &str,
+    owner: &str,
+) -> Result<MultipartUpload, ServiceError> {
+    let key = RedisKey::MultipartUpload(id.to_string());
+    if !redis.async_exists(key).await? {
+        return Err(ServiceError::NotFound(format!(
+            "Upload {} does not exist",
+            id
+        )));
+    }
+
+    let upload: MultipartUpload = redis
+        .d_async_get(RedisKey::MultipartUpload(id.to_string()))
+        .await?;
+    if upload.owner != owner {
+        return Err(ServiceError::NotFound(format!(
+            "Upload {} does not exist",
+            id
+        )));
+    }
+
+    Ok(upload)
+}
+
+/// Stores a part, replacing any earlier part with the same number. The part counts towards
+/// the quota of the owner until the upload is completed or aborted.
+pub async fn add_part(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    upload: &MultipartUpload,
+    number: u32,
+    data: Vec<u8>,
+) -> Result<Part, ServiceError> {
+    let part = Part {
+        number,
+        hash: content::hash_data(&data),
+        size: data.len(),
+        owner: upload.owner.clone(),
+    };
+    let value = serde_json::to_string(&part).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })?;
+
+    // Keeps the collector away from the part until it is recorded in the upload.
+    gc::acquire_upload(redis, &part.hash).await?;
+    let result = charge_part(redis, blobs, upload, &part, &value, data).await;
+    gc::release_upload(redis, &part.hash).await?;
+
+    if let Some(previous) = result? {
+        release_part(redis, &previous).await?;
+    }
+
+    Ok(part)
+}
+
+/// Charges a part and stores it, returning the part it replaced. The charge is taken back if
+/// the part cannot be stored.
+async fn charge_part(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    upload: &MultipartUpload,
+    part: &Part,
+    value: &str,
+    data: Vec<u8>,
+) -> Result<Option<Part>, ServiceError> {
+    quota::add_reference(redis, &part.owner, &part.hash, part.size as u64).await?;
+
+    match save_part(redis, blobs, upload, part, value, data).await {
+        Ok(previous) => Ok(previous),
+        Err(error) => {
+            release_part(redis, part).await?;
+            Err(error)
+        }
+    }
+}
+
+async fn save_part(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    upload: &MultipartUpload,
+    part: &Part,
+    value: &str,
+    data: Vec<u8>,
+) -> Result<Option<Part>, ServiceError> {
+    content::store_content(blobs, &part.hash, data)
+        .await
+        .map_err(|error| {
+            ServiceError::InternalServerError("Failed to store the part".to_string(), Some(error))
+        })?;
+
+    // The parts outlive an abandoned upload, so the collector can still release them.
+    let previous: Option<String> = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(REPLACE_PART_SCRIPT)
+                .arg(1)
+                .arg(RedisKey::MultipartParts(upload.id.clone()).to_string())
+                .arg(part.number)
+                .arg(value),
+        )
+        .await?;
+
+    previous.map(|previous| parse_part(&previous)).transpose()
+}
+
+fn parse_part(value: &str) -> Result<Part, ServiceError> {
+    serde_json::from_str(value).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to deserialize the data".to_string(),
+            Some(error.into()),
+        )
+    })
+}
+
+/// Takes back the quota charged for a part.
+async fn release_part(redis: &RedisClient, part: &Part) -> Result<(), ServiceError> {
+    if part.owner.is_empty() {
+        return Ok(());
+    }
+
+    quota::remove_reference(redis, &part.owner, &part.hash, part.size as u64).await
+}
+
+pub async fn parts(redis: &RedisClient, id: &str) -> Result<BTreeMap<u32, Part>, ServiceError> {
+    let values = redis
+        .async_hvals(RedisKey::MultipartParts(id.to_string()))
+        .await?;
+
+    let mut parts = BTreeMap::new();
+    for value in values {
+        let part = parse_part(&value)?;
+        parts.insert(part.number, part);
+    }
+
+    Ok(parts)
+}
+
+/// Reads the given parts back in order.
+pub async fn assemble(blobs: &dyn BlobStore, parts: &[Part]) -> Result<Vec<u8>, ServiceError> {
+    let mut data = Vec::with_capacity(parts.iter().map(|part| part.size).sum());
+
+    for part in parts {
+        let content = content::read_content(blobs, &part.hash)
+            .await
+            .map_err(|error| {
+                ServiceError::InternalServerError(
+                    format!("Failed to read part {}", part.number),
+                    Some(error),
+                )
+            })?;
+        data.extend_from_slice(&content);
+    }
+
+    Ok(data)
+}
+
+/// Forgets an upload and takes back the quota charged for its parts. Their blobs are left
+/// for the collector.
+pub async fn remove_upload(redis: &RedisClient, id: &str) -> Result<(), ServiceError> {
+    redis
+        .async_del(RedisKey::MultipartUpload(id.to_string()))
+        .await?;
+
+    release_parts(redis, id).await
+}
+
+/// Removes the parts one by one, so a part is only released by whoever removed it even if
+/// an upload is completed and aborted at the same time.
+async fn release_parts(redis: &RedisClient, id: &str) -> Result<(), ServiceError> {
+    for part in parts(redis, id).await?.into_values() {
+        let removed: i64 = redis
+            .execute(
+                redis::cmd("HDEL")
+                    .arg(RedisKey::MultipartParts(id.to_string()).to_string())
+                    .arg(part.number),
+            )
+            .await?;
+
+        if removed == 1 {
+            release_part(redis, &part).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Releases the parts of uploads that expired before being completed or aborted, returning
+/// how many uploads were released.
+pub async fn release_abandoned(redis: &RedisClient) -> Result<usize, ServiceError> {
+    let mut released = 0;
+    for id in upload_ids(redis).await? {
+        if !redis
+            .async_exists(RedisKey::MultipartUpload(id.clone()))
+            .await?
+        {
+            log::info!("Releasing the parts of abandoned upload {}", id);
+            release_parts(redis, &id).await?;
+            released += 1;
+        }
+    }
+
+    Ok(released)
+}
+
+/// The uploads that still have parts stored.
+async fn upload_ids(redis: &RedisClient) -> Result<Vec<String>, ServiceError> {
+    let prefix = RedisKey::MultipartUpload(String::new()).to_string();
+    let keys = redis
+        .async_scan(RedisKey::MultipartParts("*".to_string()))
+        .await?;
+
+    Ok(keys
+        .iter()
+        .map(|key| {
+            key.trim_start_matches(&prefix)
+                .trim_end_matches(":parts")
+                .to_string()
+        })
+        .collect())
+}
+
+/// The hashes of the parts of every upload in progress, which the collector must keep.
+pub async fn pending_hashes(redis: &RedisClient) -> Result<HashSet<String>, ServiceError> {
+    let mut hashes = HashSet::new();
+    for id in upload_ids(redis).await? {
+        hashes.extend(parts(redis, &id).await?.into_values().map(|part| part.hash));
+    }
+
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn parts_count_towards_the_quota_until_removed() {
+        let redis = testing::redis();
+        let blobs = testing::blobs();
+        let owner = testing::username();
+        let upload = create_upload(&redis, &owner, "bucket", "key", None)
+            .await
+            .unwrap();
+
+        add_part(&redis, blobs.as_ref(), &upload, 1, b"first".to_vec())
+            .await
+            .unwrap();
+        add_part(&redis, blobs.as_ref(), &upload, 2, b"second".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(quota::usage(&redis, &owner).await.unwrap().used, 11);
+
+        // Uploading a part again replaces its charge.
+        add_part(&redis, blobs.as_ref(), &upload, 2, b"other".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(quota::usage(&redis, &owner).await.unwrap().used, 10);
+
+        remove_upload(&redis, &upload.id).await.unwrap();
+        remove_upload(&redis, &upload.id).await.unwrap();
+        assert_eq!(quota::usage(&redis, &owner).await.unwrap().used, 0);
+        assert!(parts(&redis, &upload.id).await.unwrap().is_empty());
+    }
+}
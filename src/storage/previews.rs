@@ -0,0 +1,218 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::PREVIEW_PENDING_TTL;
+use crate::jobs::models::Job;
+use crate::jobs::queue;
+use crate::jobs::tasks::Task;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::blob::BlobStore;
+use crate::storage::{content, content_type};
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageOutputFormat};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::time::Duration;
+
+/// Longest edge of a rendered PDF page, large enough for the biggest thumbnail.
+const PDF_RENDER_SIZE: u32 = 1024;
+const SNIPPET_LENGTH: usize = 512;
+/// Rendering a hostile PDF can take forever, so `pdftoppm` is killed after this long.
+const PDF_RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Images are small on disk but not once decoded, so decoding stops past these.
+const MAX_IMAGE_DIMENSION: u32 = 16_384;
+const MAX_IMAGE_ALLOCATION: u64 = 256 * 1024 * 1024; // 256 MiB
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewKind {
+    Image,
+    Pdf,
+    Text,
+}
+
+impl PreviewSize {
+    pub const ALL: [PreviewSize; 3] = [PreviewSize::Small, PreviewSize::Medium, PreviewSize::Large];
+
+    pub fn pixels(&self) -> u32 {
+        match self {
+            PreviewSize::Small => 128,
+            PreviewSize::Medium => 256,
+            PreviewSize::Large => 512,
+        }
+    }
+
+    pub fn key(&self, hash: &str) -> String {
+        format!("{}.preview-{}", hash, self.pixels())
+    }
+}
+
+pub fn snippet_key(hash: &str) -> String {
+    format!("{}.snippet", hash)
+}
+
+/// The kind of preview a file of the given content type supports.
+pub fn preview_kind(content_type: &str, data: &[u8]) -> Option<PreviewKind> {
+    let essence = content_type::essence(content_type);
+
+    if essence == "application/pdf" {
+        Some(PreviewKind::Pdf)
+    } else if essence.starts_with("image/") && image::guess_format(data).is_ok() {
+        Some(PreviewKind::Image)
+    } else if content_type::is_text(&essence) {
+        Some(PreviewKind::Text)
+    } else {
+        None
+    }
+}
+
+/// Queues the generation of the previews of a blob, so uploads do not wait for them. A blob
+/// is only queued once in a while, however often its previews are asked for.
+pub async fn enqueue_generation(
+    redis: &RedisClient,
+    owner: &str,
+    hash: &str,
+    content_type: &str,
+) -> Result<(), ServiceError> {
+    let pending = redis
+        .async_set_nx_ex(
+            RedisKey::PreviewPending(hash.to_string()),
+            "1",
+            PREVIEW_PENDING_TTL,
+        )
+        .await?;
+    if !pending {
+        return Ok(());
+    }
+
+    let task = Task::GeneratePreviews {
+        hash: hash.to_string(),
+        content_type: content_type.to_string(),
+    };
+    queue::enqueue(redis, Job::new(owner.to_string(), task)).await?;
+
+    Ok(())
+}
+
+pub async fn generate(
+    blobs: &dyn BlobStore,
+    hash: &str,
+    content_type: &str,
+) -> Result<(), anyhow::Error> {
+    let small = PreviewSize::Small.key(hash);
+    if blobs.exists(&small).await? || blobs.exists(&snippet_key(hash)).await? {
+        return Ok(());
+    }
+
+    let data = content::read_content(blobs, hash).await?;
+
+    match preview_kind(content_type, &data) {
+        Some(PreviewKind::Image) => {
+            let thumbnails = tokio::task::spawn_blocking(move || {
+                let image = decode_image(&data)?;
+                render_thumbnails(&image)
+            })
+            .await??;
+
+            store_thumbnails(blobs, hash, thumbnails).await
+        }
+        Some(PreviewKind::Pdf) => {
+            let page = render_pdf_page(data).await?;
+            let thumbnails = tokio::task::spawn_blocking(move || {
+                let image = decode_image(&page)?;
+                render_thumbnails(&image)
+            })
+            .await??;
+
+            store_thumbnails(blobs, hash, thumbnails).await
+        }
+        Some(PreviewKind::Text) => {
+            let text = String::from_utf8_lossy(&data);
+            let snippet = text.chars().take(SNIPPET_LENGTH).collect::<String>();
+
+            blobs.put(&snippet_key(hash), snippet.as_bytes()).await
+        }
+        None => Ok(()),
+    }
+}
+
+fn decode_image(data: &[u8]) -> Result<DynamicImage, anyhow::Error> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_IMAGE_ALLOCATION);
+
+    let mut reader = Reader::new(Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+
+    Ok(reader.decode()?)
+}
+
+fn render_thumbnails(image: &DynamicImage) -> Result<Vec<(PreviewSize, Vec<u8>)>, anyhow::Error> {
+    let mut thumbnails = Vec::new();
+
+    for size in PreviewSize::ALL {
+        let thumbnail = image.thumbnail(size.pixels(), size.pixels());
+        let thumbnail = DynamicImage::ImageRgb8(thumbnail.to_rgb8());
+
+        let mut encoded = Cursor::new(Vec::new());
+        thumbnail.write_to(&mut encoded, ImageOutputFormat::Jpeg(80))?;
+        thumbnails.push((size, encoded.into_inner()));
+    }
+
+    Ok(thumbnails)
+}
+
+async fn store_thumbnails(
+    blobs: &dyn BlobStore,
+    hash: &str,
+    thumbnails: Vec<(PreviewSize, Vec<u8>)>,
+) -> Result<(), anyhow::Error> {
+    for (size, thumbnail) in thumbnails {
+        blobs.put(&size.key(hash), &thumbnail).await?;
+    }
+
+    Ok(())
+}
+
+/// Renders the first page of a PDF to PNG with poppler's `pdftoppm`.
+async fn render_pdf_page(data: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
+    let directory = std::env::temp_dir().join(format!("doc-storage-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&directory).await?;
+
+    let input = directory.join("input.pdf");
+    let output = directory.join("page");
+    tokio::fs::write(&input, data).await?;
+
+    let status = tokio::process::Command::new("pdftoppm")
+        .arg("-png")
+        .arg("-singlefile")
+        .args(["-f", "1", "-l", "1"])
+        .args(["-scale-to", &PDF_RENDER_SIZE.to_string()])
+        .arg(&input)
+        .arg(&output)
+        .kill_on_drop(true)
+        .status();
+
+    let page = match tokio::time::timeout(PDF_RENDER_TIMEOUT, status).await {
+        Ok(Ok(status)) if status.success() => tokio::fs::read(output.with_extension("png"))
+            .await
+            .map_err(Into::into),
+        Ok(Ok(status)) => Err(anyhow::anyhow!("pdftoppm exited with {}", status)),
+        Ok(Err(error)) => Err(anyhow::anyhow!("Failed to run pdftoppm: {}", error)),
+        Err(_) => Err(anyhow::anyhow!(
+            "pdftoppm did not finish within {} seconds",
+            PDF_RENDER_TIMEOUT.as_secs()
+        )),
+    };
+
+    let _ = tokio::fs::remove_dir_all(&directory).await;
+
+    page
+}
@@ -0,0 +1,149 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{DEFAULT_QUOTA, QUOTA_POLICY};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::user::models::User;
+use serde::Serialize;
+
+/// Adds a reference to a blob and charges its size to the usage counter, unless that would go
+/// over the quota (0 meaning no quota). Under the physical policy only the first reference is
+/// charged. Nothing changes and 0 is returned when the quota would be exceeded.
+const ADD_REFERENCE_SCRIPT: &str = r#"
+local references = redis.call('INCR', KEYS[1])
+if references == 1 or ARGV[3] == 'logical' then
+    local used = redis.call('INCRBY', KEYS[2], ARGV[1])
+    local quota = tonumber(ARGV[2])
+    if quota > 0 and used > quota then
+        redis.call('DECRBY', KEYS[2], ARGV[1])
+        if redis.call('DECR', KEYS[1]) <= 0 then
+            redis.call('DEL', KEYS[1])
+        end
+        return 0
+    end
+end
+return 1
+"#;
+
+const REMOVE_REFERENCE_SCRIPT: &str = r#"
+local references = redis.call('DECR', KEYS[1])
+if references <= 0 then
+    redis.call('DEL', KEYS[1])
+end
+if references <= 0 or ARGV[2] == 'logical' then
+    if redis.call('DECRBY', KEYS[2], ARGV[1]) < 0 then
+        redis.call('SET', KEYS[2], 0)
+    end
+end
+return 1
+"#;
+
+/// How shared content is charged. Logical usage counts every reference to a blob, as if each
+/// copy had its own bytes; physical usage counts each blob once per user.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+    Logical,
+    Physical,
+}
+
+impl QuotaPolicy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "physical" => QuotaPolicy::Physical,
+            _ => QuotaPolicy::Logical,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuotaPolicy::Logical => "logical",
+            QuotaPolicy::Physical => "physical",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct QuotaUsage {
+    pub used: u64,
+    pub quota: Option<u64>,
+    pub policy: QuotaPolicy,
+}
+
+pub async fn quota_for(redis: &RedisClient, owner: &str) -> Result<Option<u64>, ServiceError> {
+    let account = RedisKey::Account(owner.to_string());
+    if !redis.async_exists(account).await? {
+        return Ok(*DEFAULT_QUOTA);
+    }
+
+    let user: User = redis
+        .d_async_get(RedisKey::Account(owner.to_string()))
+        .await?;
+
+    Ok(user.quota.or(*DEFAULT_QUOTA))
+}
+
+pub async fn usage(redis: &RedisClient, owner: &str) -> Result<QuotaUsage, ServiceError> {
+    let used: Option<u64> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::Usage(owner.to_string()).to_string()))
+        .await?;
+
+    Ok(QuotaUsage {
+        used: used.unwrap_or_default(),
+        quota: quota_for(redis, owner).await?,
+        policy: *QUOTA_POLICY,
+    })
+}
+
+/// Records that one more file version of `owner` points at `hash`, charging its size according
+/// to the quota policy. Fails if the bytes do not fit in the quota.
+pub async fn add_reference(
+    redis: &RedisClient,
+    owner: &str,
+    hash: &str,
+    size: u64,
+) -> Result<(), ServiceError> {
+    let quota = quota_for(redis, owner).await?;
+
+    let added: i64 = redis
+        .execute(
+            redis::cmd("EVAL")
+                .arg(ADD_REFERENCE_SCRIPT)
+                .arg(2)
+                .arg(RedisKey::RefCount(owner.to_string(), hash.to_string()).to_string())
+                .arg(RedisKey::Usage(owner.to_string()).to_string())
+                .arg(size)
+                .arg(quota.unwrap_or_default())
+                .arg(QUOTA_POLICY.name()),
+        )
+        .await?;
+
+    if added == 0 {
+        return Err(ServiceError::InsufficientStorage(format!(
+            "Storing {} more bytes would exceed the quota of {} bytes",
+            size,
+            quota.unwrap_or_default()
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn remove_reference(
+    redis: &RedisClient,
+    owner: &str,
+    hash: &str,
+    size: u64,
+) -> Result<(), ServiceError> {
+    redis
+        .execute::<i64>(
+            redis::cmd("EVAL")
+                .arg(REMOVE_REFERENCE_SCRIPT)
+                .arg(2)
+                .arg(RedisKey::RefCount(owner.to_string(), hash.to_string()).to_string())
+                .arg(RedisKey::Usage(owner.to_string()).to_string())
+                .arg(size)
+                .arg(QUOTA_POLICY.name()),
+        )
+        .await?;
+
+    Ok(())
+}
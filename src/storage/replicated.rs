@@ -0,0 +1,251 @@
+use crate::storage::blob::{BlobInfo, BlobStore, RebuildReport, Redundancy};
+use crate::storage::content;
+use crate::storage::local::LocalBlobStore;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+
+/// Keeps `copies` full copies of every blob, spread over the configured disks.
+pub struct ReplicatedBlobStore {
+    disks: Vec<LocalBlobStore>,
+    copies: usize,
+}
+
+impl ReplicatedBlobStore {
+    pub fn new(disks: Vec<LocalBlobStore>, copies: usize) -> Result<Self, anyhow::Error> {
+        if copies == 0 || copies > disks.len() {
+            return Err(anyhow::anyhow!(
+                "Cannot keep {} copies on {} disks",
+                copies,
+                disks.len()
+            ));
+        }
+
+        Ok(Self { disks, copies })
+    }
+
+    /// The disks a blob belongs on, chosen from its key so placement is stable.
+    fn placement(&self, key: &str) -> Vec<&LocalBlobStore> {
+        let start = blake3::hash(key.as_bytes()).as_bytes()[0] as usize % self.disks.len();
+
+        (0..self.copies)
+            .map(|offset| &self.disks[(start + offset) % self.disks.len()])
+            .collect()
+    }
+
+    /// Placement disks first, then every other disk, in case the layout changed.
+    fn search_order(&self, key: &str) -> Vec<&LocalBlobStore> {
+        let mut order = self.placement(key);
+        for disk in &self.disks {
+            if !order.iter().any(|placed| std::ptr::eq(*placed, disk)) {
+                order.push(disk);
+            }
+        }
+
+        order
+    }
+}
+
+#[async_trait]
+impl BlobStore for ReplicatedBlobStore {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        let mut written = 0;
+
+        for disk in self.placement(key) {
+            match disk.put(key, data).await {
+                Ok(()) => written += 1,
+                Err(error) => log::warn!("Failed to write a copy of blob {}: {}", key, error),
+            }
+        }
+
+        if written == 0 {
+            return Err(anyhow::anyhow!("Failed to write any copy of blob {}", key));
+        }
+
+        if written < self.copies {
+            log::warn!(
+                "Blob {} was written with {} of {} copies",
+                key,
+                written,
+                self.copies
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        for disk in self.search_order(key) {
+            if let Ok(data) = disk.get(key).await {
+                return Ok(data);
+            }
+        }
+
+        Err(anyhow::anyhow!("No readable copy of blob {}", key))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, anyhow::Error> {
+        for disk in &self.disks {
+            if disk.exists(key).await? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Only copies on the disks the blob belongs on count, as those are the ones kept up.
+    async fn redundancy(&self, key: &str) -> Result<Redundancy, anyhow::Error> {
+        let mut present = 0;
+        for disk in self.placement(key) {
+            if disk.exists(key).await? {
+                present += 1;
+            }
+        }
+
+        Ok(Redundancy {
+            present,
+            expected: self.copies,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), anyhow::Error> {
+        for disk in &self.disks {
+            disk.delete(key).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<BlobInfo>, anyhow::Error> {
+        let mut blobs = BTreeMap::new();
+
+        for disk in &self.disks {
+            for blob in disk.list().await? {
+                let entry = blobs
+                    .entry(blob.key.clone())
+                    .or_insert_with(|| blob.clone());
+                entry.modified = entry.modified.max(blob.modified);
+            }
+        }
+
+        Ok(blobs.into_values().collect())
+    }
+
+    async fn replicas(&self, key: &str) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let mut replicas = Vec::new();
+
+        for disk in &self.disks {
+            if let Ok(data) = disk.get(key).await {
+                replicas.push(data);
+            }
+        }
+
+        Ok(replicas)
+    }
+
+    async fn restore(&self, key: &str, data: &[u8]) -> Result<usize, anyhow::Error> {
+        let mut rewritten = 0;
+
+        for disk in self.placement(key) {
+            match disk.get(key).await {
+                Ok(copy) if copy == data => {}
+                _ => {
+                    disk.put(key, data).await?;
+                    rewritten += 1;
+                }
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    async fn rebuild(&self) -> Result<RebuildReport, anyhow::Error> {
+        let mut report = RebuildReport::default();
+
+        for blob in self.list().await? {
+            report.scanned += 1;
+
+            let mut source = None;
+            for disk in self.search_order(&blob.key) {
+                match disk.get(&blob.key).await {
+                    Ok(copy) if content::is_intact(&blob.key, &copy) => {
+                        source = Some(copy);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+
+            match source {
+                Some(source) => report.restored += self.restore(&blob.key, &source).await?,
+                None => report.unrecoverable.push(blob.key),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(disks: usize, copies: usize) -> ReplicatedBlobStore {
+        let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+        let disks = (0..disks)
+            .map(|index| LocalBlobStore::new(root.join(index.to_string())).unwrap())
+            .collect();
+
+        ReplicatedBlobStore::new(disks, copies).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_lost_copies_and_restores_them() {
+        let store = store(3, 2);
+        let data = b"some content".to_vec();
+        let hash = content::hash_data(&data);
+
+        content::store_content(&store, &hash, data.clone())
+            .await
+            .unwrap();
+        assert!(!store.redundancy(&hash).await.unwrap().is_degraded());
+
+        store.placement(&hash)[0].delete(&hash).await.unwrap();
+        let redundancy = store.redundancy(&hash).await.unwrap();
+        assert_eq!(redundancy.present, 1);
+        assert!(redundancy.is_degraded());
+        assert!(store.exists(&hash).await.unwrap());
+
+        content::store_content(&store, &hash, data).await.unwrap();
+        assert_eq!(store.redundancy(&hash).await.unwrap().present, 2);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_from_verified_copies_only() {
+        let store = store(3, 2);
+        let data = b"some content".to_vec();
+        let hash = content::hash_data(&data);
+        content::store_content(&store, &hash, data).await.unwrap();
+
+        let placement = store.placement(&hash);
+        placement[0].put(&hash, b"rotten").await.unwrap();
+
+        let report = store.rebuild().await.unwrap();
+        assert_eq!(report.restored, 1);
+        assert!(report.unrecoverable.is_empty());
+        for disk in store.placement(&hash) {
+            let copy = disk.get(&hash).await.unwrap();
+            assert!(content::is_intact(&hash, &copy));
+        }
+
+        for disk in store.placement(&hash) {
+            disk.put(&hash, b"rotten").await.unwrap();
+        }
+        let report = store.rebuild().await.unwrap();
+        assert_eq!(report.unrecoverable, vec![hash.clone()]);
+        assert_eq!(
+            store.placement(&hash)[0].get(&hash).await.unwrap(),
+            b"rotten"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::SCRUB_RATE;
+use crate::metrics::registry::{
+    CORRUPTED_BLOBS, SCRUB_BLOBS_SCANNED, SCRUB_BYTES_SCANNED, SCRUB_CORRUPTIONS, SCRUB_REPAIRS,
+};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::blob::BlobStore;
+use crate::storage::models::FileMetadata;
+use crate::storage::{compressor, content, files};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ScrubStatus {
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub scanned_blobs: u64,
+    pub scanned_bytes: u64,
+    pub corrupted: u64,
+    pub repaired: u64,
+    /// Healthy blobs that had lost or damaged copies or shards and got them back.
+    #[serde(default)]
+    pub restored: u64,
+}
+
+enum Verification {
+    /// The stored bytes, checked against the hash, and the size of the content.
+    Healthy(Vec<u8>, u64),
+    Corrupted(String),
+}
+
+pub struct Scrubber {
+    redis: Arc<RedisClient>,
+    blobs: Arc<dyn BlobStore>,
+}
+
+impl Scrubber {
+    pub fn new(redis: Arc<RedisClient>, blobs: Arc<dyn BlobStore>) -> Self {
+        Self { redis, blobs }
+    }
+
+    pub async fn scrub(&self) -> Result<ScrubStatus, ServiceError> {
+        let mut status = ScrubStatus {
+            started_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        };
+
+        // Several files and versions may share a blob, so verify each hash once.
+        let mut owners: HashMap<String, Vec<String>> = HashMap::new();
+        for file in files::all_files(&self.redis).await? {
+            for hash in file.hashes() {
+                owners
+                    .entry(hash.to_string())
+                    .or_default()
+                    .push(file.id.clone());
+            }
+        }
+
+        log::info!("Starting integrity scrub of {} blobs", owners.len());
+
+        for (hash, file_ids) in owners {
+            match self.verify(&hash).await {
+                Verification::Healthy(stored, size) => {
+                    status.scanned_bytes += size;
+                    SCRUB_BYTES_SCANNED.add(size);
+                    self.throttle(size).await;
+
+                    if self.restore_redundancy(&hash, &stored).await {
+                        status.restored += 1;
+                    }
+
+                    // A blob flagged by an earlier pass may have been restored since.
+                    if self.is_flagged(&hash).await? {
+                        self.flag(&hash, &file_ids, false).await?;
+                    }
+                }
+                Verification::Corrupted(reason) => {
+                    log::warn!("Blob {} failed verification: {}", hash, reason);
+                    status.corrupted += 1;
+                    SCRUB_CORRUPTIONS.inc();
+
+                    if self.repair(&hash).await {
+                        log::info!("Blob {} was repaired from a replica", hash);
+                        status.repaired += 1;
+                        SCRUB_REPAIRS.inc();
+                        self.flag(&hash, &file_ids, false).await?;
+                    } else {
+                        self.flag(&hash, &file_ids, true).await?;
+                    }
+                }
+            }
+
+            status.scanned_blobs += 1;
+            SCRUB_BLOBS_SCANNED.inc();
+        }
+
+        status.finished_at = Some(chrono::Utc::now().timestamp());
+        self.redis
+            .s_async_set(RedisKey::ScrubStatus, &status)
+            .await?;
+
+        let corrupted = self.redis.async_smembers(RedisKey::CorruptedBlobs).await?;
+        CORRUPTED_BLOBS.set(corrupted.len() as u64);
+
+        log::info!(
+            "Integrity scrub finished: {} blobs, {} corrupted, {} repaired, {} restored",
+            status.scanned_blobs,
+            status.corrupted,
+            status.repaired,
+            status.restored
+        );
+
+        Ok(status)
+    }
+
+    async fn verify(&self, hash: &str) -> Verification {
+        let data = match self.blobs.get(hash).await {
+            Ok(data) => data,
+            Err(error) => return Verification::Corrupted(format!("unreadable: {}", error)),
+        };
+
+        match check_content(hash, data.clone()).await {
+            Ok(size) => Verification::Healthy(data, size),
+            Err(error) => Verification::Corrupted(error.to_string()),
+        }
+    }
+
+    async fn repair(&self, hash: &str) -> bool {
+        let replicas = match self.blobs.replicas(hash).await {
+            Ok(replicas) => replicas,
+            Err(error) => {
+                log::warn!("Failed to read the replicas of blob {}: {}", hash, error);
+                return false;
+            }
+        };
+
+        for replica in replicas {
+            if check_content(hash, replica.clone()).await.is_ok() {
+                return self.blobs.restore(hash, &replica).await.is_ok();
+            }
+        }
+
+        false
+    }
+
+    /// Compares every copy or shard of a healthy blob, not only the one reads are served
+    /// from, against the verified content, and rewrites the ones that went missing or rotted
+    /// so the next failing disk does not take the blob down.
+    async fn restore_redundancy(&self, hash: &str, stored: &[u8]) -> bool {
+        match self.blobs.restore(hash, stored).await {
+            Ok(0) => false,
+            Ok(rewritten) => {
+                log::warn!(
+                    "Blob {} had {} missing or damaged copies, which were rewritten",
+                    hash,
+                    rewritten
+                );
+                true
+            }
+            Err(error) => {
+                log::warn!("Failed to restore the copies of blob {}: {}", hash, error);
+                false
+            }
+        }
+    }
+
+    async fn is_flagged(&self, hash: &str) -> Result<bool, ServiceError> {
+        self.redis
+            .async_sismember(RedisKey::CorruptedBlobs, hash)
+            .await
+    }
+
+    async fn flag(
+        &self,
+        hash: &str,
+        file_ids: &[String],
+        corrupted: bool,
+    ) -> Result<(), ServiceError> {
+        if corrupted {
+            self.redis
+                .async_sadd(RedisKey::CorruptedBlobs, hash)
+                .await?;
+        } else {
+            self.redis
+                .async_srem(RedisKey::CorruptedBlobs, hash)
+                .await?;
+        }
+
+        for id in file_ids {
+            self.flag_file(hash, id, corrupted).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Flags the versions of a file stored under `hash`. The file is only written back if it
+    /// did not change in the meantime, so versions uploaded concurrently are never lost.
+    async fn flag_file(&self, hash: &str, id: &str, corrupted: bool) -> Result<(), ServiceError> {
+        loop {
+            let key = RedisKey::File(id.to_string());
+            let current: Option<String> = self
+                .redis
+                .execute(redis::cmd("GET").arg(key.to_string()))
+                .await?;
+
+            let current = match current {
+                Some(current) => current,
+                None => return Ok(()),
+            };
+            let mut file: FileMetadata = serde_json::from_str(&current).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to deserialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })?;
+
+            if !file.set_corrupted(hash, corrupted) {
+                return Ok(());
+            }
+
+            let value = serde_json::to_string(&file).map_err(|error| {
+                ServiceError::InternalServerError(
+                    "Failed to serialize the data".to_string(),
+                    Some(error.into()),
+                )
+            })?;
+            let mut pipeline = redis::pipe();
+            pipeline.cmd("SET").arg(key.to_string()).arg(value).ignore();
+
+            if self
+                .redis
+                .async_compare_and_exec(&[(key, Some(current))], &mut pipeline)
+                .await?
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn throttle(&self, size: u64) {
+        if *SCRUB_RATE > 0 {
+            let seconds = size as f64 / *SCRUB_RATE as f64;
+            tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+        }
+    }
+}
+
+/// Decompresses a stored blob and checks it against its recorded hash, returning the size
+/// of the content.
+async fn check_content(hash: &str, data: Vec<u8>) -> Result<u64, anyhow::Error> {
+    let data = compressor::decompress_data(data).await?;
+    let size = data.len() as u64;
+    let actual = tokio::task::spawn_blocking(move || content::hash_data(&data)).await?;
+
+    if actual != hash {
+        return Err(anyhow::anyhow!("hash mismatch, found {}", actual));
+    }
+
+    Ok(size)
+}
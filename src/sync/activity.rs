@@ -0,0 +1,69 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::ACTIVITY_BATCH_SIZE;
+use crate::redis::client::RedisClient;
+use crate::sync::journal::{self, Change, ChangeKind};
+use serde::Serialize;
+
+/// A change of the journal as shown in the activity feed.
+#[derive(Serialize)]
+pub struct Activity {
+    #[serde(flatten)]
+    pub change: Change,
+    /// Whether the change was made from another device than the one reading the feed.
+    pub other_device: bool,
+}
+
+#[derive(Serialize)]
+pub struct ActivityPage {
+    pub activities: Vec<Activity>,
+    /// Pass back as `before` to continue with older activity.
+    pub cursor: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ActivityFilter {
+    pub kind: Option<ChangeKind>,
+    /// Leaves out what the reading device did itself.
+    pub other_devices: bool,
+}
+
+/// Returns up to `limit` matching activities recorded before the `before` cursor, newest
+/// first, as seen from `device_id`.
+pub async fn feed(
+    redis: &RedisClient,
+    owner: &str,
+    device_id: &str,
+    before: Option<&str>,
+    filter: &ActivityFilter,
+    limit: usize,
+) -> Result<ActivityPage, ServiceError> {
+    let mut activities = Vec::new();
+    let mut cursor = before.map(str::to_string);
+    loop {
+        let batch = journal::history(redis, owner, cursor.as_deref(), ACTIVITY_BATCH_SIZE).await?;
+        let exhausted = batch.len() < ACTIVITY_BATCH_SIZE;
+
+        for change in batch {
+            cursor = Some(change.id.clone());
+
+            let other_device = change.device_id != device_id;
+            if matches!(filter.kind, Some(kind) if kind != change.kind)
+                || (filter.other_devices && !other_device)
+            {
+                continue;
+            }
+
+            activities.push(Activity {
+                change,
+                other_device,
+            });
+            if activities.len() == limit {
+                return Ok(ActivityPage { activities, cursor });
+            }
+        }
+
+        if exhausted {
+            return Ok(ActivityPage { activities, cursor });
+        }
+    }
+}
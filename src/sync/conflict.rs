@@ -0,0 +1,50 @@
+use crate::api::utils::errors::ServiceError;
+use crate::redis::client::RedisClient;
+use crate::storage::blob::BlobStore;
+use crate::storage::files;
+use crate::storage::models::{File, FileMetadata};
+use crate::sync::journal::{self, Change};
+
+/// Keeps a change that lost a race against another device as a sibling of the file it was
+/// meant for, named after the device that made it.
+pub async fn create_conflicted_copy(
+    redis: &RedisClient,
+    blobs: &dyn BlobStore,
+    original: &FileMetadata,
+    device_id: &str,
+    mut file: File,
+) -> Result<FileMetadata, ServiceError> {
+    file.name = conflicted_name(&original.name, device_id, chrono::Utc::now());
+
+    let copy = files::create_file(
+        redis,
+        blobs,
+        &original.owner,
+        device_id,
+        &original.folder,
+        file,
+    )
+    .await?;
+
+    let change = Change::conflict(&copy, original, device_id);
+    journal::record(redis, &original.owner, change).await?;
+
+    Ok(copy)
+}
+
+/// `report.pdf` becomes `report (conflicted copy, laptop, 2022-11-04 10-15-00).pdf`.
+pub fn conflicted_name(name: &str, device_id: &str, date: chrono::DateTime<chrono::Utc>) -> String {
+    let device = device_id.replace(['/', '\\'], "-");
+    let suffix = format!(
+        "conflicted copy, {}, {}",
+        device,
+        date.format("%Y-%m-%d %H-%M-%S")
+    );
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{} ({}).{}", stem, suffix, extension)
+        }
+        _ => format!("{} ({})", name, suffix),
+    }
+}
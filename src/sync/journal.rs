@@ -0,0 +1,258 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::JOURNAL_LENGTH;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::models::FileMetadata;
+use crate::sync::recent;
+use crate::webhooks::delivery;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Moved,
+    Copied,
+    Deleted,
+    Conflict,
+}
+
+/// An entry of a user's change journal, which devices replay to catch up with each other.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Change {
+    /// Position of the entry in the journal, usable as a cursor.
+    #[serde(default)]
+    pub id: String,
+    pub kind: ChangeKind,
+    pub file: String,
+    pub name: String,
+    pub folder: String,
+    pub hash: Option<String>,
+    pub device_id: String,
+    pub timestamp: i64,
+    /// For conflicts, the file the conflicted copy was split from.
+    #[serde(default)]
+    pub conflict_of: Option<String>,
+}
+
+impl Change {
+    pub fn new(kind: ChangeKind, file: &FileMetadata, device_id: &str) -> Self {
+        Self {
+            id: String::new(),
+            kind,
+            file: file.id.clone(),
+            name: file.name.clone(),
+            folder: file.folder.clone(),
+            hash: match kind {
+                ChangeKind::Deleted => None,
+                _ => Some(file.hash.clone()),
+            },
+            device_id: device_id.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            conflict_of: None,
+        }
+    }
+
+    pub fn conflict(copy: &FileMetadata, original: &FileMetadata, device_id: &str) -> Self {
+        let mut change = Change::new(ChangeKind::Conflict, copy, device_id);
+        change.conflict_of = Some(original.id.clone());
+
+        change
+    }
+}
+
+/// Appends a change to the owner's journal, moves the file to the top of the owner's recent
+/// files and notifies the webhooks interested in it.
+pub async fn record(
+    redis: &RedisClient,
+    owner: &str,
+    mut change: Change,
+) -> Result<(), ServiceError> {
+    let value = serde_json::to_string(&change).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })?;
+
+    change.id = redis
+        .async_xadd(
+            RedisKey::Journal(owner.to_string()),
+            "change",
+            &value,
+            JOURNAL_LENGTH,
+        )
+        .await?;
+
+    if change.kind != ChangeKind::Deleted {
+        recent::touch(redis, owner, &change.device_id, &change.file).await;
+    }
+
+    delivery::notify_change(redis, owner, &change).await;
+
+    Ok(())
+}
+
+/// Returns up to `limit` changes recorded after the `since` cursor, oldest first. Fails with
+/// `Gone` when the journal was trimmed past the cursor, as changes may have been lost and the
+/// client has to sync everything again.
+pub async fn changes(
+    redis: &RedisClient,
+    owner: &str,
+    since: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Change>, ServiceError> {
+    let start = match since {
+        Some(since) => {
+            check_cursor(redis, owner, since).await?;
+            next_id(since)?
+        }
+        None => "-".to_string(),
+    };
+
+    let entries = redis
+        .async_xrange(RedisKey::Journal(owner.to_string()), &start, limit)
+        .await?;
+
+    Ok(parse(entries))
+}
+
+/// Returns up to `limit` changes recorded before the `before` cursor, newest first.
+pub async fn history(
+    redis: &RedisClient,
+    owner: &str,
+    before: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Change>, ServiceError> {
+    let end = match before {
+        Some(before) => match previous_id(before)? {
+            Some(end) => end,
+            None => return Ok(Vec::new()),
+        },
+        None => "+".to_string(),
+    };
+
+    let entries = redis
+        .async_xrevrange(RedisKey::Journal(owner.to_string()), &end, limit)
+        .await?;
+
+    Ok(parse(entries))
+}
+
+/// The journal is trimmed from its oldest end. A cursor older than the oldest entry left may
+/// have missed the entries that were trimmed.
+async fn check_cursor(redis: &RedisClient, owner: &str, since: &str) -> Result<(), ServiceError> {
+    let cursor = parse_id(since)?;
+    let oldest = redis
+        .async_xrange(RedisKey::Journal(owner.to_string()), "-", 1)
+        .await?;
+
+    match oldest.first() {
+        Some((id, _)) if parse_id(id)? > cursor => Err(ServiceError::Gone(
+            "The journal no longer reaches back to this cursor, a full resync is needed"
+                .to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn parse(entries: Vec<(String, Vec<String>)>) -> Vec<Change> {
+    let mut changes = Vec::with_capacity(entries.len());
+    for (id, fields) in entries {
+        let value = fields
+            .chunks(2)
+            .find(|pair| pair[0] == "change")
+            .and_then(|pair| pair.get(1));
+
+        if let Some(value) = value {
+            match serde_json::from_str::<Change>(value) {
+                Ok(mut change) => {
+                    change.id = id;
+                    changes.push(change);
+                }
+                Err(error) => log::warn!("Skipping unreadable journal entry {}: {}", id, error),
+            }
+        }
+    }
+
+    changes
+}
+
+/// The smallest stream id after `id`, so ranges can start right after a cursor.
+pub fn next_id(id: &str) -> Result<String, ServiceError> {
+    let (time, sequence) = parse_id(id)?;
+
+    Ok(match sequence.checked_add(1) {
+        Some(sequence) => format!("{}-{}", time, sequence),
+        None => format!("{}-0", time + 1),
+    })
+}
+
+/// The largest stream id before `id`, `None` when nothing can come before it.
+pub fn previous_id(id: &str) -> Result<Option<String>, ServiceError> {
+    let (time, sequence) = parse_id(id)?;
+
+    Ok(match (sequence.checked_sub(1), time.checked_sub(1)) {
+        (Some(sequence), _) => Some(format!("{}-{}", time, sequence)),
+        (None, Some(time)) => Some(format!("{}-{}", time, u64::MAX)),
+        (None, None) => None,
+    })
+}
+
+fn parse_id(id: &str) -> Result<(u64, u64), ServiceError> {
+    let invalid = || ServiceError::BadRequest(format!("'{}' is not a valid cursor", id));
+
+    let (time, sequence) = id.split_once('-').ok_or_else(invalid)?;
+    let time = time.parse::<u64>().map_err(|_| invalid())?;
+    let sequence = sequence.parse::<u64>().map_err(|_| invalid())?;
+
+    Ok((time, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn cursors_step_over_sequence_boundaries() {
+        assert_eq!(next_id("5-3").unwrap(), "5-4");
+        assert_eq!(next_id(&format!("5-{}", u64::MAX)).unwrap(), "6-0");
+        assert_eq!(previous_id("5-3").unwrap().as_deref(), Some("5-2"));
+        assert_eq!(previous_id("5-0").unwrap(), Some(format!("4-{}", u64::MAX)));
+        assert_eq!(previous_id("0-0").unwrap(), None);
+        assert!(next_id("not-a-cursor").is_err());
+    }
+
+    async fn trim(redis: &RedisClient, key: RedisKey, length: usize) {
+        redis
+            .execute::<i64>(
+                redis::cmd("XTRIM")
+                    .arg(key.to_string())
+                    .arg("MAXLEN")
+                    .arg(length),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn trimmed_cursors_need_a_resync() {
+        let redis = testing::redis();
+        let owner = testing::username();
+        let key = || RedisKey::Journal(owner.clone());
+        redis.async_xadd(key(), "change", "{}", 100).await.unwrap();
+        let cursor = redis.async_xadd(key(), "change", "{}", 100).await.unwrap();
+        redis.async_xadd(key(), "change", "{}", 100).await.unwrap();
+
+        trim(&redis, key(), 2).await;
+        assert!(check_cursor(&redis, &owner, &cursor).await.is_ok());
+
+        trim(&redis, key(), 1).await;
+        let result = check_cursor(&redis, &owner, &cursor).await;
+        assert!(matches!(result, Err(ServiceError::Gone(_))));
+
+        redis.async_del(key()).await.unwrap();
+    }
+}
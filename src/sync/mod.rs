@@ -0,0 +1,4 @@
+pub mod activity;
+pub mod conflict;
+pub mod journal;
+pub mod recent;
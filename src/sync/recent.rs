@@ -0,0 +1,155 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::RECENT_FILES_LENGTH;
+use crate::jwt::presigned::PRESIGNED_DEVICE;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::storage::models::FileMetadata;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A file the user recently opened or modified.
+#[derive(Serialize)]
+pub struct RecentFile {
+    pub file: FileMetadata,
+    /// When the file was last opened or modified, as a unix timestamp.
+    pub used_at: i64,
+}
+
+/// Moves a file to the top of the owner's recent files, both overall and for the device.
+/// Pre-signed URLs act for whoever the link was handed to, so they are not counted. The list is
+/// only a convenience, so failing to update it is logged rather than failing the request.
+pub async fn touch(redis: &RedisClient, owner: &str, device_id: &str, file_id: &str) {
+    if device_id == PRESIGNED_DEVICE {
+        return;
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let mut pipeline = redis::pipe();
+    for key in [
+        RedisKey::RecentFiles(owner.to_string()),
+        RedisKey::RecentDeviceFiles(owner.to_string(), device_id.to_string()),
+    ] {
+        let key = key.to_string();
+        pipeline
+            .cmd("ZADD")
+            .arg(&key)
+            .arg(now)
+            .arg(file_id)
+            .ignore()
+            .cmd("ZREMRANGEBYRANK")
+            .arg(&key)
+            .arg(0)
+            .arg(-(RECENT_FILES_LENGTH as isize) - 1)
+            .ignore();
+    }
+
+    if let Err(error) = redis.async_compare_and_exec(&[], &mut pipeline).await {
+        log::error!("Failed to update the recent files of {}: {}", owner, error);
+    }
+}
+
+/// Returns the files the owner used most recently, on `device_id` only when given.
+/// Files deleted since then are dropped from the list on the way.
+pub async fn list(
+    redis: &RedisClient,
+    owner: &str,
+    device_id: Option<&str>,
+    limit: usize,
+) -> Result<Vec<RecentFile>, ServiceError> {
+    let key = || match device_id {
+        Some(device_id) => RedisKey::RecentDeviceFiles(owner.to_string(), device_id.to_string()),
+        None => RedisKey::RecentFiles(owner.to_string()),
+    };
+
+    // Stale entries are removed as they are found, so the files that were kept come first
+    // and the next batch starts after them.
+    let mut recent = Vec::with_capacity(limit);
+    let mut seen = HashSet::new();
+    while recent.len() < limit {
+        let count = limit - recent.len();
+        let entries = redis.async_zrevrange(key(), recent.len(), count).await?;
+        if entries.is_empty() {
+            break;
+        }
+        let exhausted = entries.len() < count;
+
+        let values = redis
+            .async_mget(
+                entries
+                    .iter()
+                    .map(|(id, _)| RedisKey::File(id.clone()))
+                    .collect(),
+            )
+            .await?;
+
+        let mut stale = Vec::new();
+        for ((id, used_at), value) in entries.into_iter().zip(values) {
+            let file = value.and_then(|value| serde_json::from_str::<FileMetadata>(&value).ok());
+
+            match file {
+                Some(file) if file.owner == owner => {
+                    // Touched again since the previous batch, and already listed.
+                    if seen.insert(id) {
+                        recent.push(RecentFile { file, used_at });
+                    }
+                }
+                _ => stale.push(id),
+            }
+        }
+
+        if stale.is_empty() {
+            break;
+        }
+        redis.async_zrem(key(), &stale).await?;
+        if exhausted {
+            break;
+        }
+    }
+
+    Ok(recent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::files;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs Redis"]
+    async fn skips_deleted_files_without_coming_up_short() {
+        let redis = testing::redis();
+        let owner = testing::username();
+
+        let mut kept = Vec::new();
+        for _ in 0..3 {
+            let file = testing::file(&owner, &testing::hash());
+            files::save_file(&redis, &file).await.unwrap();
+            touch(&redis, &owner, "test-device", &file.id).await;
+            kept.push(file.id);
+        }
+        // Used more recently, but deleted since.
+        let later = chrono::Utc::now().timestamp() + 60;
+        for _ in 0..3 {
+            let id = uuid::Uuid::new_v4().to_string();
+            let key = RedisKey::RecentFiles(owner.clone()).to_string();
+            redis
+                .execute::<i64>(redis::cmd("ZADD").arg(key).arg(later).arg(id))
+                .await
+                .unwrap();
+        }
+
+        let recent = list(&redis, &owner, None, 3).await.unwrap();
+        let mut listed = recent
+            .iter()
+            .map(|recent| recent.file.id.clone())
+            .collect::<Vec<_>>();
+        listed.sort();
+        kept.sort();
+        assert_eq!(listed, kept);
+
+        for id in kept {
+            redis.async_del(RedisKey::File(id)).await.unwrap();
+        }
+        redis.async_del(RedisKey::RecentFiles(owner)).await.unwrap();
+    }
+}
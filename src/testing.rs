@@ -0,0 +1,41 @@
+//! Helpers shared by the tests. Tests that need Redis are ignored by default: run them with
+//! `cargo test -- --ignored` against a disposable instance, configured like the server with
+//! `REDIS_HOST`, `REDIS_PORT` and `REDIS_PASSWORD`. CI runs them against a Redis service.
+
+use crate::redis::client::RedisClient;
+use crate::storage::blob::BlobStore;
+use crate::storage::local::LocalBlobStore;
+use crate::storage::models::{FileMetadata, FileVersion};
+use std::sync::Arc;
+
+pub fn redis() -> Arc<RedisClient> {
+    Arc::new(RedisClient::from_env().expect("Failed to connect to the test Redis instance"))
+}
+
+/// A blob store in a fresh temporary directory.
+pub fn blobs() -> Arc<dyn BlobStore> {
+    let root = std::env::temp_dir().join(format!("doc-storage-test-{}", uuid::Uuid::new_v4()));
+    Arc::new(LocalBlobStore::new(root).expect("Failed to create the test blob store"))
+}
+
+/// A user nobody else uses, so tests sharing an instance do not see each other's data.
+pub fn username() -> String {
+    format!("test-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// A random content hash, like the ones content is stored under.
+pub fn hash() -> String {
+    blake3::hash(uuid::Uuid::new_v4().as_bytes())
+        .to_hex()
+        .to_string()
+}
+
+pub fn file(owner: &str, hash: &str) -> FileMetadata {
+    let version = FileVersion::new(1, hash.to_string(), 4, "test-device".to_string());
+    FileMetadata::new(
+        owner.to_string(),
+        "/".to_string(),
+        "test.txt".to_string(),
+        version,
+    )
+}
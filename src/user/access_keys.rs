@@ -0,0 +1,122 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::MAX_ACCESS_KEYS;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::webhooks::delivery;
+use crate::webhooks::models::Event;
+use serde::{Deserialize, Serialize};
+
+/// A key pair for the S3 API. Unlike passwords, secrets are kept as they are: SigV4 signs
+/// requests with the secret, so the server needs it to check signatures.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AccessKey {
+    pub id: String,
+    pub secret: String,
+    pub username: String,
+    pub created_at: i64,
+}
+
+/// An access key without its secret, as listed to its owner.
+#[derive(Serialize)]
+pub struct AccessKeyInfo {
+    pub id: String,
+    pub created_at: i64,
+}
+
+impl AccessKey {
+    pub fn new(username: String) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string().to_uppercase();
+        let secret = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+
+        Self {
+            id: format!("DS{}", &id[..18]),
+            secret,
+            username,
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn info(&self) -> AccessKeyInfo {
+        AccessKeyInfo {
+            id: self.id.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+pub async fn create_key(redis: &RedisClient, username: &str) -> Result<AccessKey, ServiceError> {
+    let existing = redis
+        .async_smembers(RedisKey::UserAccessKeys(username.to_string()))
+        .await?;
+    if existing.len() >= MAX_ACCESS_KEYS {
+        return Err(ServiceError::BadRequest(format!(
+            "An account cannot have more than {} access keys",
+            MAX_ACCESS_KEYS
+        )));
+    }
+
+    let key = AccessKey::new(username.to_string());
+    redis
+        .s_async_set(RedisKey::AccessKey(key.id.clone()), &key)
+        .await?;
+    redis
+        .async_sadd(RedisKey::UserAccessKeys(username.to_string()), &key.id)
+        .await?;
+    delivery::notify(redis, username, Event::AccessKeyCreated, None, &key.info()).await;
+
+    Ok(key)
+}
+
+pub async fn get_key(redis: &RedisClient, id: &str) -> Result<Option<AccessKey>, ServiceError> {
+    if !redis
+        .async_exists(RedisKey::AccessKey(id.to_string()))
+        .await?
+    {
+        return Ok(None);
+    }
+
+    redis
+        .d_async_get(RedisKey::AccessKey(id.to_string()))
+        .await
+        .map(Some)
+}
+
+pub async fn list_keys(
+    redis: &RedisClient,
+    username: &str,
+) -> Result<Vec<AccessKey>, ServiceError> {
+    let ids = redis
+        .async_smembers(RedisKey::UserAccessKeys(username.to_string()))
+        .await?;
+
+    let mut keys = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(key) = get_key(redis, &id).await? {
+            keys.push(key);
+        }
+    }
+    keys.sort_by_key(|key| key.created_at);
+
+    Ok(keys)
+}
+
+pub async fn delete_key(redis: &RedisClient, username: &str, id: &str) -> Result<(), ServiceError> {
+    match get_key(redis, id).await? {
+        Some(key) if key.username == username => {
+            redis.async_del(RedisKey::AccessKey(id.to_string())).await?;
+            redis
+                .async_srem(RedisKey::UserAccessKeys(username.to_string()), id)
+                .await?;
+            delivery::notify(redis, username, Event::AccessKeyDeleted, None, &key.info()).await;
+
+            Ok(())
+        }
+        _ => Err(ServiceError::NotFound(format!(
+            "Access key {} does not exist",
+            id
+        ))),
+    }
+}
@@ -1,2 +1,5 @@
+pub mod access_keys;
 pub mod models;
 pub mod password;
+pub mod privacy;
+pub mod roles;
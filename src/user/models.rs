@@ -1,3 +1,4 @@
+use crate::storage::content_type::TypePolicy;
 use crate::user::password;
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +7,15 @@ pub struct User {
     pub username: String,
     pub password: String,
     pub device_id: Vec<String>,
+    #[serde(default)]
+    pub type_policy: TypePolicy,
+    /// Storage quota in bytes, `None` falls back to the default quota.
+    #[serde(default)]
+    pub quota: Option<u64>,
+    /// Removes location and identifying metadata from images served through pre-signed URLs,
+    /// whether or not the URL asks for it.
+    #[serde(default)]
+    pub strip_shared_metadata: bool,
 }
 
 impl User {
@@ -14,6 +24,9 @@ impl User {
             username,
             password,
             device_id: vec![device_id],
+            type_policy: TypePolicy::default(),
+            quota: None,
+            strip_shared_metadata: false,
         }
     }
 
@@ -0,0 +1,55 @@
+use crate::api::utils::errors::ServiceError;
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::user::models::User;
+use crate::webhooks::delivery;
+use crate::webhooks::models::Event;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct PrivacySettings {
+    pub strip_shared_metadata: bool,
+}
+
+pub async fn get_settings(
+    redis: &RedisClient,
+    username: &str,
+) -> Result<PrivacySettings, ServiceError> {
+    let user = load_user(redis, username).await?;
+
+    Ok(PrivacySettings {
+        strip_shared_metadata: user.strip_shared_metadata,
+    })
+}
+
+pub async fn set_strip_shared_metadata(
+    redis: &RedisClient,
+    username: &str,
+    strip: bool,
+) -> Result<PrivacySettings, ServiceError> {
+    let mut user = load_user(redis, username).await?;
+    user.strip_shared_metadata = strip;
+    redis
+        .s_async_set(RedisKey::Account(username.to_string()), &user)
+        .await?;
+
+    let settings = PrivacySettings {
+        strip_shared_metadata: strip,
+    };
+    delivery::notify(redis, username, Event::PrivacyUpdated, None, &settings).await;
+
+    Ok(settings)
+}
+
+async fn load_user(redis: &RedisClient, username: &str) -> Result<User, ServiceError> {
+    let account = RedisKey::Account(username.to_string());
+    if !redis.async_exists(account).await? {
+        return Err(ServiceError::NotFound(format!(
+            "User {} does not exist",
+            username
+        )));
+    }
+
+    redis
+        .d_async_get(RedisKey::Account(username.to_string()))
+        .await
+}
@@ -0,0 +1,5 @@
+use crate::constants::ADMINS;
+
+pub fn is_admin(username: &str) -> bool {
+    ADMINS.iter().any(|admin| admin == username)
+}
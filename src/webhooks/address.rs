@@ -0,0 +1,116 @@
+use crate::api::utils::errors::ServiceError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Resolves the host of a webhook URL to the address deliveries are sent to. Webhooks may only
+/// reach the public internet, so hosts resolving to any internal address are refused with
+/// `Forbidden`, whichever address the endpoint would have been reached at.
+pub async fn resolve(url: &reqwest::Url) -> Result<SocketAddr, ServiceError> {
+    let port = url.port_or_known_default().unwrap_or(443);
+    let host = url.host_str().unwrap_or_default();
+    let addresses = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map(Iterator::collect)
+            .unwrap_or_default(),
+    };
+
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(ServiceError::Forbidden(format!(
+            "Webhooks cannot be sent to {}",
+            address.ip()
+        )));
+    }
+
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| ServiceError::BadRequest(format!("The host of '{}' does not resolve", url)))
+}
+
+/// Whether an address is outside the loopback, private, link-local (which holds the cloud
+/// metadata services) and unspecified ranges.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.octets()[0] == 0)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    // Unique local addresses are fc00::/7, link-local ones fe80::/10.
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || first & 0xfe00 == 0xfc00
+        || first & 0xffc0 == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn refuses_internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!public(ip), "{} should be refused", ip);
+        }
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for ip in ["93.184.216.34", "172.32.0.1", "2606:2800:220:1::1"] {
+            assert!(public(ip), "{} should be accepted", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_addresses_in_urls() {
+        let url = reqwest::Url::parse("http://169.254.169.254/latest").unwrap();
+        assert!(matches!(
+            resolve(&url).await,
+            Err(ServiceError::Forbidden(_))
+        ));
+
+        let url = reqwest::Url::parse("https://[::1]:8443/hook").unwrap();
+        assert!(matches!(
+            resolve(&url).await,
+            Err(ServiceError::Forbidden(_))
+        ));
+
+        let url = reqwest::Url::parse("https://93.184.216.34/hook").unwrap();
+        let address = resolve(&url).await.unwrap();
+        assert_eq!(address, "93.184.216.34:443".parse().unwrap());
+    }
+}
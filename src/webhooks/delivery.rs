@@ -0,0 +1,269 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::WEBHOOK_TIMEOUT;
+use crate::jobs::models::Job;
+use crate::jobs::queue;
+use crate::jobs::tasks::Task;
+use crate::redis::client::RedisClient;
+use crate::sync::journal::Change;
+use crate::webhooks::models::{Delivery, DeliveryAttempt, DeliveryState, Event, Webhook};
+use crate::webhooks::{address, store};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const EVENT_HEADER: &str = "X-Doc-Storage-Event";
+pub const DELIVERY_HEADER: &str = "X-Doc-Storage-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Doc-Storage-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Doc-Storage-Signature";
+
+/// Signs `{timestamp}.{body}` with the secret of the webhook. Receivers compute the same
+/// signature to check the payload came from us, and reject old timestamps to stop replays.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queues a delivery of `event` to each of the owner's webhooks that wants it. File events
+/// carry the folder they happened in, for webhooks watching a single folder.
+pub async fn dispatch<T: Serialize>(
+    redis: &RedisClient,
+    owner: &str,
+    event: Event,
+    folder: Option<&str>,
+    data: &T,
+) -> Result<(), ServiceError> {
+    let webhooks = store::list_webhooks(redis, owner).await?;
+    if !webhooks
+        .iter()
+        .any(|webhook| webhook.accepts(event, folder))
+    {
+        return Ok(());
+    }
+
+    let data = serde_json::to_value(data).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to serialize the data".to_string(),
+            Some(error.into()),
+        )
+    })?;
+
+    for webhook in webhooks {
+        if webhook.accepts(event, folder) {
+            start(redis, &webhook, event, data.clone()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Dispatches `event` for an action that has already happened. Failing to queue the deliveries
+/// does not undo it, so the error is logged instead of failing the request.
+pub async fn notify<T: Serialize>(
+    redis: &RedisClient,
+    owner: &str,
+    event: Event,
+    folder: Option<&str>,
+    data: &T,
+) {
+    if let Err(error) = dispatch(redis, owner, event, folder, data).await {
+        log::error!(
+            "Failed to dispatch the {} event of {}: {}",
+            event.name(),
+            owner,
+            error
+        );
+    }
+}
+
+pub async fn notify_change(redis: &RedisClient, owner: &str, change: &Change) {
+    let event = Event::from_change(change.kind);
+
+    notify(redis, owner, event, Some(&change.folder), change).await
+}
+
+/// Sends a `ping` to a webhook, whatever it subscribed to, so its endpoint can be tested.
+pub async fn ping(redis: &RedisClient, webhook: &Webhook) -> Result<Delivery, ServiceError> {
+    let data = serde_json::json!({ "url": webhook.url });
+
+    start(redis, webhook, Event::Ping, data).await
+}
+
+async fn start(
+    redis: &RedisClient,
+    webhook: &Webhook,
+    event: Event,
+    data: serde_json::Value,
+) -> Result<Delivery, ServiceError> {
+    let delivery = Delivery::new(webhook, event, data);
+    store::log_delivery(redis, &delivery).await?;
+    enqueue(redis, webhook, &delivery).await?;
+
+    Ok(delivery)
+}
+
+async fn enqueue(
+    redis: &RedisClient,
+    webhook: &Webhook,
+    delivery: &Delivery,
+) -> Result<(), ServiceError> {
+    let task = Task::DeliverWebhook {
+        delivery_id: delivery.id.clone(),
+    };
+    queue::enqueue(redis, Job::new(webhook.owner.clone(), task)).await?;
+
+    Ok(())
+}
+
+/// Sends a past delivery again, with the payload it was first sent with.
+pub async fn redeliver(
+    redis: &RedisClient,
+    webhook: &Webhook,
+    id: &str,
+) -> Result<Delivery, ServiceError> {
+    let mut delivery = match store::get_delivery(redis, id).await? {
+        Some(delivery) if delivery.webhook_id == webhook.id => delivery,
+        _ => {
+            return Err(ServiceError::NotFound(format!(
+                "Delivery {} does not exist",
+                id
+            )))
+        }
+    };
+
+    if delivery.state == DeliveryState::Pending {
+        return Err(ServiceError::Conflict(format!(
+            "Delivery {} is still pending",
+            id
+        )));
+    }
+
+    delivery.state = DeliveryState::Pending;
+    delivery.updated_at = chrono::Utc::now().timestamp();
+    store::save_delivery(redis, &delivery).await?;
+    enqueue(redis, webhook, &delivery).await?;
+
+    Ok(delivery)
+}
+
+/// Posts a delivery to its webhook and records the attempt. Failed attempts are returned as
+/// errors, so the job queue retries them with backoff until the job runs out of attempts.
+pub async fn deliver(
+    redis: &RedisClient,
+    id: &str,
+    job: &Job,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let mut delivery = match store::get_delivery(redis, id).await.map_err(unexpected)? {
+        Some(delivery) => delivery,
+        None => return Ok(serde_json::json!({ "skipped": "The delivery expired" })),
+    };
+
+    let webhook = match store::get_webhook(redis, &delivery.webhook_id)
+        .await
+        .map_err(unexpected)?
+    {
+        Some(webhook) if webhook.active || delivery.event == Event::Ping => webhook,
+        Some(_) => return give_up(redis, delivery, "The webhook is disabled").await,
+        None => return give_up(redis, delivery, "The webhook was deleted").await,
+    };
+
+    // The host may resolve elsewhere than when the webhook was registered, so it is checked
+    // again and the request is pinned to the address that passed.
+    let url = reqwest::Url::parse(&webhook.url)?;
+    let target = match address::resolve(&url).await {
+        Ok(target) => target,
+        Err(ServiceError::Forbidden(reason)) => return give_up(redis, delivery, &reason).await,
+        Err(error) => return Err(unexpected(error)),
+    };
+
+    let body = serde_json::to_vec(&delivery.payload)?;
+    let timestamp = chrono::Utc::now().timestamp();
+    let clock = Instant::now();
+
+    let response = client(url.host_str().unwrap_or_default(), target)?
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.name())
+        .header(DELIVERY_HEADER, &delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    let (status, error) = match response {
+        Ok(response) if response.status().is_success() => (Some(response.status()), None),
+        Ok(response) => (
+            Some(response.status()),
+            Some(format!("The endpoint answered {}", response.status())),
+        ),
+        Err(error) => (
+            None,
+            Some(format!("Failed to reach the endpoint: {}", error)),
+        ),
+    };
+
+    delivery.attempts.push(DeliveryAttempt {
+        attempted_at: timestamp,
+        status: status.map(|status| status.as_u16()),
+        error: error.clone(),
+        duration_ms: clock.elapsed().as_millis() as u64,
+    });
+    delivery.state = match (&error, job.attempts >= job.max_attempts) {
+        (None, _) => DeliveryState::Delivered,
+        (Some(_), true) => DeliveryState::Failed,
+        (Some(_), false) => DeliveryState::Pending,
+    };
+    delivery.updated_at = chrono::Utc::now().timestamp();
+    store::save_delivery(redis, &delivery)
+        .await
+        .map_err(unexpected)?;
+
+    match error {
+        None => Ok(serde_json::json!({ "status": status.map(|status| status.as_u16()) })),
+        Some(error) => Err(anyhow::anyhow!(error)),
+    }
+}
+
+/// Builds the client posting a delivery, resolving its host to `target` only. Redirects are not
+/// followed, a delivery goes where it was registered to go or fails.
+fn client(host: &str, target: SocketAddr) -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("doc-storage-webhooks/", env!("CARGO_PKG_VERSION")))
+        .resolve(host, target)
+        .build()
+}
+
+async fn give_up(
+    redis: &RedisClient,
+    mut delivery: Delivery,
+    reason: &str,
+) -> Result<serde_json::Value, anyhow::Error> {
+    delivery.attempts.push(DeliveryAttempt {
+        attempted_at: chrono::Utc::now().timestamp(),
+        status: None,
+        error: Some(reason.to_string()),
+        duration_ms: 0,
+    });
+    delivery.state = DeliveryState::Failed;
+    delivery.updated_at = chrono::Utc::now().timestamp();
+    store::save_delivery(redis, &delivery)
+        .await
+        .map_err(unexpected)?;
+
+    Ok(serde_json::json!({ "skipped": reason }))
+}
+
+fn unexpected(error: ServiceError) -> anyhow::Error {
+    anyhow::anyhow!("{}", error)
+}
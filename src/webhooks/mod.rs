@@ -0,0 +1,4 @@
+pub mod address;
+pub mod delivery;
+pub mod models;
+pub mod store;
@@ -0,0 +1,196 @@
+use crate::sync::journal::ChangeKind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    #[serde(rename = "file.created")]
+    FileCreated,
+    #[serde(rename = "file.updated")]
+    FileUpdated,
+    #[serde(rename = "file.moved")]
+    FileMoved,
+    #[serde(rename = "file.copied")]
+    FileCopied,
+    #[serde(rename = "file.deleted")]
+    FileDeleted,
+    #[serde(rename = "file.conflict")]
+    FileConflict,
+    #[serde(rename = "account.login")]
+    Login,
+    #[serde(rename = "account.key_created")]
+    AccessKeyCreated,
+    #[serde(rename = "account.key_deleted")]
+    AccessKeyDeleted,
+    #[serde(rename = "account.privacy_updated")]
+    PrivacyUpdated,
+    /// Sent on request to check an endpoint, whatever it subscribed to.
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+impl Event {
+    pub fn from_change(kind: ChangeKind) -> Self {
+        match kind {
+            ChangeKind::Created => Event::FileCreated,
+            ChangeKind::Updated => Event::FileUpdated,
+            ChangeKind::Moved => Event::FileMoved,
+            ChangeKind::Copied => Event::FileCopied,
+            ChangeKind::Deleted => Event::FileDeleted,
+            ChangeKind::Conflict => Event::FileConflict,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::FileCreated => "file.created",
+            Event::FileUpdated => "file.updated",
+            Event::FileMoved => "file.moved",
+            Event::FileCopied => "file.copied",
+            Event::FileDeleted => "file.deleted",
+            Event::FileConflict => "file.conflict",
+            Event::Login => "account.login",
+            Event::AccessKeyCreated => "account.key_created",
+            Event::AccessKeyDeleted => "account.key_deleted",
+            Event::PrivacyUpdated => "account.privacy_updated",
+            Event::Ping => "ping",
+        }
+    }
+}
+
+/// An endpoint notified of a user's events. Folder filters only apply to file events.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Webhook {
+    pub id: String,
+    pub owner: String,
+    pub url: String,
+    /// Signs the payloads. Only returned when the webhook is created.
+    pub secret: String,
+    pub events: BTreeSet<Event>,
+    pub folder: Option<String>,
+    pub active: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A webhook without its secret, as listed to its owner.
+#[derive(Serialize)]
+pub struct WebhookInfo {
+    pub id: String,
+    pub url: String,
+    pub events: BTreeSet<Event>,
+    pub folder: Option<String>,
+    pub active: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Webhook {
+    pub fn new(
+        owner: String,
+        url: String,
+        events: BTreeSet<Event>,
+        folder: Option<String>,
+    ) -> Self {
+        let now = chrono::Utc::now().timestamp();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner,
+            url,
+            secret: format!(
+                "whsec_{}{}",
+                uuid::Uuid::new_v4().simple(),
+                uuid::Uuid::new_v4().simple()
+            ),
+            events,
+            folder,
+            active: true,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the webhook wants `event`, which happened in `folder` for file events.
+    pub fn accepts(&self, event: Event, folder: Option<&str>) -> bool {
+        if !self.active || !self.events.contains(&event) {
+            return false;
+        }
+
+        match (self.folder.as_deref(), folder) {
+            (Some(root), Some(folder)) => crate::storage::folders::is_within(root, folder),
+            _ => true,
+        }
+    }
+
+    pub fn info(&self) -> WebhookInfo {
+        WebhookInfo {
+            id: self.id.clone(),
+            url: self.url.clone(),
+            events: self.events.clone(),
+            folder: self.folder.clone(),
+            active: self.active,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// Waiting for its first attempt or for a retry.
+    Pending,
+    Delivered,
+    /// Out of attempts. It can still be redelivered by hand.
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeliveryAttempt {
+    pub attempted_at: i64,
+    /// The status the endpoint answered with, if it answered.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// An event sent to a webhook, with every attempt made to send it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Delivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: Event,
+    /// The body posted to the endpoint.
+    pub payload: serde_json::Value,
+    pub state: DeliveryState,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Delivery {
+    pub fn new(webhook: &Webhook, event: Event, data: serde_json::Value) -> Self {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = chrono::Utc::now().timestamp();
+        let payload = serde_json::json!({
+            "id": id,
+            "event": event.name(),
+            "webhook": webhook.id,
+            "owner": webhook.owner,
+            "created_at": now,
+            "data": data,
+        });
+
+        Self {
+            id,
+            webhook_id: webhook.id.clone(),
+            event,
+            payload,
+            state: DeliveryState::Pending,
+            attempts: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
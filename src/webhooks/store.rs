@@ -0,0 +1,168 @@
+use crate::api::utils::errors::ServiceError;
+use crate::constants::{MAX_WEBHOOKS, WEBHOOK_DELIVERY_LOG_LENGTH, WEBHOOK_DELIVERY_TTL};
+use crate::redis::client::{RedisClient, RedisKey};
+use crate::webhooks::models::{Delivery, Webhook};
+use serde::Deserialize;
+
+fn deserialize<T: for<'a> Deserialize<'a>>(value: &str) -> Result<T, ServiceError> {
+    serde_json::from_str(value).map_err(|error| {
+        ServiceError::InternalServerError(
+            "Failed to deserialize the data".to_string(),
+            Some(error.into()),
+        )
+    })
+}
+
+pub async fn create_webhook(redis: &RedisClient, webhook: &Webhook) -> Result<(), ServiceError> {
+    let existing = redis
+        .async_smembers(RedisKey::UserWebhooks(webhook.owner.clone()))
+        .await?;
+    if existing.len() >= MAX_WEBHOOKS {
+        return Err(ServiceError::BadRequest(format!(
+            "An account cannot have more than {} webhooks",
+            MAX_WEBHOOKS
+        )));
+    }
+
+    save_webhook(redis, webhook).await?;
+    redis
+        .async_sadd(RedisKey::UserWebhooks(webhook.owner.clone()), &webhook.id)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn save_webhook(redis: &RedisClient, webhook: &Webhook) -> Result<(), ServiceError> {
+    redis
+        .s_async_set(RedisKey::Webhook(webhook.id.clone()), webhook)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_webhook(redis: &RedisClient, id: &str) -> Result<Option<Webhook>, ServiceError> {
+    let value: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::Webhook(id.to_string()).to_string()))
+        .await?;
+
+    value.as_deref().map(deserialize).transpose()
+}
+
+pub async fn get_owned_webhook(
+    redis: &RedisClient,
+    id: &str,
+    username: &str,
+) -> Result<Webhook, ServiceError> {
+    match get_webhook(redis, id).await? {
+        Some(webhook) if webhook.owner == username => Ok(webhook),
+        _ => Err(ServiceError::NotFound(format!(
+            "Webhook {} does not exist",
+            id
+        ))),
+    }
+}
+
+pub async fn list_webhooks(
+    redis: &RedisClient,
+    username: &str,
+) -> Result<Vec<Webhook>, ServiceError> {
+    let ids = redis
+        .async_smembers(RedisKey::UserWebhooks(username.to_string()))
+        .await?;
+
+    let mut webhooks = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(webhook) = get_webhook(redis, &id).await? {
+            webhooks.push(webhook);
+        }
+    }
+    webhooks.sort_by_key(|webhook| webhook.created_at);
+
+    Ok(webhooks)
+}
+
+/// Deletes a webhook and its delivery log. Deliveries still queued find it gone and give up.
+pub async fn delete_webhook(redis: &RedisClient, webhook: &Webhook) -> Result<(), ServiceError> {
+    redis
+        .async_srem(RedisKey::UserWebhooks(webhook.owner.clone()), &webhook.id)
+        .await?;
+    redis
+        .async_del(RedisKey::Webhook(webhook.id.clone()))
+        .await?;
+    redis
+        .async_del(RedisKey::WebhookDeliveries(webhook.id.clone()))
+        .await?;
+
+    Ok(())
+}
+
+/// Saves a delivery, which expires `WEBHOOK_DELIVERY_TTL` seconds after it last changed.
+pub async fn save_delivery(redis: &RedisClient, delivery: &Delivery) -> Result<(), ServiceError> {
+    let key = RedisKey::WebhookDelivery(delivery.id.clone());
+    redis.s_async_set(key, delivery).await?;
+    redis
+        .async_expire(
+            RedisKey::WebhookDelivery(delivery.id.clone()),
+            WEBHOOK_DELIVERY_TTL,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Saves a new delivery and adds it to the log of its webhook, which keeps the latest
+/// `WEBHOOK_DELIVERY_LOG_LENGTH` deliveries.
+pub async fn log_delivery(redis: &RedisClient, delivery: &Delivery) -> Result<(), ServiceError> {
+    save_delivery(redis, delivery).await?;
+
+    let log = RedisKey::WebhookDeliveries(delivery.webhook_id.clone()).to_string();
+    redis
+        .execute::<i64>(redis::cmd("LPUSH").arg(&log).arg(&delivery.id))
+        .await?;
+    redis
+        .execute::<String>(
+            redis::cmd("LTRIM")
+                .arg(&log)
+                .arg(0)
+                .arg(WEBHOOK_DELIVERY_LOG_LENGTH - 1),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_delivery(redis: &RedisClient, id: &str) -> Result<Option<Delivery>, ServiceError> {
+    let value: Option<String> = redis
+        .execute(redis::cmd("GET").arg(RedisKey::WebhookDelivery(id.to_string()).to_string()))
+        .await?;
+
+    value.as_deref().map(deserialize).transpose()
+}
+
+/// The deliveries of a webhook still on record, latest first.
+pub async fn list_deliveries(
+    redis: &RedisClient,
+    webhook_id: &str,
+) -> Result<Vec<Delivery>, ServiceError> {
+    let ids: Vec<String> = redis
+        .execute(
+            redis::cmd("LRANGE")
+                .arg(RedisKey::WebhookDeliveries(webhook_id.to_string()).to_string())
+                .arg(0)
+                .arg(-1),
+        )
+        .await?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let values = redis
+        .async_mget(ids.into_iter().map(RedisKey::WebhookDelivery).collect())
+        .await?;
+
+    values
+        .iter()
+        .flatten()
+        .map(|value| deserialize(value))
+        .collect()
+}